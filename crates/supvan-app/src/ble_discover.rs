@@ -8,8 +8,10 @@
 //! advertised name (e.g. `T0182A2507162197`) is the firmware serial-name used
 //! to cross-correlate USB/BT/BLE transports for one physical printer.
 //!
-//! Gated behind the `ble` feature; without it `list_candidates` is a stub that
-//! returns nothing, so discovery wiring compiles on BlueZ-free CI.
+//! The scan only runs with `SUPVAN_BT_SCAN=1`; by default discovery reads the
+//! passive table kept by [`crate::bluez_watch`], which reuses the predicates
+//! here. Gated behind the `ble` feature; without it `list_candidates` is a
+//! stub that returns nothing, so discovery wiring compiles on BlueZ-free CI.
 
 /// One BLE-attached Supvan candidate, ready to cross-correlate with USB/BT.
 #[derive(Debug, Clone)]
@@ -20,7 +22,6 @@ pub struct BleCandidate {
 
 /// Supvan printers advertise a name beginning with a `T`/`G`/`D` model letter
 /// followed by two digits (e.g. `T0182…`, `G15…`).
-#[cfg_attr(not(feature = "ble"), allow(dead_code))]
 fn is_supvan_ble_name(name: &str) -> bool {
     let b = name.as_bytes();
     b.len() >= 3
@@ -30,14 +31,12 @@ fn is_supvan_ble_name(name: &str) -> bool {
 }
 
 /// Supvan's assigned MAC OUI.
-#[cfg_attr(not(feature = "ble"), allow(dead_code))]
 fn is_supvan_oui(addr: &str) -> bool {
     addr.len() >= 8 && addr[..8].eq_ignore_ascii_case("A4:93:40")
 }
 
 /// True if a scanned device looks like a Supvan BLE printer.
-#[cfg_attr(not(feature = "ble"), allow(dead_code))]
-fn is_supvan_ble(addr: &str, name: &str) -> bool {
+pub(crate) fn is_supvan_ble(addr: &str, name: &str) -> bool {
     is_supvan_oui(addr) && is_supvan_ble_name(name)
 }
 
@@ -110,7 +109,10 @@ async fn scan() -> bluer::Result<Vec<BleCandidate>> {
                 .any(|u| chars_for_service(*u).is_some());
             if advertises_gatt && is_supvan_ble(&astr, &name) && seen.insert(astr.clone()) {
                 log::info!("ble_discover: found {name} ({astr})");
-                out.push(BleCandidate { address: astr, name });
+                out.push(BleCandidate {
+                    address: astr,
+                    name,
+                });
             }
         }
         Ok::<(), bluer::Error>(())
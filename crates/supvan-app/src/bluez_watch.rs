@@ -0,0 +1,490 @@
+//! Live Supvan Bluetooth presence from BlueZ signals.
+//!
+//! Discovery used to run timed active scans, which disturb other Bluetooth
+//! peripherals in range. Instead a thread subscribes to BlueZ's
+//! `InterfacesAdded` / `InterfacesRemoved` and to `PropertiesChanged` on
+//! `org.bluez.Device1`, and keeps a table of the devices BlueZ already knows
+//! with their `Connected` state and `RSSI`. [`bt_candidates`] and
+//! [`ble_candidates`] feed [`crate::ipp_server::SupvanDeviceBackend::list`]
+//! from it, and transitions are applied as they arrive:
+//!
+//! - a Supvan device appearing, connecting or being heard (RSSI) registers
+//!   its address with [`device::attach_wireless`] and brings the queue back
+//!   online;
+//! - a disconnect, or BlueZ dropping the device, closes any connection over
+//!   it with [`device::detach_wireless`] and marks the printer offline unless
+//!   USB still reaches it.
+//!
+//! `SUPVAN_BT_SCAN=1` brings back the active BT Classic (with auto-pairing)
+//! and LE scans at startup, for printers BlueZ has never seen.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use dbus::Message;
+use dbus::arg::PropMap;
+use dbus::blocking::Connection;
+use dbus::blocking::stdintf::org_freedesktop_dbus::{
+    ObjectManager, ObjectManagerInterfacesAdded, ObjectManagerInterfacesRemoved,
+    PropertiesPropertiesChanged,
+};
+use dbus::message::SignalArgs;
+use ipp_printer_app::PrinterRegistry;
+use ipp_printer_app::status::AdvertiserControl;
+use supvan_proto::identity::Sighting;
+use tokio::sync::mpsc;
+
+use crate::ble_discover::{self, BleCandidate};
+use crate::discover::{self, BtCandidate};
+use crate::{device, hotplug, models};
+
+const BLUEZ_SERVICE: &str = "org.bluez";
+const DEVICE_IFACE: &str = "org.bluez.Device1";
+
+/// True if `SUPVAN_BT_SCAN=1`: discovery runs the active scans instead of
+/// reading the watcher's table.
+pub fn active_scan() -> bool {
+    std::env::var("SUPVAN_BT_SCAN")
+        .map(|v| v == "1")
+        .unwrap_or(false)
+}
+
+/// How a Supvan printer is reached over Bluetooth.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    /// Classic RFCOMM (Serial Port profile).
+    Bt,
+    /// GATT, for E11/E12-class printers.
+    Ble,
+}
+
+impl Kind {
+    fn transport(self) -> &'static str {
+        match self {
+            Kind::Bt => "bt",
+            Kind::Ble => "ble",
+        }
+    }
+}
+
+/// The `Device1` properties the watcher tracks for one BlueZ object.
+#[derive(Clone, Debug, Default)]
+struct Device {
+    address: String,
+    name: String,
+    uuids: Vec<String>,
+    connected: bool,
+    rssi: Option<i16>,
+}
+
+impl Device {
+    fn merge(&mut self, props: &PropMap) {
+        if let Some(address) = discover::get_str_prop(props, "Address") {
+            self.address = address;
+        }
+        if let Some(name) = discover::get_str_prop(props, "Name") {
+            self.name = name;
+        }
+        if let Some(uuids) = props.get("UUIDs").and_then(|v| v.0.as_iter()) {
+            self.uuids = uuids.filter_map(|u| u.as_str().map(String::from)).collect();
+        }
+        if let Some(connected) = discover::get_bool_prop(props, "Connected") {
+            self.connected = connected;
+        }
+        if let Some(rssi) = props.get("RSSI").and_then(|v| v.0.as_i64()) {
+            self.rssi = Some(rssi as i16);
+        }
+    }
+
+    /// BlueZ drops `RSSI` from the object once it stops hearing the device.
+    fn invalidate(&mut self, names: &[String]) {
+        for name in names {
+            match name.as_str() {
+                "RSSI" => self.rssi = None,
+                "Connected" => self.connected = false,
+                _ => {}
+            }
+        }
+    }
+
+    /// Classify with the same tests as the active scans: an SPP printer by
+    /// its name pattern, a BLE one by OUI, name and Supvan GATT service.
+    fn kind(&self) -> Option<Kind> {
+        let spp = self
+            .uuids
+            .iter()
+            .any(|u| u.to_lowercase().starts_with("00001101-"));
+        let gatt = self.uuids.iter().any(|u| {
+            uuid::Uuid::parse_str(u)
+                .ok()
+                .and_then(supvan_proto::ble::chars_for_service)
+                .is_some()
+        });
+        if spp && models::is_matching_bt_name(&self.name) {
+            Some(Kind::Bt)
+        } else if gatt && ble_discover::is_supvan_ble(&self.address, &self.name) {
+            Some(Kind::Ble)
+        } else {
+            None
+        }
+    }
+
+    /// BlueZ has a link to the device or is currently hearing it.
+    fn present(&self) -> bool {
+        self.connected || self.rssi.is_some()
+    }
+
+    fn seen(&self) -> Option<Seen> {
+        Some(Seen {
+            kind: self.kind()?,
+            name: self.name.clone(),
+            address: self.address.clone(),
+        })
+    }
+}
+
+/// A Supvan printer as BlueZ knows it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Seen {
+    kind: Kind,
+    name: String,
+    address: String,
+}
+
+/// A reachability transition of a Supvan printer.
+#[derive(Debug, PartialEq, Eq)]
+enum Change {
+    /// Newly known to BlueZ, connected, or heard again.
+    Up(Seen),
+    /// Disconnected and no longer heard; BlueZ still knows it.
+    Down(Seen),
+    /// BlueZ removed the device (unpaired, or an LE advert aged out).
+    Gone(Seen),
+}
+
+/// BlueZ device objects by D-Bus path.
+#[derive(Default)]
+struct Table {
+    devices: BTreeMap<String, Device>,
+}
+
+impl Table {
+    fn added(&mut self, path: &str, props: &PropMap) -> Option<Change> {
+        let device = self.devices.entry(path.to_string()).or_default();
+        let before = device.clone();
+        device.merge(props);
+        transition(&before, device)
+    }
+
+    fn changed(&mut self, path: &str, props: &PropMap, invalidated: &[String]) -> Option<Change> {
+        let device = self.devices.get_mut(path)?;
+        let before = device.clone();
+        device.merge(props);
+        device.invalidate(invalidated);
+        transition(&before, device)
+    }
+
+    fn removed(&mut self, path: &str) -> Option<Change> {
+        self.devices.remove(path)?.seen().map(Change::Gone)
+    }
+
+    fn seen(&self, kind: Kind) -> impl Iterator<Item = Seen> + '_ {
+        self.devices
+            .values()
+            .filter_map(Device::seen)
+            .filter(move |s| s.kind == kind)
+    }
+}
+
+fn transition(before: &Device, after: &Device) -> Option<Change> {
+    let seen = after.seen()?;
+    if before.seen().is_none() || (!before.present() && after.present()) {
+        Some(Change::Up(seen))
+    } else if before.present() && !after.present() {
+        Some(Change::Down(seen))
+    } else {
+        None
+    }
+}
+
+fn table() -> &'static Mutex<Table> {
+    static TABLE: OnceLock<Mutex<Table>> = OnceLock::new();
+    TABLE.get_or_init(Default::default)
+}
+
+/// The running watcher; `events` is taken by [`spawn`].
+struct Watcher {
+    events: Mutex<Option<mpsc::UnboundedReceiver<Change>>>,
+}
+
+/// The watcher, started on first use. `None` without a system bus or BlueZ.
+fn watcher() -> Option<&'static Watcher> {
+    static WATCHER: OnceLock<Option<Watcher>> = OnceLock::new();
+    WATCHER.get_or_init(start).as_ref()
+}
+
+fn start() -> Option<Watcher> {
+    let conn = match Connection::new_system() {
+        Ok(c) => c,
+        Err(e) => {
+            log::warn!("bluez_watch: D-Bus connection failed: {e}");
+            return None;
+        }
+    };
+    let (tx, rx) = mpsc::unbounded_channel();
+    // Subscribe before the snapshot so no change falls between the two.
+    if let Err(e) = subscribe(&conn, tx) {
+        log::warn!("bluez_watch: cannot subscribe to BlueZ signals: {e}");
+        return None;
+    }
+    let proxy = conn.with_proxy(BLUEZ_SERVICE, "/", Duration::from_secs(5));
+    let objects = match proxy.get_managed_objects() {
+        Ok(o) => o,
+        Err(e) => {
+            log::warn!("bluez_watch: GetManagedObjects failed: {e}");
+            return None;
+        }
+    };
+    {
+        // The startup state is discovery's business, not a transition.
+        let mut table = table().lock().unwrap();
+        for (path, ifaces) in &objects {
+            if let Some(props) = ifaces.get(DEVICE_IFACE) {
+                table.added(path, props);
+            }
+        }
+    }
+    let thread = std::thread::Builder::new()
+        .name("bluez-watch".into())
+        .spawn(move || {
+            loop {
+                if let Err(e) = conn.process(Duration::from_secs(1)) {
+                    log::warn!("bluez_watch: D-Bus connection lost: {e}");
+                    return;
+                }
+            }
+        });
+    if let Err(e) = thread {
+        log::warn!("bluez_watch: cannot start the signal thread: {e}");
+        return None;
+    }
+    log::info!("bluez_watch: watching {} BlueZ devices", objects.len());
+    Some(Watcher {
+        events: Mutex::new(Some(rx)),
+    })
+}
+
+fn subscribe(conn: &Connection, tx: mpsc::UnboundedSender<Change>) -> Result<(), dbus::Error> {
+    let notify = |tx: &mpsc::UnboundedSender<Change>, change: Option<Change>| {
+        if let Some(change) = change {
+            // Nobody listening yet (or any more) is fine: the table is current.
+            let _ = tx.send(change);
+        }
+    };
+
+    let added = tx.clone();
+    conn.add_match(
+        ObjectManagerInterfacesAdded::match_rule(None, None)
+            .static_clone()
+            .with_sender(BLUEZ_SERVICE),
+        move |sig: ObjectManagerInterfacesAdded, _: &Connection, _: &Message| {
+            if let Some(props) = sig.interfaces.get(DEVICE_IFACE) {
+                notify(&added, table().lock().unwrap().added(&sig.object, props));
+            }
+            true
+        },
+    )?;
+
+    let removed = tx.clone();
+    conn.add_match(
+        ObjectManagerInterfacesRemoved::match_rule(None, None)
+            .static_clone()
+            .with_sender(BLUEZ_SERVICE),
+        move |sig: ObjectManagerInterfacesRemoved, _: &Connection, _: &Message| {
+            if sig.interfaces.iter().any(|i| i == DEVICE_IFACE) {
+                notify(&removed, table().lock().unwrap().removed(&sig.object));
+            }
+            true
+        },
+    )?;
+
+    conn.add_match(
+        PropertiesPropertiesChanged::match_rule(None, None)
+            .static_clone()
+            .with_sender(BLUEZ_SERVICE)
+            .with_namespaced_path("/org/bluez"),
+        move |sig: PropertiesPropertiesChanged, _: &Connection, msg: &Message| {
+            if sig.interface_name == DEVICE_IFACE
+                && let Some(path) = msg.path()
+            {
+                let change = table().lock().unwrap().changed(
+                    &path,
+                    &sig.changed_properties,
+                    &sig.invalidated_properties,
+                );
+                notify(&tx, change);
+            }
+            true
+        },
+    )?;
+    Ok(())
+}
+
+/// Classic Supvan printers BlueZ knows about, connected or not.
+pub fn bt_candidates() -> Vec<BtCandidate> {
+    if watcher().is_none() {
+        return Vec::new();
+    }
+    let table = table().lock().unwrap();
+    table
+        .seen(Kind::Bt)
+        .map(|s| BtCandidate {
+            address: s.address,
+            name: s.name,
+        })
+        .collect()
+}
+
+/// BLE Supvan printers BlueZ knows about. Without the `ble` feature none are
+/// reported, matching the scan stub.
+pub fn ble_candidates() -> Vec<BleCandidate> {
+    if !cfg!(feature = "ble") || watcher().is_none() {
+        return Vec::new();
+    }
+    let table = table().lock().unwrap();
+    table
+        .seen(Kind::Ble)
+        .map(|s| BleCandidate {
+            address: s.address,
+            name: s.name,
+        })
+        .collect()
+}
+
+/// Apply BlueZ presence changes to the transport map and the printers'
+/// online state. Without BlueZ it logs and does nothing.
+pub fn spawn(registry: PrinterRegistry, advertiser: Option<Arc<dyn AdvertiserControl>>) {
+    let Some(mut events) = watcher().and_then(|w| w.events.lock().unwrap().take()) else {
+        log::warn!("bluez_watch: no BlueZ; Bluetooth changes are seen at the next start");
+        return;
+    };
+    tokio::spawn(async move {
+        while let Some(change) = events.recv().await {
+            handle(change, &registry, advertiser.as_deref());
+        }
+    });
+}
+
+fn handle(change: Change, registry: &PrinterRegistry, advertiser: Option<&dyn AdvertiserControl>) {
+    let (seen, up, forget) = match change {
+        Change::Up(seen) => (seen, true, false),
+        Change::Down(seen) => (seen, false, false),
+        Change::Gone(seen) => (seen, false, true),
+    };
+    if seen.kind == Kind::Ble && !cfg!(feature = "ble") {
+        return;
+    }
+    let transport = seen.kind.transport();
+    let address = Some(seen.address.clone());
+    let sighting = Sighting {
+        serial: Some(seen.name.clone()),
+        bt: address.clone().filter(|_| seen.kind == Kind::Bt),
+        ble: address.filter(|_| seen.kind == Kind::Ble),
+        family: Some(crate::ipp_server::family_hint(None, &seen.name)),
+        ..Default::default()
+    };
+    let slug = crate::identities::slug_for(&sighting, &seen.name);
+    let uri = format!("supvan://{slug}");
+    if up {
+        log::info!(
+            "bluez_watch: {} ({}) reachable over {transport} as {uri}",
+            seen.name,
+            seen.address
+        );
+        device::attach_wireless(&slug, transport, &seen.address);
+        hotplug::set_reachable(registry, advertiser, &uri, true);
+        return;
+    }
+    log::info!(
+        "bluez_watch: {} ({}) {} over {transport}",
+        seen.name,
+        seen.address,
+        if forget { "removed" } else { "lost" }
+    );
+    if device::detach_wireless(&slug, transport, forget) == Some(false) {
+        hotplug::set_reachable(registry, advertiser, &uri, false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dbus::arg::{RefArg, Variant};
+
+    const PATH: &str = "/org/bluez/hci0/dev_A4_93_40_AF_B0_B5";
+
+    fn props(pairs: Vec<(&str, Box<dyn RefArg>)>) -> PropMap {
+        pairs
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), Variant(v)))
+            .collect()
+    }
+
+    fn printer(name: &str, uuid: &str) -> PropMap {
+        props(vec![
+            ("Address", Box::new("A4:93:40:AF:B0:B5".to_string())),
+            ("Name", Box::new(name.to_string())),
+            ("UUIDs", Box::new(vec![uuid.to_string()])),
+            ("Connected", Box::new(false)),
+        ])
+    }
+
+    #[test]
+    fn classifies_classic_and_ble_printers() {
+        let mut table = Table::default();
+        let spp = "00001101-0000-1000-8000-00805f9b34fb";
+        let fee7 = "0000fee7-0000-1000-8000-00805f9b34fb";
+        let audio = "0000110b-0000-1000-8000-00805f9b34fb";
+        table.added(PATH, &printer("Supvan T50", spp));
+        table.added("/org/bluez/hci0/dev_2", &printer("T0182A2507162197", fee7));
+        table.added("/org/bluez/hci0/dev_3", &printer("Supvan Speaker", audio));
+        let bt: Vec<_> = table.seen(Kind::Bt).map(|s| s.name).collect();
+        let ble: Vec<_> = table.seen(Kind::Ble).map(|s| s.name).collect();
+        assert_eq!(bt, ["Supvan T50"]);
+        assert_eq!(ble, ["T0182A2507162197"]);
+    }
+
+    #[test]
+    fn connection_and_rssi_drive_transitions() {
+        let mut table = Table::default();
+        let spp = "00001101-0000-1000-8000-00805f9b34fb";
+        let seen = Seen {
+            kind: Kind::Bt,
+            name: "Supvan T50".into(),
+            address: "A4:93:40:AF:B0:B5".into(),
+        };
+        assert_eq!(
+            table.added(PATH, &printer("Supvan T50", spp)),
+            Some(Change::Up(seen.clone()))
+        );
+        let connected = props(vec![("Connected", Box::new(true))]);
+        assert_eq!(
+            table.changed(PATH, &connected, &[]),
+            Some(Change::Up(seen.clone()))
+        );
+        // RSSI updates while present are not transitions.
+        let rssi = props(vec![("RSSI", Box::new(-60i16))]);
+        assert_eq!(table.changed(PATH, &rssi, &[]), None);
+        let disconnected = props(vec![("Connected", Box::new(false))]);
+        assert_eq!(table.changed(PATH, &disconnected, &[]), None);
+        assert_eq!(
+            table.changed(PATH, &PropMap::new(), &["RSSI".into()]),
+            Some(Change::Down(seen.clone()))
+        );
+        assert_eq!(table.removed(PATH), Some(Change::Gone(seen)));
+        // Other objects and devices are ignored.
+        assert_eq!(table.changed("/org/bluez/hci0", &connected, &[]), None);
+        assert_eq!(table.removed(PATH), None);
+    }
+}
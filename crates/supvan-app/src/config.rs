@@ -0,0 +1,333 @@
+//! The printer app's configuration file.
+//!
+//! `/etc/supvan/printer-app.toml` is read first, then
+//! `$XDG_CONFIG_HOME/supvan/printer-app.toml` (else `~/.config/...`), whose
+//! keys win table by table; `$SUPVAN_CONFIG` names a single file to use
+//! instead. Either may be missing. The `SUPVAN_*` environment variables still
+//! override what the files say.
+//!
+//! ```toml
+//! transports = ["usb", "bt"]
+//!
+//! [server]
+//! host = "127.0.0.1"
+//! port = 8631
+//! poll_interval_secs = 15
+//!
+//! [dump]
+//! dir = "/var/tmp/supvan-dumps"
+//!
+//! [bluetooth]
+//! default_agent = true          # answer PINs asked for on connect
+//!
+//! [printer.T0117A2507]          # by serial or by slug
+//! darkness = 65
+//! offset_x = 4                  # dots; + moves right
+//! offset_y = -2                 # dots; + moves down the label
+//! dither = "floyd-steinberg"
+//! tone_curve = "gamma=1.4"
+//! family = "supvan_t80"
+//! margin_dots = 12
+//! ```
+//!
+//! SIGHUP re-reads the files. Transports, dumps and the per-printer
+//! settings apply from the next job (a family change re-describes the
+//! queue); the listen address, poll interval and `[bluetooth]` are read
+//! once at startup.
+//! A file that fails to parse is reported and the previous configuration
+//! kept.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use ipp_printer_app::{PrinterRegistry, Server};
+use parking_lot::RwLock;
+use serde::Deserialize;
+use supvan_render::dither::{DitherMethod, ToneCurve};
+
+/// Everything the configuration file can set.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Transport preference for `supvan://` printers, like
+    /// `SUPVAN_TRANSPORTS`.
+    pub transports: Option<Vec<String>>,
+    pub server: ServerSection,
+    pub dump: DumpSection,
+    pub bluetooth: BluetoothSection,
+    /// Per-printer settings, keyed by serial or slug.
+    #[serde(rename = "printer")]
+    pub printers: BTreeMap<String, PrinterSettings>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSection {
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    /// Seconds between status polls (the framework's default is 30).
+    pub poll_interval_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DumpSection {
+    /// Write page dumps here, as `SUPVAN_DUMP_DIR` does.
+    pub dir: Option<PathBuf>,
+    /// `false` turns dumps off, including mock mode's default directory.
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BluetoothSection {
+    /// Make the pairing agent the host's default BlueZ agent, so it also
+    /// answers printers that ask for a PIN when the app connects. It takes
+    /// that role from the desktop's agent; read once at startup.
+    pub default_agent: bool,
+}
+
+/// One printer's tuning. Unset fields keep the queue's or family's value.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrinterSettings {
+    /// Darkness 0–100 for jobs on this printer.
+    pub darkness: Option<i32>,
+    /// Calibration offsets in printhead dots.
+    pub offset_x: i32,
+    pub offset_y: i32,
+    pub dither: Option<DitherMethod>,
+    pub tone_curve: Option<ToneCurve>,
+    /// Driver family to use instead of the detected one, e.g. `supvan_t80`.
+    pub family: Option<String>,
+    /// Unprinted rows at the leading and trailing edge of each label.
+    pub margin_dots: Option<u16>,
+}
+
+impl Config {
+    /// Parse and merge `paths` in order; missing files are skipped.
+    fn load(paths: &[PathBuf]) -> Result<Self, String> {
+        let mut merged = toml::Table::new();
+        for path in paths {
+            let text = match std::fs::read_to_string(path) {
+                Ok(text) => text,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(format!("{}: {e}", path.display())),
+            };
+            let table: toml::Table =
+                toml::from_str(&text).map_err(|e| format!("{}: {e}", path.display()))?;
+            log::info!("config: read {}", path.display());
+            merge(&mut merged, table);
+        }
+        toml::Value::Table(merged)
+            .try_into()
+            .map_err(|e| format!("{}: {e}", describe(paths)))
+    }
+
+    /// The settings for the printer with `slug`, whose serial is `serial`.
+    /// A table keyed by the slug wins over one keyed by the serial.
+    pub fn printer(&self, slug: &str, serial: Option<&str>) -> Option<&PrinterSettings> {
+        self.printers
+            .get(slug)
+            .or_else(|| serial.and_then(|s| self.printers.get(s)))
+    }
+}
+
+/// Overlay `top` onto `base`, recursing into tables present in both.
+fn merge(base: &mut toml::Table, top: toml::Table) {
+    for (key, value) in top {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(b)), toml::Value::Table(t)) => merge(b, t),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+fn describe(paths: &[PathBuf]) -> String {
+    let names: Vec<_> = paths.iter().map(|p| p.display().to_string()).collect();
+    names.join(" + ")
+}
+
+/// The files read, lowest priority first.
+fn paths() -> Vec<PathBuf> {
+    if let Some(path) = std::env::var_os("SUPVAN_CONFIG").filter(|p| !p.is_empty()) {
+        return vec![PathBuf::from(path)];
+    }
+    let user = match std::env::var("XDG_CONFIG_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var("HOME").unwrap_or_else(|_| "/tmp".into())).join(".config"),
+    };
+    vec![
+        PathBuf::from("/etc/supvan/printer-app.toml"),
+        user.join("supvan/printer-app.toml"),
+    ]
+}
+
+static CURRENT: RwLock<Option<Arc<Config>>> = RwLock::new(None);
+
+/// Read the configuration at startup.
+pub fn load() -> Result<(), String> {
+    let config = Config::load(&paths())?;
+    *CURRENT.write() = Some(Arc::new(config));
+    Ok(())
+}
+
+/// The configuration in force; empty before [`load`].
+pub fn get() -> Arc<Config> {
+    CURRENT.read().clone().unwrap_or_default()
+}
+
+/// The settings for the printer at `uri` (`supvan://<slug>` or
+/// `mock://<id>`); defaults when the file names none.
+pub fn printer(uri: &str) -> PrinterSettings {
+    let Some(slug) = uri
+        .strip_prefix("supvan://")
+        .or_else(|| uri.strip_prefix("mock://"))
+    else {
+        return PrinterSettings::default();
+    };
+    let config = get();
+    if config.printers.is_empty() {
+        return PrinterSettings::default();
+    }
+    let serial = crate::identities::serial_for_slug(slug);
+    config
+        .printer(slug, serial.as_deref())
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Describe each queue with its configured driver family.
+pub fn apply(registry: &PrinterRegistry) {
+    for rec in registry.write().iter_mut() {
+        let c = &rec.config;
+        let Some(family) = printer(&c.device_uri).family else {
+            continue;
+        };
+        if family == c.driver_name {
+            continue;
+        }
+        match crate::ipp_job::redescribe(c, &family) {
+            Some(config) => {
+                log::info!("config: {} uses family {family}", c.name);
+                rec.config = config;
+            }
+            None => log::warn!("config: {}: unknown family '{family}'", c.name),
+        }
+    }
+}
+
+/// Block SIGHUP in the calling thread, and so in every thread it starts,
+/// so that [`spawn_reload`] can wait for it. Call before the runtime starts.
+pub fn block_sighup() {
+    // SAFETY: plain signal-mask calls on a zeroed, initialized set.
+    unsafe {
+        let mut set: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, libc::SIGHUP);
+        libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut());
+    }
+}
+
+/// Re-read the configuration on every SIGHUP, then re-apply family
+/// overrides and persist the queues.
+pub fn spawn_reload(registry: PrinterRegistry, state_path: PathBuf) {
+    let waiter = std::thread::Builder::new()
+        .name("config-reload".into())
+        .spawn(move || {
+            loop {
+                wait_for_sighup();
+                reload(&registry, &state_path);
+            }
+        });
+    if let Err(e) = waiter {
+        log::warn!("config: cannot watch for SIGHUP: {e}");
+    }
+}
+
+fn wait_for_sighup() {
+    // SAFETY: as in `block_sighup`; `sigwait` only writes `signal`.
+    unsafe {
+        let mut set: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, libc::SIGHUP);
+        let mut signal = 0;
+        libc::sigwait(&set, &mut signal);
+    }
+}
+
+fn reload(registry: &PrinterRegistry, state_path: &Path) {
+    let config = match Config::load(&paths()) {
+        Ok(config) => config,
+        Err(e) => {
+            log::error!("config: reload failed, keeping the previous settings: {e}");
+            return;
+        }
+    };
+    if config.server != get().server {
+        log::warn!("config: [server] changes take effect at the next start");
+    }
+    if config.bluetooth != get().bluetooth {
+        log::warn!("config: [bluetooth] changes take effect at the next start");
+    }
+    *CURRENT.write() = Some(Arc::new(config));
+    log::info!("config: reloaded");
+    apply(registry);
+    Server::persist(registry, state_path);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_file_overrides_system_file_per_key() {
+        let dir = std::env::temp_dir().join(format!("supvan-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let system = dir.join("system.toml");
+        let user = dir.join("user.toml");
+        std::fs::write(
+            &system,
+            "transports = [\"usb\"]\n[server]\nport = 9000\nhost = \"127.0.0.1\"\n\
+             [printer.T50Plus-01]\ndarkness = 40\noffset_x = 3\n",
+        )
+        .unwrap();
+        std::fs::write(
+            &user,
+            "[server]\nport = 9100\n[printer.T50Plus-01]\ndarkness = 70\n\
+             [printer.shipping]\ndither = \"atkinson\"\nmargin_dots = 12\n",
+        )
+        .unwrap();
+        let missing = dir.join("missing.toml");
+        let config = Config::load(&[system, missing, user]).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(config.server.host.as_deref(), Some("127.0.0.1"));
+        assert_eq!(config.server.port, Some(9100));
+        assert_eq!(config.transports, Some(vec!["usb".to_string()]));
+        let t50 = config.printer("t50plus-01", Some("T50Plus-01")).unwrap();
+        assert_eq!((t50.darkness, t50.offset_x), (Some(70), 3));
+        let shipping = config.printer("shipping", None).unwrap();
+        assert_eq!(shipping.dither, Some(DitherMethod::Atkinson));
+        assert_eq!(shipping.margin_dots, Some(12));
+        assert!(config.printer("other", Some("T50Plus-02")).is_none());
+    }
+
+    #[test]
+    fn mistakes_are_reported() {
+        let dir = std::env::temp_dir().join(format!("supvan-config-bad-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let typo = dir.join("typo.toml");
+        std::fs::write(&typo, "[printer.x]\ndarknes = 40\n").unwrap();
+        let bad_value = dir.join("bad.toml");
+        std::fs::write(&bad_value, "[printer.x]\ndither = \"halftone\"\n").unwrap();
+        let typo_err = Config::load(std::slice::from_ref(&typo)).unwrap_err();
+        let value_err = Config::load(std::slice::from_ref(&bad_value)).unwrap_err();
+        std::fs::remove_dir_all(dir).unwrap();
+        assert!(typo_err.contains("darknes"), "{typo_err}");
+        assert!(value_err.contains("halftone"), "{value_err}");
+    }
+}
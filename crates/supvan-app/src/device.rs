@@ -1,96 +1,40 @@
-//! Device open helpers for `supvan://`, `mock://`, and legacy
-//! `btrfcomm://` / `usbhid://` URIs.
-//!
-//! BT connections are cached per address: opening the same address twice
-//! reuses the existing RFCOMM socket instead of redialing the printer, which
-//! would beep on every connect. Each call validates the cached socket with
-//! a CHECK_DEVICE round-trip; if that fails the entry is evicted and a fresh
-//! socket is dialed (which beeps once, as expected for "printer came back").
+//! Device open helpers for `supvan://` and `mock://` URIs.
 //!
 //! `supvan://<name>` is the unified scheme — discovery cross-correlates USB
 //! and BT candidates by the printer's self-reported name and registers a
-//! per-name transport mapping via [`register_supvan`]. At open time
-//! [`open_supvan`] resolves the name to USB (preferred when present) or BT.
+//! per-name transport mapping via [`register_supvan`]. Each such printer is
+//! owned by a [`crate::device_actor`] task, which connects over the routes
+//! [`dial_supvan`] lists — USB, then BT, then BLE unless `SUPVAN_TRANSPORTS`
+//! says otherwise, and only those the printer's family has — failing over
+//! between them, and keeps the connection across opens, so the BT firmware
+//! beeps once per reconnect rather than on every status poll. A printer
+//! whose last transport goes away leaves the map, and its actor is released
+//! with it; [`spawn_sweep`] also releases the actors no queue uses.
 
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
 
+use futures::future::BoxFuture;
+use ipp_printer_app::PrinterRegistry;
+use supvan_proto::caps::TRANSPORTS;
+use supvan_proto::pairing;
 use supvan_proto::printer::Printer;
-use tokio::sync::Mutex as AsyncMutex;
 
 use crate::battery_provider;
+use crate::device_actor::{self, Hotplug, Lane, Link, Route};
+use crate::models;
 use crate::printer_device::KsDevice;
 
-/// BT printer connection cache, keyed by address. Persists across `open_bt`
-/// calls so the status poller and print jobs reuse one RFCOMM socket per
-/// printer. The outer `Mutex` guards the map (held briefly, sync); each printer
-/// sits behind an async `Mutex` so a device op can be awaited while held.
-fn bt_cache() -> &'static Mutex<HashMap<String, Arc<AsyncMutex<Printer>>>> {
-    static CACHE: OnceLock<Mutex<HashMap<String, Arc<AsyncMutex<Printer>>>>> = OnceLock::new();
-    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
-}
-
-fn dial_bt(addr: &str) -> Option<Printer> {
-    log::info!("device::open_bt: dialing {addr} (no cache entry)");
-    match Printer::open_bt(addr) {
-        Ok(p) => Some(p),
-        Err(e) => {
-            log::error!("device::open_bt: RFCOMM connect failed for {addr}: {e}");
-            None
-        }
-    }
-}
-
-/// Open `btrfcomm://host/path/AA:BB:CC:DD:EE:FF`, reusing a cached RFCOMM
-/// socket when one is available. Drops the cache entry and reconnects if the
-/// existing socket no longer responds.
-pub async fn open_bt(uri: &str) -> Option<Box<KsDevice>> {
-    let addr = uri
-        .strip_prefix("btrfcomm://")
-        .and_then(|rest| rest.find('/').map(|pos| &rest[pos + 1..]))?;
-
-    // `await` can't appear in a match guard, so validate the cached socket
-    // before deciding whether to reuse it.
-    let cached = bt_cache().lock().unwrap().get(addr).cloned();
-    let printer = match cached {
-        Some(arc) => {
-            if arc.lock().await.check_device().await.unwrap_or(false) {
-                log::debug!("device::open_bt: reusing cached socket for {addr}");
-                arc
-            } else {
-                log::info!("device::open_bt: cached socket for {addr} is dead, reconnecting");
-                bt_cache().lock().unwrap().remove(addr);
-                dial_and_cache(addr)?
-            }
-        }
-        None => dial_and_cache(addr)?,
-    };
-
-    if let Some(h) = battery_provider::handle() {
-        h.add_device(addr, 100);
-    }
-    Some(Box::new(KsDevice::from_shared(printer)))
-}
-
-/// Dial a fresh RFCOMM socket for `addr` and insert it into the connection
-/// cache, returning the shared handle.
-fn dial_and_cache(addr: &str) -> Option<Arc<AsyncMutex<Printer>>> {
-    let arced = Arc::new(AsyncMutex::new(dial_bt(addr)?));
-    bt_cache()
-        .lock()
-        .unwrap()
-        .insert(addr.to_string(), arced.clone());
-    Some(arced)
-}
-
-/// Open a device from its URI, dispatching on the scheme: `supvan://` resolves
-/// through the discovery transport map, `mock://` yields a simulator device.
-/// Any other scheme is unsupported and returns `None`.
-pub async fn open_uri(uri: &str) -> Option<KsDevice> {
+/// Open a device from its URI, dispatching on the scheme: `supvan://` goes
+/// through the printer's actor on `lane`, `mock://` yields a simulator
+/// device. Any other scheme is unsupported and returns `None`. The device
+/// carries the capabilities of family `driver_name`.
+pub async fn open_uri(uri: &str, driver_name: &str, lane: Lane) -> Option<KsDevice> {
     if uri.starts_with("supvan://") {
-        open_supvan(uri).await
+        open_supvan(uri, driver_name, lane).await
     } else if uri.starts_with("mock://") {
-        open_mock(uri)
+        open_mock(uri, driver_name)
     } else {
         None
     }
@@ -98,18 +42,18 @@ pub async fn open_uri(uri: &str) -> Option<KsDevice> {
 
 /// Open `mock://ID`. Always succeeds with a no-connection KsDevice driven by
 /// the [`crate::mock`] controller. Only registered when `SUPVAN_MOCK=1`.
-pub fn open_mock(_uri: &str) -> Option<KsDevice> {
+pub fn open_mock(_uri: &str, driver_name: &str) -> Option<KsDevice> {
     // Simulate powered-off / unplugged hardware: the device can't be opened,
     // so poll_status reports OFFLINE and the print path holds the job.
     if crate::mock::controller().is_unreachable() {
         log::info!("mock: device unreachable (SUPVAN_MOCK_UNREACHABLE)");
         return None;
     }
-    Some(KsDevice::open_mock())
+    Some(KsDevice::open_mock(models::caps_for(driver_name)))
 }
 
 /// Transport map for `supvan://NAME` URIs, populated by discovery and
-/// consulted by [`open_supvan`] / [`poll_status`].
+/// consulted by [`open_supvan`] / [`dial_supvan`].
 #[derive(Clone, Default)]
 struct SupvanTransports {
     hidraw_path: Option<String>,
@@ -117,6 +61,12 @@ struct SupvanTransports {
     ble_address: Option<String>,
 }
 
+impl SupvanTransports {
+    fn is_empty(&self) -> bool {
+        self.hidraw_path.is_none() && self.bt_address.is_none() && self.ble_address.is_none()
+    }
+}
+
 fn supvan_map() -> &'static Mutex<HashMap<String, SupvanTransports>> {
     static MAP: OnceLock<Mutex<HashMap<String, SupvanTransports>>> = OnceLock::new();
     MAP.get_or_init(|| Mutex::new(HashMap::new()))
@@ -141,82 +91,333 @@ pub fn register_supvan(
     );
 }
 
-/// Open `supvan://<slug>`. Prefers USB when available, falls back to the
-/// cached BT socket. Returns `None` if neither transport is registered or
-/// both fail to open.
-pub async fn open_supvan(uri: &str) -> Option<KsDevice> {
-    let slug = uri.strip_prefix("supvan://")?;
-    let entry = supvan_map().lock().unwrap().get(slug).cloned()?;
+/// Hotplug: the printer `supvan://<slug>` appeared on USB at `hidraw_path`.
+/// Records the path, adding the printer to the map if discovery never saw
+/// it, and lets its actor redial straight away.
+pub fn attach_usb(slug: &str, hidraw_path: &str) {
+    supvan_map()
+        .lock()
+        .unwrap()
+        .entry(slug.to_string())
+        .or_default()
+        .hidraw_path = Some(hidraw_path.to_string());
+    if let Some(actor) = device_actor::existing(&format!("supvan://{slug}")) {
+        actor.hotplug(Hotplug::Attached);
+    }
+}
 
-    if let Some(path) = entry.hidraw_path.as_deref() {
-        if let Some(dev) = KsDevice::open_usb(path) {
-            return Some(*dev);
+/// Hotplug: `hidraw_path` went away. Forgets it and drops any connection
+/// over it; a printer left with no transport leaves the map. Returns the
+/// slug of each printer it belonged to, with whether the printer is still
+/// reachable over BT or BLE.
+pub fn detach_usb(hidraw_path: &str) -> Vec<(String, bool)> {
+    let mut detached = Vec::new();
+    {
+        let mut map = supvan_map().lock().unwrap();
+        for (slug, entry) in map.iter_mut() {
+            if entry.hidraw_path.as_deref() == Some(hidraw_path) {
+                entry.hidraw_path = None;
+                detached.push((slug.clone(), !entry.is_empty()));
+            }
         }
-        log::warn!("open_supvan: USB open failed for {slug} ({path}), falling back to BT");
+        map.retain(|_, entry| !entry.is_empty());
     }
-    if let Some(addr) = entry.bt_address.as_deref() {
-        // open_bt expects a full URI; synthesize one.
-        let uri = format!("btrfcomm://bt/{addr}");
-        if let Some(dev) = open_bt(&uri).await {
-            return Some(*dev);
+    for (slug, wireless) in &detached {
+        if let Some(actor) = device_actor::existing(&format!("supvan://{slug}")) {
+            actor.hotplug(Hotplug::Detached("usb"));
+        }
+        if !wireless {
+            release(slug);
         }
-        log::warn!("open_supvan: BT open failed for {slug} ({addr}), trying BLE");
     }
-    if let Some(addr) = entry.ble_address.as_deref() {
-        return open_ble_addr(addr).await.map(|b| *b);
+    detached
+}
+
+/// BlueZ: the printer `supvan://<slug>` is reachable at `address` over
+/// `transport` (`"bt"` or `"ble"`). Records the address, adding the printer
+/// to the map if discovery never saw it, and lets its actor redial straight
+/// away.
+pub fn attach_wireless(slug: &str, transport: &'static str, address: &str) {
+    {
+        let mut map = supvan_map().lock().unwrap();
+        let entry = map.entry(slug.to_string()).or_default();
+        let slot = match transport {
+            "bt" => &mut entry.bt_address,
+            _ => &mut entry.ble_address,
+        };
+        *slot = Some(address.to_string());
+    }
+    if let Some(actor) = device_actor::existing(&format!("supvan://{slug}")) {
+        actor.hotplug(Hotplug::Attached);
     }
-    log::warn!("open_supvan: no transports for {slug}");
-    None
 }
 
-/// Open a BLE printer by address, reusing a cached GATT connection. Stub
-/// (returns `None`) without the `ble` feature.
-#[cfg(feature = "ble")]
-async fn open_ble_addr(addr: &str) -> Option<Box<KsDevice>> {
-    let cached = ble_cache().lock().unwrap().get(addr).cloned();
-    let printer = match cached {
-        Some(arc) => {
-            if arc.lock().await.check_device().await.unwrap_or(false) {
-                log::debug!("device::open_ble: reusing cached connection for {addr}");
-                arc
-            } else {
-                log::info!("device::open_ble: cached connection for {addr} dead, reconnecting");
-                ble_cache().lock().unwrap().remove(addr);
-                dial_ble_and_cache(addr).await?
+/// BlueZ: the `transport` link to `supvan://<slug>` went down. Drops any
+/// connection over it and, when `forget` is set (BlueZ removed the device),
+/// the address too; a printer left with no transport leaves the map.
+/// Returns whether the printer is still reachable over USB, or `None` if the
+/// printer is unknown.
+pub fn detach_wireless(slug: &str, transport: &'static str, forget: bool) -> Option<bool> {
+    let (usb, gone) = {
+        let mut map = supvan_map().lock().unwrap();
+        let entry = map.get_mut(slug)?;
+        if forget {
+            match transport {
+                "bt" => entry.bt_address = None,
+                _ => entry.ble_address = None,
             }
         }
-        None => dial_ble_and_cache(addr).await?,
+        let state = (entry.hidraw_path.is_some(), entry.is_empty());
+        if state.1 {
+            map.remove(slug);
+        }
+        state
     };
-    Some(Box::new(KsDevice::from_shared(printer)))
+    if let Some(actor) = device_actor::existing(&format!("supvan://{slug}")) {
+        actor.hotplug(Hotplug::Detached(transport));
+    }
+    if gone {
+        release(slug);
+    }
+    Some(usb)
 }
 
-#[cfg(not(feature = "ble"))]
-async fn open_ble_addr(addr: &str) -> Option<Box<KsDevice>> {
-    log::warn!("device: BLE address {addr} registered but the `ble` feature is off");
-    None
+/// Release the actor of `supvan://<slug>`, which has left the map.
+fn release(slug: &str) {
+    let uri = format!("supvan://{slug}");
+    device_actor::retain(|u| u != uri);
 }
 
-/// BLE printer connection cache, mirroring [`bt_cache`].
-#[cfg(feature = "ble")]
-fn ble_cache() -> &'static Mutex<HashMap<String, Arc<AsyncMutex<Printer>>>> {
-    static CACHE: OnceLock<Mutex<HashMap<String, Arc<AsyncMutex<Printer>>>>> = OnceLock::new();
-    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+/// How often [`spawn_sweep`] looks for actors to release.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Periodically release the actors of `supvan://` printers that are no
+/// longer in the map, or that no queue in `registry` prints to.
+pub fn spawn_sweep(registry: PrinterRegistry) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            tick.tick().await;
+            sweep(&registry);
+        }
+    });
 }
 
-#[cfg(feature = "ble")]
-async fn dial_ble_and_cache(addr: &str) -> Option<Arc<AsyncMutex<Printer>>> {
-    log::info!("device::open_ble: connecting {addr} (no cache entry)");
-    let printer = match Printer::open_ble(addr).await {
-        Ok(p) => p,
-        Err(e) => {
-            log::error!("device::open_ble: GATT connect failed for {addr}: {e}");
-            return None;
+fn sweep(registry: &PrinterRegistry) {
+    let uris: Vec<String> = registry
+        .read()
+        .iter()
+        .map(|p| p.config.device_uri.clone())
+        .collect();
+    let slugs: Vec<String> = supvan_map().lock().unwrap().keys().cloned().collect();
+    device_actor::retain(|uri| {
+        let mapped = uri
+            .strip_prefix("supvan://")
+            .is_none_or(|slug| slugs.iter().any(|s| s == slug));
+        mapped && uris.iter().any(|u| u == uri)
+    });
+}
+
+/// Open `supvan://<slug>` on `lane`, connecting its actor if needed.
+/// Returns `None` if no transport is registered for the name or the printer
+/// cannot be reached.
+pub async fn open_supvan(uri: &str, driver_name: &str, lane: Lane) -> Option<KsDevice> {
+    let slug = uri.strip_prefix("supvan://")?;
+    if !supvan_map().lock().unwrap().contains_key(slug) {
+        log::debug!("open_supvan: no transports for {slug}");
+        return None;
+    }
+    let slug = slug.to_string();
+    let driver = driver_name.to_string();
+    // The family is looked up on every dial, so a models.toml reload that
+    // changes its transports applies from the next connection.
+    let actor = device_actor::for_uri(uri, move || {
+        Box::new(move || dial_supvan(&slug, &models::caps_for(&driver).transports))
+    });
+    let caps = models::caps_for(driver_name);
+    actor
+        .open(lane)
+        .await
+        .map(|link| KsDevice::from_link(link, caps))
+}
+
+/// The transport preference from `SUPVAN_TRANSPORTS` (comma-separated
+/// `usb`, `bt`, `ble`), else the config file's `transports`, else USB, then
+/// BT, then BLE. Transports left out are not used.
+fn transport_preference() -> Vec<&'static str> {
+    if let Ok(list) = std::env::var("SUPVAN_TRANSPORTS") {
+        return parse_preference(&list);
+    }
+    match &crate::config::get().transports {
+        Some(list) => parse_preference(&list.join(",")),
+        None => TRANSPORTS.to_vec(),
+    }
+}
+
+fn parse_preference(list: &str) -> Vec<&'static str> {
+    let mut order = Vec::new();
+    for name in list.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+        match TRANSPORTS.iter().find(|&&t| t == name) {
+            Some(&t) if !order.contains(&t) => order.push(t),
+            Some(_) => {}
+            None => log::warn!("transports: unknown transport '{name}'"),
         }
+    }
+    if order.is_empty() {
+        log::warn!("transport preference names no transport; using usb,bt,ble");
+        return TRANSPORTS.to_vec();
+    }
+    order
+}
+
+/// The routes to `supvan://<slug>`, one per registered transport in
+/// `available` (the family's), in preference order; the actor tries them in
+/// turn. Re-reads the transport map on every dial, so a printer plugged in
+/// after discovery is picked up on the next reconnect.
+fn dial_supvan(slug: &str, available: &[&str]) -> Vec<Route> {
+    let Some(entry) = supvan_map().lock().unwrap().get(slug).cloned() else {
+        return Vec::new();
     };
-    let arced = Arc::new(AsyncMutex::new(printer));
-    ble_cache()
-        .lock()
-        .unwrap()
-        .insert(addr.to_string(), arced.clone());
-    Some(arced)
+    let slug = slug.to_string();
+    let mut routes = Vec::new();
+    for transport in transport_preference() {
+        if !available.contains(&transport) {
+            continue;
+        }
+        let open = match transport {
+            "usb" => entry
+                .hidraw_path
+                .clone()
+                .map(|path| dial_usb(slug.clone(), path)),
+            "bt" => entry
+                .bt_address
+                .clone()
+                .map(|addr| dial_bt(slug.clone(), addr)),
+            _ => entry
+                .ble_address
+                .clone()
+                .map(|addr| dial_ble(slug.clone(), addr)),
+        };
+        if let Some(open) = open {
+            routes.push(Route { transport, open });
+        }
+    }
+    routes
+}
+
+fn dial_usb(slug: String, path: String) -> BoxFuture<'static, Option<Box<dyn Link>>> {
+    Box::pin(async move {
+        match Printer::open_usb(&path) {
+            Ok(p) => Some(Box::new(p) as Box<dyn Link>),
+            Err(e) => {
+                log::warn!("dial_supvan: USB open failed for {slug} ({path}): {e}");
+                None
+            }
+        }
+    })
+}
+
+fn dial_bt(slug: String, addr: String) -> BoxFuture<'static, Option<Box<dyn Link>>> {
+    Box::pin(async move {
+        // A printer that wants a PIN on connect is answered by the agent.
+        let _expected = pairing::expect(&addr);
+        match Printer::open_bt(&addr) {
+            Ok(p) => {
+                if let Some(h) = battery_provider::handle() {
+                    h.add_device(&addr, 100);
+                }
+                Some(Box::new(p) as Box<dyn Link>)
+            }
+            Err(e) => {
+                log::warn!("dial_supvan: RFCOMM connect failed for {slug} ({addr}): {e}");
+                None
+            }
+        }
+    })
+}
+
+/// Connect a BLE printer by address. Stub (returns `None`) without the `ble`
+/// feature.
+#[cfg(feature = "ble")]
+fn dial_ble(slug: String, addr: String) -> BoxFuture<'static, Option<Box<dyn Link>>> {
+    Box::pin(async move {
+        let _expected = pairing::expect(&addr);
+        match Printer::open_ble(&addr).await {
+            Ok(p) => Some(Box::new(p) as Box<dyn Link>),
+            Err(e) => {
+                log::error!("dial_supvan: GATT connect failed for {slug} ({addr}): {e}");
+                None
+            }
+        }
+    })
+}
+
+#[cfg(not(feature = "ble"))]
+fn dial_ble(slug: String, addr: String) -> BoxFuture<'static, Option<Box<dyn Link>>> {
+    Box::pin(async move {
+        log::warn!("device: {slug}: BLE address {addr} registered but the `ble` feature is off");
+        None
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transport_preference_parses_and_falls_back() {
+        assert_eq!(parse_preference("bt, usb"), ["bt", "usb"]);
+        assert_eq!(parse_preference("ble,ble,serial"), ["ble"]);
+        assert_eq!(parse_preference(""), TRANSPORTS);
+    }
+
+    #[test]
+    fn usb_hotplug_updates_the_transport_map() {
+        attach_usb("hotplug-a", "/dev/hidraw90");
+        register_supvan(
+            "hotplug-b",
+            Some("/dev/hidraw91".into()),
+            Some("AA:BB:CC:DD:EE:FF".into()),
+            None,
+        );
+        assert_eq!(dial_supvan("hotplug-a", &TRANSPORTS)[0].transport, "usb");
+        // A family without USB is only dialed over BT.
+        let bt_only = dial_supvan("hotplug-b", &["bt"]);
+        assert_eq!((bt_only.len(), bt_only[0].transport), (1, "bt"));
+
+        assert_eq!(
+            detach_usb("/dev/hidraw90"),
+            [("hotplug-a".to_string(), false)]
+        );
+        assert!(!supvan_map().lock().unwrap().contains_key("hotplug-a"));
+        assert_eq!(
+            detach_usb("/dev/hidraw91"),
+            [("hotplug-b".to_string(), true)]
+        );
+        let routes: Vec<_> = dial_supvan("hotplug-b", &TRANSPORTS)
+            .iter()
+            .map(|r| r.transport)
+            .collect();
+        assert_eq!(routes, ["bt"]);
+        assert!(detach_usb("/dev/hidraw91").is_empty());
+    }
+
+    #[test]
+    fn bluez_presence_updates_the_transport_map() {
+        attach_wireless("presence-a", "ble", "A4:93:40:AF:B0:B5");
+        attach_usb("presence-a", "/dev/hidraw92");
+        let routes: Vec<_> = dial_supvan("presence-a", &TRANSPORTS)
+            .iter()
+            .map(|r| r.transport)
+            .collect();
+        assert_eq!(routes, ["usb", "ble"]);
+
+        // A disconnect keeps the address for the next dial; removal forgets it.
+        assert_eq!(detach_wireless("presence-a", "ble", false), Some(true));
+        assert_eq!(dial_supvan("presence-a", &TRANSPORTS).len(), 2);
+        assert_eq!(detach_wireless("presence-a", "ble", true), Some(true));
+        assert_eq!(dial_supvan("presence-a", &TRANSPORTS)[0].transport, "usb");
+        assert_eq!(dial_supvan("presence-a", &TRANSPORTS).len(), 1);
+        assert_eq!(detach_wireless("presence-unknown", "bt", true), None);
+    }
 }
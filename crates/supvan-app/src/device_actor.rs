@@ -0,0 +1,893 @@
+//! One long-lived task per physical printer, owning its connection.
+//!
+//! Every device operation — status polls, material queries, Identify-Printer
+//! and print jobs — is a message to the printer's actor, which runs them one
+//! at a time on the connection it holds. Messages arrive on two lanes: the
+//! job lane is always drained before the poll lane, so a status poll never
+//! lands between the frames of a raster transfer. While a job holds the
+//! device, polls are answered from the last status and material the actor
+//! saw instead of going to the printer.
+//!
+//! The actor dials lazily and keeps the connection until it goes bad: a
+//! socket error (EPIPE, ENOTCONN, the printer closing the link) or several
+//! requests in a row going unanswered. The next message then redials, with
+//! a backoff after failed attempts, and a fresh link must answer
+//! CHECK_DEVICE before it is used. A printer reachable several ways (USB,
+//! BT, BLE) is dialed over each route in turn, healthiest first, so losing
+//! one transport fails over to the next. A label the link dropped before
+//! the printer accepted it is resent over the new connection, whichever
+//! transport that is. Reusing the link matters on BT, where each connect
+//! makes the firmware beep.
+//!
+//! An actor lives until [`retain`] releases it, when its queue is deleted
+//! or its printer leaves the transport map; it then ends, dropping the
+//! connection, once the last link on it is gone.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use supvan_proto::error::{Error as ProtoError, Result as ProtoResult};
+use supvan_proto::printer::{Label, Printer};
+use supvan_proto::status::{MaterialInfo, PrinterStatus};
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Instant;
+
+/// Which queue a [`DeviceLink`] submits to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lane {
+    /// Print jobs: served first, and holds the device while open.
+    Job,
+    /// Status polls and Identify-Printer.
+    Poll,
+}
+
+/// The operations the actor needs from a connection. Implemented for
+/// [`Printer`]; tests substitute a scripted link.
+#[async_trait::async_trait]
+pub trait Link: Send + Sync {
+    async fn query_status(&self) -> ProtoResult<Option<PrinterStatus>>;
+    async fn query_material(&self) -> ProtoResult<Option<MaterialInfo>>;
+    async fn check_device(&self) -> ProtoResult<bool>;
+    async fn print_label(&self, label: &Label) -> ProtoResult<()>;
+}
+
+#[async_trait::async_trait]
+impl Link for Printer {
+    async fn query_status(&self) -> ProtoResult<Option<PrinterStatus>> {
+        Printer::query_status(self).await
+    }
+
+    async fn query_material(&self) -> ProtoResult<Option<MaterialInfo>> {
+        Printer::query_material(self).await
+    }
+
+    async fn check_device(&self) -> ProtoResult<bool> {
+        Printer::check_device(self).await
+    }
+
+    async fn print_label(&self, label: &Label) -> ProtoResult<()> {
+        Printer::print_label(self, label).await
+    }
+}
+
+/// A connection and the transport it runs over (`usb`, `bt`, `ble`).
+type Connection = (Box<dyn Link>, &'static str);
+
+/// One way to reach the printer: a transport and the future that opens it.
+pub struct Route {
+    pub transport: &'static str,
+    pub open: BoxFuture<'static, Option<Box<dyn Link>>>,
+}
+
+/// Lists the routes to the actor's printer for a fresh connection, in
+/// preference order.
+pub type Dialer = Box<dyn FnMut() -> Vec<Route> + Send>;
+
+/// A transport of the actor's printer appeared or went away.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hotplug {
+    /// A route came up: redial at once rather than waiting out the backoff.
+    Attached,
+    /// The transport's device is gone: drop a connection over it.
+    Detached(&'static str),
+}
+
+enum Request {
+    Open(oneshot::Sender<Option<&'static str>>),
+    Release,
+    Hotplug(Hotplug),
+    Status(oneshot::Sender<ProtoResult<Option<PrinterStatus>>>),
+    Material(oneshot::Sender<ProtoResult<Option<MaterialInfo>>>),
+    Check(oneshot::Sender<ProtoResult<bool>>),
+    Print(Label, oneshot::Sender<ProtoResult<&'static str>>),
+}
+
+/// The senders for one actor's two lanes.
+#[derive(Clone)]
+pub struct Actor {
+    job: mpsc::UnboundedSender<Request>,
+    poll: mpsc::UnboundedSender<Request>,
+}
+
+fn actors() -> &'static Mutex<HashMap<String, Actor>> {
+    static ACTORS: OnceLock<Mutex<HashMap<String, Actor>>> = OnceLock::new();
+    ACTORS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// The actor for the printer at `uri`, spawning it with `dialer` on first use.
+pub fn for_uri(uri: &str, dialer: impl FnOnce() -> Dialer) -> Actor {
+    actors()
+        .lock()
+        .unwrap()
+        .entry(uri.to_string())
+        .or_insert_with(|| Actor::spawn(uri, dialer()))
+        .clone()
+}
+
+/// The actor for `uri`, if one has been started.
+pub fn existing(uri: &str) -> Option<Actor> {
+    actors().lock().unwrap().get(uri).cloned()
+}
+
+/// Release the actors whose URI `keep` rejects. Requests already queued
+/// are still answered.
+pub fn retain(keep: impl Fn(&str) -> bool) {
+    actors().lock().unwrap().retain(|uri, _| {
+        let kept = keep(uri);
+        if !kept {
+            log::info!("device actor {uri}: released");
+        }
+        kept
+    });
+}
+
+impl Actor {
+    /// Start an actor for `name` (used in logs) that connects with `dial`.
+    pub fn spawn(name: &str, dial: Dialer) -> Self {
+        let (job, job_rx) = mpsc::unbounded_channel();
+        let (poll, poll_rx) = mpsc::unbounded_channel();
+        let state = State {
+            name: name.to_string(),
+            dial,
+            conn: None,
+            jobs: 0,
+            last_status: None,
+            last_material: None,
+            misses: 0,
+            backoff: BACKOFF_MIN,
+            retry_at: None,
+            failures: Vec::new(),
+        };
+        tokio::spawn(state.run(job_rx, poll_rx));
+        Actor { job, poll }
+    }
+
+    /// Tell the actor a transport came or went. Handled ahead of queued polls.
+    pub fn hotplug(&self, event: Hotplug) {
+        let _ = self.job.send(Request::Hotplug(event));
+    }
+
+    /// Connect (or confirm the connection) on `lane`. On the job lane this
+    /// holds the device until the returned link is dropped.
+    pub async fn open(&self, lane: Lane) -> Option<DeviceLink> {
+        let tx = match lane {
+            Lane::Job => self.job.clone(),
+            Lane::Poll => self.poll.clone(),
+        };
+        let (reply, rx) = oneshot::channel();
+        tx.send(Request::Open(reply)).ok()?;
+        let transport = rx.await.ok()??;
+        Some(DeviceLink {
+            tx,
+            lane,
+            transport,
+        })
+    }
+}
+
+/// A caller's handle on a printer's actor, bound to one lane.
+pub struct DeviceLink {
+    tx: mpsc::UnboundedSender<Request>,
+    lane: Lane,
+    /// `usb`, `bt` or `ble`, as connected when the link was opened.
+    pub transport: &'static str,
+}
+
+impl DeviceLink {
+    async fn call<T>(
+        &self,
+        request: impl FnOnce(oneshot::Sender<ProtoResult<T>>) -> Request,
+    ) -> ProtoResult<T> {
+        let (reply, rx) = oneshot::channel();
+        self.tx.send(request(reply)).map_err(|_| gone())?;
+        rx.await.map_err(|_| gone())?
+    }
+
+    /// Query INQUIRY_STA, returning a parsed [`PrinterStatus`].
+    pub async fn query_status(&self) -> ProtoResult<Option<PrinterStatus>> {
+        self.call(Request::Status).await
+    }
+
+    /// Query RETURN_MAT (loaded label + RFID + remaining count).
+    pub async fn query_material(&self) -> ProtoResult<Option<MaterialInfo>> {
+        self.call(Request::Material).await
+    }
+
+    /// CHECK_DEVICE — poke the device to confirm presence.
+    pub async fn check_device(&self) -> ProtoResult<bool> {
+        self.call(Request::Check).await
+    }
+
+    /// Stream a compressed label to the device. Returns the transport the
+    /// label went over, which differs from [`transport`] after a failover.
+    ///
+    /// [`transport`]: Self::transport
+    pub async fn print_label(&self, label: &Label) -> ProtoResult<&'static str> {
+        self.call(|reply| Request::Print(label.clone(), reply))
+            .await
+    }
+}
+
+impl Drop for DeviceLink {
+    fn drop(&mut self) {
+        if self.lane == Lane::Job {
+            let _ = self.tx.send(Request::Release);
+        }
+    }
+}
+
+fn gone() -> ProtoError {
+    ProtoError::Io(std::io::Error::new(
+        std::io::ErrorKind::BrokenPipe,
+        "device actor stopped",
+    ))
+}
+
+fn not_connected() -> ProtoError {
+    ProtoError::Io(std::io::Error::new(
+        std::io::ErrorKind::NotConnected,
+        "printer unreachable",
+    ))
+}
+
+/// Unanswered requests in a row after which the link is presumed dead.
+const MAX_MISSES: u32 = 3;
+
+/// Redial backoff after a failed connect: doubles from the first step up to
+/// the cap, and resets once a connection answers CHECK_DEVICE.
+const BACKOFF_MIN: Duration = Duration::from_secs(1);
+const BACKOFF_MAX: Duration = Duration::from_secs(60);
+
+/// How long a transport's failures count against it when ordering routes.
+const HEALTH_WINDOW: Duration = Duration::from_secs(600);
+
+/// Times a label the link dropped before accepting is resent, and the pause
+/// before redialing for it (the firmware beeps and resets after a drop).
+const LABEL_RETRIES: u32 = 2;
+const RESEND_DELAY: Duration = Duration::from_millis(500);
+
+struct State {
+    name: String,
+    dial: Dialer,
+    conn: Option<Connection>,
+    /// Open job-lane links; while non-zero, polls are served from the cache.
+    jobs: usize,
+    last_status: Option<PrinterStatus>,
+    last_material: Option<MaterialInfo>,
+    /// Consecutive requests the printer did not answer.
+    misses: u32,
+    backoff: Duration,
+    /// No redial before this, after a failed connect.
+    retry_at: Option<Instant>,
+    /// When each transport last failed to connect or lost its link.
+    failures: Vec<(&'static str, Instant)>,
+}
+
+impl State {
+    async fn run(
+        mut self,
+        mut job: mpsc::UnboundedReceiver<Request>,
+        mut poll: mpsc::UnboundedReceiver<Request>,
+    ) {
+        loop {
+            let (request, lane) = tokio::select! {
+                biased;
+                Some(r) = job.recv() => (r, Lane::Job),
+                Some(r) = poll.recv() => (r, Lane::Poll),
+                else => break,
+            };
+            self.handle(request, lane).await;
+        }
+        log::debug!("device actor {}: stopped", self.name);
+    }
+
+    async fn handle(&mut self, request: Request, lane: Lane) {
+        // A poll while a job holds the device must not touch the link.
+        let cached = lane == Lane::Poll && self.jobs > 0;
+        match request {
+            Request::Open(reply) => {
+                let transport = if cached {
+                    self.conn.as_ref().map(|(_, t)| *t)
+                } else {
+                    self.connect().await
+                };
+                if lane == Lane::Job && transport.is_some() {
+                    self.jobs += 1;
+                }
+                let _ = reply.send(transport);
+            }
+            Request::Release => self.jobs = self.jobs.saturating_sub(1),
+            Request::Hotplug(Hotplug::Attached) => {
+                self.backoff = BACKOFF_MIN;
+                self.retry_at = None;
+            }
+            Request::Hotplug(Hotplug::Detached(transport)) => {
+                if self.conn.as_ref().is_some_and(|(_, t)| *t == transport) {
+                    log::info!("device actor {}: {transport} unplugged", self.name);
+                    self.conn = None;
+                    self.misses = 0;
+                }
+            }
+            Request::Status(reply) if cached => {
+                // Before the job's first status, report nothing wrong.
+                let status = self.last_status.clone().unwrap_or_default();
+                let _ = reply.send(Ok(Some(status)));
+            }
+            Request::Material(reply) if cached => {
+                let _ = reply.send(Ok(self.last_material.clone()));
+            }
+            Request::Check(reply) if cached => {
+                let _ = reply.send(Ok(self.conn.is_some()));
+            }
+            Request::Status(reply) => {
+                let result = self.with_link(|l| l.query_status(), Option::is_some).await;
+                if let Ok(Some(s)) = &result {
+                    self.last_status = Some(s.clone());
+                }
+                let _ = reply.send(result);
+            }
+            Request::Material(reply) => {
+                let result = self
+                    .with_link(|l| l.query_material(), Option::is_some)
+                    .await;
+                if let Ok(m) = &result {
+                    self.last_material = m.clone();
+                }
+                let _ = reply.send(result);
+            }
+            Request::Check(reply) => {
+                let result = self.with_link(|l| l.check_device(), |&ok| ok).await;
+                let _ = reply.send(result);
+            }
+            Request::Print(label, reply) => {
+                let _ = reply.send(self.print(label).await);
+            }
+        }
+    }
+
+    /// Print a label, resending it over a fresh connection if the link
+    /// dropped before the printer accepted it.
+    async fn print(&mut self, label: Label) -> ProtoResult<&'static str> {
+        let mut resends = 0;
+        loop {
+            let label = label.clone();
+            let result = self
+                .with_link(
+                    move |l| Box::pin(async move { l.print_label(&label).await }),
+                    |_| true,
+                )
+                .await;
+            match result {
+                Ok(()) => return Ok(self.conn.as_ref().map_or("", |(_, t)| *t)),
+                Err(ProtoError::Unsent(e)) if resends < LABEL_RETRIES => {
+                    resends += 1;
+                    log::warn!(
+                        "device actor {}: {e} before the label was accepted; resending ({resends}/{LABEL_RETRIES})",
+                        self.name
+                    );
+                    tokio::time::sleep(RESEND_DELAY).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// The connected transport, dialing if there is no connection and the
+    /// redial backoff has elapsed.
+    async fn connect(&mut self) -> Option<&'static str> {
+        if self.conn.is_none() {
+            if let Some(at) = self.retry_at
+                && Instant::now() < at
+            {
+                return None;
+            }
+            self.conn = self.dial_checked().await;
+            match &self.conn {
+                Some((_, transport)) => {
+                    log::info!("device actor {}: connected over {transport}", self.name);
+                    self.backoff = BACKOFF_MIN;
+                    self.retry_at = None;
+                    self.misses = 0;
+                }
+                None => {
+                    log::warn!(
+                        "device actor {}: printer unreachable; next attempt in {:?}",
+                        self.name,
+                        self.backoff
+                    );
+                    self.retry_at = Some(Instant::now() + self.backoff);
+                    self.backoff = (self.backoff * 2).min(BACKOFF_MAX);
+                }
+            }
+        }
+        self.conn.as_ref().map(|(_, t)| *t)
+    }
+
+    /// Try the dialer's routes, healthiest first, and keep the first
+    /// connection that answers CHECK_DEVICE. Routes are ordered by their
+    /// failures within [`HEALTH_WINDOW`], ties keeping the dialer's order.
+    async fn dial_checked(&mut self) -> Option<Connection> {
+        let mut routes = (self.dial)();
+        let since = Instant::now().checked_sub(HEALTH_WINDOW);
+        self.failures
+            .retain(|&(_, at)| since.is_none_or(|s| at > s));
+        routes.sort_by_key(|r| {
+            self.failures
+                .iter()
+                .filter(|(t, _)| *t == r.transport)
+                .count()
+        });
+        for Route { transport, open } in routes {
+            log::info!("device actor {}: connecting over {transport}", self.name);
+            if let Some(link) = open.await {
+                match link.check_device().await {
+                    Ok(true) => return Some((link, transport)),
+                    Ok(false) => log::warn!(
+                        "device actor {}: no answer to CHECK_DEVICE over {transport}",
+                        self.name
+                    ),
+                    Err(e) => log::warn!(
+                        "device actor {}: CHECK_DEVICE over {transport} failed: {e}",
+                        self.name
+                    ),
+                }
+            }
+            self.failures.push((transport, Instant::now()));
+        }
+        None
+    }
+
+    /// Drop the connection, counting it against its transport.
+    fn disconnect(&mut self, transport: &'static str) {
+        self.failures.push((transport, Instant::now()));
+        self.misses = 0;
+    }
+
+    /// Run `op` on the connection, dialing first if needed. The connection
+    /// is dropped, so the next request redials, when the link fails or
+    /// [`MAX_MISSES`] results in a row are not `answered`.
+    async fn with_link<T>(
+        &mut self,
+        op: impl FnOnce(&dyn Link) -> BoxFuture<'_, ProtoResult<T>>,
+        answered: impl Fn(&T) -> bool,
+    ) -> ProtoResult<T> {
+        if self.connect().await.is_none() {
+            return Err(not_connected());
+        }
+        let conn = self.conn.take().expect("connected above");
+        let result = op(conn.0.as_ref()).await;
+        match &result {
+            Err(e) if e.is_link_lost() => {
+                log::info!("device actor {}: {e}; will reconnect", self.name);
+                self.disconnect(conn.1);
+            }
+            Ok(v) if !answered(v) && self.misses + 1 >= MAX_MISSES => {
+                log::info!(
+                    "device actor {}: {MAX_MISSES} requests unanswered; will reconnect",
+                    self.name
+                );
+                self.disconnect(conn.1);
+            }
+            Ok(v) => {
+                self.misses = if answered(v) { 0 } else { self.misses + 1 };
+                self.conn = Some(conn);
+            }
+            Err(_) => self.conn = Some(conn),
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use supvan_proto::cmd::{CMD_BUF_FULL, CMD_NEXT_ZIPPEDBULK, CMD_START_PRINT};
+    use supvan_proto::transport::Transport;
+
+    /// Shared knobs and a log of the operations the scripted printer saw.
+    #[derive(Clone, Default)]
+    struct Script {
+        log: Arc<Mutex<Vec<&'static str>>>,
+        dials: Arc<AtomicUsize>,
+        /// The dialer fails while set.
+        unreachable: Arc<AtomicBool>,
+        /// The next `query_status` fails with an I/O error.
+        broken: Arc<AtomicBool>,
+        /// `query_status` gets no answer while set.
+        silent: Arc<AtomicBool>,
+        /// The next print loses the link before the label is accepted.
+        drop_label: Arc<AtomicBool>,
+        /// A transport whose links do not answer CHECK_DEVICE.
+        deaf: Arc<Mutex<Option<&'static str>>>,
+        /// The route this link was opened over.
+        transport: &'static str,
+    }
+
+    impl Script {
+        fn push(&self, op: &'static str) {
+            self.log.lock().unwrap().push(op);
+        }
+
+        fn ops(&self) -> Vec<&'static str> {
+            self.log.lock().unwrap().clone()
+        }
+
+        fn dials(&self) -> usize {
+            self.dials.load(Ordering::SeqCst)
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Link for Script {
+        async fn query_status(&self) -> ProtoResult<Option<PrinterStatus>> {
+            self.push("status");
+            if self.broken.swap(false, Ordering::SeqCst) {
+                return Err(not_connected());
+            }
+            if self.silent.load(Ordering::SeqCst) {
+                return Ok(None);
+            }
+            Ok(Some(PrinterStatus {
+                print_count: 7,
+                ..Default::default()
+            }))
+        }
+
+        async fn query_material(&self) -> ProtoResult<Option<MaterialInfo>> {
+            self.push("material");
+            Ok(None)
+        }
+
+        async fn check_device(&self) -> ProtoResult<bool> {
+            self.push("check");
+            Ok(*self.deaf.lock().unwrap() != Some(self.transport))
+        }
+
+        async fn print_label(&self, _: &Label) -> ProtoResult<()> {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            self.push("print");
+            if self.drop_label.swap(false, Ordering::SeqCst) {
+                return Err(ProtoError::Unsent(Box::new(not_connected())));
+            }
+            Ok(())
+        }
+    }
+
+    /// Dials `s`'s printer over `transports`, in that order.
+    fn dialer(transports: &'static [&'static str], s: Script) -> Dialer {
+        Box::new(move || {
+            s.dials.fetch_add(1, Ordering::SeqCst);
+            let reachable = !s.unreachable.load(Ordering::SeqCst);
+            transports
+                .iter()
+                .map(|&transport| {
+                    let link = Script {
+                        transport,
+                        ..s.clone()
+                    };
+                    Route {
+                        transport,
+                        open: Box::pin(async move {
+                            reachable.then(|| Box::new(link) as Box<dyn Link>)
+                        }),
+                    }
+                })
+                .collect()
+        })
+    }
+
+    /// An actor whose printer is reachable over `transports`, in that order.
+    fn actor_over(transports: &'static [&'static str]) -> (Actor, Script) {
+        let script = Script::default();
+        let actor = Actor::spawn("test", dialer(transports, script.clone()));
+        (actor, script)
+    }
+
+    fn label() -> Label {
+        Label::single(vec![0], 1)
+    }
+
+    fn actor() -> (Actor, Script) {
+        actor_over(&["usb"])
+    }
+
+    #[tokio::test]
+    async fn polls_during_a_job_are_served_from_the_cache() {
+        let (actor, script) = actor();
+        let job = actor.open(Lane::Job).await.unwrap();
+        assert_eq!(job.transport, "usb");
+        job.query_status().await.unwrap();
+
+        // A poll racing the transfer neither interleaves with it nor reaches
+        // the printer; it gets the status the job last saw.
+        let poll = actor.open(Lane::Poll).await.unwrap();
+        let label = label();
+        let (printed, polled) = tokio::join!(job.print_label(&label), async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            poll.query_status().await
+        });
+        printed.unwrap();
+        assert_eq!(polled.unwrap().unwrap().print_count, 7);
+        assert!(poll.check_device().await.unwrap());
+        assert_eq!(script.ops(), ["check", "status", "print"]);
+
+        // Once the job lets go, polls reach the printer again, over the same
+        // connection.
+        drop(job);
+        poll.query_status().await.unwrap();
+        assert_eq!(script.ops(), ["check", "status", "print", "status"]);
+        assert_eq!(script.dials(), 1);
+    }
+
+    #[tokio::test]
+    async fn a_released_actor_drops_its_connection() {
+        let script = Script::default();
+        let uri = "supvan://released";
+        let actor = for_uri(uri, || dialer(&["usb"], script.clone()));
+        let poll = actor.open(Lane::Poll).await.unwrap();
+        assert!(poll.check_device().await.unwrap());
+        drop((actor, poll));
+
+        retain(|u| u != uri);
+        assert!(existing(uri).is_none());
+        // Once stopped, the actor no longer holds the link or its dialer.
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while Arc::strong_count(&script.log) > 1 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_dropped_link_is_redialed_on_the_next_request() {
+        let (actor, script) = actor();
+        let poll = actor.open(Lane::Poll).await.unwrap();
+        script.broken.store(true, Ordering::SeqCst);
+        assert!(matches!(poll.query_status().await, Err(ProtoError::Io(_))));
+        assert_eq!(script.dials(), 1);
+        poll.query_material().await.unwrap();
+        assert_eq!(script.dials(), 2);
+        // The fresh link is checked before it is used.
+        assert_eq!(script.ops(), ["check", "status", "check", "material"]);
+    }
+
+    #[tokio::test]
+    async fn an_unanswering_link_is_redialed() {
+        let (actor, script) = actor();
+        let poll = actor.open(Lane::Poll).await.unwrap();
+        script.silent.store(true, Ordering::SeqCst);
+        for _ in 0..MAX_MISSES {
+            assert!(poll.query_status().await.unwrap().is_none());
+        }
+        assert_eq!(script.dials(), 1);
+        poll.query_material().await.unwrap();
+        assert_eq!(script.dials(), 2);
+    }
+
+    #[tokio::test]
+    async fn redials_back_off_while_the_printer_is_unreachable() {
+        let (actor, script) = actor();
+        script.unreachable.store(true, Ordering::SeqCst);
+        assert!(actor.open(Lane::Poll).await.is_none());
+        // Within the backoff the actor does not dial again.
+        script.unreachable.store(false, Ordering::SeqCst);
+        assert!(actor.open(Lane::Poll).await.is_none());
+        assert_eq!(script.dials(), 1);
+        tokio::time::sleep(BACKOFF_MIN).await;
+        assert!(actor.open(Lane::Poll).await.is_some());
+        assert_eq!(script.dials(), 2);
+    }
+
+    #[tokio::test]
+    async fn a_label_lost_before_acceptance_is_resent() {
+        let (actor, script) = actor();
+        let job = actor.open(Lane::Job).await.unwrap();
+        script.drop_label.store(true, Ordering::SeqCst);
+        job.print_label(&label()).await.unwrap();
+        assert_eq!(script.dials(), 2);
+        assert_eq!(script.ops(), ["check", "print", "check", "print"]);
+    }
+
+    #[tokio::test]
+    async fn a_transport_that_fails_its_check_fails_over() {
+        let (actor, script) = actor_over(&["usb", "bt"]);
+        *script.deaf.lock().unwrap() = Some("usb");
+        let job = actor.open(Lane::Job).await.unwrap();
+        assert_eq!(job.transport, "bt");
+        assert_eq!(script.ops(), ["check", "check"]);
+    }
+
+    #[tokio::test]
+    async fn a_label_lost_over_one_transport_is_resent_over_another() {
+        let (actor, script) = actor_over(&["usb", "bt"]);
+        let job = actor.open(Lane::Job).await.unwrap();
+        assert_eq!(job.transport, "usb");
+        // The USB link drops mid-label; its failure puts BT first on redial.
+        script.drop_label.store(true, Ordering::SeqCst);
+        assert_eq!(job.print_label(&label()).await.unwrap(), "bt");
+        // BT stays in use while it works.
+        assert_eq!(job.print_label(&label()).await.unwrap(), "bt");
+        assert_eq!(script.dials(), 2);
+    }
+
+    #[tokio::test]
+    async fn hotplug_evicts_the_unplugged_link_and_skips_the_backoff() {
+        let (actor, script) = actor_over(&["usb", "bt"]);
+        let poll = actor.open(Lane::Poll).await.unwrap();
+        assert_eq!(poll.transport, "usb");
+        // Unplugging USB drops that link; the next request fails over.
+        actor.hotplug(Hotplug::Detached("usb"));
+        *script.deaf.lock().unwrap() = Some("usb");
+        assert_eq!(actor.open(Lane::Poll).await.unwrap().transport, "bt");
+        actor.hotplug(Hotplug::Detached("usb"));
+        poll.query_status().await.unwrap();
+        assert_eq!(script.dials(), 2);
+
+        // With nothing reachable the actor backs off, until a transport is
+        // attached again.
+        actor.hotplug(Hotplug::Detached("bt"));
+        script.unreachable.store(true, Ordering::SeqCst);
+        assert!(actor.open(Lane::Poll).await.is_none());
+        script.unreachable.store(false, Ordering::SeqCst);
+        assert!(actor.open(Lane::Poll).await.is_none());
+        actor.hotplug(Hotplug::Attached);
+        assert!(actor.open(Lane::Poll).await.is_some());
+    }
+
+    /// The wire under a real [`Printer`]: answers every command, reports
+    /// printing from START_PRINT until the label's last BUF_FULL, and drops
+    /// the link on the `drop_at`th data transfer (counted across links).
+    #[derive(Clone, Default)]
+    struct Wire {
+        log: Arc<Mutex<Vec<&'static str>>>,
+        sent: Arc<AtomicUsize>,
+        drop_at: usize,
+        transfers: usize,
+        printing: Arc<AtomicBool>,
+        buf_fulls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait::async_trait]
+    impl Transport for Wire {
+        async fn send_cmd(&self, cmd: u8, _: u16) -> ProtoResult<Option<Vec<u8>>> {
+            if cmd == CMD_START_PRINT {
+                self.log.lock().unwrap().push("start");
+                self.printing.store(true, Ordering::SeqCst);
+            }
+            Ok(Some(vec![cmd]))
+        }
+
+        async fn send_cmd_two(&self, cmd: u8, _: u16, _: u16) -> ProtoResult<Option<Vec<u8>>> {
+            if cmd == CMD_BUF_FULL {
+                self.log.lock().unwrap().push("buf_full");
+                if self.buf_fulls.fetch_add(1, Ordering::SeqCst) + 1 == self.transfers {
+                    self.printing.store(false, Ordering::SeqCst);
+                }
+            }
+            Ok(Some(vec![cmd]))
+        }
+
+        async fn send_bulk_header(&self, _: u16, _: usize) -> ProtoResult<Option<Vec<u8>>> {
+            Ok(Some(vec![CMD_NEXT_ZIPPEDBULK]))
+        }
+
+        async fn send_bulk_data(&self, _: &[u8], _: bool) -> ProtoResult<Option<Vec<u8>>> {
+            self.log.lock().unwrap().push("data");
+            if self.sent.fetch_add(1, Ordering::SeqCst) + 1 == self.drop_at {
+                return Err(not_connected());
+            }
+            Ok(None)
+        }
+
+        fn parse_status_response(&self, _: &[u8]) -> Option<PrinterStatus> {
+            Some(PrinterStatus {
+                printing: self.printing.load(Ordering::SeqCst),
+                ..Default::default()
+            })
+        }
+
+        fn parse_material_response(&self, _: &[u8]) -> Option<MaterialInfo> {
+            None
+        }
+
+        fn validate_response(&self, resp: &[u8], expected_cmd: u8) -> bool {
+            resp.first() == Some(&expected_cmd)
+        }
+
+        fn parse_device_name_response(&self, _: &[u8]) -> Option<String> {
+            None
+        }
+
+        fn parse_firmware_version_response(&self, _: &[u8]) -> Option<u8> {
+            None
+        }
+
+        fn parse_version_response(&self, _: &[u8]) -> Option<String> {
+            None
+        }
+    }
+
+    /// Print a two-transfer label through an actor whose link drops on the
+    /// `drop_at`th transfer; returns the result and what went over the wire.
+    async fn print_two_transfers(drop_at: usize) -> (ProtoResult<&'static str>, Vec<&'static str>) {
+        let wire = Wire {
+            drop_at,
+            transfers: 2,
+            ..Wire::default()
+        };
+        let w = wire.clone();
+        let actor = Actor::spawn(
+            "test",
+            Box::new(move || {
+                // Each connection starts with an idle printer.
+                let link = Wire {
+                    printing: Arc::default(),
+                    buf_fulls: Arc::default(),
+                    ..w.clone()
+                };
+                vec![Route {
+                    transport: "usb",
+                    open: Box::pin(async move {
+                        Some(Box::new(Printer::new(Box::new(link))) as Box<dyn Link>)
+                    }),
+                }]
+            }),
+        );
+        let job = actor.open(Lane::Job).await.unwrap();
+        let label = Label {
+            transfers: vec![vec![1], vec![2]],
+            speed: 1,
+            density: None,
+        };
+        let result = job.print_label(&label).await;
+        let ops = wire.log.lock().unwrap().clone();
+        (result, ops)
+    }
+
+    #[tokio::test]
+    async fn a_multi_transfer_label_is_resent_only_before_its_first_buf_full() {
+        // Lost during the first transfer: nothing printed, so it is resent.
+        let (result, ops) = print_two_transfers(1).await;
+        assert_eq!(result.unwrap(), "usb");
+        assert_eq!(
+            ops,
+            [
+                "start", "data", "start", "data", "buf_full", "data", "buf_full"
+            ]
+        );
+
+        // Lost after the printer acknowledged the first transfer: it has
+        // started printing, so the label fails instead of printing twice.
+        let (result, ops) = print_two_transfers(2).await;
+        let err = result.unwrap_err();
+        assert!(err.is_link_lost());
+        assert!(!matches!(err, ProtoError::Unsent(_)), "{err}");
+        assert_eq!(ops, ["start", "data", "buf_full", "data"]);
+    }
+}
@@ -2,8 +2,11 @@
 //!
 //! 1. Connect to system D-Bus, find adapter
 //! 2. Run a 4-second active BT Classic scan
-//! 3. Auto-pair matching unpaired devices
+//! 3. Auto-pair matching unpaired devices through the app's pairing agent
 //! 4. Report matching paired devices with SPP UUID
+//!
+//! The scan is opt-in (`SUPVAN_BT_SCAN=1`); by default discovery reads the
+//! passive table kept by [`crate::bluez_watch`].
 
 use dbus::blocking::Connection;
 use dbus::blocking::stdintf::org_freedesktop_dbus::ObjectManager;
@@ -11,14 +14,14 @@ use std::time::Duration;
 
 use crate::models;
 
-type PropMap = std::collections::HashMap<String, dbus::arg::Variant<Box<dyn dbus::arg::RefArg>>>;
+type PropMap = dbus::arg::PropMap;
 type IfaceMap = std::collections::HashMap<String, PropMap>;
 type ManagedObjects = std::collections::HashMap<dbus::Path<'static>, IfaceMap>;
 
 const SPP_UUID_PREFIX: &str = "00001101-";
 const BLUEZ_SERVICE: &str = "org.bluez";
 
-fn has_spp_uuid(props: &PropMap) -> bool {
+pub(crate) fn has_spp_uuid(props: &PropMap) -> bool {
     props
         .get("UUIDs")
         .and_then(|v| v.0.as_iter())
@@ -28,11 +31,11 @@ fn has_spp_uuid(props: &PropMap) -> bool {
         .any(|uuid| uuid.to_lowercase().starts_with(SPP_UUID_PREFIX))
 }
 
-fn get_str_prop(props: &PropMap, key: &str) -> Option<String> {
+pub(crate) fn get_str_prop(props: &PropMap, key: &str) -> Option<String> {
     props.get(key).and_then(|v| v.0.as_str().map(String::from))
 }
 
-fn get_bool_prop(props: &PropMap, key: &str) -> Option<bool> {
+pub(crate) fn get_bool_prop(props: &PropMap, key: &str) -> Option<bool> {
     props.get(key).and_then(|v| {
         v.0.as_u64()
             .map(|n| n != 0)
@@ -96,31 +99,11 @@ fn run_discovery(conn: &Connection, adapter: &dbus::Path<'_>) {
     log::info!("run_discovery: scan complete");
 }
 
-fn auto_pair_device(conn: &Connection, path: &dbus::Path<'_>, addr: &str) {
-    let proxy = conn.with_proxy(BLUEZ_SERVICE, path, Duration::from_secs(30));
-
-    log::info!("auto_pair_device: pairing {addr} ({path})");
-    match proxy.method_call::<(), _, _, _>("org.bluez.Device1", "Pair", ()) {
-        Ok(()) => log::info!("auto_pair_device: paired {addr}"),
-        Err(e) => {
-            let msg = e.to_string();
-            if msg.contains("AlreadyExists") {
-                log::debug!("auto_pair_device: {addr} already paired");
-            } else if msg.contains("AgentNotAvailable") {
-                log::warn!("auto_pair_device: {addr} needs agent for pairing — skipping");
-                return;
-            } else {
-                log::error!("auto_pair_device: Pair({addr}) failed: {e}");
-                return;
-            }
-        }
-    }
-
-    use dbus::blocking::stdintf::org_freedesktop_dbus::Properties;
-    if let Err(e) = proxy.set("org.bluez.Device1", "Trusted", true) {
-        log::warn!("auto_pair_device: failed to set Trusted on {addr}: {e}");
-    } else {
-        log::debug!("auto_pair_device: set Trusted=true on {addr}");
+/// Pair and trust a matching device. PIN and passkey requests are answered
+/// by the app's agent ([`crate::pairing_agent`]).
+fn auto_pair_device(addr: &str) {
+    if let Err(e) = crate::pairing_agent::pair(addr) {
+        log::warn!("auto_pair_device: {addr}: {e}");
     }
 }
 
@@ -173,7 +156,7 @@ where
         }
     };
 
-    for (path, ifaces) in &objects {
+    for ifaces in objects.values() {
         let props = match ifaces.get("org.bluez.Device1") {
             Some(p) => p,
             None => continue,
@@ -195,7 +178,7 @@ where
         };
 
         log::info!("discover: found unpaired match: {name} ({address})");
-        auto_pair_device(&conn, path, &address);
+        auto_pair_device(&address);
     }
 
     // Phase 4: Final read — report matching paired devices with SPP
@@ -261,7 +244,8 @@ pub struct BtCandidate {
 
 /// Like [`discover`] but returns structured candidates instead of invoking a
 /// callback. Used by the unified cross-transport list in
-/// [`crate::ipp_server::SupvanDeviceBackend::list`].
+/// [`crate::ipp_server::SupvanDeviceBackend::list`] when active scanning is
+/// enabled.
 pub fn list_candidates() -> Vec<BtCandidate> {
     let mut out = Vec::new();
     discover(|info, uri, _id| {
@@ -1,5 +1,5 @@
 use std::path::PathBuf;
-use std::sync::OnceLock;
+use std::sync::Mutex;
 use std::sync::atomic::{AtomicU32, Ordering};
 
 use serde::Serialize;
@@ -7,45 +7,54 @@ use serde::Serialize;
 /// Global sequence counter — allocated per page via [`JobDump::allocate`].
 static DUMP_SEQ: AtomicU32 = AtomicU32::new(0);
 
-/// Resolve the dump directory once per process.
+/// Resolve the dump directory for the next page.
 ///
 /// Priority:
 /// 1. Explicit `SUPVAN_DUMP_DIR` env var.
-/// 2. In `SUPVAN_MOCK=1` mode, default to `$XDG_RUNTIME_DIR/supvan-mock`
+/// 2. `[dump] enabled = false` in the config file: no dumps.
+/// 3. `[dump] dir` in the config file.
+/// 4. In `SUPVAN_MOCK=1` mode, default to `$XDG_RUNTIME_DIR/supvan-mock`
 ///    (falling back to `/tmp/supvan-mock` if `XDG_RUNTIME_DIR` is unset).
-/// 3. Outside mock mode with no explicit env var: `None` (no dumps).
-fn dump_dir() -> Option<&'static PathBuf> {
-    static DIR: OnceLock<Option<PathBuf>> = OnceLock::new();
-    DIR.get_or_init(|| {
-        let explicit = std::env::var("SUPVAN_DUMP_DIR")
+/// 5. Otherwise `None` (no dumps).
+///
+/// Re-resolved on every call so a config reload takes effect; the directory
+/// is created (and logged) when it changes.
+fn dump_dir() -> Option<PathBuf> {
+    static CREATED: Mutex<Option<PathBuf>> = Mutex::new(None);
+    let explicit = std::env::var("SUPVAN_DUMP_DIR")
+        .ok()
+        .filter(|s| !s.is_empty())
+        .map(PathBuf::from);
+    let config = crate::config::get();
+    let resolved = if let Some(p) = explicit {
+        p
+    } else if config.dump.enabled == Some(false) {
+        return None;
+    } else if let Some(dir) = &config.dump.dir {
+        dir.clone()
+    } else if crate::util::is_mock_mode() {
+        let xdg = std::env::var("XDG_RUNTIME_DIR")
             .ok()
             .filter(|s| !s.is_empty())
-            .map(PathBuf::from);
-        let resolved = if let Some(p) = explicit {
-            Some(p)
-        } else if crate::util::is_mock_mode() {
-            let xdg = std::env::var("XDG_RUNTIME_DIR")
-                .ok()
-                .filter(|s| !s.is_empty())
-                .unwrap_or_else(|| "/tmp".to_string());
-            Some(PathBuf::from(format!("{xdg}/supvan-mock")))
-        } else {
-            None
-        };
-        if let Some(ref dir) = resolved {
-            if let Err(e) = std::fs::create_dir_all(dir) {
-                log::warn!("dump: cannot create {}: {e}", dir.display());
-                return None;
-            }
-            log::info!("dump: writing to {}", dir.display());
+            .unwrap_or_else(|| "/tmp".to_string());
+        PathBuf::from(format!("{xdg}/supvan-mock"))
+    } else {
+        return None;
+    };
+    let mut created = CREATED.lock().unwrap();
+    if created.as_ref() != Some(&resolved) {
+        if let Err(e) = std::fs::create_dir_all(&resolved) {
+            log::warn!("dump: cannot create {}: {e}", resolved.display());
+            return None;
         }
-        resolved
-    })
-    .as_ref()
+        log::info!("dump: writing to {}", resolved.display());
+        *created = Some(resolved.clone());
+    }
+    Some(resolved)
 }
 
-/// True if dump writes will land somewhere — explicit `SUPVAN_DUMP_DIR` or
-/// the mock-mode default.
+/// True if dump writes will land somewhere — explicit `SUPVAN_DUMP_DIR`, the
+/// config file's `[dump] dir`, or the mock-mode default.
 pub fn dumps_enabled() -> bool {
     dump_dir().is_some()
 }
@@ -154,6 +163,8 @@ pub struct JobManifest {
     pub printhead_width_dots: u32,
     pub copies: u32,
     pub mock: bool,
+    /// `usb`, `bt`, `ble` or `mock`: how the page reached the printer.
+    pub transport: String,
     pub simulated_outcome: String,
     pub elapsed_ms: u128,
 }
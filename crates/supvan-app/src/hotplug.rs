@@ -0,0 +1,260 @@
+//! USB hotplug from the kernel's uevent netlink socket.
+//!
+//! Discovery runs once at startup, so without this a printer plugged in
+//! later, or unplugged, was only noticed when a job or status poll tripped
+//! over it. A thread reads `NETLINK_KOBJECT_UEVENT` directly (no libudev)
+//! and hands hidraw add/remove events to a task that:
+//!
+//! - on add, identifies the node as a Supvan printer (VID 0x1820, a known
+//!   PID) and registers it with [`device::attach_usb`], then brings the
+//!   printer's queue back online;
+//! - on remove, drops the path and any connection over it with
+//!   [`device::detach_usb`], and marks the printer offline unless BT or BLE
+//!   still reach it.
+//!
+//! The status poller reconciles the same state on its own cadence; this
+//! just gets there within a second of the cable moving.
+
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::sync::Arc;
+
+use ipp_printer_app::printer::IppPrinterState;
+use ipp_printer_app::status::AdvertiserControl;
+use ipp_printer_app::{PrinterReason, PrinterRegistry};
+use supvan_proto::identity::Sighting;
+use tokio::sync::mpsc;
+
+use crate::device;
+
+/// Kernel uevent multicast group (udev's own re-broadcasts are group 2).
+const KERNEL_UEVENTS: u32 = 1;
+
+/// A hidraw node appearing or going away.
+#[derive(Debug, PartialEq, Eq)]
+enum Event {
+    Added(String),
+    Removed(String),
+}
+
+/// Start watching for hidraw hotplug. Without a uevent socket (some
+/// containers) it logs and leaves discovery to the next start.
+pub fn spawn(registry: PrinterRegistry, advertiser: Option<Arc<dyn AdvertiserControl>>) {
+    let socket = match open_socket() {
+        Ok(fd) => fd,
+        Err(e) => {
+            log::warn!("hotplug: no uevent socket ({e}); USB changes are seen at the next start");
+            return;
+        }
+    };
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let reader = std::thread::Builder::new()
+        .name("uevent".into())
+        .spawn(move || read_events(&socket, &tx));
+    if let Err(e) = reader {
+        log::warn!("hotplug: cannot start the uevent reader: {e}");
+        return;
+    }
+    tokio::spawn(async move {
+        while let Some(event) = rx.recv().await {
+            handle(event, &registry, advertiser.as_deref()).await;
+        }
+    });
+}
+
+fn open_socket() -> std::io::Result<OwnedFd> {
+    let fd = unsafe {
+        libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+            libc::NETLINK_KOBJECT_UEVENT,
+        )
+    };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+    addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+    addr.nl_groups = KERNEL_UEVENTS;
+    let ret = unsafe {
+        libc::bind(
+            fd.as_raw_fd(),
+            &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+            std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+        )
+    };
+    if ret < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(fd)
+}
+
+/// Blocking read loop; ends when the socket fails or the handler task is gone.
+fn read_events(socket: &OwnedFd, tx: &mpsc::UnboundedSender<Event>) {
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = unsafe {
+            libc::recv(
+                socket.as_raw_fd(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+                0,
+            )
+        };
+        if n < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            log::warn!("hotplug: uevent socket failed: {err}");
+            return;
+        }
+        if let Some(event) = parse_uevent(&buf[..n as usize])
+            && tx.send(event).is_err()
+        {
+            return;
+        }
+    }
+}
+
+/// Decode a kernel uevent (`ACTION@DEVPATH`, then NUL-separated `KEY=VALUE`
+/// pairs) into a hidraw [`Event`]. Other subsystems and actions are `None`.
+fn parse_uevent(msg: &[u8]) -> Option<Event> {
+    let mut fields = msg.split(|&b| b == 0).filter(|f| !f.is_empty());
+    // The header repeats ACTION/DEVPATH; the pairs below are authoritative.
+    fields.next()?;
+    let (mut action, mut subsystem, mut devname) = (None, None, None);
+    for field in fields {
+        let field = std::str::from_utf8(field).ok()?;
+        let Some((key, value)) = field.split_once('=') else {
+            continue;
+        };
+        match key {
+            "ACTION" => action = Some(value),
+            "SUBSYSTEM" => subsystem = Some(value),
+            "DEVNAME" => devname = Some(value),
+            _ => {}
+        }
+    }
+    if subsystem != Some("hidraw") {
+        return None;
+    }
+    // DEVNAME is relative to /dev; keep just the node name.
+    let name = devname?.rsplit('/').next()?.to_string();
+    match action? {
+        "add" => Some(Event::Added(name)),
+        "remove" => Some(Event::Removed(name)),
+        _ => None,
+    }
+}
+
+async fn handle(
+    event: Event,
+    registry: &PrinterRegistry,
+    advertiser: Option<&dyn AdvertiserControl>,
+) {
+    match event {
+        Event::Added(name) => {
+            let Some(usb) = crate::usb_discover::probe_hotplugged(&name).await else {
+                return;
+            };
+            // Same identity as discovery: the registered printer for the
+            // firmware-reported name or USB id, else the name / USB id.
+            let key = usb.printer_name.as_ref().unwrap_or(&usb.uri_id);
+            let sighting = Sighting {
+                serial: usb.printer_name.clone(),
+                usb: Some(usb.uri_id.clone()),
+                family: Some(crate::ipp_server::family_hint(Some(&usb), key)),
+                ..Default::default()
+            };
+            let slug = crate::identities::slug_for(&sighting, key);
+            log::info!(
+                "hotplug: {} plugged in at {} as supvan://{slug}",
+                usb.model_name,
+                usb.hidraw_path
+            );
+            device::attach_usb(&slug, &usb.hidraw_path);
+            set_reachable(registry, advertiser, &format!("supvan://{slug}"), true);
+        }
+        Event::Removed(name) => {
+            for (slug, wireless) in device::detach_usb(&format!("/dev/{name}")) {
+                log::info!("hotplug: supvan://{slug} unplugged from /dev/{name}");
+                if !wireless {
+                    set_reachable(registry, advertiser, &format!("supvan://{slug}"), false);
+                }
+            }
+        }
+    }
+}
+
+/// Move the printers on `uri` between idle and stopped (offline), as the
+/// status poller would, and reconcile their discovery adverts. A printer in
+/// the middle of a job is left to the job.
+pub(crate) fn set_reachable(
+    registry: &PrinterRegistry,
+    advertiser: Option<&dyn AdvertiserControl>,
+    uri: &str,
+    reachable: bool,
+) {
+    let mut changed = Vec::new();
+    for rec in registry.write().iter_mut() {
+        if rec.config.device_uri != uri || rec.state == IppPrinterState::Processing {
+            continue;
+        }
+        if reachable {
+            rec.reasons.remove(PrinterReason::OFFLINE);
+            rec.state = IppPrinterState::Idle;
+        } else {
+            rec.reasons = PrinterReason::OFFLINE;
+            rec.state = IppPrinterState::Stopped;
+        }
+        log::info!("hotplug: {} state -> {:?}", rec.config.name, rec.state);
+        changed.push(rec.clone());
+    }
+    let Some(adv) = advertiser else {
+        return;
+    };
+    for rec in changed {
+        if !reachable {
+            adv.withdraw(&rec.config.name);
+        } else if !adv.is_advertised(&rec.config.name) {
+            adv.publish(&rec);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uevent(pairs: &[&str]) -> Vec<u8> {
+        let mut msg = b"add@/devices/pci0000:00/usb1/1-2/1-2:1.0/hidraw/hidraw3\0".to_vec();
+        for pair in pairs {
+            msg.extend_from_slice(pair.as_bytes());
+            msg.push(0);
+        }
+        msg
+    }
+
+    #[test]
+    fn hidraw_uevents_are_decoded() {
+        let add = uevent(&[
+            "ACTION=add",
+            "SUBSYSTEM=hidraw",
+            "DEVNAME=hidraw3",
+            "SEQNUM=42",
+        ]);
+        assert_eq!(parse_uevent(&add), Some(Event::Added("hidraw3".into())));
+        let remove = uevent(&["ACTION=remove", "SUBSYSTEM=hidraw", "DEVNAME=hidraw3"]);
+        assert_eq!(
+            parse_uevent(&remove),
+            Some(Event::Removed("hidraw3".into()))
+        );
+        // Other subsystems, actions and udev's re-broadcasts are ignored.
+        let usb = uevent(&["ACTION=add", "SUBSYSTEM=usb", "DEVNAME=bus/usb/001/007"]);
+        assert_eq!(parse_uevent(&usb), None);
+        let change = uevent(&["ACTION=change", "SUBSYSTEM=hidraw", "DEVNAME=hidraw3"]);
+        assert_eq!(parse_uevent(&change), None);
+        assert_eq!(parse_uevent(b"libudev\0\xfe\xed\xca\xfe"), None);
+    }
+}
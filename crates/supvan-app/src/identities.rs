@@ -0,0 +1,161 @@
+//! The app's side of the printer identity registry
+//! ([`supvan_proto::identity`]).
+//!
+//! Discovery, USB hotplug and BlueZ presence resolve a printer's
+//! `supvan://` slug through [`resolve`] / [`slug_for`], which record the
+//! sighting. The registry is kept in memory and re-read when the file's
+//! mtime moves, so a `supvan-cli printers rename` or `forget` made while the
+//! app runs is not overwritten; renames reach the queues at the next start
+//! ([`apply_names`]). A sighting is written back only when it changes the
+//! registry, or when the stored `last_seen` is [`LAST_SEEN_RESOLUTION`] old.
+
+use std::path::Path;
+use std::time::SystemTime;
+
+use ipp_printer_app::PrinterRegistry;
+use parking_lot::Mutex;
+use supvan_proto::identity::{self, Identities, Identity, Sighting};
+use supvan_proto::ledger;
+
+/// How stale a stored `last_seen` may get before a sighting rewrites the
+/// file for it alone, in seconds.
+const LAST_SEEN_RESOLUTION: u64 = 15 * 60;
+
+/// The registry as this process last read or wrote it.
+struct Cached {
+    ids: Identities,
+    /// The file's mtime then; `None` while there is no file.
+    mtime: Option<SystemTime>,
+}
+
+/// Also serializes this process's load–update–save cycles.
+static CACHE: Mutex<Option<Cached>> = Mutex::new(None);
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn load(path: &Path) -> Option<Identities> {
+    match Identities::load(path) {
+        Ok(ids) => Some(ids),
+        Err(e) => {
+            // Leave a damaged file alone rather than replace it.
+            log::warn!("identities {}: {e}", path.display());
+            None
+        }
+    }
+}
+
+/// The cached registry, re-read first if the file changed on disk since.
+fn fresh<'a>(cache: &'a mut Option<Cached>, path: &Path) -> Option<&'a mut Cached> {
+    let mtime = modified(path);
+    if cache.as_ref().is_none_or(|c| c.mtime != mtime) {
+        *cache = None;
+        *cache = Some(Cached {
+            ids: load(path)?,
+            mtime,
+        });
+    }
+    cache.as_mut()
+}
+
+/// Record `sighting` and return the printer it belongs to, or `None` if it
+/// has no serial and no known USB id (or the registry is unreadable).
+pub fn resolve(sighting: &Sighting) -> Option<Identity> {
+    let path = identity::default_path();
+    let mut cache = CACHE.lock();
+    let cached = fresh(&mut cache, &path)?;
+    let now = ledger::unix_now();
+    let last_seen = cached
+        .ids
+        .printers()
+        .find(|p| match (&sighting.serial, &sighting.usb) {
+            (Some(serial), _) => p.serial == *serial,
+            (None, Some(usb)) => p.usb.contains(usb),
+            (None, None) => false,
+        })
+        .map(|p| p.last_seen);
+    let (printer, changed) = cached.ids.observe(sighting, now)?;
+    let printer = printer.clone();
+    if changed {
+        log::info!(
+            "identities: {} is {} (usb={:?} bt={:?} ble={:?})",
+            printer.serial,
+            printer.uri(),
+            printer.usb,
+            printer.bt,
+            printer.ble
+        );
+    }
+    let stale = last_seen.is_none_or(|t| now.saturating_sub(t) >= LAST_SEEN_RESOLUTION);
+    if changed || stale {
+        match cached.ids.save(&path) {
+            Ok(()) => cached.mtime = modified(&path),
+            Err(e) => {
+                log::warn!("identities {}: {e}", path.display());
+                // Re-read next time rather than build on an unsaved table.
+                *cache = None;
+            }
+        }
+    }
+    Some(printer)
+}
+
+/// The slug for `sighting`: its registered one, else `fallback` slugified.
+pub fn slug_for(sighting: &Sighting, fallback: &str) -> String {
+    resolve(sighting)
+        .map(|p| p.slug)
+        .unwrap_or_else(|| identity::slugify(fallback))
+}
+
+/// The serial of the printer last seen on USB id `usb`, if any.
+pub fn serial_for_usb(usb: &str) -> Option<String> {
+    let mut cache = CACHE.lock();
+    let ids = &fresh(&mut cache, &identity::default_path())?.ids;
+    let printer = ids.printers().find(|p| p.usb.iter().any(|u| u == usb))?;
+    Some(printer.serial.clone())
+}
+
+/// The serial of the printer registered as `supvan://<slug>`, if any.
+pub fn serial_for_slug(slug: &str) -> Option<String> {
+    let mut cache = CACHE.lock();
+    let ids = &fresh(&mut cache, &identity::default_path())?.ids;
+    let printer = ids.printers().find(|p| p.slug == slug)?;
+    Some(printer.serial.clone())
+}
+
+/// The queue's human-readable name: the given name once renamed, else
+/// "Supvan <model> <serial>".
+pub fn label(printer: &Identity, model: &str) -> String {
+    if printer.display_name != printer.serial {
+        printer.display_name.clone()
+    } else {
+        format!("Supvan {model} {}", printer.serial)
+    }
+}
+
+/// Carry renames onto existing `supvan://` queues.
+pub fn apply_names(registry: &PrinterRegistry) {
+    // A copy, so the cache is not held under the registry lock.
+    let ids = fresh(&mut CACHE.lock(), &identity::default_path()).map(|c| c.ids.clone());
+    let Some(ids) = ids else {
+        return;
+    };
+    for rec in registry.write().iter_mut() {
+        let Some(slug) = rec.config.device_uri.strip_prefix("supvan://") else {
+            continue;
+        };
+        let Some(printer) = ids.printers().find(|p| p.slug == slug) else {
+            continue;
+        };
+        if printer.display_name != printer.serial && rec.config.display_name != printer.display_name
+        {
+            log::info!(
+                "identities: {} renamed to \"{}\"",
+                rec.config.name,
+                printer.display_name
+            );
+            rec.config.display_name = printer.display_name.clone();
+        }
+    }
+}
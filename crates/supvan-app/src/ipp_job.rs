@@ -6,39 +6,97 @@ use std::pin::pin;
 use ipp_printer_app::{JobFailure, JobOptions, PrinterHandle, RasterDriver};
 use print_raster::reader::cups::unified::CupsRasterUnifiedReader;
 use print_raster::reader::{RasterPageReader, RasterReader};
+use supvan_proto::buffer::MediaKind;
+use supvan_proto::status::MaterialInfo;
+use supvan_render::dither::Dither;
+use supvan_render::imaging::{self, Rotation, ScaleMode};
+use supvan_render::label::{LabelGeometry, mm_to_dots};
+use supvan_render::raster::{self, RasterFormat};
 use tokio_util::compat::TokioAsyncReadCompatExt;
 
-use crate::job::KsJob;
+use crate::device_actor::Lane;
+use crate::job::{Feed, KsJob, Trim};
 use crate::models;
+use crate::printer_device::KsDevice;
 
-/// Printhead resolution in dots per millimetre (matches supvan-proto).
-const DOTS_PER_MM: i32 = 8;
+/// How far a job's media may differ from the loaded roll per axis, in
+/// hundredths of a millimetre. The RFID tag reports whole millimetres, and
+/// inch sizes (2x1") land a fraction off the metric roll they are meant for.
+const MEDIA_TOLERANCE_HMM: i32 = 200;
+
+/// The queue a job prints on and the job-wide settings every document path
+/// needs. Built once per job in the print callback.
+#[derive(Debug, Clone, Copy)]
+pub struct JobContext<'a> {
+    /// Queue name.
+    pub name: &'a str,
+    pub uri: &'a str,
+    /// Driver family, for the device's capabilities.
+    pub driver: &'a str,
+    /// 0–100.
+    pub darkness: i32,
+    /// The family's native resolution.
+    pub dpi: u32,
+    pub printhead_width_dots: u32,
+    pub trim: Trim,
+    /// The job's `copies`; 0 when the client sent none.
+    pub copies: u32,
+}
+
+impl<'a> JobContext<'a> {
+    /// A job on the queue described by `config`.
+    pub fn new(config: &'a ipp_printer_app::PrinterConfig, trim: Trim, copies: u32) -> Self {
+        Self {
+            name: &config.name,
+            uri: &config.device_uri,
+            driver: &config.driver_name,
+            darkness: config.darkness,
+            dpi: config.dpi.max(1) as u32,
+            printhead_width_dots: config.printhead_width_dots,
+            trim,
+            copies,
+        }
+    }
+}
 
 /// Run a full CUPS raster document through [`KsJob`]. Runs on the caller's
 /// tokio runtime (the framework's print worker) — no nested runtime.
+///
+/// Pages already in the printhead's format (`black_1` or 8-bit gray at the
+/// family's `dpi`) stream straight through line by line. Anything else a
+/// client sends — another resolution, 1-bit gray, `black_8`, sRGB — is
+/// converted to gray and area-resampled to `dpi` first, so it prints at its
+/// physical size.
+///
+/// With `split`, each page is sliced into label-height segments at the loaded
+/// roll's label pitch (height + gap from `MaterialInfo`), and each segment
+/// prints as its own label in the same session. On a continuous roll each
+/// page prints at exactly its own length, less the job's `trim`. A job
+/// without `copies` prints each page as often as its header asks.
 pub async fn run_cups_raster_job(
-    printer_name: &str,
-    device_uri: &str,
-    darkness: i32,
-    printhead_width_dots: u32,
-    driver_name: &str,
+    job: &JobContext<'_>,
+    dither: &Dither,
+    split: LabelSplit,
     raster: &[u8],
-    copies_override: u32,
 ) -> Result<(), JobFailure> {
-    let dev = crate::device::open_uri(device_uri).await.ok_or_else(|| {
-        JobFailure::new(
-            ipp_printer_app::PrinterReason::OFFLINE,
-            format!("cannot open device {device_uri}"),
-        )
-    })?;
-
-    let record = job_record(
-        printer_name,
-        device_uri,
-        driver_name,
-        printhead_width_dots,
-        darkness,
-    );
+    let dev = open_device(job.uri, job.driver).await?;
+    let dpi = job.dpi;
+    let mat = dev.material().await;
+    let feed = feed_for(mat.as_ref(), job.trim);
+    // Label height and gap in dots, when slicing pages into labels.
+    let pitch = match (split, &mat) {
+        (LabelSplit::None, _) => None,
+        (_, Some(mat)) if mat.height_mm > 0 => Some((
+            mm_to_dots(f32::from(mat.height_mm), dpi),
+            mm_to_dots(f32::from(mat.gap_mm), dpi),
+        )),
+        _ => {
+            log::warn!("label split: loaded roll reports no label height; printing pages whole");
+            None
+        }
+    };
+
+    let record = job_record(job);
     let handle = PrinterHandle { record: &record };
 
     let cursor = Cursor::new(raster);
@@ -52,46 +110,108 @@ pub async fn run_cups_raster_job(
         .await
         .map_err(|e| JobFailure::other(format!("cups raster page: {e}")))?;
 
-    let mut job: Option<KsJob> = None;
+    let mut ks_job: Option<KsJob> = None;
     let mut page_num = 0u32;
 
     while let Some(mut page) = page_next {
         let h = &page.header().v1;
-        let copies = if copies_override > 0 {
-            copies_override
+        let copies = if job.copies > 0 {
+            job.copies
         } else if h.num_copies < 1 {
             1
         } else {
             h.num_copies
         };
-        let options = JobOptions::from_cups_v1(
-            h.width,
-            h.height,
+        let format = RasterFormat::of(
+            h.color_space,
+            h.color_order,
+            h.bits_per_color,
             h.bits_per_pixel,
-            h.bytes_per_line,
-            copies,
-        );
-
-        if job.is_none() {
-            job = Some(RasterDriver::start_job(&handle, &options, &dev)?);
-        }
+        )
+        .ok_or_else(|| {
+            JobFailure::other(format!(
+                "cups raster page {}: unsupported {:?} {}-bit {:?} pixels",
+                page_num + 1,
+                h.color_space,
+                h.bits_per_pixel,
+                h.color_order
+            ))
+        })?;
+        let (xres, yres) = (h.resolution.cross_feed, h.resolution.feed);
+        let (width, height, bpp, bpl) = (h.width, h.height, h.bits_per_pixel, h.bytes_per_line);
+        // Unknown (zero) resolution is taken as native.
+        let native = (xres == dpi || xres == 0) && (yres == dpi || yres == 0);
+        let passthrough = native && matches!(format, RasterFormat::Black1 | RasterFormat::Gray8);
+        let size = format
+            .page_bytes(width, height, bpl)
+            .map_err(|e| JobFailure::other(format!("cups raster page {}: {e}", page_num + 1)))?;
 
-        let state = job.as_mut().unwrap();
-        RasterDriver::start_page(state, &options, page_num, &dev)?;
+        let mut data = vec![0u8; size];
+        futures::AsyncReadExt::read_exact(page.content_mut(), &mut data)
+            .await
+            .map_err(|e| JobFailure::other(format!("raster page {}: {e}", page_num + 1)))?;
+        let (options, data) = if passthrough {
+            let options = JobOptions::from_cups_v1(width, height, bpp, bpl, copies);
+            (options, data)
+        } else {
+            let luma = format.to_luma(width, height, bpl, &data);
+            let luma = raster::resample_to_dpi(&luma, (xres, yres), dpi);
+            let (w, h) = luma.dimensions();
+            log::debug!(
+                "cups raster page {}: {format:?} {width}x{height} at {xres}x{yres} dpi \
+                 -> {w}x{h} 8-bit gray at {dpi} dpi",
+                page_num + 1
+            );
+            let options = JobOptions {
+                width: w,
+                height: h,
+                bits_per_pixel: 8,
+                bytes_per_line: w,
+                copies,
+            };
+            (options, luma.into_raw())
+        };
 
+        // One label per page, or one per roll label when splitting.
+        let (labels, label_options) = match pitch {
+            Some((label_h, gap)) if options.height > label_h => (
+                label_rows(options.height, label_h, gap),
+                JobOptions {
+                    height: label_h,
+                    ..options.clone()
+                },
+            ),
+            _ => (
+                label_rows(options.height, options.height, 0),
+                options.clone(),
+            ),
+        };
         let bpl = options.bytes_per_line as usize;
-        let height = options.height as usize;
-        let mut line = vec![0u8; bpl];
-        let content = page.content_mut();
-        for y in 0..height {
-            futures::AsyncReadExt::read_exact(content, &mut line)
-                .await
-                .map_err(|e| JobFailure::other(format!("raster line {y}: {e}")))?;
-            RasterDriver::write_line(state, &options, y as u32, &line)?;
-        }
+        let blank_line = vec![if options.bits_per_pixel == 8 { 0xFF } else { 0 }; bpl];
+        for rows in labels {
+            let label = &data[rows.start as usize * bpl..rows.end as usize * bpl];
+            if split == LabelSplit::LabelsSkipBlank && is_blank(label, options.bits_per_pixel) {
+                log::debug!("cups raster: skipping blank label at rows {rows:?}");
+                continue;
+            }
 
-        RasterDriver::end_page(state, &options, page_num, &dev).await?;
-        page_num += 1;
+            if ks_job.is_none() {
+                let mut ks: KsJob = RasterDriver::start_job(&handle, &label_options, &dev)?;
+                ks.dither = dither.clone();
+                ks.feed = feed;
+                ks_job = Some(ks);
+            }
+            let state = ks_job.as_mut().unwrap();
+            RasterDriver::start_page(state, &label_options, page_num, &dev)?;
+            let lines = label
+                .chunks_exact(bpl)
+                .chain(std::iter::repeat(&blank_line[..]));
+            for (y, line) in lines.take(label_options.height as usize).enumerate() {
+                RasterDriver::write_line(state, &label_options, y as u32, line)?;
+            }
+            RasterDriver::end_page(state, &label_options, page_num, &dev).await?;
+            page_num += 1;
+        }
 
         page_next = page
             .next_page()
@@ -99,75 +219,201 @@ pub async fn run_cups_raster_job(
             .map_err(|e| JobFailure::other(format!("cups raster next page: {e}")))?;
     }
 
-    if let Some(j) = job.take() {
+    if let Some(j) = ks_job.take() {
         RasterDriver::end_job(j, &dev).await;
     }
 
     Ok(())
 }
 
+/// The vendor `supvan-label-split` job attribute: slice tall raster pages
+/// (a sheet of labels, or one long page) into the loaded roll's labels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LabelSplit {
+    /// Print each page as one label.
+    #[default]
+    None,
+    /// One label per label-height segment.
+    Labels,
+    /// As [`Labels`](Self::Labels), dropping segments with nothing to print.
+    LabelsSkipBlank,
+}
+
+impl std::str::FromStr for LabelSplit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "labels" => Ok(Self::Labels),
+            "labels-skip-blank" => Ok(Self::LabelsSkipBlank),
+            other => Err(format!("unknown supvan-label-split '{other}'")),
+        }
+    }
+}
+
+/// Row ranges of the labels a `height`-row page holds: `label` rows, then a
+/// `gap` the roll skips, repeated. The last label may be cut short.
+fn label_rows(height: u32, label: u32, gap: u32) -> Vec<std::ops::Range<u32>> {
+    let pitch = (label + gap).max(1);
+    (0..height)
+        .step_by(pitch as usize)
+        .map(|start| start..(start + label).min(height))
+        .collect()
+}
+
+/// Luma at or above this is paper: JPEG noise and faint anti-aliasing in an
+/// otherwise empty label do not make it print.
+const BLANK_LUMA: u8 = 0xF8;
+
+/// Whether rows of `bits_per_pixel` raster would burn no dots.
+fn is_blank(rows: &[u8], bits_per_pixel: u32) -> bool {
+    if bits_per_pixel == 8 {
+        rows.iter().all(|&l| l >= BLANK_LUMA)
+    } else {
+        rows.iter().all(|&b| b == 0)
+    }
+}
+
 /// Build the throwaway [`PrinterRecord`] that backs the [`PrinterHandle`] a
 /// [`KsJob`] reads (only `darkness` + `printhead_width_dots` matter). Shared by
 /// the raster and JPEG paths.
-fn job_record(
-    printer_name: &str,
-    device_uri: &str,
-    driver_name: &str,
-    printhead_width_dots: u32,
-    darkness: i32,
-) -> ipp_printer_app::PrinterRecord {
+fn job_record(job: &JobContext<'_>) -> ipp_printer_app::PrinterRecord {
     ipp_printer_app::PrinterRecord::new(ipp_printer_app::PrinterConfig {
-        name: printer_name.to_string(),
+        name: job.name.to_string(),
         display_name: String::new(),
-        driver_name: driver_name.to_string(),
+        driver_name: job.driver.to_string(),
         make_and_model: String::new(),
         device_id: String::new(),
-        device_uri: device_uri.to_string(),
-        dpi: 203,
-        printhead_width_dots,
+        device_uri: job.uri.to_string(),
+        dpi: job.dpi as i32,
+        printhead_width_dots: job.printhead_width_dots,
         media_names: vec![],
         media_sizes: vec![],
-        darkness,
+        darkness: job.darkness,
         document_formats: vec![],
     })
 }
 
-/// Decode an `image/jpeg` document and print it. Decodes to grayscale,
-/// contain-fits it onto the loaded label (aspect preserved, centered, white
-/// padding), then drives [`KsJob`]'s existing 8bpp path (dither → device).
+/// Image document formats decoded in-process by [`run_image_job`], by MIME
+/// type. JPEG is the IPP Everywhere baseline; the rest are what screenshots,
+/// label exports and mobile clients send.
+pub const IMAGE_FORMATS: [(&str, image::ImageFormat); 6] = [
+    ("image/jpeg", image::ImageFormat::Jpeg),
+    ("image/png", image::ImageFormat::Png),
+    ("image/gif", image::ImageFormat::Gif),
+    ("image/bmp", image::ImageFormat::Bmp),
+    ("image/tiff", image::ImageFormat::Tiff),
+    ("image/webp", image::ImageFormat::WebP),
+];
+
+/// The decoder for an image `document-format`, if we decode it in-process.
+pub fn image_format(document_format: &str) -> Option<image::ImageFormat> {
+    IMAGE_FORMATS
+        .iter()
+        .find(|(mime, _)| document_format.eq_ignore_ascii_case(mime))
+        .map(|&(_, format)| format)
+}
+
+/// Decode an image document to grayscale, compositing any alpha onto white
+/// paper. Animated GIFs and multi-page TIFFs yield their first frame.
+fn decode_luma(
+    document: &[u8],
+    format: image::ImageFormat,
+) -> Result<image::GrayImage, JobFailure> {
+    let img = image::load_from_memory_with_format(document, format)
+        .map_err(|e| JobFailure::other(format!("{} decode: {e}", format.extensions_str()[0])))?;
+    Ok(imaging::luma_on_white(&img))
+}
+
+/// How decoded pages are laid onto the label, from the job's `media` /
+/// `media-col`, `print-scaling` and `orientation-requested`. The default is
+/// what a job that sets none of them gets: the loaded roll, `auto` scaling,
+/// and a quarter-turn when the page and label orientations disagree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageLayout {
+    /// The job's label size in hundredths of a millimetre; `None` uses the
+    /// roll the printer reports as loaded.
+    pub media_size_hmm: Option<[i32; 2]>,
+    pub scaling: ScaleMode,
+    pub rotation: Rotation,
+}
+
+impl Default for PageLayout {
+    fn default() -> Self {
+        Self {
+            media_size_hmm: None,
+            scaling: ScaleMode::Auto,
+            rotation: Rotation::Auto,
+        }
+    }
+}
+
+/// How pages feed on the loaded roll: die-cut unless the device reports a
+/// continuous one.
+fn feed_for(mat: Option<&MaterialInfo>, trim: Trim) -> Feed {
+    Feed {
+        media: mat.map_or(MediaKind::Gap, MaterialInfo::media_kind),
+        trim,
+    }
+}
+
+/// The label size a decoded document is laid onto — the job's requested
+/// size, else the roll the device reports as loaded, else
+/// `default_media_size_hmm` — and how it feeds. A continuous roll with no
+/// job size yields a zero height: the page is as long as its content.
+async fn label_media(
+    dev: &KsDevice,
+    layout: &PageLayout,
+    default_media_size_hmm: [i32; 2],
+    trim: Trim,
+) -> ([i32; 2], Feed) {
+    let mat = dev.material().await;
+    let feed = feed_for(mat.as_ref(), trim);
+    let size = match (layout.media_size_hmm, mat) {
+        (Some(size), _) => size,
+        (None, Some(mat)) if mat.width_mm > 0 => [
+            i32::from(mat.width_mm) * 100,
+            i32::from(mat.height_mm) * 100,
+        ],
+        _ => default_media_size_hmm,
+    };
+    (size, feed)
+}
+
+/// Decode an image document ([`IMAGE_FORMATS`]) and print it. Decodes to
+/// grayscale (alpha flattened onto white), rotates and scales it onto the
+/// label per `layout`, then drives [`KsJob`]'s existing 8bpp path
+/// (dither → device). On a continuous roll with no job size, the label is as
+/// long as the image at the roll width.
 ///
-/// JPEG decode + fit is synchronous; the device transfer is awaited like the
+/// Decode + fit is synchronous; the device transfer is awaited like the
 /// raster path. Runs on the caller's tokio runtime (the print worker).
-pub async fn run_jpeg_job(
-    printer_name: &str,
-    device_uri: &str,
-    darkness: i32,
-    printhead_width_dots: u32,
-    media_size_hmm: [i32; 2],
-    jpeg: &[u8],
-    copies: u32,
+pub async fn run_image_job(
+    job: &JobContext<'_>,
+    dither: &Dither,
+    layout: &PageLayout,
+    default_media_size_hmm: [i32; 2],
+    format: image::ImageFormat,
+    document: &[u8],
 ) -> Result<(), JobFailure> {
-    let img = image::load_from_memory_with_format(jpeg, image::ImageFormat::Jpeg)
-        .map_err(|e| JobFailure::other(format!("jpeg decode: {e}")))?
-        .to_luma8();
+    let img = decode_luma(document, format)?;
 
-    let (canvas, label_w, label_h) = fit_luma(&img, media_size_hmm, printhead_width_dots);
+    let dev = open_device(job.uri, job.driver).await?;
+    let (media_size_hmm, feed) = label_media(&dev, layout, default_media_size_hmm, job.trim).await;
+    let (canvas, label_w, label_h) = fit_luma(
+        img,
+        media_size_hmm,
+        job.dpi,
+        job.printhead_width_dots,
+        layout,
+    );
     if label_w == 0 || label_h == 0 {
         return Err(JobFailure::other(format!(
-            "jpeg: empty label geometry from media_size {media_size_hmm:?}"
+            "image: empty label geometry from media_size {media_size_hmm:?}"
         )));
     }
-
-    let dev = crate::device::open_uri(device_uri).await.ok_or_else(|| {
-        JobFailure::new(
-            ipp_printer_app::PrinterReason::OFFLINE,
-            format!("cannot open device {device_uri}"),
-        )
-    })?;
-    // The throwaway record only feeds KsJob's darkness + printhead width; the
-    // driver name is irrelevant on this path.
-    let record = job_record(printer_name, device_uri, "", printhead_width_dots, darkness);
-    let handle = PrinterHandle { record: &record };
+    log::debug!("image: {label_w}x{label_h} dots, {layout:?}");
 
     // 8bpp grayscale, one byte per pixel; KsJob's 8bpp branch dithers each row.
     let options = JobOptions {
@@ -175,60 +421,355 @@ pub async fn run_jpeg_job(
         height: label_h,
         bits_per_pixel: 8,
         bytes_per_line: label_w,
-        copies: copies.max(1),
+        copies: job.copies.max(1),
     };
+    drive_pages(&dev, job, dither, feed, [Ok((options, canvas))]).await
+}
+
+/// Decode a URF document (see [`crate::urf`]) and print each page as one
+/// label. Pages are laid out like [`run_image_job`] — scaling also absorbs
+/// the difference between the client's raster resolution and the family's —
+/// then dithered by [`KsJob`]'s 8bpp path. On a continuous roll each page
+/// is as long as its own content. Each page is decoded and fitted only when
+/// the previous one has printed.
+pub async fn run_urf_job(
+    job: &JobContext<'_>,
+    dither: &Dither,
+    layout: &PageLayout,
+    default_media_size_hmm: [i32; 2],
+    document: &[u8],
+) -> Result<(), JobFailure> {
+    let pages = crate::urf::decode(document)?;
+
+    let dev = open_device(job.uri, job.driver).await?;
+    let (media_size_hmm, feed) = label_media(&dev, layout, default_media_size_hmm, job.trim).await;
+    let labels = pages.enumerate().map(|(n, page)| {
+        let page = page?;
+        log::debug!(
+            "urf: page {}: {}x{} at {} dpi",
+            n + 1,
+            page.luma.width(),
+            page.luma.height(),
+            page.dpi
+        );
+        let (canvas, label_w, label_h) = fit_luma(
+            page.luma,
+            media_size_hmm,
+            job.dpi,
+            job.printhead_width_dots,
+            layout,
+        );
+        if label_w == 0 || label_h == 0 {
+            return Err(JobFailure::other(format!(
+                "urf: empty label geometry from media_size {media_size_hmm:?}"
+            )));
+        }
+        let options = JobOptions {
+            width: label_w,
+            height: label_h,
+            bits_per_pixel: 8,
+            bytes_per_line: label_w,
+            copies: job.copies.max(1),
+        };
+        Ok((options, canvas))
+    });
+
+    drive_pages(&dev, job, dither, feed, labels).await
+}
 
-    let mut job: KsJob = RasterDriver::start_job(&handle, &options, &dev)?;
-    RasterDriver::start_page(&mut job, &options, 0, &dev)?;
-    let w = label_w as usize;
-    for y in 0..label_h as usize {
-        RasterDriver::write_line(&mut job, &options, y as u32, &canvas[y * w..(y + 1) * w])?;
+/// Pre-flight check of the job's media against the roll the device reports
+/// as loaded. A mismatch fails with `media-needed`, which the print callback
+/// turns into a held job that the framework retries until the right roll is
+/// in. Unknown material (no RFID tag, mid-print, transport error) passes.
+pub async fn check_loaded_media(
+    device_uri: &str,
+    driver_name: &str,
+    job_size_hmm: [i32; 2],
+) -> Result<(), JobFailure> {
+    let dev = open_device(device_uri, driver_name).await?;
+    let Some(mat) = dev.material().await else {
+        return Ok(());
+    };
+    match media_mismatch(job_size_hmm, &mat) {
+        Some(message) => Err(JobFailure::new(
+            ipp_printer_app::PrinterReason::MEDIA_NEEDED,
+            message,
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Why a job of `job_size_hmm` does not fit the loaded roll, or `None` if it
+/// does within [`MEDIA_TOLERANCE_HMM`]. Either orientation fits, since
+/// pages turn to match the label. A continuous roll only has a width to
+/// match; a roll reporting no width is not checked.
+fn media_mismatch(job_size_hmm: [i32; 2], mat: &MaterialInfo) -> Option<String> {
+    if mat.width_mm == 0 {
+        return None;
     }
-    // end_page transfers `options.copies` times internally — do not loop here.
-    RasterDriver::end_page(&mut job, &options, 0, &dev).await?;
-    RasterDriver::end_job(job, &dev).await;
+    let roll = [
+        i32::from(mat.width_mm) * 100,
+        i32::from(mat.height_mm) * 100,
+    ];
+    let near = |a: i32, b: i32| (a - b).abs() <= MEDIA_TOLERANCE_HMM;
+    let [w, h] = job_size_hmm;
+    let fits = if mat.is_continuous() {
+        near(w, roll[0]) || near(h, roll[0])
+    } else {
+        (near(w, roll[0]) && near(h, roll[1])) || (near(w, roll[1]) && near(h, roll[0]))
+    };
+    let mm = |hmm: i32| format!("{}", f64::from(hmm) / 100.0);
+    let loaded = if mat.is_continuous() {
+        format!("{} mm continuous", mat.width_mm)
+    } else {
+        format!("{}x{} mm", mat.width_mm, mat.height_mm)
+    };
+    (!fits).then(|| {
+        format!(
+            "load {}x{} mm labels (loaded roll is {loaded})",
+            mm(w),
+            mm(h)
+        )
+    })
+}
+
+async fn open_device(device_uri: &str, driver_name: &str) -> Result<KsDevice, JobFailure> {
+    let dev = crate::device::open_uri(device_uri, driver_name, Lane::Job)
+        .await
+        .ok_or_else(|| {
+            JobFailure::new(
+                ipp_printer_app::PrinterReason::OFFLINE,
+                format!("cannot open device {device_uri}"),
+            )
+        })?;
+    crate::job_ledger::note_transport(dev.transport);
+    Ok(dev)
+}
+
+/// Drive in-memory pages, each with its own size, through [`KsJob`] on an
+/// already-open device. Pages are taken from `pages` one at a time, so a
+/// lazily produced document is never held whole; an error from it ends the
+/// job.
+async fn drive_pages<P: AsRef<[u8]>>(
+    dev: &KsDevice,
+    job: &JobContext<'_>,
+    dither: &Dither,
+    feed: Feed,
+    pages: impl IntoIterator<Item = Result<(JobOptions, P), JobFailure>>,
+) -> Result<(), JobFailure> {
+    let mut pages = pages.into_iter();
+    let Some(first) = pages.next().transpose()? else {
+        return Ok(());
+    };
+    // The throwaway record only feeds KsJob's darkness, printhead width and
+    // per-printer settings; the device carries the family's capabilities.
+    let record = job_record(job);
+    let handle = PrinterHandle { record: &record };
+
+    let mut ks_job: KsJob = RasterDriver::start_job(&handle, &first.0, dev)?;
+    ks_job.dither = dither.clone();
+    ks_job.feed = feed;
+    for (page_num, page) in std::iter::once(Ok(first)).chain(pages).enumerate() {
+        let (options, page) = &page?;
+        let bpl = options.bytes_per_line as usize;
+        let page_num = page_num as u32;
+        RasterDriver::start_page(&mut ks_job, options, page_num, dev)?;
+        for (y, line) in page
+            .as_ref()
+            .chunks_exact(bpl)
+            .take(options.height as usize)
+            .enumerate()
+        {
+            RasterDriver::write_line(&mut ks_job, options, y as u32, line)?;
+        }
+        // end_page transfers `options.copies` times internally — do not loop here.
+        RasterDriver::end_page(&mut ks_job, options, page_num, dev).await?;
+    }
+    RasterDriver::end_job(ks_job, dev).await;
     Ok(())
 }
 
-/// Contain-fit a grayscale image onto the label canvas: scale preserving aspect
-/// to fit inside the label (W×H dots, capped at the printhead width), then
-/// center on a white (`0xFF` luma) ground. Returns `(row-major 8bpp canvas,
-/// label_w, label_h)`. Pure function — unit-tested.
+/// SVG documents, rendered in-process by [`run_svg_job`].
+pub const SVG_FORMAT: &str = "image/svg+xml";
+
+/// Render an SVG document at the family's native resolution onto the label
+/// that is actually loaded (from the device's `MaterialInfo`; the configured
+/// default size when the device does not report one) and print it as a 1-bit
+/// page. On a continuous roll the label is as long as the document's aspect
+/// ratio makes it, up to the family's longest label. Vector content is
+/// thresholded per dot and only embedded bitmaps are dithered (see
+/// [`supvan_render::svg`]), so nothing is resampled after rendering.
+pub async fn run_svg_job(
+    job: &JobContext<'_>,
+    media_size_hmm: [i32; 2],
+    document: &[u8],
+) -> Result<(), JobFailure> {
+    let dev = open_device(job.uri, job.driver).await?;
+    let mat = dev.material().await;
+    let feed = feed_for(mat.as_ref(), job.trim);
+    let geom = match mat {
+        Some(mat) => LabelGeometry::from_roll(&mat, job.dpi, job.printhead_width_dots),
+        None => LabelGeometry::from_mm(
+            media_size_hmm[0].max(0) as f32 / 100.0,
+            media_size_hmm[1].max(0) as f32 / 100.0,
+            job.dpi,
+            job.printhead_width_dots,
+        ),
+    };
+    let max_length_mm = models::family_by_driver(job.driver)
+        .unwrap_or_else(models::default_family)
+        .max_length_mm();
+    let canvas = supvan_render::svg::render(document, &geom, max_length_mm)
+        .map_err(|e| JobFailure::other(format!("svg: {e}")))?;
+    let options = JobOptions {
+        width: canvas.width(),
+        height: canvas.height(),
+        bits_per_pixel: 1,
+        bytes_per_line: canvas.bytes_per_line(),
+        copies: job.copies.max(1),
+    };
+    drive_pages(
+        &dev,
+        job,
+        &Dither::default(),
+        feed,
+        [Ok((options, canvas.data()))],
+    )
+    .await
+}
+
+/// Label template documents (see [`supvan_render::template`]), TOML or JSON.
+/// Field values travel inside the document's `[values]` table.
+pub const TEMPLATE_TOML_FORMAT: &str = "application/vnd.supvan.label+toml";
+pub const TEMPLATE_JSON_FORMAT: &str = "application/vnd.supvan.label+json";
+
+/// Render a label template document and print it. The template's label size
+/// must be one of the printer's media sizes; it is rendered at the family's
+/// native resolution and sent as a 1-bit page, so nothing is resampled.
+/// Templates received over IPP cannot reference image files.
+pub async fn run_template_job(
+    job: &JobContext<'_>,
+    media_sizes_hmm: &[[i32; 2]],
+    document_format: &str,
+    document: &[u8],
+) -> Result<(), JobFailure> {
+    let canvas = render_template(
+        document_format,
+        document,
+        job.dpi as i32,
+        job.printhead_width_dots,
+        media_sizes_hmm,
+    )
+    .map_err(|e| JobFailure::other(format!("label template: {e}")))?;
+
+    let dev = open_device(job.uri, job.driver).await?;
+    let feed = feed_for(dev.material().await.as_ref(), job.trim);
+    let options = JobOptions {
+        width: canvas.width(),
+        height: canvas.height(),
+        bits_per_pixel: 1,
+        bytes_per_line: canvas.bytes_per_line(),
+        copies: job.copies.max(1),
+    };
+    drive_pages(
+        &dev,
+        job,
+        &Dither::default(),
+        feed,
+        [Ok((options, canvas.data()))],
+    )
+    .await
+}
+
+/// Parse, validate against the media list, and render a template document.
+/// Pure function — unit-tested.
+fn render_template(
+    document_format: &str,
+    document: &[u8],
+    dpi: i32,
+    printhead_width_dots: u32,
+    media_sizes_hmm: &[[i32; 2]],
+) -> supvan_render::error::Result<supvan_render::canvas::Canvas> {
+    use supvan_render::error::Error;
+    use supvan_render::template::Template;
+
+    let text = std::str::from_utf8(document)
+        .map_err(|e| Error::Template(format!("document is not UTF-8: {e}")))?;
+    let template = if document_format == TEMPLATE_JSON_FORMAT {
+        Template::from_json_str(text)?
+    } else {
+        Template::from_toml_str(text)?
+    };
+    let media_mm: Vec<[u32; 2]> = media_sizes_hmm
+        .iter()
+        .map(|&[w, h]| [hmm_to_mm(w), hmm_to_mm(h)])
+        .collect();
+    template.check_media(&media_mm)?;
+    template.render(&Default::default(), dpi.max(1) as u32, printhead_width_dots)
+}
+
+/// Hundredths of a millimetre to the nearest whole millimetre, as template
+/// sizes are given: a 2" (50.8 mm) PWG size is a 51 mm template.
+fn hmm_to_mm(hmm: i32) -> u32 {
+    ((hmm.max(0) + 50) / 100) as u32
+}
+
+/// Lay a grayscale image onto the label canvas: rotate and scale it per
+/// `layout` onto the label's W×H dots at `dpi` (capped at the printhead
+/// width), centered on
+/// a white (`0xFF` luma) ground. `layout.media_size_hmm` is ignored; the
+/// caller has already resolved the size. A zero height is continuous media:
+/// the label is as long as the rotated image at the label width. Returns
+/// `(row-major 8bpp canvas, label_w, label_h)`. Pure function — unit-tested.
 fn fit_luma(
-    img: &image::GrayImage,
+    img: image::GrayImage,
     media_size_hmm: [i32; 2],
+    dpi: u32,
     printhead_width_dots: u32,
+    layout: &PageLayout,
 ) -> (Vec<u8>, u32, u32) {
-    let label_w = ((media_size_hmm[0].max(0) / 100 * DOTS_PER_MM) as u32).min(printhead_width_dots);
-    let label_h = (media_size_hmm[1].max(0) / 100 * DOTS_PER_MM) as u32;
-    if label_w == 0 || label_h == 0 {
+    let dots = |hmm: i32| mm_to_dots(hmm as f32 / 100.0, dpi);
+    let label_w = dots(media_size_hmm[0]).min(printhead_width_dots);
+    let label_h = dots(media_size_hmm[1]);
+    if label_w == 0 {
         return (Vec::new(), 0, 0);
     }
-
-    let canvas_len = (label_w * label_h) as usize;
-    let (iw, ih) = img.dimensions();
-    if iw == 0 || ih == 0 {
-        return (vec![0xFF; canvas_len], label_w, label_h);
-    }
-
-    let scale = (label_w as f32 / iw as f32).min(label_h as f32 / ih as f32);
-    let rw = ((iw as f32 * scale).round() as u32).clamp(1, label_w);
-    let rh = ((ih as f32 * scale).round() as u32).clamp(1, label_h);
-    let resized = image::imageops::resize(img, rw, rh, image::imageops::FilterType::Triangle);
-
-    let mut canvas = vec![0xFFu8; canvas_len];
-    let ox = (label_w - rw) / 2;
-    let oy = (label_h - rh) / 2;
-    let src = resized.as_raw();
-    for y in 0..rh as usize {
-        let dst_start = ((oy as usize + y) * label_w as usize) + ox as usize;
-        let src_start = y * rw as usize;
-        canvas[dst_start..dst_start + rw as usize]
-            .copy_from_slice(&src[src_start..src_start + rw as usize]);
+    let (img, label_h) = if label_h == 0 {
+        let img = imaging::rotate(img, layout.rotation, label_w, u32::MAX);
+        let (iw, ih) = img.dimensions();
+        let length = imaging::continuous_length(iw, ih, label_w, layout.scaling);
+        (img, length)
+    } else {
+        (
+            imaging::rotate(img, layout.rotation, label_w, label_h),
+            label_h,
+        )
+    };
+    if label_h == 0 {
+        return (Vec::new(), 0, 0);
     }
+    let canvas = imaging::fit_luma(&img, label_w, label_h, layout.scaling);
     (canvas, label_w, label_h)
 }
 
+/// `config` described by family `driver` instead: its media, resolution and
+/// head width, keeping the queue's name, device and darkness. `None` for an
+/// unknown family.
+pub fn redescribe(
+    config: &ipp_printer_app::PrinterConfig,
+    driver: &str,
+) -> Option<ipp_printer_app::PrinterConfig> {
+    let mut new = config_from_family(
+        &config.name,
+        &config.display_name,
+        driver,
+        &config.device_uri,
+        &config.device_id,
+    )?;
+    new.darkness = config.darkness;
+    Some(new)
+}
+
 /// Build printer config from a driver family name.
 pub fn config_from_family(
     name: &str,
@@ -237,9 +778,7 @@ pub fn config_from_family(
     uri: &str,
     device_id: &str,
 ) -> Option<ipp_printer_app::PrinterConfig> {
-    let family = models::families()
-        .iter()
-        .find(|f| f.driver_name.to_string_lossy() == driver)?;
+    let family = models::family_by_driver(driver)?;
     let make = String::from_utf8_lossy(&family.make_and_model).into_owned();
     let media_names: Vec<String> = family
         .media_names
@@ -260,15 +799,23 @@ pub fn config_from_family(
         media_names,
         media_sizes: family.media_sizes.clone(),
         darkness: 50,
-        // We accept PWG/CUPS raster (CUPS' driverless path) and decode
-        // image/jpeg ourselves (run_jpeg_job) — the last IPP Everywhere
-        // required format.
-        document_formats: vec![
-            "image/pwg-raster".to_string(),
-            "application/vnd.cups-raster".to_string(),
-            "application/octet-stream".to_string(),
-            "image/jpeg".to_string(),
-        ],
+        // We accept PWG/CUPS raster (CUPS' driverless path), decode common
+        // image formats ourselves (run_image_job; image/jpeg is the last IPP
+        // Everywhere required format), plus our own label templates
+        // (run_template_job). image/urf is what AirPrint clients prefer
+        // (run_urf_job); SVG is rendered at native resolution (run_svg_job).
+        document_formats: [
+            "image/pwg-raster",
+            "application/vnd.cups-raster",
+            "application/octet-stream",
+            crate::urf::URF_FORMAT,
+        ]
+        .into_iter()
+        .chain(IMAGE_FORMATS.iter().map(|&(mime, _)| mime))
+        .chain([SVG_FORMAT])
+        .chain([TEMPLATE_TOML_FORMAT, TEMPLATE_JSON_FORMAT])
+        .map(str::to_string)
+        .collect(),
     })
 }
 
@@ -277,11 +824,18 @@ mod tests {
     use super::*;
     use image::{GrayImage, Luma};
 
+    /// Contain-fit, no rotation.
+    const CONTAIN: PageLayout = PageLayout {
+        media_size_hmm: None,
+        scaling: ScaleMode::Fit,
+        rotation: Rotation::R0,
+    };
+
     #[test]
     fn fit_luma_contains_and_centers() {
         // 10x10 all-black image onto a 40x30mm label (320x240 dots).
         let img = GrayImage::from_pixel(10, 10, Luma([0]));
-        let (canvas, w, h) = fit_luma(&img, [4000, 3000], 384);
+        let (canvas, w, h) = fit_luma(img, [4000, 3000], 203, 384, &CONTAIN);
         assert_eq!((w, h), (320, 240));
         assert_eq!(canvas.len(), 320 * 240);
         // Square image contain-fits to 240x240, centered at x-offset 40.
@@ -290,25 +844,195 @@ mod tests {
         assert_eq!(canvas[center], 0, "center is black content");
     }
 
+    #[test]
+    fn job_media_is_checked_against_the_loaded_roll() {
+        let roll = |width_mm, height_mm| MaterialInfo {
+            width_mm,
+            height_mm,
+            ..MaterialInfo::default()
+        };
+        assert_eq!(media_mismatch([5000, 3000], &roll(50, 30)), None);
+        assert_eq!(media_mismatch([3000, 5000], &roll(50, 30)), None);
+        // 2x1" is close enough to a 50x25 roll.
+        assert_eq!(media_mismatch([5080, 2540], &roll(50, 25)), None);
+        assert_eq!(
+            media_mismatch([5000, 3000], &roll(30, 15)).as_deref(),
+            Some("load 50x30 mm labels (loaded roll is 30x15 mm)")
+        );
+        // Continuous tape only has a width; no width means no tag data.
+        assert_eq!(media_mismatch([1200, 8000], &roll(12, 0)), None);
+        assert_eq!(
+            media_mismatch([2540, 5080], &roll(12, 0)).as_deref(),
+            Some("load 25.4x50.8 mm labels (loaded roll is 12 mm continuous)")
+        );
+        assert_eq!(media_mismatch([5000, 3000], &roll(0, 0)), None);
+    }
+
+    #[test]
+    fn tall_pages_split_at_the_label_pitch() {
+        // 25 mm labels with a 3 mm gap at 8 dots/mm: 200 rows every 224.
+        assert_eq!(label_rows(600, 200, 24), vec![0..200, 224..424, 448..600]);
+        assert_eq!(label_rows(200, 200, 24), vec![0..200]);
+        assert_eq!(label_rows(10, 4, 0), vec![0..4, 4..8, 8..10]);
+
+        assert!(is_blank(&[0, 0], 1));
+        assert!(!is_blank(&[0, 1], 1));
+        assert!(is_blank(&[0xFF, 0xF9], 8));
+        assert!(!is_blank(&[0xFF, 0x80], 8));
+        assert_eq!("labels-skip-blank".parse(), Ok(LabelSplit::LabelsSkipBlank));
+        assert!("sheet".parse::<LabelSplit>().is_err());
+    }
+
     #[test]
     fn fit_luma_caps_at_printhead_width() {
         let img = GrayImage::from_pixel(4, 4, Luma([0]));
         // 60mm label = 480 dots, capped to the 384-dot printhead.
-        let (_canvas, w, _h) = fit_luma(&img, [6000, 3000], 384);
+        let (_canvas, w, _h) = fit_luma(img, [6000, 3000], 203, 384, &CONTAIN);
         assert_eq!(w, 384);
     }
 
+    #[test]
+    fn continuous_media_takes_the_image_length() {
+        // A 12 mm tape is 96 dots wide; a 200x50 banner runs along the feed
+        // at auto rotation and comes out 96 dots across, 384 long.
+        let img = GrayImage::from_pixel(200, 50, Luma([0]));
+        let (canvas, w, h) = fit_luma(img, [1200, 0], 203, 384, &PageLayout::default());
+        assert_eq!((w, h), (96, 384));
+        assert!(canvas.iter().all(|&p| p == 0));
+    }
+
     #[test]
     fn fit_luma_white_image_stays_white() {
         let img = GrayImage::from_pixel(8, 8, Luma([255]));
-        let (canvas, _w, _h) = fit_luma(&img, [4000, 3000], 384);
+        let (canvas, _w, _h) = fit_luma(img, [4000, 3000], 203, 384, &CONTAIN);
         assert!(canvas.iter().all(|&p| p == 0xFF));
     }
 
+    #[test]
+    fn fit_luma_honours_orientation_and_scaling() {
+        // A 20x10 landscape image: black left half, white right half.
+        let img = GrayImage::from_fn(20, 10, |x, _| Luma([if x < 10 { 0 } else { 255 }]));
+        // 15x30 mm portrait label, 120x240 dots.
+        let auto = PageLayout::default();
+        let (canvas, w, h) = fit_luma(img.clone(), [1500, 3000], 203, 384, &auto);
+        assert_eq!((w, h), (120, 240));
+        // Auto-rotated clockwise, the black half ends up on top and, with
+        // matching aspect ratios, `auto` fills the whole label.
+        assert_eq!(canvas[10 * 120 + 60], 0);
+        assert_eq!(canvas[230 * 120 + 60], 0xFF);
+        assert_eq!(canvas[60 * 120], 0, "left edge is content, not padding");
+
+        // Landscape (4) is a counter-clockwise turn: black half at the bottom.
+        let landscape = PageLayout {
+            rotation: Rotation::R270,
+            ..auto
+        };
+        let (canvas, _, _) = fit_luma(img.clone(), [1500, 3000], 203, 384, &landscape);
+        assert_eq!(canvas[10 * 120 + 60], 0xFF);
+        assert_eq!(canvas[230 * 120 + 60], 0);
+
+        // `none` prints the 20x10 pixels 1:1 in the middle of the label.
+        let none = PageLayout {
+            scaling: ScaleMode::None,
+            ..CONTAIN
+        };
+        let (canvas, _, _) = fit_luma(img, [1500, 3000], 203, 384, &none);
+        assert_eq!(canvas.iter().filter(|&&p| p == 0).count(), 100);
+    }
+
+    const TAG: &str = r#"
+width_mm = 25
+height_mm = 25
+[fields.sku]
+[values]
+sku = "0042"
+[[elements]]
+type = "qr"
+x = 2
+y = 2
+w = 21
+h = 21
+data = "{sku}"
+"#;
+
+    #[test]
+    fn template_renders_at_label_size() {
+        let canvas = render_template(
+            TEMPLATE_TOML_FORMAT,
+            TAG.as_bytes(),
+            203,
+            384,
+            &[[2500, 2500]],
+        )
+        .unwrap();
+        assert_eq!((canvas.width(), canvas.height()), (200, 200));
+        assert!(canvas.data().iter().any(|&b| b != 0));
+    }
+
+    #[test]
+    fn template_rejects_media_outside_family() {
+        let err = render_template(
+            TEMPLATE_TOML_FORMAT,
+            TAG.as_bytes(),
+            203,
+            384,
+            &[[4000, 3000]],
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string().contains("not a supported media size"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn template_sizes_match_inch_media_to_the_nearest_mm() {
+        // 2x1" is 50.8x25.4 mm, which truncating would take for 50x25.
+        let tag = TAG.replace("width_mm = 25", "width_mm = 51");
+        let canvas = render_template(
+            TEMPLATE_TOML_FORMAT,
+            tag.as_bytes(),
+            203,
+            576,
+            &[[5080, 2540]],
+        )
+        .unwrap();
+        assert_eq!(canvas.width(), 408);
+    }
+
+    #[test]
+    fn image_formats_resolve_case_insensitively() {
+        assert_eq!(image_format("image/PNG"), Some(image::ImageFormat::Png));
+        assert_eq!(image_format("image/webp"), Some(image::ImageFormat::WebP));
+        assert_eq!(image_format("image/pwg-raster"), None);
+    }
+
+    #[test]
+    fn transparent_png_decodes_to_white() {
+        let rgba = image::RgbaImage::from_pixel(4, 4, image::Rgba([0, 0, 0, 0]));
+        let mut png = Vec::new();
+        image::DynamicImage::ImageRgba8(rgba)
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let luma = decode_luma(&png, image::ImageFormat::Png).unwrap();
+        assert!(luma.pixels().all(|p| p.0[0] == 0xFF));
+    }
+
+    #[test]
+    fn fit_luma_sizes_the_label_at_the_family_resolution() {
+        // A 2x1" label is 610x305 dots on a 305-dpi TP76, not 400x200.
+        let img = GrayImage::from_pixel(20, 10, Luma([0]));
+        let (canvas, w, h) = fit_luma(img.clone(), [5080, 2540], 305, 912, &CONTAIN);
+        assert_eq!((w, h), (610, 305));
+        assert_eq!(canvas.len(), 610 * 305);
+        let (_, w, h) = fit_luma(img, [5080, 2540], 203, 384, &CONTAIN);
+        assert_eq!((w, h), (384, 203), "capped at the 48 mm head");
+    }
+
     #[test]
     fn fit_luma_zero_geometry_is_empty() {
         let img = GrayImage::from_pixel(4, 4, Luma([0]));
-        let (canvas, w, h) = fit_luma(&img, [0, 0], 384);
+        let (canvas, w, h) = fit_luma(img, [0, 0], 203, 384, &CONTAIN);
         assert!(canvas.is_empty());
         assert_eq!((w, h), (0, 0));
     }
@@ -3,15 +3,21 @@
 use std::collections::{BTreeMap, HashMap};
 use std::sync::{Arc, Mutex, OnceLock};
 
+use ipp_printer_app::status::AdvertiserControl;
 use ipp_printer_app::{
     DeviceBackend, DiscoveredDevice, JobContext, JobFailure, JobOutcome, PollStatus, PrinterConfig,
     PrinterReason, PrinterRegistry, ReadyMedia, Server, ServerOptions, default_state_path,
 };
 use parking_lot::RwLock;
+use supvan_proto::identity::Sighting;
 
 use crate::ble_discover::BleCandidate;
+use crate::device_actor::Lane;
 use crate::discover::BtCandidate;
-use crate::ipp_job::{config_from_family, run_cups_raster_job};
+use crate::ipp_job::{
+    TEMPLATE_JSON_FORMAT, TEMPLATE_TOML_FORMAT, config_from_family, run_cups_raster_job,
+    run_template_job,
+};
 use crate::models;
 use crate::usb_discover::UsbCandidate;
 
@@ -36,29 +42,16 @@ fn roll_cache() -> &'static Mutex<HashMap<String, RollFingerprint>> {
 
 pub struct SupvanDeviceBackend;
 
-/// Slugify a printer-reported name into something CUPS can use as a queue
-/// name. Lowercase ASCII alphanumerics; everything else becomes a hyphen.
-fn slug(name: &str) -> String {
-    let s: String = name
-        .chars()
-        .map(|c| {
-            if c.is_ascii_alphanumeric() {
-                c.to_ascii_lowercase()
-            } else {
-                '-'
-            }
-        })
-        .collect();
-    let s: String = s
-        .split('-')
-        .filter(|p| !p.is_empty())
-        .collect::<Vec<_>>()
-        .join("-");
-    if s.is_empty() {
-        "printer".to_string()
-    } else {
-        s
-    }
+pub(crate) use supvan_proto::identity::slugify as slug;
+
+/// The driver family a printer's USB model, else its reported name, points
+/// at; recorded in the identity registry.
+pub(crate) fn family_hint(usb: Option<&UsbCandidate>, name: &str) -> String {
+    let hint = usb.map_or(name, |u| u.model_name.as_str());
+    models::family_for_model_hint(hint)
+        .driver_name
+        .to_string_lossy()
+        .into_owned()
 }
 
 #[async_trait::async_trait]
@@ -78,11 +71,21 @@ impl DeviceBackend for SupvanDeviceBackend {
         }
 
         // Collect all candidates. USB probes RD_DEV_NAME silently per device;
-        // BT pulls the firmware-reported name from BlueZ; BLE scans for
-        // E11/E12-class advertisers (no-op without the `ble` feature).
+        // BT and BLE come from the devices BlueZ already knows, without
+        // scanning, unless SUPVAN_BT_SCAN=1 asks for the active scans (BLE is
+        // a no-op without the `ble` feature).
         let usb = crate::usb_discover::list_candidates().await;
-        let bt = crate::discover::list_candidates();
-        let ble = crate::ble_discover::list_candidates().await;
+        let (bt, ble) = if crate::bluez_watch::active_scan() {
+            (
+                crate::discover::list_candidates(),
+                crate::ble_discover::list_candidates().await,
+            )
+        } else {
+            (
+                crate::bluez_watch::bt_candidates(),
+                crate::bluez_watch::ble_candidates(),
+            )
+        };
 
         // Group by printer-reported name. USB candidates carry their
         // `device_sn` (parsed from `RETURN_MAT` at offset 40); BT and BLE carry
@@ -90,13 +93,18 @@ impl DeviceBackend for SupvanDeviceBackend {
         // transports into one logical printer.
         //
         // If a USB candidate failed to surface its serial (e.g. the device
-        // was busy and RETURN_MAT didn't reply), fall back to its bus URI
-        // as the group key. A final 1-USB-only + 1-BT-only sweep merges
-        // them under the BT name to keep single-printer households tidy.
+        // was busy and RETURN_MAT didn't reply), fall back to the serial the
+        // identity registry last saw on its USB id, else its bus URI as the
+        // group key. A final 1-USB-only + 1-BT-only sweep merges them under
+        // the BT name to keep single-printer households tidy.
         type Group = (Option<UsbCandidate>, Option<BtCandidate>, Option<BleCandidate>);
         let mut by_name: BTreeMap<String, Group> = BTreeMap::new();
         for u in usb {
-            let key = u.printer_name.clone().unwrap_or_else(|| u.uri_id.clone());
+            let key = u
+                .printer_name
+                .clone()
+                .or_else(|| crate::identities::serial_for_usb(&u.uri_id))
+                .unwrap_or_else(|| u.uri_id.clone());
             by_name.entry(key).or_default().0 = Some(u);
         }
         for b in bt {
@@ -136,8 +144,24 @@ impl DeviceBackend for SupvanDeviceBackend {
                 .or_else(|| bt.as_ref().map(|_| "T50 Series".to_string()))
                 .or_else(|| ble.as_ref().map(|_| "E-Series".to_string()))
                 .unwrap_or_else(|| "T50 Series".to_string());
-            let info = format!("Supvan {model} {name}");
-            let uri = format!("supvan://{}", slug(&name));
+            // The identity registry keeps the slug stable whichever
+            // transport saw the printer first.
+            let sighting = Sighting {
+                serial: usb
+                    .as_ref()
+                    .and_then(|u| u.printer_name.clone())
+                    .or_else(|| bt.as_ref().map(|b| b.name.clone()))
+                    .or_else(|| ble.as_ref().map(|e| e.name.clone())),
+                usb: usb.as_ref().map(|u| u.uri_id.clone()),
+                bt: bt.as_ref().map(|b| b.address.clone()),
+                ble: ble.as_ref().map(|e| e.address.clone()),
+                family: Some(family_hint(usb.as_ref(), &name)),
+            };
+            let (slug, info) = match crate::identities::resolve(&sighting) {
+                Some(printer) => (printer.slug.clone(), crate::identities::label(&printer, &model)),
+                None => (slug(&name), format!("Supvan {model} {name}")),
+            };
+            let uri = format!("supvan://{slug}");
             let device_id = format!("MFG:Supvan;MDL:{model};CMD:SUPVAN;");
             log::info!(
                 "discover: emitting {uri} (usb={}, bt={}, ble={})",
@@ -147,7 +171,7 @@ impl DeviceBackend for SupvanDeviceBackend {
             );
             // Register the name → transport mapping so open_supvan can resolve it.
             crate::device::register_supvan(
-                &slug(&name),
+                &slug,
                 usb.as_ref().map(|u| u.hidraw_path.clone()),
                 bt.as_ref().map(|b| b.address.clone()),
                 ble.as_ref().map(|e| e.address.clone()),
@@ -162,7 +186,8 @@ impl DeviceBackend for SupvanDeviceBackend {
     }
 
     async fn poll_status(&self, config: &PrinterConfig) -> Option<PollStatus> {
-        let dev = crate::device::open_uri(&config.device_uri).await;
+        let dev =
+            crate::device::open_uri(&config.device_uri, &config.driver_name, Lane::Poll).await;
         let Some(dev) = dev else {
             // Device unreachable (powered off / unplugged / BT down). Report
             // OFFLINE so the framework marks us printer-state=stopped and CUPS
@@ -179,7 +204,7 @@ impl DeviceBackend for SupvanDeviceBackend {
         let mut supply_percent = None;
 
         // Material query: surfaces labels-remaining + roll-swap detection.
-        // Skipped on mock devices (dev.material() returns None).
+        // Mock devices report only a `SUPVAN_MOCK_ROLL`.
         if let Some(mat) = dev.material().await {
             let fp = RollFingerprint {
                 uuid: mat.uuid.clone(),
@@ -234,10 +259,14 @@ impl DeviceBackend for SupvanDeviceBackend {
                 } else if remaining <= MEDIA_LOW_THRESHOLD {
                     reasons |= PrinterReason::MARKER_SUPPLY_LOW;
                 }
-                // The firmware reports remaining *labels*, not a percentage, and
-                // we don't know the roll's original count. Clamp to 0–100 as a
-                // gauge: full while plenty remain, counting down near empty.
-                supply_percent = Some(remaining.min(100) as u8);
+                // The firmware reports remaining *labels*, not a percentage;
+                // the roll ledger supplies the capacity. A roll it cannot
+                // track (no tag uuid) falls back to clamping the count to
+                // 0–100: full while plenty remain, counting down near empty.
+                supply_percent = Some(match crate::rolls::observe(&config.name, &mat) {
+                    Some(roll) => roll.percent(),
+                    None => remaining.min(100) as u8,
+                });
             }
         }
 
@@ -252,7 +281,9 @@ impl DeviceBackend for SupvanDeviceBackend {
         // Map Identify-Printer to a physical beep via CHECK_DEVICE. Any action
         // keyword (display/sound/flash) triggers the same buzzer. Mock devices
         // no-op on identify.
-        if let Some(dev) = crate::device::open_uri(&config.device_uri).await {
+        if let Some(dev) =
+            crate::device::open_uri(&config.device_uri, &config.driver_name, Lane::Poll).await
+        {
             log::info!("identify {} (actions={actions:?})", config.name);
             dev.identify().await;
         }
@@ -277,8 +308,24 @@ impl DeviceBackend for SupvanDeviceBackend {
     }
 }
 
+/// The label the decoded-document paths fit onto when the job names no size
+/// and the device reports no roll: the first configured media size, else
+/// 40×30 mm (in hundredths of a millimetre).
+fn default_media_size(cfg: &ipp_printer_app::PrinterConfig) -> [i32; 2] {
+    const DEFAULT_MEDIA_SIZE_HMM: [i32; 2] = [4000, 3000];
+    cfg.media_sizes
+        .first()
+        .copied()
+        .unwrap_or(DEFAULT_MEDIA_SIZE_HMM)
+}
+
 pub async fn run_server(host: &str, port: u16) -> std::io::Result<()> {
-    models::load();
+    models::load().map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    // Answer PIN and passkey requests before discovery or a job dials a
+    // printer that is not paired yet.
+    if !crate::util::is_mock_mode() {
+        crate::pairing_agent::start();
+    }
 
     let registry: PrinterRegistry = Arc::new(RwLock::new(Vec::new()));
     let state_path = default_state_path("supvan-printer-app");
@@ -287,14 +334,18 @@ pub async fn run_server(host: &str, port: u16) -> std::io::Result<()> {
     Server::bootstrap_printers(&registry, backend.as_ref(), &state_path, config_from_family).await;
 
     prune_stale_supvan(&registry);
+    crate::identities::apply_names(&registry);
+    crate::config::apply(&registry);
     Server::persist(&registry, &state_path);
+    crate::config::spawn_reload(registry.clone(), state_path.clone());
+    crate::models_watch::spawn(registry.clone(), state_path.clone());
 
     let registry_print = registry.clone();
     let print_job = Arc::new(
         move |ctx: JobContext, raster: Arc<[u8]>, copies: u32| -> ipp_printer_app::PrintJobFuture {
             let registry_print = registry_print.clone();
             Box::pin(async move {
-                let cfg = {
+                let mut cfg = {
                     let guard = registry_print.read();
                     match guard.iter().find(|p| p.config.name == ctx.printer_name) {
                         Some(p) => p.config.clone(),
@@ -306,39 +357,91 @@ pub async fn run_server(host: &str, port: u16) -> std::io::Result<()> {
                         }
                     }
                 };
-                // image/jpeg is decoded in-process (run_jpeg_job); everything else
-                // is CUPS/PWG raster (CUPS' driverless path already rasterizes).
-                let result = if ctx.document_format == "image/jpeg" {
-                    // Fallback when the config carries no media size: 40×30 mm,
-                    // expressed in hundredths of a millimetre.
-                    const DEFAULT_MEDIA_SIZE_HMM: [i32; 2] = [4000, 3000];
-                    let media_size = cfg
-                        .media_sizes
-                        .first()
-                        .copied()
-                        .unwrap_or(DEFAULT_MEDIA_SIZE_HMM);
-                    crate::ipp_job::run_jpeg_job(
-                        &cfg.name,
-                        &cfg.device_uri,
-                        cfg.darkness,
-                        cfg.printhead_width_dots,
-                        media_size,
-                        &raster,
-                        copies,
-                    )
-                    .await
-                } else {
-                    run_cups_raster_job(
-                        &cfg.name,
-                        &cfg.device_uri,
-                        cfg.darkness,
-                        cfg.printhead_width_dots,
-                        &cfg.driver_name,
-                        &raster,
-                        copies,
-                    )
-                    .await
-                };
+                // The printer's darkness and screening from the config file
+                // stand in for the queue's and the family's.
+                let settings = crate::config::printer(&cfg.device_uri);
+                if let Some(darkness) = settings.darkness {
+                    cfg.darkness = darkness.clamp(0, 100);
+                }
+                // Screening for continuous-tone pages: the family default,
+                // overridden by the job's print-content-optimize / print-quality.
+                let mut family_dither = models::family_by_driver(&cfg.driver_name)
+                    .map(|f| f.dither.clone())
+                    .unwrap_or_default();
+                if let Some(method) = settings.dither {
+                    family_dither.method = method;
+                }
+                if let Some(curve) = settings.tone_curve {
+                    family_dither.curve = curve;
+                }
+                let job_attrs = crate::job_attrs::get(ctx.id).await;
+                let dither = job_attrs.dither(&family_dither);
+                log::info!(
+                    "job {}: dither {} on tone curve {}",
+                    ctx.id,
+                    dither.method,
+                    dither.curve
+                );
+                // Label size, scaling and rotation for decoded images and URF.
+                let layout = job_attrs.layout(&cfg.media_names, &cfg.media_sizes);
+                // Images, SVG, URF and label templates are decoded in-process;
+                // everything else is CUPS/PWG raster (CUPS' driverless path
+                // already rasterizes).
+                let format = ctx.document_format.as_str();
+                // Each job lands in the job ledger with what it printed.
+                let result = crate::job_ledger::record(&cfg.name, ctx.id, format, async {
+                    // Hold a job whose media does not match the loaded roll,
+                    // unless the client chose supvan-media-mismatch=ignore.
+                    let preflight = match layout.media_size_hmm {
+                        Some(size) if job_attrs.check_media() => {
+                            crate::ipp_job::check_loaded_media(
+                                &cfg.device_uri,
+                                &cfg.driver_name,
+                                size,
+                            )
+                            .await
+                        }
+                        _ => Ok(()),
+                    };
+                    let job = crate::ipp_job::JobContext::new(&cfg, job_attrs.trim(), copies);
+                    if let Err(f) = preflight {
+                        Err(f)
+                    } else if format == TEMPLATE_TOML_FORMAT || format == TEMPLATE_JSON_FORMAT {
+                        run_template_job(&job, &cfg.media_sizes, format, &raster).await
+                    } else if format.eq_ignore_ascii_case(crate::ipp_job::SVG_FORMAT) {
+                        crate::ipp_job::run_svg_job(&job, default_media_size(&cfg), &raster).await
+                    } else if format.eq_ignore_ascii_case(crate::urf::URF_FORMAT) {
+                        crate::ipp_job::run_urf_job(
+                            &job,
+                            &dither,
+                            &layout,
+                            default_media_size(&cfg),
+                            &raster,
+                        )
+                        .await
+                    } else if let Some(image_format) = crate::ipp_job::image_format(format) {
+                        crate::ipp_job::run_image_job(
+                            &job,
+                            &dither,
+                            &layout,
+                            default_media_size(&cfg),
+                            image_format,
+                            &raster,
+                        )
+                        .await
+                    } else {
+                        run_cups_raster_job(&job, &dither, job_attrs.label_split(), &raster).await
+                    }
+                })
+                .await;
+                match &result {
+                    // The framework reports no message for a held job.
+                    Err(f) if f.printer_reasons.is_recoverable() => {
+                        log::info!("job {}: held: {}", ctx.id, f.message);
+                        crate::job_attrs::set_state_message(ctx.id, Some(f.message.clone()));
+                    }
+                    _ => crate::job_attrs::forget(ctx.id),
+                }
                 match result {
                     Ok(()) => JobOutcome::Completed,
                     // A clearable physical condition — printer off / BT down, paper
@@ -364,18 +467,46 @@ pub async fn run_server(host: &str, port: u16) -> std::io::Result<()> {
     // for an AirPrint printer. This requires `cups-browsed` to be off — it
     // would otherwise build a broken same-host `implicitclass://` queue from
     // our advert (it's legacy; modern cupsd does driverless natively).
-    Server::run(ServerOptions {
+    //
+    // This is `Server::run` with the router wrapped by `job_attrs::layer`, so
+    // the print callback sees the job-template attributes.
+    let addr = format!("{host}:{port}");
+    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    log::info!("ipp-printer-app listening on http://{addr}");
+
+    // Advertise the DNS-SD service directly at bind time. No queue UUID to
+    // stamp (we own no queue), so there's nothing to coordinate first.
+    let advertiser: Option<Arc<dyn AdvertiserControl>> =
+        match ipp_printer_app::mdns::Advertiser::register_all(&registry, port) {
+            Ok(adv) => Some(Arc::new(adv)),
+            Err(e) => {
+                log::warn!("mdns: failed to register printers: {e}");
+                None
+            }
+        };
+    // Background status poller; withdraws/republishes adverts as devices go
+    // offline and come back.
+    let _status =
+        ipp_printer_app::status::spawn(backend.clone(), registry.clone(), advertiser.clone());
+    // Device actors outlive neither their queue nor their transports.
+    crate::device::spawn_sweep(registry.clone());
+    // USB plug/unplug and BlueZ presence changes update the transport map
+    // as they happen rather than at the next start.
+    if !crate::util::is_mock_mode() {
+        crate::hotplug::spawn(registry.clone(), advertiser.clone());
+        crate::bluez_watch::spawn(registry.clone(), advertiser.clone());
+    }
+
+    let router = Server::router(ServerOptions {
         host: host.to_string(),
         port,
         printers: registry,
         device_backend: backend,
         print_job,
         state_path,
-        // Advertise the DNS-SD service directly at bind time. No queue UUID to
-        // stamp (we own no queue), so there's nothing to coordinate first.
-        advertise_mdns: true,
-    })
-    .await
+        advertise_mdns: false,
+    });
+    axum::serve(listener, crate::job_attrs::layer(router)).await
 }
 
 /// Drop persisted entries whose URI scheme this build no longer recognises
@@ -1,22 +1,19 @@
-use std::sync::atomic::Ordering;
 use std::time::Instant;
 
 use ipp_printer_app::{JobFailure, JobOptions, PrinterHandle, PrinterReason, RasterDriver};
-use supvan_proto::bitmap::{DEFAULT_MARGIN_DOTS, center_in_printhead, raster_to_column_major};
-use supvan_proto::buffer::split_into_buffers;
-use supvan_proto::compress::compress_buffers;
+use supvan_proto::bitmap::{center_in_printhead, raster_to_column_major};
+use supvan_proto::buffer::{MediaKind, set_cut, split_into_buffers};
+use supvan_proto::caps::Capabilities;
+use supvan_proto::compress::compress_transfers;
 use supvan_proto::error::Error as ProtoError;
-use supvan_proto::speed::calc_speed;
+use supvan_proto::printer::Label;
 use supvan_proto::status::PrinterStatus;
+use supvan_render::dither::{Dither, Ditherer};
 
-use crate::dither::dither_line;
 use crate::dump::{JobDump, JobManifest, PgmAccumulator, dumps_enabled};
 use crate::mock;
 use crate::printer_device::KsDevice;
 
-/// Maximum device print density; darkness (0-100%) scales onto 0..=MAX_DENSITY.
-const MAX_DENSITY: i32 = 15;
-
 /// Poll cadence and budget while waiting for print completion
 /// (COMPLETION_POLLS × COMPLETION_POLL_INTERVAL = 30s).
 const COMPLETION_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
@@ -68,13 +65,100 @@ pub fn failure_from_status(s: &PrinterStatus, context: &str) -> JobFailure {
 }
 
 fn failure_from_proto(e: ProtoError, context: &str) -> JobFailure {
-    let reasons = match &e {
-        ProtoError::Io(_) => PrinterReason::OFFLINE,
-        _ => PrinterReason::OTHER,
+    let reasons = if e.is_link_lost() {
+        PrinterReason::OFFLINE
+    } else {
+        PrinterReason::OTHER
     };
     JobFailure::new(reasons, format!("{context}: {e}"))
 }
 
+/// The vendor `supvan-trim-whitespace` job attribute: blank rows dropped
+/// from the ends of each page on continuous media, so the tape is only as
+/// long as the content. Die-cut labels have a fixed length and are never
+/// trimmed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Trim {
+    #[default]
+    None,
+    /// Drop blank rows after the last printed row.
+    Trailing,
+    /// Drop blank rows before the first and after the last printed row.
+    Both,
+}
+
+impl std::str::FromStr for Trim {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "trailing" => Ok(Self::Trailing),
+            "both" => Ok(Self::Both),
+            other => Err(format!("unknown supvan-trim-whitespace '{other}'")),
+        }
+    }
+}
+
+/// How pages leave the printer: the loaded roll's media kind, and the
+/// whitespace trimmed on continuous media.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Feed {
+    pub media: MediaKind,
+    pub trim: Trim,
+}
+
+impl Feed {
+    /// Rows of a 1bpp page (`bpl`-byte lines) that reach the buffers. The
+    /// `margin`-row feed margins are kept around the content, as they never
+    /// reach the head; a page with nothing to print is left whole.
+    fn rows(&self, raster: &[u8], bpl: u32, height: u32, margin: u32) -> std::ops::Range<u32> {
+        if self.media != MediaKind::Continuous || self.trim == Trim::None || bpl == 0 {
+            return 0..height;
+        }
+        let inked = |y: &u32| {
+            let start = (y * bpl) as usize;
+            raster[start..start + bpl as usize].iter().any(|&b| b != 0)
+        };
+        let (Some(first), Some(last)) = ((0..height).find(inked), (0..height).rfind(inked)) else {
+            return 0..height;
+        };
+        let start = match self.trim {
+            Trim::Both => first.saturating_sub(margin),
+            _ => 0,
+        };
+        // The buffers need more rows than the two feed margins.
+        let end = (last + 1 + margin).max(start + 2 * margin + 1);
+        start..end.min(height)
+    }
+}
+
+/// Move a column-major LSB-first canvas (`bpl` bytes per column) by
+/// `(dx, dy)`: `dx` dots across the head, `dy` columns along the feed.
+/// Whatever moves off the canvas is dropped.
+fn shift_canvas(canvas: &[u8], bpl: u32, num_cols: u32, (dx, dy): (i32, i32)) -> Vec<u8> {
+    let bpl = bpl as i64;
+    let dots = bpl * 8;
+    let mut out = vec![0u8; canvas.len()];
+    for col in 0..i64::from(num_cols) {
+        let to_col = col + i64::from(dy);
+        if !(0..i64::from(num_cols)).contains(&to_col) {
+            continue;
+        }
+        for dot in 0..dots {
+            let to_dot = dot + i64::from(dx);
+            if !(0..dots).contains(&to_dot) {
+                continue;
+            }
+            let from = (col * bpl + dot / 8) as usize;
+            if canvas[from] >> (dot % 8) & 1 != 0 {
+                out[(to_col * bpl + to_dot / 8) as usize] |= 1 << (to_dot % 8);
+            }
+        }
+    }
+    out
+}
+
 pub struct KsJob {
     pub width: u32,
     pub height: u32,
@@ -84,11 +168,22 @@ pub struct KsJob {
     pub density: u8,
     pub printhead_width_dots: u32,
     pub pgm_acc: Option<PgmAccumulator>,
+    /// Screening for 8bpp pages; set after `start_job` to override the default.
+    pub dither: Dither,
+    /// Media kind and trimming; set after `start_job` for continuous rolls.
+    pub feed: Feed,
+    /// Calibration shift in printhead dots (`+x` right, `+y` down the label).
+    pub offset: (i32, i32),
+    /// Unprinted rows at each end of a label.
+    pub margin_dots: u16,
+    /// The printer family's capabilities, from the device.
+    pub caps: Capabilities,
+    ditherer: Option<Ditherer>,
 }
 
 impl KsJob {
     pub fn start(
-        _dev: &KsDevice,
+        dev: &KsDevice,
         w: u32,
         h: u32,
         bpl: u32,
@@ -107,6 +202,12 @@ impl KsJob {
             density,
             printhead_width_dots,
             pgm_acc: None,
+            dither: Dither::default(),
+            feed: Feed::default(),
+            offset: (0, 0),
+            margin_dots: dev.caps.margin_dots,
+            caps: dev.caps.clone(),
+            ditherer: None,
         })
     }
 
@@ -138,42 +239,72 @@ impl KsJob {
         if let Some(acc) = self.pgm_acc.take() {
             dump.pgm(&acc);
         }
-        dump.pbm(
+        let rows = self.feed.rows(
             &self.raster_data,
-            self.width,
-            self.height,
             self.bytes_per_line,
+            self.height,
+            u32::from(self.margin_dots),
         );
+        if rows.len() as u32 != self.height {
+            log::info!(
+                "KsJob::transfer_page: trimmed to rows {rows:?} of {}",
+                self.height
+            );
+        }
+        let height = rows.len() as u32;
+        let bpl = self.bytes_per_line as usize;
+        let raster = &self.raster_data[rows.start as usize * bpl..rows.end as usize * bpl];
+        dump.pbm(raster, self.width, height, self.bytes_per_line);
 
-        let (col_data, num_cols, _) =
-            raster_to_column_major(&self.raster_data, self.width, self.height);
-        let (canvas, canvas_bpl) =
+        let (col_data, num_cols, _) = raster_to_column_major(raster, self.width, height);
+        let (mut canvas, canvas_bpl) =
             center_in_printhead(&col_data, num_cols, self.width, self.printhead_width_dots);
+        if self.offset != (0, 0) {
+            canvas = shift_canvas(&canvas, canvas_bpl, num_cols, self.offset);
+        }
         dump.printhead_pbm(&canvas, num_cols, canvas_bpl, self.printhead_width_dots);
 
-        let buffers = split_into_buffers(
+        // The buffers need at least one row between the margins.
+        let margin = self
+            .margin_dots
+            .min((num_cols.saturating_sub(1) / 2) as u16);
+        let mut buffers = split_into_buffers(
             &canvas,
             canvas_bpl as u8,
             num_cols as u16,
-            DEFAULT_MARGIN_DOTS,
-            DEFAULT_MARGIN_DOTS,
-            self.density,
+            margin,
+            margin,
+            self.caps.header_density(self.density),
+            self.feed.media,
         );
+        if self.caps.cutter
+            && let Some(last) = buffers.last_mut()
+        {
+            set_cut(last, 1);
+        }
 
-        let (compressed, avg) = compress_buffers(&buffers)
+        let (transfers, avg) = compress_transfers(&buffers, self.caps.max_transfer_bytes)
             .map_err(|e| JobFailure::other(format!("compression: {e}")))?;
-        let speed = calc_speed(avg);
+        let label = Label {
+            transfers,
+            speed: self.caps.speeds.speed_for(avg),
+            density: self.caps.command_density(self.density),
+        };
 
+        // The transport the label actually went over: the link's own, or
+        // another after a failover mid-job.
+        let mut transport = dev.transport;
         let outcome: Result<(), JobFailure> = if let Some(ref printer) = dev.printer {
-            dev.printing.store(true, Ordering::Release);
-            let result = printer.print_compressed(&compressed, speed).await;
-            dev.printing.store(false, Ordering::Release);
-            match result {
-                Ok(()) => Ok(()),
+            match printer.print_label(&label).await {
+                Ok(used) => {
+                    transport = used;
+                    Ok(())
+                }
                 Err(ProtoError::InvalidResponse(msg)) => {
                     if let Ok(Some(s)) = printer.query_status().await {
+                        let s = self.caps.mask(s);
                         if s.has_error() {
-                            Err(failure_from_status(&s, "print_compressed"))
+                            Err(failure_from_status(&s, "print_label"))
                         } else {
                             Err(JobFailure::other(msg))
                         }
@@ -181,7 +312,7 @@ impl KsJob {
                         Err(JobFailure::other(msg))
                     }
                 }
-                Err(e) => Err(failure_from_proto(e, "print_compressed")),
+                Err(e) => Err(failure_from_proto(e, "print_label")),
             }
         } else {
             // Mock device: simulate the print delay, then check the simulator
@@ -197,6 +328,11 @@ impl KsJob {
             }
         };
 
+        if outcome.is_ok() {
+            crate::job_ledger::note_label(self.density);
+            crate::job_ledger::note_transport(transport);
+        }
+
         // Manifest reflects what really happened (real or simulated).
         let (sim_outcome, _len) = match &outcome {
             Ok(()) => ("completed".to_string(), 0usize),
@@ -205,12 +341,13 @@ impl KsJob {
         dump.manifest(&JobManifest {
             timestamp: now_iso(),
             width: self.width,
-            height: self.height,
+            height,
             bytes_per_line: self.bytes_per_line,
             density: self.density,
             printhead_width_dots: self.printhead_width_dots,
             copies: 1,
             mock: is_mock,
+            transport: transport.to_string(),
             simulated_outcome: sim_outcome,
             elapsed_ms: started.elapsed().as_millis(),
         });
@@ -231,6 +368,7 @@ impl KsJob {
                 match printer.query_status().await {
                     Ok(Some(s)) if !s.printing && !s.device_busy => {
                         log::info!("KsJob::end: complete after {i} polls");
+                        crate::job_ledger::note_odometer(s.print_count);
                         settled = true;
                         break;
                     }
@@ -245,7 +383,6 @@ impl KsJob {
             if !settled {
                 log::warn!("KsJob::end: timeout waiting for completion");
             }
-            dev.printing.store(false, Ordering::Release);
         }
     }
 }
@@ -267,18 +404,55 @@ impl RasterDriver for KsJob {
             options.bytes_per_line
         };
 
-        let darkness = printer.darkness();
-        // darkness is 0-100%; scale to 0-MAX_DENSITY, rounding to nearest.
-        let density = ((darkness * MAX_DENSITY + 50) / 100) as u8;
+        // darkness is 0-100%; scale onto the family's density range.
+        let density = dev.caps.density_for(printer.darkness());
         let printhead_width_dots = printer.printhead_width_dots();
 
         let mut ks = KsJob::start(dev, w, h, bpl, density, printhead_width_dots)?;
+        // Per-printer calibration from the config file; its margin stands in
+        // for the family's.
+        let settings = crate::config::printer(&printer.record.config.device_uri);
+        ks.offset = (settings.offset_x, settings.offset_y);
+        if let Some(margin) = settings.margin_dots {
+            ks.margin_dots = margin;
+        }
         if options.bits_per_pixel == 8 && dumps_enabled() {
             ks.pgm_acc = Some(PgmAccumulator::new(w, h));
         }
         Ok(ks)
     }
 
+    /// Pages of one job may differ in size (continuous media prints each
+    /// page at its own length); re-size the page buffer when they do.
+    fn start_page(
+        &mut self,
+        options: &JobOptions,
+        _page: u32,
+        _dev: &Self::Device,
+    ) -> Result<(), JobFailure> {
+        let bpl = if options.bits_per_pixel == 8 {
+            options.width.div_ceil(8)
+        } else {
+            options.bytes_per_line
+        };
+        if (options.width, options.height, bpl) != (self.width, self.height, self.bytes_per_line) {
+            log::info!(
+                "KsJob::start_page: {}x{}, bpl={bpl}",
+                options.width,
+                options.height
+            );
+            self.width = options.width;
+            self.height = options.height;
+            self.bytes_per_line = bpl;
+            self.raster_data = vec![0u8; (options.height * bpl) as usize];
+            self.lines_received = 0;
+            if self.pgm_acc.is_some() {
+                self.pgm_acc = Some(PgmAccumulator::new(options.width, options.height));
+            }
+        }
+        Ok(())
+    }
+
     fn write_line(&mut self, options: &JobOptions, y: u32, line: &[u8]) -> Result<(), JobFailure> {
         if options.bits_per_pixel == 8 {
             let width = options.width;
@@ -288,7 +462,11 @@ impl RasterDriver for KsJob {
             }
             let bpl_1bpp = width.div_ceil(8) as usize;
             let mut mono = vec![0u8; bpl_1bpp];
-            dither_line(input, width, y, &mut mono);
+            // Error diffusion carries state down the page; restart it per page.
+            if y == 0 || self.ditherer.is_none() {
+                self.ditherer = Some(Ditherer::new(&self.dither, width).mirrored(true));
+            }
+            self.ditherer.as_mut().unwrap().row(input, &mut mono);
             if !self.append_line(y, &mono) {
                 return Err(JobFailure::other(format!(
                     "write_line: y={y} out of bounds"
@@ -325,3 +503,45 @@ impl RasterDriver for KsJob {
         self.end(dev).await;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use supvan_proto::bitmap::DEFAULT_MARGIN_DOTS;
+
+    #[test]
+    fn continuous_pages_trim_to_their_content() {
+        // One byte per row; ink on rows 30..=40 of 100.
+        let mut raster = vec![0u8; 100];
+        raster[30..=40].fill(0x80);
+        let feed = |media, trim| Feed { media, trim };
+        let margin = DEFAULT_MARGIN_DOTS as u32;
+
+        let both = feed(MediaKind::Continuous, Trim::Both);
+        assert_eq!(both.rows(&raster, 1, 100, margin), 30 - margin..41 + margin);
+        let trailing = feed(MediaKind::Continuous, Trim::Trailing);
+        assert_eq!(trailing.rows(&raster, 1, 100, margin), 0..41 + margin);
+
+        // Die-cut labels keep their length; blank pages are left whole.
+        assert_eq!(
+            feed(MediaKind::Gap, Trim::Both).rows(&raster, 1, 100, margin),
+            0..100
+        );
+        assert_eq!(both.rows(&[0; 100], 1, 100, margin), 0..100);
+        let mut top = vec![0u8; 100];
+        top[0] = 1;
+        assert_eq!(trailing.rows(&top, 1, 100, margin), 0..2 * margin + 1);
+        assert_eq!("trailing".parse(), Ok(Trim::Trailing));
+    }
+
+    #[test]
+    fn calibration_offsets_shift_the_canvas() {
+        // Two 16-dot columns; one dot at column 0, dot 3.
+        let canvas = [0b0000_1000, 0, 0, 0];
+        assert_eq!(shift_canvas(&canvas, 2, 2, (6, 1)), [0, 0, 0, 0b10]);
+        assert_eq!(shift_canvas(&canvas, 2, 2, (-3, 0)), [1, 0, 0, 0]);
+        // Shifted off the head or past the last column: dropped.
+        assert_eq!(shift_canvas(&canvas, 2, 2, (-4, 0)), [0; 4]);
+        assert_eq!(shift_canvas(&canvas, 2, 2, (0, 2)), [0; 4]);
+    }
+}
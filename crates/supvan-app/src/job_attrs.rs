@@ -0,0 +1,1207 @@
+//! Job-template attributes the framework does not hand to the print callback.
+//!
+//! `ipp-printer-app` gives the callback only the job id and document format.
+//! [`layer`] wraps the IPP router: it reads the job-template attributes off
+//! each Print-Job / Create-Job request and, once the framework's response
+//! names the new job, files them under that job id. The print callback picks
+//! them up with [`get`] and drops them with [`forget`] when the job is done.
+//! Only the attribute groups of an `application/ipp` POST are read; the
+//! document behind them streams through unbuffered.
+//!
+//! The framework also does not know about `print-scaling` or our vendor
+//! `supvan-label-split`, `supvan-trim-whitespace` and `supvan-media-mismatch`,
+//! so the layer adds their `-supported` / `-default` attributes to
+//! Get-Printer-Attributes responses;
+//! clients only send job attributes a printer advertises. It also turns the
+//! `custom_min_` / `custom_max_` media of continuous-tape families into the
+//! media-size ranges the framework cannot express, and describes the loaded
+//! roll from the roll ledger ([`crate::rolls`]).
+//!
+//! A job the framework holds for a recoverable condition gets no
+//! `job-state-message` from it; the callback records one with
+//! [`set_state_message`] and the layer adds it to Get-Job-Attributes and
+//! Get-Jobs responses.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::Cursor;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use axum::Router;
+use axum::body::{Body, Bytes, HttpBody};
+use axum::extract::Request;
+use axum::middleware::{self, Next};
+use axum::response::Response;
+use futures::StreamExt;
+use ipp::model::Operation;
+use ipp::parser::IppParser;
+use ipp::prelude::{DelimiterTag, IppAttribute, IppAttributes, IppValue};
+use ipp::reader::IppReader;
+use ipp::value::IppName;
+use ipp_printer_app::JobId;
+use supvan_proto::ledger::{RollRecord, format_utc};
+use supvan_render::dither::{Dither, DitherMethod, ToneCurve};
+use supvan_render::imaging::{Rotation, ScaleMode};
+use tokio::sync::Notify;
+
+use crate::ipp_job::{LabelSplit, PageLayout};
+use crate::job::Trim;
+
+/// Jobs whose attributes are remembered; older entries (e.g. canceled jobs
+/// the callback never saw) are dropped first.
+const MAX_TRACKED_JOBS: usize = 256;
+
+/// How much of a request the layer reads looking for the end of its
+/// attribute groups before passing it through unread.
+const MAX_REQUEST_ATTRIBUTES_BYTES: usize = 256 * 1024;
+
+/// Largest response the layer buffers to edit.
+const MAX_RESPONSE_BYTES: usize = 4 * 1024 * 1024;
+
+/// `end-of-attributes-tag` (RFC 8010 §3.5.1).
+const END_OF_ATTRIBUTES_TAG: u8 = 0x03;
+
+/// Job-template attributes the layer advertises on the framework's behalf:
+/// name, supported keywords, default.
+const EXTRA_JOB_TEMPLATE: [(&str, &[&str], &str); 4] = [
+    // PWG 5100.13 §6.2.17.
+    (
+        "print-scaling",
+        &["auto", "auto-fit", "fill", "fit", "none"],
+        "auto",
+    ),
+    (
+        "supvan-label-split",
+        &["none", "labels", "labels-skip-blank"],
+        "none",
+    ),
+    (
+        "supvan-trim-whitespace",
+        &["none", "trailing", "both"],
+        "none",
+    ),
+    ("supvan-media-mismatch", &["hold", "ignore"], "hold"),
+];
+
+/// How long [`get`] waits for the response side of the layer to file a job.
+/// The framework spawns the print worker just before it returns the response,
+/// so this only ever covers that gap.
+const REGISTRATION_WAIT: Duration = Duration::from_secs(1);
+
+/// The job-template attributes we act on. Absent attributes mean "printer
+/// default".
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JobAttributes {
+    /// `print-quality`: 3 draft, 4 normal, 5 high.
+    pub print_quality: Option<i32>,
+    /// `print-content-optimize`: `auto`, `photo`, `graphic`, `text`,
+    /// `text-and-graphic`.
+    pub print_content_optimize: Option<String>,
+    /// `print-scaling`: `auto`, `auto-fit`, `fill`, `fit`, `none`.
+    pub print_scaling: Option<String>,
+    /// `orientation-requested`: 3 portrait, 4 landscape, 5 reverse-landscape,
+    /// 6 reverse-portrait.
+    pub orientation_requested: Option<i32>,
+    /// `media`, or `media-col`'s `media-size-name`.
+    pub media: Option<String>,
+    /// `media-col`'s `media-size`, in hundredths of a millimetre.
+    pub media_size_hmm: Option<[i32; 2]>,
+    /// `supvan-label-split`: `none`, `labels`, `labels-skip-blank`.
+    pub label_split: Option<String>,
+    /// `supvan-trim-whitespace`: `none`, `trailing`, `both`.
+    pub trim_whitespace: Option<String>,
+    /// `supvan-media-mismatch`: `hold`, `ignore`.
+    pub media_mismatch: Option<String>,
+}
+
+impl JobAttributes {
+    fn from_ipp(attributes: &IppAttributes) -> Self {
+        let mut attrs = Self::default();
+        for group in attributes.groups() {
+            for attr in group.attributes().values() {
+                match (attr.name().as_str(), attr.value()) {
+                    ("print-quality", IppValue::Enum(q)) => attrs.print_quality = Some(*q),
+                    ("print-content-optimize", IppValue::Keyword(k)) => {
+                        attrs.print_content_optimize = Some(k.as_str().to_string());
+                    }
+                    ("print-scaling", IppValue::Keyword(k)) => {
+                        attrs.print_scaling = Some(k.as_str().to_string());
+                    }
+                    ("supvan-label-split", IppValue::Keyword(k)) => {
+                        attrs.label_split = Some(k.as_str().to_string());
+                    }
+                    ("supvan-trim-whitespace", IppValue::Keyword(k)) => {
+                        attrs.trim_whitespace = Some(k.as_str().to_string());
+                    }
+                    ("supvan-media-mismatch", IppValue::Keyword(k)) => {
+                        attrs.media_mismatch = Some(k.as_str().to_string());
+                    }
+                    ("orientation-requested", IppValue::Enum(o)) => {
+                        attrs.orientation_requested = Some(*o);
+                    }
+                    ("media", IppValue::Keyword(k)) => {
+                        attrs.media.get_or_insert_with(|| k.as_str().to_string());
+                    }
+                    ("media-col", IppValue::Collection(col)) => {
+                        if let Some(IppValue::Keyword(k)) = col.get("media-size-name") {
+                            attrs.media = Some(k.as_str().to_string());
+                        }
+                        if let Some(IppValue::Collection(size)) = col.get("media-size")
+                            && let (Some(IppValue::Integer(x)), Some(IppValue::Integer(y))) =
+                                (size.get("x-dimension"), size.get("y-dimension"))
+                        {
+                            attrs.media_size_hmm = Some([*x, *y]);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        attrs
+    }
+
+    /// Screening for this job's continuous-tone pages. An explicit
+    /// `print-content-optimize` picks the method (text is cut at 50 % on a
+    /// linear curve so anti-aliased edges stay put; photos get error
+    /// diffusion on the printer's curve). Otherwise `print-quality` draft
+    /// and high step down to a plain threshold or up to Floyd–Steinberg, and
+    /// normal keeps the printer's default.
+    pub fn dither(&self, printer_default: &Dither) -> Dither {
+        let with = |method| Dither {
+            method,
+            curve: printer_default.curve.clone(),
+        };
+        match self.print_content_optimize.as_deref() {
+            Some("text") => Dither {
+                method: DitherMethod::Threshold,
+                curve: ToneCurve::linear(),
+            },
+            Some("text-and-graphic") => with(DitherMethod::Atkinson),
+            Some("graphic") => with(DitherMethod::Bayer8),
+            Some("photo") => with(DitherMethod::FloydSteinberg),
+            _ => match self.print_quality {
+                Some(3) => with(DitherMethod::Threshold),
+                Some(5) => with(DitherMethod::FloydSteinberg),
+                _ => printer_default.clone(),
+            },
+        }
+    }
+
+    /// Whether and how raster pages are sliced into labels. Unknown values
+    /// mean `none`.
+    pub fn label_split(&self) -> LabelSplit {
+        self.label_split
+            .as_deref()
+            .and_then(|k| k.parse().ok())
+            .unwrap_or_default()
+    }
+
+    /// Blank rows trimmed off pages on continuous media. Unknown values mean
+    /// `none`.
+    pub fn trim(&self) -> Trim {
+        self.trim_whitespace
+            .as_deref()
+            .and_then(|k| k.parse().ok())
+            .unwrap_or_default()
+    }
+
+    /// Whether the job's media is checked against the loaded roll before it
+    /// prints; `supvan-media-mismatch=ignore` is the deliberate override.
+    pub fn check_media(&self) -> bool {
+        self.media_mismatch.as_deref() != Some("ignore")
+    }
+
+    /// Page layout for decoded documents. `media-col`'s `media-size` wins
+    /// over a media name; names are looked up in the printer's media list
+    /// (`media_names` / `media_sizes`, index-aligned) and then read as a PWG
+    /// self-describing name (`om_custom_30x15mm`). With no orientation the
+    /// page turns to match the label.
+    pub fn layout(&self, media_names: &[String], media_sizes: &[[i32; 2]]) -> PageLayout {
+        let media_size_hmm = self.media_size_hmm.or_else(|| {
+            let name = self.media.as_deref()?;
+            media_names
+                .iter()
+                .position(|n| n == name)
+                .and_then(|i| media_sizes.get(i).copied())
+                .or_else(|| pwg_size_hmm(name))
+        });
+        let scaling = self
+            .print_scaling
+            .as_deref()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(ScaleMode::Auto);
+        // RFC 8011 §5.2.10: landscape turns the content 90° counter-clockwise.
+        let rotation = match self.orientation_requested {
+            Some(3) => Rotation::R0,
+            Some(4) => Rotation::R270,
+            Some(5) => Rotation::R90,
+            Some(6) => Rotation::R180,
+            _ => Rotation::Auto,
+        };
+        PageLayout {
+            media_size_hmm,
+            scaling,
+            rotation,
+        }
+    }
+}
+
+/// Replace the fixed `custom_min_` / `custom_max_` entries the framework
+/// lists in `media-col-database` and `media-size-supported` with one
+/// `rangeOfInteger` media size spanning them (PWG 5100.7), so clients offer
+/// any tape length in between.
+fn add_custom_size_ranges(attrs: &mut HashMap<IppName, IppAttribute>) {
+    let custom = |prefix: &str| {
+        let attr = attrs.get("media-supported")?;
+        attr.value().into_iter().find_map(|v| match v {
+            IppValue::Keyword(k) if k.as_str().starts_with(prefix) => pwg_size_hmm(k.as_str()),
+            _ => None,
+        })
+    };
+    let (Some(min), Some(max)) = (custom("custom_min_"), custom("custom_max_")) else {
+        return;
+    };
+    let dimension = |axis: usize| IppValue::RangeOfInteger {
+        min: min[axis],
+        max: max[axis],
+    };
+    let range = IppValue::Collection(BTreeMap::from([
+        (IppName::new("x-dimension").unwrap(), dimension(0)),
+        (IppName::new("y-dimension").unwrap(), dimension(1)),
+    ]));
+    let bound = |size: &BTreeMap<IppName, IppValue>| {
+        [min, max].iter().any(|[x, y]| {
+            size.get("x-dimension") == Some(&IppValue::Integer(*x))
+                && size.get("y-dimension") == Some(&IppValue::Integer(*y))
+        })
+    };
+
+    let mut replace = |name: &str, keep: &dyn Fn(&IppValue) -> bool, entry: IppValue| {
+        let Some(attr) = attrs.get_mut(name) else {
+            return;
+        };
+        let mut values: Vec<IppValue> = attr
+            .value()
+            .into_iter()
+            .filter(|v| keep(v))
+            .cloned()
+            .collect();
+        values.push(entry);
+        if let Ok(updated) = IppAttribute::with_name(name, IppValue::Array(values)) {
+            *attr = updated;
+        }
+    };
+    replace(
+        "media-size-supported",
+        &|v| !matches!(v, IppValue::Collection(size) if bound(size)),
+        range.clone(),
+    );
+    replace(
+        "media-col-database",
+        &|v| {
+            !matches!(v, IppValue::Collection(col)
+                if matches!(col.get("media-size-name"), Some(IppValue::Keyword(k))
+                    if k.as_str().starts_with("custom_m")))
+        },
+        IppValue::Collection(BTreeMap::from([(
+            IppName::new("media-size").unwrap(),
+            range,
+        )])),
+    );
+}
+
+/// The size in a PWG 5101.1 self-describing media name (`class_name_WxHmm` or
+/// `..._WxHin`), in hundredths of a millimetre.
+fn pwg_size_hmm(name: &str) -> Option<[i32; 2]> {
+    let dims = name.rsplit('_').next()?;
+    let (dims, hmm_per_unit) = if let Some(d) = dims.strip_suffix("mm") {
+        (d, 100.0)
+    } else {
+        (dims.strip_suffix("in")?, 2540.0)
+    };
+    let (w, h) = dims.split_once('x')?;
+    let hmm = |v: &str| {
+        v.parse::<f32>()
+            .ok()
+            .filter(|v| *v > 0.0)
+            .map(|v| (v * hmm_per_unit).round() as i32)
+    };
+    Some([hmm(w)?, hmm(h)?])
+}
+
+struct Registry {
+    jobs: Mutex<VecDeque<(JobId, JobAttributes)>>,
+    filed: Notify,
+    /// `job-state-message` of held jobs.
+    messages: Mutex<HashMap<JobId, String>>,
+}
+
+fn registry() -> &'static Registry {
+    static R: OnceLock<Registry> = OnceLock::new();
+    R.get_or_init(|| Registry {
+        jobs: Mutex::new(VecDeque::new()),
+        filed: Notify::new(),
+        messages: Mutex::new(HashMap::new()),
+    })
+}
+
+fn file(job_id: JobId, attrs: JobAttributes) {
+    let r = registry();
+    {
+        let mut jobs = r.jobs.lock().unwrap();
+        jobs.retain(|(id, _)| *id != job_id);
+        if jobs.len() >= MAX_TRACKED_JOBS
+            && let Some((dropped, _)) = jobs.pop_front()
+        {
+            r.messages.lock().unwrap().remove(&dropped);
+        }
+        jobs.push_back((job_id, attrs));
+    }
+    r.filed.notify_waiters();
+}
+
+fn lookup(job_id: JobId) -> Option<JobAttributes> {
+    let jobs = registry().jobs.lock().unwrap();
+    jobs.iter()
+        .find(|(id, _)| *id == job_id)
+        .map(|(_, attrs)| attrs.clone())
+}
+
+/// The attributes a job was created with, or the defaults if none were filed
+/// (server not wrapped by [`layer`], or the request did not parse).
+pub async fn get(job_id: JobId) -> JobAttributes {
+    let wait = async {
+        loop {
+            let filed = registry().filed.notified();
+            if let Some(attrs) = lookup(job_id) {
+                return attrs;
+            }
+            filed.await;
+        }
+    };
+    tokio::time::timeout(REGISTRATION_WAIT, wait)
+        .await
+        .unwrap_or_else(|_| {
+            log::debug!("job {job_id}: no job attributes filed; using printer defaults");
+            JobAttributes::default()
+        })
+}
+
+/// Drop a finished job's attributes and state message.
+pub fn forget(job_id: JobId) {
+    registry()
+        .jobs
+        .lock()
+        .unwrap()
+        .retain(|(id, _)| *id != job_id);
+    set_state_message(job_id, None);
+}
+
+/// Set (or clear) the `job-state-message` reported while a job is held.
+pub fn set_state_message(job_id: JobId, message: Option<String>) {
+    let mut messages = registry().messages.lock().unwrap();
+    match message {
+        Some(m) => messages.insert(job_id, m),
+        None => messages.remove(&job_id),
+    };
+}
+
+fn state_message(job_id: JobId) -> Option<String> {
+    registry().messages.lock().unwrap().get(&job_id).cloned()
+}
+
+/// Wrap the framework's router so job attributes reach the print callback.
+pub fn layer(router: Router) -> Router {
+    router.layer(middleware::from_fn(capture))
+}
+
+/// What the request side of [`capture`] needs the response side to do.
+enum Intercept {
+    /// File the job's attributes under the job id in the response.
+    JobCreation(JobAttributes),
+    /// Add our printer attributes; holds `requested-attributes` (empty for
+    /// all).
+    PrinterAttributes(Vec<String>),
+    /// Add held jobs' `job-state-message`; holds the job a
+    /// Get-Job-Attributes request names, and whether the client asked for
+    /// the message at all.
+    JobStatus(Option<JobId>, bool),
+}
+
+async fn capture(request: Request, next: Next) -> Response {
+    if !is_ipp_post(&request) {
+        return next.run(request).await;
+    }
+    let (parts, body) = request.into_parts();
+    // Read only as far as the end of the attribute groups; the document that
+    // follows streams through untouched.
+    let mut stream = body.into_data_stream();
+    let mut head = Vec::new();
+    let end = loop {
+        if let Some(end) = attributes_end(&head) {
+            break Some(end);
+        }
+        if head.len() > MAX_REQUEST_ATTRIBUTES_BYTES {
+            break None;
+        }
+        match stream.next().await {
+            Some(Ok(chunk)) => head.extend_from_slice(&chunk),
+            Some(Err(_)) => return next.run(Request::from_parts(parts, Body::empty())).await,
+            None => break None,
+        }
+    };
+    let head = Bytes::from(head);
+    let intercept = end.and_then(|end| intercept(&head.slice(..end)));
+    // Printer URIs end in the printer name (`/ipp/print/NAME`).
+    let path_printer = parts
+        .uri
+        .path()
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    let body = Body::from_stream(futures::stream::iter([Ok(head)]).chain(stream));
+    let response = next.run(Request::from_parts(parts, body)).await;
+    let Some(intercept) = intercept else {
+        return response;
+    };
+
+    let (mut parts, body) = response.into_parts();
+    // The responses edited here are attribute-only; anything else (an error
+    // page, or a body of unknown or outsized length) passes through.
+    if !is_ipp(&parts.headers)
+        || body
+            .size_hint()
+            .upper()
+            .is_none_or(|n| n > MAX_RESPONSE_BYTES as u64)
+    {
+        return Response::from_parts(parts, body);
+    }
+    let Ok(body) = axum::body::to_bytes(body, MAX_RESPONSE_BYTES).await else {
+        return Response::from_parts(parts, Body::empty());
+    };
+    let body = match intercept {
+        Intercept::JobCreation(attrs) => {
+            if let Some(job_id) = created_job_id(&body) {
+                log::debug!("job {job_id}: {attrs:?}");
+                file(job_id, attrs);
+            }
+            body
+        }
+        Intercept::PrinterAttributes(requested) => {
+            let body = add_printer_attributes(body, &requested, &path_printer);
+            parts.headers.remove(axum::http::header::CONTENT_LENGTH);
+            body
+        }
+        Intercept::JobStatus(job_id, true) => {
+            let body = add_state_messages(body, job_id);
+            parts.headers.remove(axum::http::header::CONTENT_LENGTH);
+            body
+        }
+        Intercept::JobStatus(_, false) => body,
+    };
+    Response::from_parts(parts, Body::from(body))
+}
+
+/// An IPP operation: a `POST` of `application/ipp`.
+fn is_ipp_post(request: &Request) -> bool {
+    request.method() == axum::http::Method::POST && is_ipp(request.headers())
+}
+
+fn is_ipp(headers: &axum::http::HeaderMap) -> bool {
+    headers
+        .get(axum::http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .is_some_and(|v| v.trim().eq_ignore_ascii_case("application/ipp"))
+}
+
+/// The length of an IPP message's header and attribute groups, through the
+/// end-of-attributes-tag, or `None` if `message` stops short of it
+/// (RFC 8010 §3.1).
+fn attributes_end(message: &[u8]) -> Option<usize> {
+    // version-number, operation-id / status-code, request-id.
+    let mut at = 8;
+    loop {
+        let tag = *message.get(at)?;
+        at += 1;
+        match tag {
+            END_OF_ATTRIBUTES_TAG => return Some(at),
+            // Other delimiter tags start a group and carry nothing else.
+            0x00..=0x0f => {}
+            // A value: name-length, name, value-length, value.
+            _ => {
+                for _ in 0..2 {
+                    let len = message.get(at..at + 2)?;
+                    at += 2 + u16::from_be_bytes([len[0], len[1]]) as usize;
+                }
+            }
+        }
+    }
+}
+
+fn parse(body: &Bytes) -> Option<(u16, IppAttributes)> {
+    // The parser wants an owned reader; cloning `Bytes` is a refcount bump.
+    let (header, attributes, _payload) = IppParser::new(IppReader::new(Cursor::new(body.clone())))
+        .parse_parts()
+        .ok()?;
+    Some((header.operation_or_status, attributes))
+}
+
+fn intercept(body: &Bytes) -> Option<Intercept> {
+    let (op, attributes) = parse(body)?;
+    if op == Operation::PrintJob as u16 || op == Operation::CreateJob as u16 {
+        Some(Intercept::JobCreation(JobAttributes::from_ipp(&attributes)))
+    } else if op == Operation::GetPrinterAttributes as u16 {
+        Some(Intercept::PrinterAttributes(requested_attributes(
+            &attributes,
+        )))
+    } else if op == Operation::GetJobAttributes as u16 || op == Operation::GetJobs as u16 {
+        // Without `requested-attributes`, Get-Jobs returns only job-uri and
+        // job-id (RFC 8011 §4.2.6.1).
+        let requested = requested_attributes(&attributes);
+        let wanted = if requested.is_empty() {
+            op == Operation::GetJobAttributes as u16
+        } else {
+            requested
+                .iter()
+                .any(|r| matches!(r.as_str(), "job-state-message" | "job-description" | "all"))
+        };
+        Some(Intercept::JobStatus(requested_job_id(&attributes), wanted))
+    } else {
+        None
+    }
+}
+
+/// The job-template attributes of a Print-Job or Create-Job request.
+#[cfg(test)]
+fn job_creation_attributes(body: &Bytes) -> Option<JobAttributes> {
+    match intercept(body)? {
+        Intercept::JobCreation(attrs) => Some(attrs),
+        Intercept::PrinterAttributes(_) | Intercept::JobStatus(..) => None,
+    }
+}
+
+/// The job a Get-Job-Attributes request names, by `job-id` or the tail of
+/// `job-uri`.
+fn requested_job_id(attributes: &IppAttributes) -> Option<JobId> {
+    let ops = attributes
+        .groups_of(DelimiterTag::OperationAttributes)
+        .next()?
+        .attributes();
+    match ops.get("job-id").map(|a| a.value()) {
+        Some(IppValue::Integer(id)) => JobId::try_from(*id).ok(),
+        _ => match ops.get("job-uri")?.value() {
+            IppValue::Uri(uri) => uri.as_str().rsplit('/').next()?.parse().ok(),
+            _ => None,
+        },
+    }
+}
+
+fn requested_attributes(attributes: &IppAttributes) -> Vec<String> {
+    let Some(attr) = attributes
+        .groups_of(DelimiterTag::OperationAttributes)
+        .find_map(|group| group.attributes().get("requested-attributes"))
+    else {
+        return Vec::new();
+    };
+    attr.value()
+        .into_iter()
+        .filter_map(|v| match v {
+            IppValue::Keyword(k) => Some(k.as_str().to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Add the [`EXTRA_JOB_TEMPLATE`] `-supported` / `-default` attributes (and
+/// their names in `job-creation-attributes-supported`) to a successful
+/// Get-Printer-Attributes response, as far as the client asked for them,
+/// along with the loaded roll's supply attributes.
+fn add_printer_attributes(body: Bytes, requested: &[String], path_printer: &str) -> Bytes {
+    let wants = |name: &str| {
+        requested.is_empty()
+            || requested
+                .iter()
+                .any(|r| r == name || r == "all" || r == "job-template")
+    };
+    let Ok(mut response) = IppParser::new(IppReader::new(Cursor::new(body.clone()))).parse() else {
+        return body;
+    };
+    if response.header().operation_or_status >= 0x0100 {
+        return body;
+    }
+    let Some(group) = response
+        .attributes_mut()
+        .groups_mut()
+        .iter_mut()
+        .find(|g| g.tag() == DelimiterTag::PrinterAttributes)
+    else {
+        return body;
+    };
+    let attrs = group.attributes_mut();
+    let mut add = |name: &str, value: IppValue| {
+        if wants(name)
+            && let Ok(attr) = IppAttribute::with_name(name, value)
+        {
+            attrs.insert(attr.name().clone(), attr);
+        }
+    };
+    for (name, supported, default) in EXTRA_JOB_TEMPLATE {
+        add(
+            &format!("{name}-supported"),
+            IppValue::Array(supported.iter().filter_map(|k| keyword(k)).collect()),
+        );
+        if let Some(default) = keyword(default) {
+            add(&format!("{name}-default"), default);
+        }
+    }
+    add_custom_size_ranges(attrs);
+    let printer = match attrs.get("printer-name").map(|a| a.value()) {
+        Some(IppValue::NameWithoutLanguage(name)) => name.to_string(),
+        _ => path_printer.to_string(),
+    };
+    if let Some(roll) = Some(printer)
+        .filter(|p| !p.is_empty())
+        .and_then(|p| crate::rolls::current(&p))
+    {
+        add_roll_attributes(attrs, requested, &roll);
+    }
+    if let Some(attr) = attrs.get_mut("job-creation-attributes-supported") {
+        let mut values: Vec<IppValue> = attr.value().into_iter().cloned().collect();
+        values.extend(
+            EXTRA_JOB_TEMPLATE
+                .iter()
+                .filter_map(|(name, ..)| keyword(name)),
+        );
+        if let Ok(updated) = IppAttribute::with_name(attr.name().as_str(), IppValue::Array(values))
+        {
+            *attr = updated;
+        }
+    }
+    response.to_bytes()
+}
+
+/// Add the recorded `job-state-message` of held jobs to a successful
+/// Get-Job-Attributes / Get-Jobs response. Job groups are matched by their
+/// `job-id`, else by the job the request named.
+fn add_state_messages(body: Bytes, requested_job: Option<JobId>) -> Bytes {
+    let Ok(mut response) = IppParser::new(IppReader::new(Cursor::new(body.clone()))).parse() else {
+        return body;
+    };
+    if response.header().operation_or_status >= 0x0100 {
+        return body;
+    }
+    let mut changed = false;
+    for group in response.attributes_mut().groups_mut().iter_mut() {
+        if group.tag() != DelimiterTag::JobAttributes {
+            continue;
+        }
+        let attrs = group.attributes_mut();
+        let job_id = match attrs.get("job-id").map(|a| a.value()) {
+            Some(IppValue::Integer(id)) => JobId::try_from(*id).ok(),
+            _ => requested_job,
+        };
+        let Some(message) = job_id.and_then(state_message) else {
+            continue;
+        };
+        if let Ok(text) = message.as_str().try_into()
+            && let Ok(attr) =
+                IppAttribute::with_name("job-state-message", IppValue::TextWithoutLanguage(text))
+        {
+            attrs.insert(attr.name().clone(), attr);
+            changed = true;
+        }
+    }
+    if changed { response.to_bytes() } else { body }
+}
+
+/// Describe the loaded roll from the roll ledger: a `printer-supply-description`
+/// with the label count, and vendor `supvan-labels-remaining`,
+/// `supvan-roll-capacity` and `supvan-roll-history` (one `TIME COUNT` text
+/// per change in the count since the roll was first seen).
+fn add_roll_attributes(
+    attrs: &mut HashMap<IppName, IppAttribute>,
+    requested: &[String],
+    roll: &RollRecord,
+) {
+    let wants = |name: &str| {
+        requested.is_empty()
+            || requested
+                .iter()
+                .any(|r| r == name || r == "all" || r == "printer-description")
+    };
+    let text = |s: String| {
+        s.as_str()
+            .try_into()
+            .ok()
+            .map(IppValue::TextWithoutLanguage)
+    };
+    let size = if roll.height_mm == 0 {
+        format!("{} mm continuous", roll.width_mm)
+    } else {
+        format!("{}x{} mm", roll.width_mm, roll.height_mm)
+    };
+    let history = std::iter::once(&roll.first)
+        .chain(&roll.history)
+        .filter_map(|s| text(format!("{} {}", format_utc(s.at), s.remaining)))
+        .collect();
+    let values = [
+        (
+            "printer-supply-description",
+            text(format!(
+                "Label Stock {size}: {} of {} labels",
+                roll.remaining(),
+                roll.capacity
+            ))
+            .map(|t| IppValue::Array(vec![t])),
+        ),
+        (
+            "supvan-labels-remaining",
+            Some(IppValue::Integer(roll.remaining() as i32)),
+        ),
+        (
+            "supvan-roll-capacity",
+            Some(IppValue::Integer(roll.capacity as i32)),
+        ),
+        ("supvan-roll-history", Some(IppValue::Array(history))),
+    ];
+    for (name, value) in values {
+        if wants(name)
+            && let Some(value) = value
+            && let Ok(attr) = IppAttribute::with_name(name, value)
+        {
+            attrs.insert(attr.name().clone(), attr);
+        }
+    }
+}
+
+fn keyword(k: &str) -> Option<IppValue> {
+    Some(IppValue::Keyword(k.try_into().ok()?))
+}
+
+/// The `job-id` of a successful job-creation response.
+fn created_job_id(body: &Bytes) -> Option<JobId> {
+    let (status, attributes) = parse(body)?;
+    if status >= 0x0100 {
+        return None;
+    }
+    attributes
+        .groups()
+        .iter()
+        .find_map(|group| match group.attributes().get("job-id")?.value() {
+            IppValue::Integer(id) => JobId::try_from(*id).ok(),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ipp::prelude::{
+        DelimiterTag, IppAttribute, IppAttributeGroup, IppRequestResponse, IppVersion,
+    };
+
+    fn request(op: Operation, attrs: &[(&str, IppValue)]) -> Bytes {
+        let mut req = IppRequestResponse::new(IppVersion::v1_1(), op, None).unwrap();
+        for (name, value) in attrs {
+            req.attributes_mut().add(
+                DelimiterTag::JobAttributes,
+                IppAttribute::with_name(*name, value.clone()).unwrap(),
+            );
+        }
+        req.to_bytes()
+    }
+
+    #[test]
+    fn reads_job_template_attributes_from_job_creation() {
+        let body = request(
+            Operation::PrintJob,
+            &[
+                ("print-quality", IppValue::Enum(5)),
+                (
+                    "print-content-optimize",
+                    IppValue::Keyword("photo".try_into().unwrap()),
+                ),
+            ],
+        );
+        let attrs = job_creation_attributes(&body).unwrap();
+        assert_eq!(attrs.print_quality, Some(5));
+        assert_eq!(attrs.print_content_optimize.as_deref(), Some("photo"));
+
+        let body = request(Operation::GetPrinterAttributes, &[]);
+        assert!(job_creation_attributes(&body).is_none());
+    }
+
+    #[test]
+    fn finds_the_end_of_the_attributes_ahead_of_the_document() {
+        let attrs = request(Operation::PrintJob, &[("print-quality", IppValue::Enum(5))]);
+        let mut message = attrs.to_vec();
+        message.extend_from_slice(b"%PDF-1.7 document");
+        assert_eq!(attributes_end(&message), Some(attrs.len()));
+        for short in [0, 8, 12, attrs.len() - 1] {
+            assert_eq!(attributes_end(&message[..short]), None);
+        }
+        let job = job_creation_attributes(&Bytes::from(message).slice(..attrs.len()));
+        assert_eq!(job.unwrap().print_quality, Some(5));
+    }
+
+    #[test]
+    fn reads_job_id_from_response() {
+        let mut resp = IppRequestResponse::new_response(
+            IppVersion::v1_1(),
+            ipp::model::StatusCode::SuccessfulOk,
+            1,
+        )
+        .unwrap();
+        resp.attributes_mut().add(
+            DelimiterTag::JobAttributes,
+            IppAttribute::with_name("job-id", IppValue::Integer(42)).unwrap(),
+        );
+        assert_eq!(created_job_id(&resp.to_bytes()), Some(42));
+    }
+
+    #[test]
+    fn content_optimize_beats_quality() {
+        let default = Dither::default();
+        let attrs = |quality, content: Option<&str>| JobAttributes {
+            print_quality: quality,
+            print_content_optimize: content.map(str::to_string),
+            ..Default::default()
+        };
+        assert_eq!(attrs(None, None).dither(&default), default);
+        assert_eq!(attrs(Some(4), Some("auto")).dither(&default), default);
+        assert_eq!(
+            attrs(Some(5), None).dither(&default).method,
+            DitherMethod::FloydSteinberg
+        );
+        assert_eq!(
+            attrs(Some(3), None).dither(&default).method,
+            DitherMethod::Threshold
+        );
+        let text = attrs(Some(5), Some("text")).dither(&default);
+        assert_eq!(text.method, DitherMethod::Threshold);
+        assert_eq!(text.curve, ToneCurve::linear());
+        let photo = attrs(Some(3), Some("photo")).dither(&default);
+        assert_eq!(photo.method, DitherMethod::FloydSteinberg);
+        assert_eq!(photo.curve, default.curve);
+    }
+
+    #[test]
+    fn reads_media_col_and_layout_attributes() {
+        let size = IppValue::Collection(
+            [
+                ("x-dimension".try_into().unwrap(), IppValue::Integer(3000)),
+                ("y-dimension".try_into().unwrap(), IppValue::Integer(1500)),
+            ]
+            .into(),
+        );
+        let media_col = IppValue::Collection([("media-size".try_into().unwrap(), size)].into());
+        let body = request(
+            Operation::PrintJob,
+            &[
+                ("media-col", media_col),
+                (
+                    "print-scaling",
+                    IppValue::Keyword("fill".try_into().unwrap()),
+                ),
+                ("orientation-requested", IppValue::Enum(4)),
+            ],
+        );
+        let layout = job_creation_attributes(&body).unwrap().layout(&[], &[]);
+        assert_eq!(
+            layout,
+            PageLayout {
+                media_size_hmm: Some([3000, 1500]),
+                scaling: ScaleMode::Fill,
+                rotation: Rotation::R270,
+            }
+        );
+    }
+
+    #[test]
+    fn media_names_resolve_through_the_printer_then_pwg() {
+        let names = ["om_supvan-40x30_40x30mm".to_string()];
+        let sizes = [[4000, 3000]];
+        let layout = |media: &str| {
+            JobAttributes {
+                media: Some(media.to_string()),
+                ..Default::default()
+            }
+            .layout(&names, &sizes)
+            .media_size_hmm
+        };
+        assert_eq!(layout("om_supvan-40x30_40x30mm"), Some([4000, 3000]));
+        assert_eq!(layout("om_roll_30x15mm"), Some([3000, 1500]));
+        assert_eq!(layout("na_index-4x6_4x6in"), Some([10160, 15240]));
+        assert_eq!(layout("roll-max"), None);
+
+        let defaults = JobAttributes::default().layout(&names, &sizes);
+        assert_eq!(defaults, PageLayout::default());
+    }
+
+    #[test]
+    fn advertises_print_scaling() {
+        let mut resp = IppRequestResponse::new_response(
+            IppVersion::v1_1(),
+            ipp::model::StatusCode::SuccessfulOk,
+            1,
+        )
+        .unwrap();
+        resp.attributes_mut().add(
+            DelimiterTag::PrinterAttributes,
+            IppAttribute::with_name(
+                "job-creation-attributes-supported",
+                IppValue::Array(vec![keyword("copies").unwrap()]),
+            )
+            .unwrap(),
+        );
+        let printer_attrs = |body: Bytes| {
+            let (_, attrs) = parse(&body).unwrap();
+            attrs
+                .groups_of(DelimiterTag::PrinterAttributes)
+                .next()
+                .unwrap()
+                .attributes()
+                .clone()
+        };
+
+        let all = printer_attrs(add_printer_attributes(resp.to_bytes(), &[], ""));
+        assert_eq!(
+            all["print-scaling-default"].value(),
+            &keyword("auto").unwrap()
+        );
+        assert_eq!(
+            all["supvan-label-split-default"].value(),
+            &keyword("none").unwrap()
+        );
+        let creation: Vec<_> = all["job-creation-attributes-supported"]
+            .value()
+            .into_iter()
+            .cloned()
+            .collect();
+        assert!(creation.contains(&keyword("print-scaling").unwrap()));
+        assert!(creation.contains(&keyword("supvan-label-split").unwrap()));
+        assert!(creation.contains(&keyword("supvan-trim-whitespace").unwrap()));
+
+        let only = printer_attrs(add_printer_attributes(
+            resp.to_bytes(),
+            &["printer-state".to_string()],
+            "",
+        ));
+        assert!(!only.contains_key("print-scaling-supported"));
+    }
+
+    #[test]
+    fn custom_media_bounds_become_size_ranges() {
+        let size = |[x, y]: [i32; 2]| {
+            IppValue::Collection(BTreeMap::from([
+                (IppName::new("x-dimension").unwrap(), IppValue::Integer(x)),
+                (IppName::new("y-dimension").unwrap(), IppValue::Integer(y)),
+            ]))
+        };
+        let media = [
+            ("om_40x30mm_40x30mm", [4000, 3000]),
+            ("custom_min_12x10mm", [1200, 1000]),
+            ("custom_max_48x500mm", [4800, 50000]),
+        ];
+        let mut resp = IppRequestResponse::new_response(
+            IppVersion::v1_1(),
+            ipp::model::StatusCode::SuccessfulOk,
+            1,
+        )
+        .unwrap();
+        let attrs = [
+            (
+                "media-supported",
+                media.iter().map(|(n, _)| keyword(n).unwrap()).collect(),
+            ),
+            (
+                "media-size-supported",
+                media.iter().map(|(_, s)| size(*s)).collect(),
+            ),
+            (
+                "media-col-database",
+                media
+                    .iter()
+                    .map(|(n, s)| {
+                        IppValue::Collection(BTreeMap::from([
+                            (IppName::new("media-size").unwrap(), size(*s)),
+                            (
+                                IppName::new("media-size-name").unwrap(),
+                                keyword(n).unwrap(),
+                            ),
+                        ]))
+                    })
+                    .collect(),
+            ),
+        ];
+        for (name, values) in attrs {
+            resp.attributes_mut().add(
+                DelimiterTag::PrinterAttributes,
+                IppAttribute::with_name(name, IppValue::Array(values)).unwrap(),
+            );
+        }
+
+        let body = add_printer_attributes(resp.to_bytes(), &[], "");
+        let (_, attrs) = parse(&body).unwrap();
+        let printer = attrs
+            .groups_of(DelimiterTag::PrinterAttributes)
+            .next()
+            .unwrap()
+            .attributes();
+        let range = IppValue::Collection(BTreeMap::from([
+            (
+                IppName::new("x-dimension").unwrap(),
+                IppValue::RangeOfInteger {
+                    min: 1200,
+                    max: 4800,
+                },
+            ),
+            (
+                IppName::new("y-dimension").unwrap(),
+                IppValue::RangeOfInteger {
+                    min: 1000,
+                    max: 50000,
+                },
+            ),
+        ]));
+        let values =
+            |name: &str| -> Vec<IppValue> { printer[name].value().into_iter().cloned().collect() };
+        assert_eq!(
+            values("media-size-supported"),
+            vec![size([4000, 3000]), range.clone()]
+        );
+        let database = values("media-col-database");
+        assert_eq!(database.len(), 2);
+        assert_eq!(
+            database[1],
+            IppValue::Collection(BTreeMap::from([(
+                IppName::new("media-size").unwrap(),
+                range
+            )]))
+        );
+    }
+
+    #[tokio::test]
+    async fn get_waits_for_the_response_to_file_the_job() {
+        let attrs = JobAttributes {
+            print_quality: Some(5),
+            ..Default::default()
+        };
+        let waiter = tokio::spawn(get(9001));
+        tokio::task::yield_now().await;
+        file(9001, attrs.clone());
+        assert_eq!(waiter.await.unwrap(), attrs);
+        forget(9001);
+        assert!(lookup(9001).is_none());
+    }
+
+    #[test]
+    fn held_jobs_report_their_state_message() {
+        let mut req =
+            IppRequestResponse::new(IppVersion::v1_1(), Operation::GetJobAttributes, None).unwrap();
+        req.attributes_mut().add(
+            DelimiterTag::OperationAttributes,
+            IppAttribute::with_name("job-id", IppValue::Integer(9002)).unwrap(),
+        );
+        let Some(Intercept::JobStatus(job_id, true)) = intercept(&req.to_bytes()) else {
+            panic!("Get-Job-Attributes wants the job's state message");
+        };
+        assert_eq!(job_id, Some(9002));
+        // Get-Jobs only returns job-uri and job-id unless asked.
+        let body = request(Operation::GetJobs, &[]);
+        assert!(matches!(
+            intercept(&body),
+            Some(Intercept::JobStatus(None, false))
+        ));
+
+        set_state_message(9002, Some("load 50x30 mm labels".into()));
+        let mut resp = IppRequestResponse::new_response(
+            IppVersion::v1_1(),
+            ipp::model::StatusCode::SuccessfulOk,
+            1,
+        )
+        .unwrap();
+        for id in [9002, 9003] {
+            // `add` would merge into one group; Get-Jobs has one per job.
+            let mut group = IppAttributeGroup::new(DelimiterTag::JobAttributes);
+            let attr = IppAttribute::with_name("job-id", IppValue::Integer(id)).unwrap();
+            group.attributes_mut().insert(attr.name().clone(), attr);
+            resp.attributes_mut().groups_mut().push(group);
+        }
+        let body = add_state_messages(resp.to_bytes(), job_id);
+        let parsed = IppParser::new(IppReader::new(Cursor::new(body)))
+            .parse()
+            .unwrap();
+        let messages: Vec<_> = parsed
+            .attributes()
+            .groups_of(DelimiterTag::JobAttributes)
+            .map(|g| {
+                g.attributes()
+                    .get("job-state-message")
+                    .map(|a| a.value().to_string())
+            })
+            .collect();
+        assert_eq!(messages, [Some("load 50x30 mm labels".into()), None]);
+
+        forget(9002);
+        assert!(state_message(9002).is_none());
+    }
+
+    #[test]
+    fn media_check_can_be_overridden() {
+        assert!(JobAttributes::default().check_media());
+        let body = request(
+            Operation::PrintJob,
+            &[(
+                "supvan-media-mismatch",
+                IppValue::Keyword("ignore".try_into().unwrap()),
+            )],
+        );
+        assert!(!job_creation_attributes(&body).unwrap().check_media());
+    }
+
+    #[test]
+    fn loaded_roll_describes_the_supply() {
+        let sample = |at, remaining| supvan_proto::ledger::Sample { at, remaining };
+        let roll = RollRecord {
+            uuid: "A".into(),
+            code: "SKU1".into(),
+            width_mm: 40,
+            height_mm: 30,
+            printer: "t50".into(),
+            capacity: 500,
+            first: sample(1_792_373_707, 500),
+            history: vec![sample(1_792_373_767, 375)],
+            last_seen: 1_792_373_767,
+        };
+        let mut attrs = HashMap::new();
+        add_roll_attributes(&mut attrs, &[], &roll);
+        assert_eq!(
+            attrs["printer-supply-description"].value().to_string(),
+            "[Label Stock 40x30 mm: 375 of 500 labels]"
+        );
+        assert_eq!(
+            attrs["supvan-labels-remaining"].value(),
+            &IppValue::Integer(375)
+        );
+        assert_eq!(
+            attrs["supvan-roll-capacity"].value(),
+            &IppValue::Integer(500)
+        );
+        let history: Vec<_> = attrs["supvan-roll-history"]
+            .value()
+            .into_iter()
+            .map(|v| v.to_string())
+            .collect();
+        assert_eq!(
+            history,
+            ["2026-10-19T01:35:07Z 500", "2026-10-19T01:36:07Z 375"]
+        );
+
+        let mut attrs = HashMap::new();
+        add_roll_attributes(&mut attrs, &["printer-state".to_string()], &roll);
+        assert!(attrs.is_empty());
+    }
+}
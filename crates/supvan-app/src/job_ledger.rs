@@ -0,0 +1,168 @@
+//! Records each print job in the job ledger ([`supvan_proto::ledger`]).
+//!
+//! The print callback wraps a job in [`record`], which keeps a per-job tally
+//! in a task-local while the job path runs. The parts of the pipeline that
+//! learn something add to it as they go — [`KsJob`] counts the labels it
+//! transfers and reads the odometer when the device settles, the device
+//! handle notes its transport and the roll it reports — so none of the
+//! `run_*` signatures change. Outside a [`record`] scope, as in status polls,
+//! the notes do nothing.
+//!
+//! [`KsJob`]: crate::job::KsJob
+
+use std::cell::RefCell;
+use std::path::Path;
+use std::time::Instant;
+
+use ipp_printer_app::{JobFailure, JobId};
+use supvan_proto::ledger::{self, JobRecord, Outcome};
+
+#[derive(Default)]
+struct Tally {
+    labels: u32,
+    density: Option<u8>,
+    roll: Option<String>,
+    transport: Option<&'static str>,
+    odometer: Option<u32>,
+}
+
+tokio::task_local! {
+    static TALLY: RefCell<Tally>;
+}
+
+fn note(f: impl FnOnce(&mut Tally)) {
+    let _ = TALLY.try_with(|t| f(&mut t.borrow_mut()));
+}
+
+/// A label went to the printer at `density`.
+pub fn note_label(density: u8) {
+    note(|t| {
+        t.labels += 1;
+        t.density = Some(density);
+    });
+}
+
+/// The job's device was opened, or its last label printed, over `transport`.
+pub fn note_transport(transport: &'static str) {
+    note(|t| t.transport = Some(transport));
+}
+
+/// The device reported the roll with tag `uuid` as loaded.
+pub fn note_roll(uuid: &str) {
+    if !uuid.is_empty() {
+        note(|t| t.roll = Some(uuid.to_string()));
+    }
+}
+
+/// The device's lifetime print counter after the job.
+pub fn note_odometer(count: u16) {
+    note(|t| t.odometer = Some(u32::from(count)));
+}
+
+/// Run a job and append its [`JobRecord`]. A held attempt that fed no labels
+/// is not recorded: the framework retries it every few seconds, and the
+/// attempt that prints is.
+pub async fn record(
+    printer: &str,
+    job_id: JobId,
+    document_format: &str,
+    job: impl Future<Output = Result<(), JobFailure>>,
+) -> Result<(), JobFailure> {
+    record_to(&ledger::jobs_path(), printer, job_id, document_format, job).await
+}
+
+async fn record_to(
+    path: &Path,
+    printer: &str,
+    job_id: JobId,
+    document_format: &str,
+    job: impl Future<Output = Result<(), JobFailure>>,
+) -> Result<(), JobFailure> {
+    let at = ledger::unix_now();
+    let started = Instant::now();
+    let (result, tally) = TALLY
+        .scope(RefCell::new(Tally::default()), async {
+            let result = job.await;
+            (result, TALLY.with(|t| t.take()))
+        })
+        .await;
+    let (outcome, message) = match &result {
+        Ok(()) => (Outcome::Completed, String::new()),
+        Err(f) if f.printer_reasons.is_recoverable() => (Outcome::Held, f.message.clone()),
+        Err(f) => (Outcome::Failed, f.message.clone()),
+    };
+    if outcome == Outcome::Held && tally.labels == 0 {
+        return result;
+    }
+    let record = JobRecord {
+        at,
+        printer: printer.to_string(),
+        job_id: Some(job_id),
+        document_format: document_format.to_string(),
+        labels: tally.labels,
+        roll: tally.roll,
+        density: tally.density,
+        transport: tally.transport.map(str::to_string),
+        elapsed_ms: started.elapsed().as_millis() as u64,
+        outcome,
+        message,
+        odometer: tally.odometer,
+    };
+    if let Err(e) = ledger::append_job(path, &record) {
+        log::warn!("job ledger {}: {e}", path.display());
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ipp_printer_app::PrinterReason;
+
+    #[tokio::test]
+    async fn jobs_record_what_the_pipeline_noted() {
+        let dir = std::env::temp_dir().join(format!("supvan-job-ledger-{}", std::process::id()));
+        let path = dir.join("jobs.jsonl");
+        let result = record_to(&path, "t50", 7, "image/png", async {
+            note_transport("usb");
+            note_roll("A");
+            note_label(8);
+            note_label(8);
+            note_odometer(1234);
+            Ok(())
+        })
+        .await;
+        assert!(result.is_ok());
+        // A held attempt that printed nothing is left out.
+        let held = record_to(&path, "t50", 8, "image/png", async {
+            Err(JobFailure::new(
+                PrinterReason::MEDIA_NEEDED,
+                "load 50x30 mm labels",
+            ))
+        })
+        .await;
+        assert!(held.is_err());
+        let failed = record_to(&path, "t50", 9, "image/png", async {
+            Err(JobFailure::other("bad document"))
+        })
+        .await;
+        assert!(failed.is_err());
+        // Notes outside a job are dropped.
+        note_label(8);
+
+        let jobs = ledger::load_jobs(&path).unwrap();
+        assert_eq!(jobs.len(), 2);
+        let job = &jobs[0];
+        assert_eq!(job.job_id, Some(7));
+        assert_eq!(
+            (job.labels, job.density, job.odometer),
+            (2, Some(8), Some(1234))
+        );
+        assert_eq!(job.roll.as_deref(), Some("A"));
+        assert_eq!(job.transport.as_deref(), Some("usb"));
+        assert_eq!(job.outcome, Outcome::Completed);
+        assert_eq!((jobs[1].outcome, jobs[1].labels), (Outcome::Failed, 0));
+        assert_eq!(jobs[1].message, "bad document");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
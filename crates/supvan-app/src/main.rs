@@ -3,39 +3,72 @@
 //! Wires the `ipp-printer-app` framework (the IPP/HTTP server + job model) to
 //! the Supvan device layer (`supvan-proto`): discovers USB + Bluetooth printers
 //! and unifies them into one `supvan://` device, decodes incoming jobs
-//! (PWG/CUPS raster or `image/jpeg`) to the printhead bitmap, and drives the
+//! (PWG/CUPS raster; JPEG, PNG, GIF, BMP, TIFF or WebP; `image/urf`; SVG;
+//! TOML or JSON label templates) to the printhead bitmap, and drives the
 //! transfer. An in-process registrar auto-creates the direct CUPS queue and
 //! coexists with `cups-browsed` via a matching mDNS `UUID=` key. The binary
-//! takes no arguments; it is configured via `SUPVAN_*` environment variables.
+//! takes no arguments; it is configured by `printer-app.toml` (see
+//! [`config`]) and `SUPVAN_*` environment variables, which take precedence.
 
 mod battery_provider;
 mod ble_discover;
+mod bluez_watch;
+mod config;
 mod device;
+mod device_actor;
 mod discover;
-mod dither;
 mod dump;
+mod hotplug;
+mod identities;
 mod ipp_job;
 mod ipp_server;
 mod job;
+mod job_attrs;
+mod job_ledger;
 mod mock;
 mod models;
+mod models_watch;
+mod pairing_agent;
 mod printer_device;
+mod rolls;
+mod urf;
 mod usb_discover;
 mod util;
 
 use std::env;
 
-#[tokio::main]
-async fn main() {
+fn main() {
     let _ = env_logger::try_init();
 
-    let host = env::var("SUPVAN_HOST").unwrap_or_else(|_| "0.0.0.0".into());
+    if let Err(e) = config::load() {
+        log::error!("config: {e}");
+        std::process::exit(1);
+    }
+    let config = config::get();
+    let host = env::var("SUPVAN_HOST")
+        .ok()
+        .or_else(|| config.server.host.clone())
+        .unwrap_or_else(|| "0.0.0.0".into());
     let port: u16 = env::var("SUPVAN_PORT")
         .ok()
         .and_then(|s| s.parse().ok())
+        .or(config.server.port)
         .unwrap_or(8631);
+    // The framework's status poller reads its cadence from the environment.
+    if let Some(secs) = config.server.poll_interval_secs
+        && env::var_os("IPP_PRINTER_APP_POLL_SECS").is_none()
+    {
+        // SAFETY: no other thread exists yet; the runtime starts below.
+        unsafe { env::set_var("IPP_PRINTER_APP_POLL_SECS", secs.to_string()) };
+    }
+    // Before the runtime's threads start, so they inherit the mask.
+    config::block_sighup();
 
-    if let Err(e) = ipp_server::run_server(&host, port).await {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("tokio runtime");
+    if let Err(e) = runtime.block_on(ipp_server::run_server(&host, port)) {
         log::error!("server error: {e}");
         std::process::exit(1);
     }
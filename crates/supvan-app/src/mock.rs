@@ -10,6 +10,7 @@
 //! | `SUPVAN_MOCK_STICKY` | comma-separated reason tokens for `printer-state-reasons` |
 //! | `SUPVAN_MOCK_UNREACHABLE=1` | the device can't be opened (simulates powered-off / unplugged): `poll_status` reports OFFLINE and jobs are held |
 //! | `SUPVAN_MOCK_RECOVER_AFTER_MS` | sticky reasons AND unreachability auto-clear after N ms from server start |
+//! | `SUPVAN_MOCK_ROLL` | loaded roll as `WxH[:N]` mm (`H` 0 for continuous, `N` labels remaining), reported by `KsDevice::material`; a second, comma-separated roll is swapped in at the recover deadline |
 //!
 //! Tokens (parser is case-insensitive on the hyphenated form):
 //! `media-empty`, `label-not-installed`, `media-jam`, `label-rw-error`,
@@ -20,7 +21,7 @@ use std::sync::{Mutex, OnceLock};
 use std::time::{Duration, Instant};
 
 use ipp_printer_app::{JobFailure, PrinterReason};
-use supvan_proto::status::PrinterStatus;
+use supvan_proto::status::{MaterialInfo, PrinterStatus};
 
 use crate::job::{failure_from_status, reasons_from_status};
 
@@ -49,6 +50,8 @@ pub struct MockController {
     /// When true the device can't be opened at all (simulates powered-off /
     /// unplugged hardware), until `sticky_until` elapses.
     unreachable: bool,
+    /// Loaded roll before and after `sticky_until`.
+    rolls: [Option<MaterialInfo>; 2],
 }
 
 pub fn controller() -> &'static MockController {
@@ -67,10 +70,12 @@ impl MockController {
                 .ok()
                 .as_deref(),
             std::env::var("SUPVAN_MOCK_UNREACHABLE").ok().as_deref(),
+            std::env::var("SUPVAN_MOCK_ROLL").ok().as_deref(),
             Instant::now(),
         )
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn new(
         delay_ms: Option<&str>,
         fail_repeat: Option<&str>,
@@ -78,6 +83,7 @@ impl MockController {
         sticky_tokens: Option<&str>,
         recover_ms: Option<&str>,
         unreachable: Option<&str>,
+        roll: Option<&str>,
         started_at: Instant,
     ) -> Self {
         let delay = delay_ms
@@ -93,6 +99,9 @@ impl MockController {
         let sticky_until = recover_ms
             .and_then(|s| s.parse::<u64>().ok())
             .map(|ms| started_at + Duration::from_millis(ms));
+        let mut rolls = roll.unwrap_or_default().split(',').map(parse_roll);
+        let before = rolls.next().flatten();
+        let after = rolls.next().flatten().or_else(|| before.clone());
 
         Self {
             delay,
@@ -102,6 +111,7 @@ impl MockController {
             sticky_reasons: sticky.reasons,
             sticky_until,
             unreachable: unreachable == Some("1"),
+            rolls: [before, after],
         }
     }
 
@@ -117,6 +127,11 @@ impl MockController {
         self.unreachable && !self.past_recover_deadline()
     }
 
+    /// The roll `SUPVAN_MOCK_ROLL` reports as loaded, if any.
+    pub fn material(&self) -> Option<MaterialInfo> {
+        self.rolls[usize::from(self.past_recover_deadline())].clone()
+    }
+
     pub fn delay(&self) -> Duration {
         self.delay
     }
@@ -143,6 +158,29 @@ impl MockController {
     }
 }
 
+/// `WxH` in millimetres, the way the RFID tag reports a roll, optionally
+/// with `:N` labels remaining. Each size gets its own tag uuid.
+fn parse_roll(s: &str) -> Option<MaterialInfo> {
+    let (size, remaining) = match s.trim().split_once(':') {
+        Some((size, n)) => (size, Some(n.parse().ok()?)),
+        None => (s.trim(), None),
+    };
+    let (w, h) = size.split_once(['x', 'X'])?;
+    let roll = MaterialInfo {
+        uuid: format!("MOCK{}", size.to_ascii_uppercase()),
+        code: "MOCK".into(),
+        width_mm: w.parse().ok()?,
+        height_mm: h.parse().ok()?,
+        gap_mm: 3,
+        remaining,
+        ..MaterialInfo::default()
+    };
+    if roll.is_continuous() {
+        return Some(MaterialInfo { gap_mm: 0, ..roll });
+    }
+    Some(roll)
+}
+
 fn parse_status(s: &str) -> ParsedStatus {
     // Set the backing `PrinterStatus` flags from tokens, then derive the IPP
     // reasons once via the shared mapping so the mock can't drift from the real
@@ -180,7 +218,16 @@ mod tests {
         sticky: Option<&str>,
         recover_ms: Option<&str>,
     ) -> MockController {
-        MockController::new(None, repeat, fail, sticky, recover_ms, None, Instant::now())
+        MockController::new(
+            None,
+            repeat,
+            fail,
+            sticky,
+            recover_ms,
+            None,
+            None,
+            Instant::now(),
+        )
     }
 
     #[test]
@@ -250,7 +297,16 @@ mod tests {
     #[test]
     fn unreachable_until_recovery() {
         // Unreachable, recovering after 1ms.
-        let c = MockController::new(None, None, None, None, Some("1"), Some("1"), Instant::now());
+        let c = MockController::new(
+            None,
+            None,
+            None,
+            None,
+            Some("1"),
+            Some("1"),
+            None,
+            Instant::now(),
+        );
         assert!(c.is_unreachable(), "device should start unreachable");
         std::thread::sleep(Duration::from_millis(5));
         assert!(
@@ -261,9 +317,42 @@ mod tests {
 
     #[test]
     fn unreachable_without_recovery_persists() {
-        let c = MockController::new(None, None, None, None, None, Some("1"), Instant::now());
+        let c = MockController::new(
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some("1"),
+            None,
+            Instant::now(),
+        );
         assert!(c.is_unreachable());
         std::thread::sleep(Duration::from_millis(5));
         assert!(c.is_unreachable());
     }
+
+    #[test]
+    fn roll_swaps_at_recovery() {
+        let c = MockController::new(
+            None,
+            None,
+            None,
+            None,
+            Some("1"),
+            None,
+            Some("30x15:120,50x0"),
+            Instant::now(),
+        );
+        let roll = c.material().expect("first roll loaded");
+        assert_eq!((roll.width_mm, roll.height_mm, roll.gap_mm), (30, 15, 3));
+        assert_eq!(
+            (roll.uuid.as_str(), roll.remaining),
+            ("MOCK30X15", Some(120))
+        );
+        std::thread::sleep(Duration::from_millis(5));
+        let roll = c.material().expect("second roll loaded");
+        assert_eq!((roll.width_mm, roll.height_mm, roll.gap_mm), (50, 0, 0));
+        assert!(ctrl(None, None, None, None).material().is_none());
+    }
 }
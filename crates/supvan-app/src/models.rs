@@ -1,78 +1,79 @@
 //! Central model registry: driver families, USB PIDs, media tables.
 //!
-//! Loaded at startup from `data/models.toml`. Call [`load()`] once before
-//! accessing any other function in this module.
+//! Loaded at startup from `data/models.toml` (validated by
+//! [`supvan_render::models`]). Call [`load()`] before accessing any other
+//! function in this module. [`reload()`] swaps in a new table at runtime;
+//! lookups hand out `Arc`-backed or owned values, so one made before the
+//! swap keeps what it got and the old table is freed with its last user.
 
 use std::collections::HashMap;
 use std::ffi::{CString, c_int};
-use std::sync::OnceLock;
+use std::sync::Arc;
 
-use serde::Deserialize;
+use parking_lot::RwLock;
+use supvan_proto::caps::Capabilities;
+use supvan_render::dither::Dither;
+use supvan_render::models::{self as table, ModelTable};
 
 // ---------------------------------------------------------------------------
 // Public runtime types
 // ---------------------------------------------------------------------------
 
 /// A driver family groups models sharing the same printhead and DPI.
+#[derive(PartialEq)]
 pub struct DriverFamily {
     pub driver_name: CString,
     pub make_and_model: Vec<u8>,
     pub dpi: c_int,
     pub printhead_width_dots: u32,
+    /// Fixed label sizes, then the `custom_min_` / `custom_max_` pair
+    /// bounding custom sizes when the family takes continuous tape.
     pub media_names: Vec<CString>,
     pub media_sizes: Vec<[c_int; 2]>,
+    /// Default screening for continuous-tone pages; jobs may override it.
+    pub dither: Dither,
+    /// PIN (and numeric passkey) the pairing agent answers with.
+    pub bt_pin: String,
+    /// What the print path and the device link branch on: density range and
+    /// mode, speeds, transfer size, margins, transports, cutter and ribbon.
+    pub caps: Capabilities,
+}
+
+impl DriverFamily {
+    /// The longest label the family prints, in mm: the `continuous_mm`
+    /// maximum on tape families, else the longest fixed size.
+    pub fn max_length_mm(&self) -> u32 {
+        let longest = self.media_sizes.iter().map(|[_, h]| *h).max();
+        (longest.unwrap_or(0).max(0) / 100) as u32
+    }
 }
 
 /// A USB model identified by PID (VID is always 0x1820).
+#[derive(Clone)]
 pub struct UsbModel {
     pub pid: String,
     pub name: String,
 }
 
-// ---------------------------------------------------------------------------
-// TOML serde types (private)
-// ---------------------------------------------------------------------------
-
-#[derive(Deserialize)]
-struct FamilyToml {
-    name: String,
-    description: String,
-    dpi: i32,
-    printhead_dots: u32,
-    media_mm: Vec<[i32; 2]>,
-}
-
-#[derive(Deserialize)]
-struct ModelToml {
-    pid: String,
-    name: String,
-    family: String,
-}
-
-#[derive(Deserialize)]
-struct ModelsToml {
-    families: Vec<FamilyToml>,
-    models: Vec<ModelToml>,
-    bt_patterns: HashMap<String, Vec<String>>,
-}
-
 // ---------------------------------------------------------------------------
 // Registry singleton
 // ---------------------------------------------------------------------------
 
 struct Registry {
-    families: Vec<DriverFamily>,
+    families: Vec<Arc<DriverFamily>>,
     models: Vec<UsbModel>,
     /// (pattern, family_idx) — longest patterns first for correct matching.
     bt_patterns: Vec<(String, usize)>,
     default_family_idx: usize,
+    roll_capacities: HashMap<String, u32>,
 }
 
-static REGISTRY: OnceLock<Registry> = OnceLock::new();
+static REGISTRY: RwLock<Option<Arc<Registry>>> = RwLock::new(None);
 
-fn registry() -> &'static Registry {
+fn registry() -> Arc<Registry> {
     REGISTRY
-        .get()
+        .read()
+        .clone()
         .expect("models::load() must be called before accessing the registry")
 }
 
@@ -80,108 +81,111 @@ fn registry() -> &'static Registry {
 // Loading
 // ---------------------------------------------------------------------------
 
-/// Load the model registry from TOML. Panics if the file is not found or
-/// invalid.
-///
-/// Must be called exactly once, before any other function in this module.
 /// The default model table, baked into the binary so a `cargo install`'d
 /// (or otherwise relocated) binary is self-contained. Overridden by
-/// `$SUPVAN_MODELS` or a `models.toml` found on disk — see [`find_toml_path`].
+/// `$SUPVAN_MODELS` or a `models.toml` found on disk — see [`toml_path`].
 const EMBEDDED_MODELS: &str = include_str!("../../../data/models.toml");
 
-pub fn load() {
-    let (contents, source) = match find_toml_path() {
-        Some(path) => {
-            let c = std::fs::read_to_string(&path)
-                .unwrap_or_else(|e| panic!("failed to read {path}: {e}"));
-            (c, path)
-        }
-        None => (EMBEDDED_MODELS.to_string(), "<embedded>".to_string()),
-    };
-    let toml: ModelsToml =
-        toml::from_str(&contents).unwrap_or_else(|e| panic!("failed to parse {source}: {e}"));
+/// Load the model registry. Fails if the file cannot be read or does not
+/// validate.
+pub fn load() -> Result<(), String> {
+    let registry = read()?;
+    *REGISTRY.write() = Some(Arc::new(registry));
+    Ok(())
+}
 
-    let families: Vec<DriverFamily> = toml
+/// Re-read the model table and swap it in. On error the current table
+/// stays. Returns the driver names of families that changed or went away.
+pub fn reload() -> Result<Vec<String>, String> {
+    let new = read()?;
+    let old = registry();
+    let changed = old
         .families
         .iter()
-        .map(|f| {
-            let media_names: Vec<CString> = f
-                .media_mm
-                .iter()
-                // PWG 5101.1 self-describing name: metric dimensions take the
-                // `om_` (other-metric) class prefix; `oe_` is for inches and
-                // fails the IPP Everywhere media-name regex.
-                .map(|[w, h]| CString::new(format!("om_{w}x{h}mm_{w}x{h}mm")).unwrap())
-                .collect();
-            let media_sizes: Vec<[c_int; 2]> =
-                f.media_mm.iter().map(|[w, h]| [w * 100, h * 100]).collect();
-
-            DriverFamily {
-                driver_name: CString::new(f.name.as_str()).unwrap(),
-                make_and_model: f.description.as_bytes().to_vec(),
-                dpi: f.dpi,
-                printhead_width_dots: f.printhead_dots,
-                media_names,
-                media_sizes,
-            }
-        })
-        .collect();
-
-    // Build family name → index map
-    let family_index: HashMap<&str, usize> = families
-        .iter()
-        .enumerate()
-        .map(|(i, f)| (f.driver_name.to_str().unwrap(), i))
-        .collect();
-
-    let default_family_idx = *family_index
-        .get("supvan_t50")
-        .expect("models.toml must define a 'supvan_t50' family");
-
-    let models: Vec<UsbModel> = toml
-        .models
-        .iter()
-        .map(|m| {
-            assert!(
-                family_index.contains_key(m.family.as_str()),
-                "model '{}' references unknown family '{}'",
-                m.name,
-                m.family
-            );
-            UsbModel {
-                pid: m.pid.clone(),
-                name: m.name.clone(),
-            }
-        })
+        .filter(|f| !new.families.contains(f))
+        .map(|f| f.driver_name.to_string_lossy().into_owned())
         .collect();
+    *REGISTRY.write() = Some(Arc::new(new));
+    Ok(changed)
+}
 
-    // Flatten bt_patterns: (pattern, family_idx), sorted longest-first
-    let mut bt_patterns: Vec<(String, usize)> = Vec::new();
-    for (family_name, patterns) in &toml.bt_patterns {
-        let idx = *family_index
-            .get(family_name.as_str())
-            .unwrap_or_else(|| panic!("bt_patterns references unknown family '{family_name}'"));
-        for pattern in patterns {
-            bt_patterns.push((pattern.clone(), idx));
+fn read() -> Result<Registry, String> {
+    let (contents, source) = match toml_path() {
+        Some(path) => {
+            let c = std::fs::read_to_string(&path).map_err(|e| format!("{path}: {e}"))?;
+            (c, path)
         }
-    }
-    bt_patterns.sort_by_key(|p| std::cmp::Reverse(p.0.len()));
+        None => (EMBEDDED_MODELS.to_string(), "<embedded>".to_string()),
+    };
+    let table = table::parse(&contents).map_err(|e| format!("{source}: {e}"))?;
+    log::info!(
+        "models: {} families, {} USB models from {source}",
+        table.families.len(),
+        table.models.len()
+    );
+    Ok(Registry::from(table))
+}
 
-    if REGISTRY
-        .set(Registry {
+impl From<ModelTable> for Registry {
+    fn from(table: ModelTable) -> Self {
+        let families = table
+            .families
+            .into_iter()
+            .map(|f| {
+                let mut media_names: Vec<CString> = f
+                    .media_mm
+                    .iter()
+                    // PWG 5101.1 self-describing name: metric dimensions take the
+                    // `om_` (other-metric) class prefix; `oe_` is for inches and
+                    // fails the IPP Everywhere media-name regex.
+                    .map(|[w, h]| CString::new(format!("om_{w}x{h}mm_{w}x{h}mm")).unwrap())
+                    .collect();
+                let mut media_sizes: Vec<[c_int; 2]> =
+                    f.media_mm.iter().map(|[w, h]| [w * 100, h * 100]).collect();
+                // PWG 5101.1 custom size bounds; the IPP layer turns the pair into
+                // media-size ranges.
+                if let Some([min, max]) = f.continuous_mm {
+                    for (bound, [w, h]) in [("min", min), ("max", max)] {
+                        media_names
+                            .push(CString::new(format!("custom_{bound}_{w}x{h}mm")).unwrap());
+                        media_sizes.push([w * 100, h * 100]);
+                    }
+                }
+
+                Arc::new(DriverFamily {
+                    // Validated free of NULs by `supvan_render::models::parse`.
+                    driver_name: CString::new(f.name).unwrap(),
+                    make_and_model: f.description.into_bytes(),
+                    dpi: f.dpi,
+                    printhead_width_dots: f.printhead_dots,
+                    media_names,
+                    media_sizes,
+                    dither: f.dither,
+                    bt_pin: f.bt_pin,
+                    caps: f.caps,
+                })
+            })
+            .collect();
+        Registry {
             families,
-            models,
-            bt_patterns,
-            default_family_idx,
-        })
-        .is_err()
-    {
-        panic!("models::load() called more than once");
+            models: table
+                .models
+                .into_iter()
+                .map(|m| UsbModel {
+                    pid: m.pid,
+                    name: m.name,
+                })
+                .collect(),
+            bt_patterns: table.bt_patterns,
+            default_family_idx: table.default_family,
+            roll_capacities: table.roll_capacities,
+        }
     }
 }
 
-/// Locate a `models.toml` override on disk, or `None` to use [`EMBEDDED_MODELS`].
-fn find_toml_path() -> Option<String> {
+/// The `models.toml` on disk the registry is read from, or `None` to use
+/// [`EMBEDDED_MODELS`].
+pub fn toml_path() -> Option<String> {
     // 1. Explicit override
     if let Ok(path) = std::env::var("SUPVAN_MODELS") {
         return Some(path);
@@ -203,39 +207,51 @@ fn find_toml_path() -> Option<String> {
 // Public API
 // ---------------------------------------------------------------------------
 
-/// All driver families.
-pub fn families() -> &'static [DriverFamily] {
-    &registry().families
+/// Look up a driver family by its driver name (e.g. `"supvan_t50"`).
+pub fn family_by_driver(driver_name: &str) -> Option<Arc<DriverFamily>> {
+    registry()
+        .families
+        .iter()
+        .find(|f| f.driver_name.to_string_lossy() == driver_name)
+        .cloned()
 }
 
 /// The default driver family (supvan_t50).
-pub fn default_family() -> &'static DriverFamily {
-    &registry().families[registry().default_family_idx]
+pub fn default_family() -> Arc<DriverFamily> {
+    let reg = registry();
+    reg.families[reg.default_family_idx].clone()
+}
+
+/// The capabilities of family `driver_name`, else of the default family.
+pub fn caps_for(driver_name: &str) -> Capabilities {
+    family_by_driver(driver_name)
+        .unwrap_or_else(default_family)
+        .caps
+        .clone()
+}
+
+/// Known labels per roll, by the RFID tag's SKU code.
+pub fn roll_capacities() -> HashMap<String, u32> {
+    registry().roll_capacities.clone()
 }
 
 /// Find a USB model by its PID string (lowercase hex, e.g. `"2073"`).
-pub fn model_by_pid(pid: &str) -> Option<&'static UsbModel> {
+pub fn model_by_pid(pid: &str) -> Option<UsbModel> {
     registry()
         .models
         .iter()
         .find(|m| m.pid.eq_ignore_ascii_case(pid))
+        .cloned()
 }
 
 /// Determine the driver family from a model name or BT broadcast name.
 ///
 /// Uses substring matching against bt_patterns (longest first).
 /// Falls back to the default family for unknown names.
-pub fn family_for_model_hint(name: &str) -> &'static DriverFamily {
-    let lower = name.to_lowercase();
+pub fn family_for_model_hint(name: &str) -> Arc<DriverFamily> {
     let reg = registry();
-
-    for (pattern, idx) in &reg.bt_patterns {
-        if lower.contains(pattern.as_str()) {
-            return &reg.families[*idx];
-        }
-    }
-
-    &reg.families[reg.default_family_idx]
+    let idx = table::match_name(&reg.bt_patterns, name).unwrap_or(reg.default_family_idx);
+    reg.families[idx].clone()
 }
 
 /// Check if a Bluetooth device name matches any known Supvan printer pattern.
@@ -246,10 +262,7 @@ pub fn is_matching_bt_name(name: &str) -> bool {
         return true;
     }
 
-    registry()
-        .bt_patterns
-        .iter()
-        .any(|(pattern, _)| lower.contains(pattern.as_str()))
+    table::match_name(&registry().bt_patterns, name).is_some()
 }
 
 /// Parse the MDL field from an IEEE 1284 device ID string.
@@ -0,0 +1,177 @@
+//! Hot reload of `models.toml`.
+//!
+//! Adding a USB PID or a media size used to mean restarting the service.
+//! A thread holds an inotify watch on the directory of the file the
+//! registry was loaded from (editors and package managers replace the file
+//! rather than write it in place) and, once writes to it have settled,
+//! runs [`models::reload`]. A table that fails validation is logged and
+//! the current one kept; otherwise queues of families that changed are
+//! re-described from the new table, and the roll capacities refreshed.
+//! The embedded table has no file to watch.
+
+use std::ffi::{CString, OsStr};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use ipp_printer_app::{PrinterRegistry, Server};
+
+use crate::models;
+
+/// Quiet time after the last write before the file is read.
+const SETTLE_MS: i32 = 250;
+
+/// Start watching the model table, if it came from a file.
+pub fn spawn(registry: PrinterRegistry, state_path: PathBuf) {
+    let Some(path) = models::toml_path().map(PathBuf::from) else {
+        return;
+    };
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let Some(name) = path.file_name().map(OsStr::to_owned) else {
+        return;
+    };
+    let fd = match open_watch(&dir) {
+        Ok(fd) => fd,
+        Err(e) => {
+            log::warn!(
+                "models: cannot watch {} ({e}); changes are read at the next start",
+                dir.display()
+            );
+            return;
+        }
+    };
+    log::info!("models: watching {} for changes", path.display());
+    let watcher = std::thread::Builder::new()
+        .name("models-watch".into())
+        .spawn(move || {
+            while wait_for_change(&fd, &name) {
+                reload(&registry, &state_path);
+            }
+        });
+    if let Err(e) = watcher {
+        log::warn!("models: cannot start the watcher: {e}");
+    }
+}
+
+fn open_watch(dir: &Path) -> std::io::Result<OwnedFd> {
+    let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    let dir = CString::new(dir.as_os_str().as_bytes())?;
+    let mask = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_CREATE;
+    if unsafe { libc::inotify_add_watch(fd.as_raw_fd(), dir.as_ptr(), mask) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(fd)
+}
+
+/// Block until `name` has been written and left alone for [`SETTLE_MS`].
+/// `false` once the watch fails.
+fn wait_for_change(fd: &OwnedFd, name: &OsStr) -> bool {
+    let mut pending = false;
+    let mut buf = [0u8; 4096];
+    loop {
+        let mut pfd = libc::pollfd {
+            fd: fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = if pending { SETTLE_MS } else { -1 };
+        let ready = unsafe { libc::poll(&mut pfd, 1, timeout) };
+        if ready == 0 {
+            return true;
+        }
+        let n = if ready > 0 {
+            unsafe { libc::read(fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) }
+        } else {
+            -1
+        };
+        if n < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            log::warn!("models: watch failed: {err}");
+            return false;
+        }
+        pending |= names(&buf[..n as usize]).any(|n| n == name.as_bytes());
+    }
+}
+
+/// The file names in a buffer of `inotify_event`s.
+fn names(mut buf: &[u8]) -> impl Iterator<Item = &[u8]> {
+    const HEADER: usize = std::mem::size_of::<libc::inotify_event>();
+    std::iter::from_fn(move || {
+        if buf.len() < HEADER {
+            return None;
+        }
+        let len = u32::from_ne_bytes(buf[12..16].try_into().unwrap()) as usize;
+        let end = (HEADER + len).min(buf.len());
+        let name = &buf[HEADER..end];
+        buf = &buf[end..];
+        // The name is NUL-padded.
+        Some(&name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())])
+    })
+}
+
+fn reload(registry: &PrinterRegistry, state_path: &Path) {
+    let changed = match models::reload() {
+        Ok(changed) => changed,
+        Err(e) => {
+            log::error!("models: not reloaded, keeping the current table: {e}");
+            return;
+        }
+    };
+    crate::rolls::set_capacities(models::roll_capacities());
+    let mut redescribed = false;
+    for rec in registry.write().iter_mut() {
+        if !changed.contains(&rec.config.driver_name) {
+            continue;
+        }
+        let driver = rec.config.driver_name.clone();
+        match crate::ipp_job::redescribe(&rec.config, &driver) {
+            Some(config) => {
+                log::info!("models: {} re-described from family {driver}", config.name);
+                rec.config = config;
+                redescribed = true;
+            }
+            None => log::warn!(
+                "models: family {driver} of {} is gone; the queue keeps its description",
+                rec.config.name
+            ),
+        }
+    }
+    if redescribed {
+        Server::persist(registry, state_path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(name: &str, padded: usize) -> Vec<u8> {
+        let mut e = vec![0u8; 16];
+        e[12..16].copy_from_slice(&(padded as u32).to_ne_bytes());
+        e.extend_from_slice(name.as_bytes());
+        e.resize(16 + padded, 0);
+        e
+    }
+
+    #[test]
+    fn event_names_are_split_and_unpadded() {
+        let mut buf = event("models.toml", 16);
+        buf.extend(event("", 0));
+        buf.extend(event(".models.toml.swp", 32));
+        let names: Vec<_> = names(&buf).collect();
+        assert_eq!(
+            names,
+            [&b"models.toml"[..], &b""[..], &b".models.toml.swp"[..]]
+        );
+    }
+}
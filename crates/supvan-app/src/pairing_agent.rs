@@ -0,0 +1,55 @@
+//! The app's BlueZ pairing agent.
+//!
+//! Registers [`supvan_proto::pairing::Agent`] for the life of the process so
+//! a printer asking for a PIN or passkey while discovery pairs it is answered
+//! without a `bluetoothctl` session. Supvan printers get `SUPVAN_BT_PIN` if
+//! set, else their family's `bt_pin` from `models.toml` (default `0000`);
+//! other devices, and any device the app is not pairing or dialing, are
+//! rejected. Printers that ask for a PIN on connect instead need the agent to
+//! be the host's default (`[bluetooth] default_agent = true`).
+
+use std::sync::{Arc, OnceLock};
+
+use supvan_proto::pairing::{self, Agent, Peer};
+
+use crate::{ble_discover, config, models};
+
+static AGENT: OnceLock<Option<Agent>> = OnceLock::new();
+
+/// Register the agent. Without BlueZ it logs and pairing is left to the
+/// system's own agent.
+pub fn start() {
+    AGENT.get_or_init(|| {
+        let default = config::get().bluetooth.default_agent;
+        match Agent::register(Arc::new(pin_for), default) {
+            Ok(agent) => Some(agent),
+            Err(e) => {
+                log::warn!(
+                    "pairing_agent: not registered ({e}); printers needing a PIN will not pair"
+                );
+                None
+            }
+        }
+    });
+}
+
+/// Pair and trust `address` through the app's agent, else through the
+/// host's default agent.
+pub fn pair(address: &str) -> supvan_proto::error::Result<()> {
+    match AGENT.get() {
+        Some(Some(agent)) => agent.pair(address),
+        _ => pairing::pair(address),
+    }
+}
+
+fn pin_for(peer: &Peer) -> Option<String> {
+    let supvan = models::is_matching_bt_name(&peer.name)
+        || ble_discover::is_supvan_ble(&peer.address, &peer.name);
+    if !supvan {
+        return None;
+    }
+    Some(
+        std::env::var("SUPVAN_BT_PIN")
+            .unwrap_or_else(|_| models::family_for_model_hint(&peer.name).bt_pin.clone()),
+    )
+}
@@ -0,0 +1,52 @@
+//! The app's copy of the roll ledger ([`supvan_proto::ledger`]).
+//!
+//! Loaded on first use from [`ledger::default_path`], with the per-SKU
+//! capacities from `models.toml`. The status poller records each material
+//! report with [`observe`]; the IPP layer reads the loaded roll back with
+//! [`current`]. The file is rewritten only when a roll's count changes.
+
+use std::sync::{Mutex, OnceLock};
+
+use supvan_proto::ledger::{self, RollLedger, RollRecord};
+use supvan_proto::status::MaterialInfo;
+
+use crate::models;
+
+fn ledger() -> &'static Mutex<RollLedger> {
+    static L: OnceLock<Mutex<RollLedger>> = OnceLock::new();
+    L.get_or_init(|| {
+        let path = ledger::default_path();
+        let mut ledger = RollLedger::load(&path).unwrap_or_else(|e| {
+            log::warn!("roll ledger {}: {e}; starting a new one", path.display());
+            RollLedger::default()
+        });
+        ledger.set_capacities(models::roll_capacities());
+        Mutex::new(ledger)
+    })
+}
+
+/// Replace the per-SKU capacities, after `models.toml` is reloaded.
+pub fn set_capacities(capacities: std::collections::HashMap<String, u32>) {
+    ledger().lock().unwrap().set_capacities(capacities);
+}
+
+/// Record a material report from `printer`, saving the ledger if it
+/// changed. `None` for rolls the ledger cannot track (no tag uuid or
+/// remaining count).
+pub fn observe(printer: &str, mat: &MaterialInfo) -> Option<RollRecord> {
+    let mut ledger = ledger().lock().unwrap();
+    let (record, changed) = ledger.observe(printer, mat, ledger::unix_now())?;
+    let record = record.clone();
+    if changed {
+        let path = ledger::default_path();
+        if let Err(e) = ledger.save(&path) {
+            log::warn!("roll ledger {}: {e}", path.display());
+        }
+    }
+    Some(record)
+}
+
+/// The roll most recently seen in `printer`.
+pub fn current(printer: &str) -> Option<RollRecord> {
+    ledger().lock().unwrap().current(printer).cloned()
+}
@@ -0,0 +1,293 @@
+//! Apple URF (`image/urf`, "UNIRAST") decoder for AirPrint clients.
+//!
+//! A URF document is an 8-byte `UNIRAST\0` magic and a big-endian page count,
+//! then per page a 32-byte header followed by the compressed pixel data:
+//!
+//! | offset | size | field                                          |
+//! |--------|------|------------------------------------------------|
+//! | 0      | 1    | bits per pixel (8 or 24 here)                  |
+//! | 1      | 1    | color space (0 = sGray, 1 = sRGB, 4 = W, 5 = RGB) |
+//! | 2      | 1    | duplex                                         |
+//! | 3      | 1    | print quality                                  |
+//! | 12     | 4    | width in pixels (BE)                           |
+//! | 16     | 4    | height in pixels (BE)                          |
+//! | 20     | 4    | resolution in dpi (BE)                         |
+//!
+//! Each group of identical lines starts with a repeat byte (`n` → `n + 1`
+//! copies of the line), then PackBits-style runs of whole pixels: `0..=127`
+//! repeats the next pixel `n + 1` times, `129..=255` copies `257 - n` literal
+//! pixels, and `128` fills the rest of the line with white.
+//!
+//! Only the formats advertised in `urf-supported` (`W8`, `SRGB24`) are
+//! decoded; pages come out as 8-bit luma (`0` black, `255` white), one at a
+//! time, so a job holds a single decoded page however long the document.
+
+use image::{GrayImage, Pixel, Rgb};
+use ipp_printer_app::JobFailure;
+
+/// MIME type iOS and macOS use for URF documents.
+pub const URF_FORMAT: &str = "image/urf";
+
+const MAGIC: &[u8; 8] = b"UNIRAST\0";
+const PAGE_HEADER_LEN: usize = 32;
+
+/// Refuse pages larger than this many pixels. A Letter page at 600 dpi is
+/// ~34 Mpx; anything beyond is a corrupt or hostile header.
+const MAX_PIXELS: u64 = 64 * 1024 * 1024;
+
+/// Refuse documents whose pages add up to more than this many pixels. Runs
+/// and line repeats make a page of a few bytes decode to [`MAX_PIXELS`], so
+/// the page count alone bounds nothing.
+const MAX_DOCUMENT_PIXELS: u64 = 4 * MAX_PIXELS;
+
+/// One decoded URF page.
+#[derive(Debug)]
+pub struct UrfPage {
+    /// Resolution the client rasterized at, in dpi.
+    pub dpi: u32,
+    /// 8-bit grayscale pixels.
+    pub luma: GrayImage,
+}
+
+/// Check a URF document's file header and return its pages, each decoded
+/// as the iterator reaches it. The header page count is advisory (clients
+/// streaming a job may write 0); pages are read until end of data.
+pub fn decode(document: &[u8]) -> Result<Pages<'_>, JobFailure> {
+    let rest = document
+        .strip_prefix(MAGIC.as_slice())
+        .ok_or_else(|| urf_error("missing UNIRAST header"))?;
+    if rest.len() < 4 {
+        return Err(urf_error("truncated file header"));
+    }
+    let input = &rest[4..];
+    if input.is_empty() {
+        return Err(urf_error("document has no pages"));
+    }
+    Ok(Pages {
+        input,
+        page_num: 0,
+        pixels: 0,
+    })
+}
+
+/// The pages of a URF document (see [`decode`]). Ends after the first error.
+pub struct Pages<'a> {
+    input: &'a [u8],
+    page_num: usize,
+    /// Pixels of the pages decoded so far, against [`MAX_DOCUMENT_PIXELS`].
+    pixels: u64,
+}
+
+impl Iterator for Pages<'_> {
+    type Item = Result<UrfPage, JobFailure>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.input.is_empty() {
+            return None;
+        }
+        self.page_num += 1;
+        match decode_page(self.input, self.page_num, &mut self.pixels) {
+            Ok((page, rest)) => {
+                self.input = rest;
+                Some(Ok(page))
+            }
+            Err(e) => {
+                self.input = &[];
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Decode the page at the start of `input`, adding its size to `pixels`.
+fn decode_page<'a>(
+    input: &'a [u8],
+    page_num: usize,
+    pixels: &mut u64,
+) -> Result<(UrfPage, &'a [u8]), JobFailure> {
+    if input.len() < PAGE_HEADER_LEN {
+        return Err(urf_error(format!("page {page_num}: truncated page header")));
+    }
+    let (header, mut data) = input.split_at(PAGE_HEADER_LEN);
+    let be32 = |at: usize| u32::from_be_bytes(header[at..at + 4].try_into().unwrap());
+    let (bpp, color_space) = (header[0], header[1]);
+    let (width, height, dpi) = (be32(12), be32(16), be32(20));
+
+    let bytes_per_pixel = match (bpp, color_space) {
+        (8, 0 | 4) => 1,
+        (24, 1 | 5) => 3,
+        _ => {
+            return Err(urf_error(format!(
+                "page {page_num}: unsupported {bpp}-bit color space {color_space} \
+                 (only W8 and SRGB24 are supported)"
+            )));
+        }
+    };
+    let size = u64::from(width) * u64::from(height);
+    if width == 0 || height == 0 || size > MAX_PIXELS {
+        return Err(urf_error(format!(
+            "page {page_num}: bad page size {width}x{height}"
+        )));
+    }
+    *pixels += size;
+    if *pixels > MAX_DOCUMENT_PIXELS {
+        return Err(urf_error(format!(
+            "page {page_num}: document is over {MAX_DOCUMENT_PIXELS} pixels"
+        )));
+    }
+
+    let line_len = width as usize * bytes_per_pixel;
+    let mut line = vec![0u8; line_len];
+    let mut luma = GrayImage::new(width, height);
+    let mut y = 0u32;
+    while y < height {
+        let (&repeat, rest) = data
+            .split_first()
+            .ok_or_else(|| urf_error(format!("page {page_num}: truncated at line {y}")))?;
+        data = unpack_line(rest, &mut line, bytes_per_pixel)
+            .ok_or_else(|| urf_error(format!("page {page_num}: corrupt data at line {y}")))?;
+
+        let count = (u32::from(repeat) + 1).min(height - y);
+        for _ in 0..count {
+            let row = &mut luma.as_mut()[(y * width) as usize..((y + 1) * width) as usize];
+            if bytes_per_pixel == 1 {
+                row.copy_from_slice(&line);
+            } else {
+                for (dst, rgb) in row.iter_mut().zip(line.chunks_exact(3)) {
+                    *dst = Rgb([rgb[0], rgb[1], rgb[2]]).to_luma().0[0];
+                }
+            }
+            y += 1;
+        }
+    }
+    Ok((UrfPage { dpi, luma }, data))
+}
+
+/// Unpack one line's pixel runs into `line`. Returns the unread input, or
+/// `None` if the runs overflow the line or the data ends early.
+fn unpack_line<'a>(mut data: &'a [u8], line: &mut [u8], bpp: usize) -> Option<&'a [u8]> {
+    let mut x = 0;
+    while x < line.len() {
+        let (&op, rest) = data.split_first()?;
+        data = rest;
+        match op {
+            128 => {
+                line[x..].fill(0xFF);
+                x = line.len();
+            }
+            0..=127 => {
+                let len = (usize::from(op) + 1) * bpp;
+                let pixel = data.get(..bpp)?;
+                line.get_mut(x..x + len)?
+                    .chunks_exact_mut(bpp)
+                    .for_each(|p| p.copy_from_slice(pixel));
+                data = &data[bpp..];
+                x += len;
+            }
+            _ => {
+                let len = (257 - usize::from(op)) * bpp;
+                line.get_mut(x..x + len)?.copy_from_slice(data.get(..len)?);
+                data = &data[len..];
+                x += len;
+            }
+        }
+    }
+    Some(data)
+}
+
+fn urf_error(msg: impl std::fmt::Display) -> JobFailure {
+    JobFailure::other(format!("urf: {msg}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page_header(bpp: u8, color_space: u8, width: u32, height: u32, dpi: u32) -> Vec<u8> {
+        let mut h = vec![0u8; PAGE_HEADER_LEN];
+        h[0] = bpp;
+        h[1] = color_space;
+        h[12..16].copy_from_slice(&width.to_be_bytes());
+        h[16..20].copy_from_slice(&height.to_be_bytes());
+        h[20..24].copy_from_slice(&dpi.to_be_bytes());
+        h
+    }
+
+    fn decode_all(document: &[u8]) -> Result<Vec<UrfPage>, JobFailure> {
+        decode(document)?.collect()
+    }
+
+    fn document(pages: &[Vec<u8>]) -> Vec<u8> {
+        let mut doc = MAGIC.to_vec();
+        doc.extend_from_slice(&(pages.len() as u32).to_be_bytes());
+        pages.iter().for_each(|p| doc.extend_from_slice(p));
+        doc
+    }
+
+    #[test]
+    fn decodes_gray_runs_literals_and_line_repeat() {
+        let mut page = page_header(8, 0, 4, 3, 203);
+        // Line 0 twice: two black pixels, then literals 0x40 0x80.
+        page.extend_from_slice(&[1, 1, 0x00, 255, 0x40, 0x80]);
+        // Line 2 once: one grey pixel, then fill-white.
+        page.extend_from_slice(&[0, 0, 0x7F, 128]);
+        let pages = decode_all(&document(&[page])).unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].dpi, 203);
+        assert_eq!(
+            pages[0].luma.as_raw(),
+            &[0, 0, 0x40, 0x80, 0, 0, 0x40, 0x80, 0x7F, 0xFF, 0xFF, 0xFF]
+        );
+    }
+
+    #[test]
+    fn decodes_srgb_to_luma() {
+        let mut page = page_header(24, 1, 2, 1, 300);
+        page.extend_from_slice(&[0, 0, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0]);
+        let pages = decode_all(&document(&[page])).unwrap();
+        assert_eq!(pages[0].luma.as_raw(), &[0xFF, 0]);
+    }
+
+    #[test]
+    fn reads_pages_past_a_zero_page_count() {
+        let mut page = page_header(8, 0, 1, 1, 203);
+        page.extend_from_slice(&[0, 0, 0x00]);
+        let mut doc = document(&[page.clone(), page]);
+        doc[8..12].fill(0);
+        assert_eq!(decode_all(&doc).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        assert!(decode_all(b"RaS2").is_err());
+        assert!(decode_all(&document(&[])).is_err());
+        // Unsupported 16-bit gray.
+        let page = page_header(16, 0, 1, 1, 203);
+        assert!(decode_all(&document(&[page])).is_err());
+        // A run that overflows the line.
+        let mut page = page_header(8, 0, 2, 1, 203);
+        page.extend_from_slice(&[0, 5, 0x00]);
+        assert!(decode_all(&document(&[page])).is_err());
+        // Truncated pixel data.
+        let mut page = page_header(8, 0, 2, 2, 203);
+        page.extend_from_slice(&[0, 1, 0x00]);
+        assert!(decode_all(&document(&[page])).is_err());
+    }
+
+    #[test]
+    fn caps_the_pixels_a_document_decodes_to() {
+        // 8192x8192 white, 256 lines per repeat byte: 64 bytes per
+        // MAX_PIXELS page. Four fit the document budget, the fifth does not.
+        let mut page = page_header(8, 0, 8192, 8192, 203);
+        for _ in 0..32 {
+            page.extend_from_slice(&[255, 128]);
+        }
+        let doc = document(&vec![page; 5]);
+        let mut pages = decode(&doc).unwrap();
+        for _ in 0..4 {
+            assert!(pages.next().unwrap().is_ok());
+        }
+        assert!(pages.next().unwrap().is_err());
+        assert!(pages.next().is_none());
+    }
+}
@@ -95,6 +95,42 @@ pub async fn list_candidates() -> Vec<UsbCandidate> {
     out
 }
 
+/// Name probes for a hotplugged node, and the pause between them. The
+/// kernel announces the node before udev applies the `etc/udev` access
+/// rules, so the first opens may be refused.
+const HOTPLUG_PROBE_ATTEMPTS: u32 = 10;
+const HOTPLUG_PROBE_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Identify a hidraw node that just appeared (`name` as in `hidraw3`) as a
+/// Supvan printer, probing its firmware-reported name like
+/// [`list_candidates`]. `None` for other devices.
+pub async fn probe_hotplugged(name: &str) -> Option<UsbCandidate> {
+    let ids = read_usb_ids(&Path::new("/sys/class/hidraw").join(name).join("device"))?;
+    if ids.vid != SUPVAN_USB_VID {
+        return None;
+    }
+    let model = models::model_by_pid(&ids.pid)?;
+    let uri_id = ids
+        .serial
+        .clone()
+        .or_else(|| ids.bus_path.as_ref().map(|p| format!("bus-{p}")))?;
+    let hidraw_path = format!("/dev/{name}");
+    let mut printer_name = None;
+    for _ in 0..HOTPLUG_PROBE_ATTEMPTS {
+        printer_name = probe_printer_name(&hidraw_path).await;
+        if printer_name.is_some() {
+            break;
+        }
+        tokio::time::sleep(HOTPLUG_PROBE_INTERVAL).await;
+    }
+    Some(UsbCandidate {
+        hidraw_path,
+        uri_id,
+        model_name: model.name.clone(),
+        printer_name,
+    })
+}
+
 /// Low-level scan of /sys/class/hidraw: calls `cb(dev_path, usb_ids)` for each
 /// Supvan device found. Return `false` from `cb` to stop early.
 fn scan_hidraw_paths<F>(mut cb: F)
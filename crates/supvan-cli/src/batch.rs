@@ -0,0 +1,155 @@
+//! CSV mail-merge for `template batch`: one label per CSV row, with the
+//! header row naming the template fields.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+use std::str::FromStr;
+
+/// An inclusive range of 1-based data rows (the header is not counted).
+/// Parsed from `N`, `A-B`, `A-` or `-B`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RowRange {
+    pub first: usize,
+    pub last: Option<usize>,
+}
+
+impl Default for RowRange {
+    fn default() -> Self {
+        Self {
+            first: 1,
+            last: None,
+        }
+    }
+}
+
+impl RowRange {
+    pub fn contains(&self, row: usize) -> bool {
+        row >= self.first && self.last.is_none_or(|last| row <= last)
+    }
+}
+
+impl FromStr for RowRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let num = |t: &str| -> Result<usize, String> {
+            let n: usize = t
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid row number '{t}' in range '{s}'"))?;
+            if n == 0 {
+                return Err("rows are numbered from 1".to_string());
+            }
+            Ok(n)
+        };
+        let range = match s.split_once('-') {
+            None => {
+                let n = num(s)?;
+                Self {
+                    first: n,
+                    last: Some(n),
+                }
+            }
+            Some((a, b)) => Self {
+                first: if a.trim().is_empty() { 1 } else { num(a)? },
+                last: if b.trim().is_empty() {
+                    None
+                } else {
+                    Some(num(b)?)
+                },
+            },
+        };
+        if range.last.is_some_and(|last| last < range.first) {
+            return Err(format!("row range '{s}' is empty"));
+        }
+        Ok(range)
+    }
+}
+
+/// One CSV data row: its 1-based number and its fields keyed by header.
+pub struct Row {
+    pub number: usize,
+    pub values: BTreeMap<String, String>,
+}
+
+/// Read the data rows of `path` that fall in `range`.
+pub fn read_rows(path: &Path, range: RowRange) -> Result<Vec<Row>, Box<dyn Error>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::Headers)
+        .from_path(path)
+        .map_err(|e| format!("{}: {e}", path.display()))?;
+    let headers = reader.headers()?.clone();
+    let mut rows = Vec::new();
+    for (i, record) in reader.records().enumerate() {
+        let number = i + 1;
+        if range.last.is_some_and(|last| number > last) {
+            break;
+        }
+        let record = record.map_err(|e| format!("{} row {number}: {e}", path.display()))?;
+        if !range.contains(number) {
+            continue;
+        }
+        let values = headers
+            .iter()
+            .zip(record.iter())
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        rows.push(Row { number, values });
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_row_ranges() {
+        assert_eq!(
+            "7".parse(),
+            Ok(RowRange {
+                first: 7,
+                last: Some(7)
+            })
+        );
+        assert_eq!(
+            "5-20".parse(),
+            Ok(RowRange {
+                first: 5,
+                last: Some(20)
+            })
+        );
+        assert_eq!(
+            "5-".parse(),
+            Ok(RowRange {
+                first: 5,
+                last: None
+            })
+        );
+        assert_eq!(
+            "-3".parse(),
+            Ok(RowRange {
+                first: 1,
+                last: Some(3)
+            })
+        );
+        assert!("0".parse::<RowRange>().is_err());
+        assert!("9-2".parse::<RowRange>().is_err());
+        assert!("a-b".parse::<RowRange>().is_err());
+    }
+
+    #[test]
+    fn reads_rows_in_range_keyed_by_header() {
+        let path = std::env::temp_dir().join(format!("supvan-batch-{}.csv", std::process::id()));
+        std::fs::write(&path, " sku ,name\nA1,Drill\nA2,\"Saw, hand\"\nA3,Vise\n").unwrap();
+        let rows = read_rows(&path, "2-".parse().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].number, 2);
+        assert_eq!(rows[0].values["sku"], "A2");
+        assert_eq!(rows[0].values["name"], "Saw, hand");
+        assert_eq!(rows[1].values["name"], "Vise");
+    }
+}
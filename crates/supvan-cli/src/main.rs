@@ -2,15 +2,43 @@
 //! bypassing the IPP/CUPS stack. Connect over Bluetooth (an address) or USB HID
 //! (a `/dev/hidrawN` path) and run a subcommand: `probe` (device/status/material/
 //! version), `material` (loaded label + RFID + remaining count), `test-print`
-//! (a built-in pattern), or `discover` (scan for Supvan Bluetooth devices).
+//! (a built-in pattern), `print` (an image or CUPS/PWG raster file), `code` (a QR / Data Matrix label at exact dot pitch),
+//! `template` (render a label template with field values, validate one, or
+//! mail-merge it over a CSV file), `rolls` / `ledger` (the printer app's roll
+//! and job ledgers), `printers` (list, rename or forget entries in its identity
+//! registry), `models check` (validate a `models.toml` before the printer app
+//! reloads it), `discover` (scan for Supvan Bluetooth devices), or `pair`
+//! (pair and trust one over Bluetooth, answering its PIN; needs the default
+//! `pairing` feature).
 
+mod batch;
+mod models;
+mod print;
+
+use std::collections::BTreeMap;
 use std::error::Error;
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
+#[cfg(feature = "pairing")]
+use std::sync::Arc;
 
-use clap::{Parser, Subcommand};
-use supvan_proto::bitmap::PRINTHEAD_WIDTH_MM;
+use batch::RowRange;
+use clap::{Args, Parser, Subcommand};
+use supvan_proto::bitmap::{PRINTHEAD_WIDTH_DOTS, PRINTHEAD_WIDTH_MM};
+use supvan_proto::buffer::MediaKind;
+use supvan_proto::identity::{self, Identities};
+use supvan_proto::ledger::{self, JobRecord, Outcome, RollLedger, RollRecord};
+#[cfg(feature = "pairing")]
+use supvan_proto::pairing::{Agent, Peer};
 use supvan_proto::printer::Printer;
 use supvan_proto::status::{DEFAULT_LABEL_GAP_MM, DEFAULT_LABEL_HEIGHT_MM, MaterialInfo};
+use supvan_render::code2d::{self, CodeSpec, EcLevel, Symbology};
+use supvan_render::imaging::{Rotation, ScaleMode};
+use supvan_render::label::LabelGeometry;
+use supvan_render::template::Template;
+
+/// Dot pitch of the T50 Pro head (8 dots/mm).
+const PRINTER_DPI: u32 = 203;
 
 type CliResult = Result<(), Box<dyn Error>>;
 
@@ -41,6 +69,51 @@ enum Command {
         #[arg(short, long, default_value_t = 4)]
         density: u8,
     },
+    /// Print an image (PNG, JPEG, PBM/PGM) or a CUPS/PWG raster file
+    Print {
+        /// Bluetooth address or /dev/hidrawN path
+        target: String,
+        /// File to print
+        file: PathBuf,
+        /// Print density (0-15)
+        #[arg(short, long, default_value_t = 4)]
+        density: u8,
+        /// Number of copies
+        #[arg(short = 'n', long, default_value_t = 1)]
+        copies: u32,
+        /// Clockwise rotation for images: 0, 90, 180, 270 or auto
+        #[arg(short, long, default_value = "0")]
+        rotate: Rotation,
+        /// Image scaling: fit (letterbox), fill (crop), none (1 pixel = 1 dot),
+        /// auto (fill if the aspect nearly matches, else fit) or auto-fit
+        /// (fit if larger than the label, else none)
+        #[arg(short, long, default_value = "fit")]
+        scale: ScaleMode,
+    },
+    /// Print a QR code or Data Matrix sized to the loaded label
+    Code {
+        /// Bluetooth address or /dev/hidrawN path
+        target: String,
+        /// Text to encode
+        payload: String,
+        /// Symbology: qr or datamatrix
+        #[arg(short, long, default_value = "qr")]
+        symbology: Symbology,
+        /// QR error-correction level: L, M, Q or H (ignored for Data Matrix)
+        #[arg(short, long, default_value = "M")]
+        ec: EcLevel,
+        /// Quiet zone in modules (default: 4 for QR, 1 for Data Matrix)
+        #[arg(short, long)]
+        quiet_zone: Option<u32>,
+        /// Print density (0-15)
+        #[arg(short, long, default_value_t = 4)]
+        density: u8,
+    },
+    /// Render and print label templates
+    Template {
+        #[command(subcommand)]
+        action: TemplateAction,
+    },
     /// Feed/advance one blank label (PAPER_SKIP)
     Feed {
         /// Bluetooth address or /dev/hidrawN path
@@ -48,6 +121,135 @@ enum Command {
     },
     /// Scan for Supvan Bluetooth devices (via BlueZ D-Bus)
     Discover,
+    /// Pair and trust a printer over Bluetooth, answering its PIN or passkey
+    #[cfg(feature = "pairing")]
+    Pair {
+        /// Bluetooth address
+        address: String,
+        /// PIN / passkey (default: $SUPVAN_BT_PIN, else the model's from models.toml)
+        #[arg(long)]
+        pin: Option<String>,
+    },
+    /// List, rename or forget printers in the printer app's identity registry
+    Printers {
+        #[command(subcommand)]
+        action: Option<PrintersAction>,
+    },
+    /// Work with printer model tables (models.toml)
+    Models {
+        #[command(subcommand)]
+        action: ModelsAction,
+    },
+    /// List the rolls in the printer app's roll ledger, or one roll's history
+    Rolls {
+        /// RFID UID of a roll to show in full
+        uuid: Option<String>,
+    },
+    /// Export the printer app's job ledger, or sum it up per printer
+    Ledger {
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+        /// Only jobs on this printer (queue name)
+        #[arg(short, long)]
+        printer: Option<String>,
+        /// Per-printer totals and the latest odometer instead of every job
+        #[arg(short, long)]
+        summary: bool,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+enum ExportFormat {
+    Csv,
+    Json,
+}
+
+#[derive(Subcommand)]
+enum PrintersAction {
+    /// Every known printer with its URI, transports and family (the default)
+    List,
+    /// Set a printer's display name (its queue's printer-info at the next app start)
+    Rename {
+        /// Slug, serial, display name, USB id or Bluetooth address
+        printer: String,
+        /// New display name
+        name: String,
+    },
+    /// Drop a printer; it is registered afresh, with a new slug if needed, when next seen
+    Forget {
+        /// Slug, serial, display name, USB id or Bluetooth address
+        printer: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ModelsAction {
+    /// Validate a model table, reporting every problem with its line and column
+    Check {
+        /// models.toml file
+        file: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum TemplateAction {
+    /// Fill in a template's fields and print one label
+    Print {
+        /// Bluetooth address or /dev/hidrawN path
+        target: String,
+        /// Template file (.toml or .json)
+        template: PathBuf,
+        /// Field value as NAME=VALUE (repeatable)
+        #[arg(short, long = "set", value_parser = parse_field)]
+        set: Vec<(String, String)>,
+        /// Print density (0-15)
+        #[arg(short, long, default_value_t = 4)]
+        density: u8,
+    },
+    /// Print one label per CSV row, all over a single connection
+    Batch(BatchArgs),
+    /// Validate a template against a printer family's media sizes
+    Check {
+        /// Template file (.toml or .json)
+        template: PathBuf,
+        /// Driver family from models.toml
+        #[arg(short, long, default_value = "supvan_t50")]
+        family: String,
+    },
+}
+
+#[derive(Args)]
+struct BatchArgs {
+    /// Template file (.toml or .json)
+    template: PathBuf,
+    /// CSV file whose header row names the template fields
+    csv: PathBuf,
+    /// Bluetooth address or /dev/hidrawN path to print on
+    #[arg(short, long, required_unless_present = "dry_run")]
+    target: Option<String>,
+    /// Write one PBM preview per row into DIR instead of printing
+    #[arg(long, value_name = "DIR", conflicts_with = "target")]
+    dry_run: Option<PathBuf>,
+    /// Data rows to print: N, A-B, A- or -B (1-based, header excluded)
+    #[arg(short, long, default_value = "1-")]
+    rows: RowRange,
+    /// Skip rows before N, e.g. to continue after a failed row
+    #[arg(long, value_name = "N")]
+    resume_from: Option<usize>,
+    /// Field value for every row as NAME=VALUE; CSV columns take precedence
+    #[arg(short, long = "set", value_parser = parse_field)]
+    set: Vec<(String, String)>,
+    /// Print density (0-15)
+    #[arg(short, long, default_value_t = 4)]
+    density: u8,
+}
+
+fn parse_field(s: &str) -> Result<(String, String), String> {
+    let (k, v) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=VALUE, got '{s}'"))?;
+    Ok((k.trim().to_string(), v.to_string()))
 }
 
 fn connect(target: &str) -> Result<Printer, Box<dyn Error>> {
@@ -95,7 +297,7 @@ async fn cmd_probe(target: &str) -> CliResult {
 
     if let Some(mat) = printer.query_material().await? {
         eprintln!("Material:");
-        eprintln!("  Label:     {}mm x {}mm", mat.width_mm, mat.height_mm);
+        eprintln!("  Label:     {}", label_size(&mat));
         eprintln!("  Type:      {}", mat.label_type);
         eprintln!("  Gap:       {}mm", mat.gap_mm);
         eprintln!("  SN:        {}", mat.sn);
@@ -124,15 +326,23 @@ async fn cmd_material(target: &str) -> CliResult {
         .ok_or("no material info (label not installed?)")?;
 
     println!(
-        "Label:     {}mm x {}mm  (type={}, gap={}mm)",
-        mat.width_mm, mat.height_mm, mat.label_type, mat.gap_mm
+        "Label:     {}  (type={}, gap={}mm)",
+        label_size(&mat),
+        mat.label_type,
+        mat.gap_mm
     );
     println!("Label SN:  {}", mat.sn);
     println!("RFID UID:  {}", mat.uuid);
     println!("RFID code: {}", mat.code);
-    match mat.remaining {
-        Some(r) => println!("Remaining: {r} labels"),
-        None => println!("Remaining: (not reported)"),
+    let ledger = RollLedger::load(&ledger::default_path()).unwrap_or_default();
+    match (mat.remaining, ledger.get(&mat.uuid)) {
+        (Some(r), Some(roll)) => println!(
+            "Remaining: {r} labels ({}% of {}, per the roll ledger)",
+            roll.percent(),
+            roll.capacity
+        ),
+        (Some(r), None) => println!("Remaining: {r} labels"),
+        (None, _) => println!("Remaining: (not reported)"),
     }
     match mat.device_sn {
         Some(s) => println!("Device SN: {s}"),
@@ -141,12 +351,223 @@ async fn cmd_material(target: &str) -> CliResult {
     Ok(())
 }
 
+/// `Wmm x Hmm` or `Wmm continuous` for a ledger roll.
+fn roll_size(roll: &RollRecord) -> String {
+    label_size(&MaterialInfo {
+        width_mm: roll.width_mm,
+        height_mm: roll.height_mm,
+        ..Default::default()
+    })
+}
+
+#[cfg(feature = "pairing")]
+fn cmd_pair(address: &str, pin: Option<String>) -> CliResult {
+    let pin = pin.or_else(|| std::env::var("SUPVAN_BT_PIN").ok());
+    let target = address.to_string();
+    // Answer only the printer being paired; the model PIN is looked up from
+    // the name it reports.
+    let agent = Agent::register(
+        Arc::new(move |peer: &Peer| {
+            if !peer.address.eq_ignore_ascii_case(&target) {
+                return None;
+            }
+            match &pin {
+                Some(pin) => Some(pin.clone()),
+                None => models::bt_pin(&peer.name).ok(),
+            }
+        }),
+        false,
+    )?;
+    agent.pair(address)?;
+    drop(agent);
+    println!("Paired and trusted {address}");
+    Ok(())
+}
+
+fn cmd_printers(action: PrintersAction) -> CliResult {
+    let path = identity::default_path();
+    let mut ids = Identities::load(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+    match action {
+        PrintersAction::List => {
+            if ids.printers().next().is_none() {
+                println!("No printers recorded in {}", path.display());
+            }
+            for p in ids.printers() {
+                let transports: Vec<String> = [("usb", &p.usb), ("bt", &p.bt), ("ble", &p.ble)]
+                    .into_iter()
+                    .flat_map(|(kind, ids)| ids.iter().map(move |id| format!("{kind}:{id}")))
+                    .collect();
+                println!(
+                    "{:<28} {:<24} {:<20} {:<12} {}  {}",
+                    p.uri(),
+                    p.display_name,
+                    p.serial,
+                    p.family.as_deref().unwrap_or("-"),
+                    ledger::format_utc(p.last_seen),
+                    transports.join(" ")
+                );
+            }
+            return Ok(());
+        }
+        PrintersAction::Rename { printer, name } => {
+            let p = ids
+                .rename(&printer, &name)
+                .ok_or_else(|| format!("no printer '{printer}' in {}", path.display()))?;
+            println!("{} is now \"{name}\"", p.uri());
+        }
+        PrintersAction::Forget { printer } => {
+            let p = ids
+                .forget(&printer)
+                .ok_or_else(|| format!("no printer '{printer}' in {}", path.display()))?;
+            println!("Forgot {} ({})", p.uri(), p.serial);
+        }
+    }
+    ids.save(&path)
+        .map_err(|e| format!("{}: {e}", path.display()))?;
+    Ok(())
+}
+
+fn cmd_models_check(file: &Path) -> CliResult {
+    let text = std::fs::read_to_string(file).map_err(|e| format!("{}: {e}", file.display()))?;
+    match supvan_render::models::parse(&text) {
+        Ok(table) => {
+            println!(
+                "{}: OK, {} families, {} USB models, {} Bluetooth name patterns",
+                file.display(),
+                table.families.len(),
+                table.models.len(),
+                table.bt_patterns.len()
+            );
+            Ok(())
+        }
+        Err(e) => {
+            for issue in &e.0 {
+                eprintln!(
+                    "{}:{}:{}: {}",
+                    file.display(),
+                    issue.line,
+                    issue.column,
+                    issue.message
+                );
+            }
+            Err(format!("{}: {} problem(s)", file.display(), e.0.len()).into())
+        }
+    }
+}
+
+fn cmd_rolls(uuid: Option<&str>) -> CliResult {
+    let path = ledger::default_path();
+    let ledger = RollLedger::load(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let Some(uuid) = uuid else {
+        if ledger.rolls().is_empty() {
+            println!("No rolls recorded in {}", path.display());
+        }
+        for roll in ledger.rolls() {
+            println!(
+                "{:<16} {:<10} {:<18} {:>5}/{:<5} {:>3}%  {}  {}",
+                roll.uuid,
+                roll.code,
+                roll_size(roll),
+                roll.remaining(),
+                roll.capacity,
+                roll.percent(),
+                ledger::format_utc(roll.last_seen),
+                roll.printer
+            );
+        }
+        return Ok(());
+    };
+    let roll = ledger
+        .get(uuid)
+        .ok_or_else(|| format!("roll {uuid} is not in {}", path.display()))?;
+    println!("RFID UID:  {}", roll.uuid);
+    println!("RFID code: {}", roll.code);
+    println!("Label:     {}", roll_size(roll));
+    println!("Printer:   {}", roll.printer);
+    println!(
+        "Remaining: {} of {} labels ({}%), {} used since first seen",
+        roll.remaining(),
+        roll.capacity,
+        roll.percent(),
+        roll.used()
+    );
+    println!("Last seen: {}", ledger::format_utc(roll.last_seen));
+    println!("History:");
+    for sample in std::iter::once(&roll.first).chain(&roll.history) {
+        println!(
+            "  {}  {:>5}",
+            ledger::format_utc(sample.at),
+            sample.remaining
+        );
+    }
+    Ok(())
+}
+
+fn cmd_ledger(format: ExportFormat, printer: Option<&str>, summary: bool) -> CliResult {
+    let path = ledger::jobs_path();
+    let jobs: Vec<JobRecord> = ledger::load_jobs(&path)
+        .map_err(|e| format!("{}: {e}", path.display()))?
+        .into_iter()
+        .filter(|j| printer.is_none_or(|p| j.printer == p))
+        .collect();
+    if summary {
+        print_ledger_summary(&jobs);
+        return Ok(());
+    }
+    match format {
+        ExportFormat::Json => println!("{}", serde_json::to_string_pretty(&jobs)?),
+        ExportFormat::Csv => {
+            let mut out = csv::Writer::from_writer(std::io::stdout());
+            for job in &jobs {
+                out.serialize(job)?;
+            }
+            out.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// Jobs, labels and the latest odometer reading per printer.
+fn print_ledger_summary(jobs: &[JobRecord]) {
+    let mut printers: BTreeMap<&str, (u32, u32, u32, Option<u32>)> = BTreeMap::new();
+    for job in jobs {
+        let (count, failed, labels, odometer) = printers.entry(&job.printer).or_default();
+        *count += 1;
+        *failed += u32::from(job.outcome == Outcome::Failed);
+        *labels += job.labels;
+        *odometer = job.odometer.or(*odometer);
+    }
+    for (printer, (count, failed, labels, odometer)) in printers {
+        let odometer = odometer.map_or("-".to_string(), |n| n.to_string());
+        println!("{printer}: {count} jobs ({failed} failed), {labels} labels, odometer {odometer}");
+    }
+}
+
 async fn cmd_test_print(target: &str, density: u8) -> CliResult {
     let printer = connect(target)?;
 
     // Query material to get label dimensions, falling back to printhead-width
     // defaults if no label is installed.
-    let mat = match printer.query_material().await? {
+    let mat = material_or_default(&printer).await?;
+
+    eprintln!("Printing test pattern on {} label...", label_size(&mat));
+    printer.test_print(&mat, density).await?;
+    eprintln!("Done.");
+    Ok(())
+}
+
+/// `W mm x H mm`, or `W mm continuous` for tape with no label length.
+fn label_size(mat: &MaterialInfo) -> String {
+    if mat.is_continuous() {
+        format!("{}mm continuous", mat.width_mm)
+    } else {
+        format!("{}mm x {}mm", mat.width_mm, mat.height_mm)
+    }
+}
+
+/// The loaded roll, or printhead-width defaults when none is reported.
+async fn material_or_default(printer: &Printer) -> Result<MaterialInfo, Box<dyn Error>> {
+    Ok(match printer.query_material().await? {
         Some(m) => m,
         None => {
             eprintln!(
@@ -159,17 +580,254 @@ async fn cmd_test_print(target: &str, density: u8) -> CliResult {
                 ..Default::default()
             }
         }
+    })
+}
+
+/// How the loaded roll feeds; die-cut labels when none is reported.
+async fn media_kind(printer: &Printer) -> Result<MediaKind, Box<dyn Error>> {
+    Ok(printer
+        .query_material()
+        .await?
+        .map_or(MediaKind::Gap, |mat| mat.media_kind()))
+}
+
+async fn cmd_print(
+    target: &str,
+    path: &Path,
+    density: u8,
+    copies: u32,
+    rotation: Rotation,
+    scale: ScaleMode,
+) -> CliResult {
+    let bytes = std::fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    // Decode before connecting so a bad file fails without touching the printer.
+    let image = if print::is_raster(&bytes) {
+        None
+    } else {
+        Some(image::load_from_memory(&bytes).map_err(|e| format!("{}: {e}", path.display()))?)
+    };
+
+    let printer = connect(target)?;
+    let (pages, media) = match image {
+        None => (
+            print::raster_pages(&bytes, PRINTER_DPI).await?,
+            media_kind(&printer).await?,
+        ),
+        Some(img) => {
+            let mat = material_or_default(&printer).await?;
+            let geom = LabelGeometry::from_roll(&mat, PRINTER_DPI, PRINTHEAD_WIDTH_DOTS);
+            (
+                vec![print::image_to_label(&img, &geom, rotation, scale)],
+                mat.media_kind(),
+            )
+        }
+    };
+
+    let total = pages.len() as u32 * copies;
+    let mut n = 0;
+    for copy in 0..copies {
+        for (i, page) in pages.iter().enumerate() {
+            n += 1;
+            eprintln!(
+                "[{n}/{total}] page {} copy {}: {}x{} dots",
+                i + 1,
+                copy + 1,
+                page.width(),
+                page.height()
+            );
+            printer
+                .print_raster(
+                    page.data(),
+                    page.width(),
+                    page.height(),
+                    PRINTHEAD_WIDTH_DOTS,
+                    density,
+                    media,
+                )
+                .await?;
+        }
+    }
+    eprintln!("Done.");
+    Ok(())
+}
+
+async fn cmd_code(target: &str, payload: &str, spec: CodeSpec, density: u8) -> CliResult {
+    let printer = connect(target)?;
+
+    let mat = printer
+        .query_material()
+        .await?
+        .ok_or("no material info (label not installed?)")?;
+    let geom = LabelGeometry::from_material(&mat, PRINTER_DPI, PRINTHEAD_WIDTH_DOTS);
+    let (canvas, module_dots) = code2d::render_on_label(payload.as_bytes(), &spec, &geom)?;
+
+    eprintln!(
+        "Printing {:?} on {} label at {module_dots} dots/module...",
+        spec.symbology,
+        label_size(&mat)
+    );
+    printer
+        .print_raster(
+            canvas.data(),
+            canvas.width(),
+            canvas.height(),
+            PRINTHEAD_WIDTH_DOTS,
+            density,
+            mat.media_kind(),
+        )
+        .await?;
+    eprintln!("Done.");
+    Ok(())
+}
+
+/// Refuse to print a template onto a roll of a different size. A zero
+/// dimension means the printer didn't report it.
+fn check_roll(template: &Template, mat: &MaterialInfo) -> CliResult {
+    let differs = |roll: u8, tpl: f32| roll != 0 && (roll as f32 - tpl).abs() >= 0.5;
+    if differs(mat.width_mm, template.width_mm) || differs(mat.height_mm, template.height_mm) {
+        return Err(format!(
+            "loaded label is {}mm x {}mm but the template is {}mm x {}mm",
+            mat.width_mm, mat.height_mm, template.width_mm, template.height_mm
+        )
+        .into());
+    }
+    Ok(())
+}
+
+async fn cmd_template_print(
+    target: &str,
+    path: &Path,
+    values: BTreeMap<String, String>,
+    density: u8,
+) -> CliResult {
+    let template = Template::load(path)?;
+    let canvas = template.render(&values, PRINTER_DPI, PRINTHEAD_WIDTH_DOTS)?;
+
+    let printer = connect(target)?;
+    let media = match printer.query_material().await? {
+        Some(mat) => {
+            check_roll(&template, &mat)?;
+            mat.media_kind()
+        }
+        None => MediaKind::Gap,
     };
 
     eprintln!(
-        "Printing test pattern on {}mm x {}mm label...",
-        mat.width_mm, mat.height_mm
+        "Printing {} ({}mm x {}mm)...",
+        path.display(),
+        template.width_mm,
+        template.height_mm
     );
-    printer.test_print(&mat, density).await?;
+    printer
+        .print_raster(
+            canvas.data(),
+            canvas.width(),
+            canvas.height(),
+            PRINTHEAD_WIDTH_DOTS,
+            density,
+            media,
+        )
+        .await?;
     eprintln!("Done.");
     Ok(())
 }
 
+async fn cmd_template_batch(args: BatchArgs) -> CliResult {
+    let template = Template::load(&args.template)?;
+    let mut range = args.rows;
+    if let Some(n) = args.resume_from {
+        range.first = range.first.max(n);
+    }
+    let rows = batch::read_rows(&args.csv, range)?;
+    if rows.is_empty() {
+        return Err(format!("no rows of {} in the selected range", args.csv.display()).into());
+    }
+
+    // Render everything up front so a bad row fails before any label is used.
+    let shared: BTreeMap<String, String> = args.set.into_iter().collect();
+    let mut labels = Vec::with_capacity(rows.len());
+    for row in rows {
+        let mut values = shared.clone();
+        values.extend(row.values);
+        let canvas = template
+            .render(&values, PRINTER_DPI, PRINTHEAD_WIDTH_DOTS)
+            .map_err(|e| format!("row {}: {e}", row.number))?;
+        labels.push((row.number, canvas));
+    }
+
+    if let Some(dir) = args.dry_run {
+        std::fs::create_dir_all(&dir)?;
+        for (number, canvas) in &labels {
+            let path = dir.join(format!("row-{number:04}.pbm"));
+            std::fs::write(&path, canvas.to_pbm())?;
+        }
+        eprintln!("Wrote {} previews to {}", labels.len(), dir.display());
+        return Ok(());
+    }
+
+    let target = args.target.as_deref().ok_or("no target printer")?;
+    let printer = connect(target)?;
+    let media = match printer.query_material().await? {
+        Some(mat) => {
+            check_roll(&template, &mat)?;
+            mat.media_kind()
+        }
+        None => MediaKind::Gap,
+    };
+    let total = labels.len();
+    for (i, (number, canvas)) in labels.iter().enumerate() {
+        eprintln!("[{}/{total}] row {number}", i + 1);
+        printer
+            .print_raster(
+                canvas.data(),
+                canvas.width(),
+                canvas.height(),
+                PRINTHEAD_WIDTH_DOTS,
+                args.density,
+                media,
+            )
+            .await
+            .map_err(|e| format!("row {number}: {e}\nResume with --resume-from {number}"))?;
+    }
+    eprintln!("Done: {total} labels.");
+    Ok(())
+}
+
+fn cmd_template_check(path: &Path, family: &str) -> CliResult {
+    let template = Template::load(path)?;
+    let family = models::family(family)?;
+    // Validated positive by `supvan_render::models::parse`.
+    let media_mm: Vec<[u32; 2]> = family
+        .media_mm
+        .iter()
+        .map(|&[w, h]| [w as u32, h as u32])
+        .collect();
+    template.check_media(&media_mm)?;
+    // With every field resolvable (defaults or inline values), a trial render
+    // at the family's resolution also catches codes and text that don't fit.
+    let values = BTreeMap::new();
+    if template.resolve(&values).is_ok() {
+        template.render(&values, family.dpi as u32, family.printhead_dots)?;
+    }
+
+    println!(
+        "{}: {}mm x {}mm, {} elements, OK for {}",
+        path.display(),
+        template.width_mm,
+        template.height_mm,
+        template.elements.len(),
+        family.description
+    );
+    for (name, def) in &template.fields {
+        match (&def.default, &def.description) {
+            (Some(d), _) => println!("  {{{name}}} default \"{d}\""),
+            (None, Some(desc)) => println!("  {{{name}}} required — {desc}"),
+            (None, None) => println!("  {{{name}}} required"),
+        }
+    }
+    Ok(())
+}
+
 async fn cmd_feed(target: &str) -> CliResult {
     let printer = connect(target)?;
     printer.paper_skip().await?;
@@ -194,11 +852,56 @@ async fn main() -> ExitCode {
         Command::Probe { target } => cmd_probe(&target).await,
         Command::Material { target } => cmd_material(&target).await,
         Command::TestPrint { target, density } => cmd_test_print(&target, density).await,
+        Command::Print {
+            target,
+            file,
+            density,
+            copies,
+            rotate,
+            scale,
+        } => cmd_print(&target, &file, density, copies, rotate, scale).await,
+        Command::Code {
+            target,
+            payload,
+            symbology,
+            ec,
+            quiet_zone,
+            density,
+        } => {
+            let spec = CodeSpec {
+                symbology,
+                ec_level: ec,
+                quiet_zone,
+            };
+            cmd_code(&target, &payload, spec, density).await
+        }
+        Command::Template { action } => match action {
+            TemplateAction::Print {
+                target,
+                template,
+                set,
+                density,
+            } => cmd_template_print(&target, &template, set.into_iter().collect(), density).await,
+            TemplateAction::Batch(args) => cmd_template_batch(args).await,
+            TemplateAction::Check { template, family } => cmd_template_check(&template, &family),
+        },
         Command::Feed { target } => cmd_feed(&target).await,
         Command::Discover => {
             cmd_discover();
             Ok(())
         }
+        #[cfg(feature = "pairing")]
+        Command::Pair { address, pin } => cmd_pair(&address, pin),
+        Command::Printers { action } => cmd_printers(action.unwrap_or(PrintersAction::List)),
+        Command::Models { action } => match action {
+            ModelsAction::Check { file } => cmd_models_check(&file),
+        },
+        Command::Rolls { uuid } => cmd_rolls(uuid.as_deref()),
+        Command::Ledger {
+            format,
+            printer,
+            summary,
+        } => cmd_ledger(format, printer.as_deref(), summary),
     };
 
     match result {
@@ -212,7 +915,10 @@ async fn main() -> ExitCode {
 
 #[cfg(test)]
 mod tests {
-    use super::{Cli, Command};
+    use super::{
+        Cli, Command, EcLevel, ExportFormat, ModelsAction, PrintersAction, Rotation, RowRange,
+        ScaleMode, Symbology, TemplateAction,
+    };
     use clap::Parser;
 
     #[test]
@@ -249,6 +955,157 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_print_options() {
+        let cli = Cli::try_parse_from([
+            "supvan-cli",
+            "print",
+            "/dev/hidraw3",
+            "label.png",
+            "-n",
+            "3",
+            "--rotate",
+            "auto",
+            "--scale",
+            "none",
+        ])
+        .unwrap();
+        match cli.command {
+            Command::Print {
+                file,
+                density,
+                copies,
+                rotate,
+                scale,
+                ..
+            } => {
+                assert_eq!(file.to_str(), Some("label.png"));
+                assert_eq!((density, copies), (4, 3));
+                assert_eq!(rotate, Rotation::Auto);
+                assert_eq!(scale, ScaleMode::None);
+            }
+            _ => panic!("expected Print"),
+        }
+        assert!(Cli::try_parse_from(["supvan-cli", "print", "t", "f", "--rotate", "45"]).is_err());
+    }
+
+    #[test]
+    fn parse_code_options() {
+        let cli = Cli::try_parse_from([
+            "supvan-cli",
+            "code",
+            "/dev/hidraw3",
+            "ASSET-0042",
+            "--symbology",
+            "datamatrix",
+            "--ec",
+            "h",
+            "--quiet-zone",
+            "2",
+        ])
+        .unwrap();
+        match cli.command {
+            Command::Code {
+                target,
+                payload,
+                symbology,
+                ec,
+                quiet_zone,
+                density,
+            } => {
+                assert_eq!(target, "/dev/hidraw3");
+                assert_eq!(payload, "ASSET-0042");
+                assert_eq!(symbology, Symbology::DataMatrix);
+                assert_eq!(ec, EcLevel::H);
+                assert_eq!(quiet_zone, Some(2));
+                assert_eq!(density, 4);
+            }
+            _ => panic!("expected Code"),
+        }
+    }
+
+    #[test]
+    fn parse_template_print_fields() {
+        let cli = Cli::try_parse_from([
+            "supvan-cli",
+            "template",
+            "print",
+            "/dev/hidraw3",
+            "asset.toml",
+            "--set",
+            "sku=A=1",
+            "-s",
+            "site=HQ",
+        ])
+        .unwrap();
+        match cli.command {
+            Command::Template {
+                action: TemplateAction::Print { template, set, .. },
+            } => {
+                assert_eq!(template.to_str(), Some("asset.toml"));
+                assert_eq!(
+                    set,
+                    [
+                        ("sku".to_string(), "A=1".to_string()),
+                        ("site".to_string(), "HQ".to_string())
+                    ]
+                );
+            }
+            _ => panic!("expected Template Print"),
+        }
+        assert!(
+            Cli::try_parse_from([
+                "supvan-cli",
+                "template",
+                "print",
+                "t",
+                "a.toml",
+                "--set",
+                "x"
+            ])
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn parse_template_batch() {
+        let cli = Cli::try_parse_from([
+            "supvan-cli",
+            "template",
+            "batch",
+            "asset.toml",
+            "skus.csv",
+            "--dry-run",
+            "/tmp/out",
+            "--rows",
+            "10-20",
+            "--resume-from",
+            "14",
+        ])
+        .unwrap();
+        match cli.command {
+            Command::Template {
+                action: TemplateAction::Batch(args),
+            } => {
+                assert_eq!(
+                    args.rows,
+                    RowRange {
+                        first: 10,
+                        last: Some(20)
+                    }
+                );
+                assert_eq!(args.resume_from, Some(14));
+                assert!(args.target.is_none());
+            }
+            _ => panic!("expected Template Batch"),
+        }
+        // Either a printer or a preview directory is required, not both.
+        let base = ["supvan-cli", "template", "batch", "a.toml", "b.csv"];
+        assert!(Cli::try_parse_from(base).is_err());
+        assert!(Cli::try_parse_from([&base[..], &["-t", "x", "--dry-run", "d"]].concat()).is_err());
+        assert!(Cli::try_parse_from([&base[..], &["-t", "/dev/hidraw3"]].concat()).is_ok());
+    }
+
     #[test]
     fn parse_feed_with_target() {
         let cli = Cli::try_parse_from(["supvan-cli", "feed", "/dev/hidraw3"]).unwrap();
@@ -263,4 +1120,96 @@ mod tests {
         let cli = Cli::try_parse_from(["supvan-cli", "discover"]).unwrap();
         assert!(matches!(cli.command, Command::Discover));
     }
+
+    #[test]
+    #[cfg(feature = "pairing")]
+    fn parse_pair_with_pin() {
+        let cli = Cli::try_parse_from(["supvan-cli", "pair", "A4:93:40:AF:B0:B5"]).unwrap();
+        assert!(matches!(cli.command, Command::Pair { pin: None, .. }));
+        let cli = Cli::try_parse_from(["supvan-cli", "pair", "A4:93:40:AF:B0:B5", "--pin", "1234"])
+            .unwrap();
+        match cli.command {
+            Command::Pair { address, pin } => {
+                assert_eq!(address, "A4:93:40:AF:B0:B5");
+                assert_eq!(pin.as_deref(), Some("1234"));
+            }
+            _ => panic!("expected Pair"),
+        }
+    }
+
+    #[test]
+    fn parse_printers_actions() {
+        let cli = Cli::try_parse_from(["supvan-cli", "printers"]).unwrap();
+        assert!(matches!(cli.command, Command::Printers { action: None }));
+        let cli =
+            Cli::try_parse_from(["supvan-cli", "printers", "rename", "t50-a", "Desk 2"]).unwrap();
+        match cli.command {
+            Command::Printers {
+                action: Some(PrintersAction::Rename { printer, name }),
+            } => assert_eq!((printer.as_str(), name.as_str()), ("t50-a", "Desk 2")),
+            _ => panic!("expected Printers Rename"),
+        }
+        let cli = Cli::try_parse_from(["supvan-cli", "printers", "forget", "t50-a"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Printers {
+                action: Some(PrintersAction::Forget { .. })
+            }
+        ));
+    }
+
+    #[test]
+    fn parse_models_check() {
+        let cli = Cli::try_parse_from(["supvan-cli", "models", "check", "models.toml"]).unwrap();
+        match cli.command {
+            Command::Models {
+                action: ModelsAction::Check { file },
+            } => assert_eq!(file.to_str(), Some("models.toml")),
+            _ => panic!("expected Models Check"),
+        }
+        assert!(Cli::try_parse_from(["supvan-cli", "models", "check"]).is_err());
+    }
+
+    #[test]
+    fn parse_rolls_with_and_without_uuid() {
+        let cli = Cli::try_parse_from(["supvan-cli", "rolls"]).unwrap();
+        assert!(matches!(cli.command, Command::Rolls { uuid: None }));
+        let cli = Cli::try_parse_from(["supvan-cli", "rolls", "04A1B2C3"]).unwrap();
+        match cli.command {
+            Command::Rolls { uuid } => assert_eq!(uuid.as_deref(), Some("04A1B2C3")),
+            _ => panic!("expected Rolls"),
+        }
+    }
+
+    #[test]
+    fn parse_ledger_export_options() {
+        let cli = Cli::try_parse_from(["supvan-cli", "ledger"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Ledger {
+                format: ExportFormat::Csv,
+                printer: None,
+                summary: false
+            }
+        ));
+        let cli = Cli::try_parse_from([
+            "supvan-cli",
+            "ledger",
+            "--format",
+            "json",
+            "--printer",
+            "supvan_t50",
+        ])
+        .unwrap();
+        match cli.command {
+            Command::Ledger {
+                format, printer, ..
+            } => {
+                assert_eq!(format, ExportFormat::Json);
+                assert_eq!(printer.as_deref(), Some("supvan_t50"));
+            }
+            _ => panic!("expected Ledger"),
+        }
+        assert!(Cli::try_parse_from(["supvan-cli", "ledger", "--format", "xml"]).is_err());
+    }
 }
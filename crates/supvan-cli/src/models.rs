@@ -0,0 +1,81 @@
+//! The printer model table (`data/models.toml`), for commands that validate
+//! against a family's media sizes or pair a printer.
+//!
+//! Looked up the same way the printer app does: `$SUPVAN_MODELS`, then
+//! `data/models.toml`, then the system install, then the copy baked into the
+//! binary. Validated by [`supvan_render::models::parse`], so every command
+//! accepts exactly the tables `models check` and the printer app accept.
+
+use std::error::Error;
+
+use supvan_render::models::{self as table, Family, ModelTable};
+
+const EMBEDDED_MODELS: &str = include_str!("../../../data/models.toml");
+
+const SEARCH_PATHS: [&str; 2] = [
+    "data/models.toml",
+    "/usr/share/supvan-printer-app/models.toml",
+];
+
+/// Load and validate the table, reporting where it came from.
+fn load() -> Result<(ModelTable, String), Box<dyn Error>> {
+    let path = std::env::var("SUPVAN_MODELS").ok().or_else(|| {
+        SEARCH_PATHS
+            .into_iter()
+            .find(|p| std::path::Path::new(p).exists())
+            .map(str::to_string)
+    });
+    let (contents, source) = match path {
+        Some(p) => (
+            std::fs::read_to_string(&p).map_err(|e| format!("{p}: {e}"))?,
+            p,
+        ),
+        None => (EMBEDDED_MODELS.to_string(), "<embedded>".to_string()),
+    };
+    let table = table::parse(&contents).map_err(|e| format!("{source}: {e}"))?;
+    Ok((table, source))
+}
+
+/// The pairing PIN for a printer advertising `name`, the same way the
+/// printer app picks it: the `bt_pin` of the family its name matches, else
+/// of the default family.
+#[cfg(feature = "pairing")]
+pub fn bt_pin(name: &str) -> Result<String, Box<dyn Error>> {
+    Ok(load()?.0.family_for_name(name).bt_pin.clone())
+}
+
+/// Look up one family by driver name (e.g. `supvan_t50`).
+pub fn family(name: &str) -> Result<Family, Box<dyn Error>> {
+    let (table, source) = load()?;
+    table.family(name).cloned().ok_or_else(|| {
+        let known: Vec<&str> = table.families.iter().map(|f| f.name.as_str()).collect();
+        format!(
+            "no family '{name}' in {source} (known: {})",
+            known.join(", ")
+        )
+        .into()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn embedded_table_has_t50_media() {
+        let table = table::parse(EMBEDDED_MODELS).unwrap();
+        let t50 = table.family("supvan_t50").unwrap();
+        assert_eq!((t50.dpi, t50.printhead_dots), (203, 384));
+        assert!(t50.media_mm.contains(&[25, 25]));
+    }
+
+    #[test]
+    fn pairing_pin_follows_the_bt_patterns() {
+        let mut table = table::parse(EMBEDDED_MODELS).unwrap();
+        assert_eq!(table.family_for_name("T50 Pro").bt_pin, "0000");
+        let g = table.families.iter_mut().find(|f| f.name == "supvan_g");
+        g.unwrap().bt_pin = "1234".into();
+        assert_eq!(table.family_for_name("G15Mini").bt_pin, "1234");
+        assert_eq!(table.family_for_name("Headphones").name, "supvan_t50");
+    }
+}
@@ -0,0 +1,190 @@
+//! Decoding for `supvan-cli print`. Image files go through the same
+//! fit → dither steps as the app's `image/jpeg` path; CUPS / PWG raster pages
+//! go through [`supvan_render::raster`] like the app's raster path: `black_1`
+//! at the printer's resolution as-is, anything else converted to gray,
+//! resampled to the printer's resolution and dithered.
+
+use std::error::Error;
+use std::io::Cursor;
+use std::pin::pin;
+
+use print_raster::reader::cups::unified::CupsRasterUnifiedReader;
+use print_raster::reader::{RasterPageReader, RasterReader};
+use supvan_render::canvas::Canvas;
+use supvan_render::dither::dither_canvas;
+use supvan_render::imaging::{self, Rotation, ScaleMode};
+use supvan_render::label::LabelGeometry;
+use supvan_render::raster::{self, RasterFormat};
+use tokio_util::compat::TokioAsyncReadCompatExt;
+
+/// CUPS raster sync words (v1/v2/v3, either byte order); PWG raster is a
+/// `RaS2` stream.
+const RASTER_SYNC: [&[u8; 4]; 6] = [b"RaSt", b"tSaR", b"RaS2", b"2SaR", b"RaS3", b"3SaR"];
+
+/// Whether `bytes` is a CUPS or PWG raster stream.
+pub fn is_raster(bytes: &[u8]) -> bool {
+    RASTER_SYNC.iter().any(|sync| bytes.starts_with(*sync))
+}
+
+/// Rotate, scale and dither a decoded image onto the label. On continuous
+/// media the label is as long as the rotated image at the tape width.
+pub fn image_to_label(
+    img: &image::DynamicImage,
+    geom: &LabelGeometry,
+    rotation: Rotation,
+    scale: ScaleMode,
+) -> Canvas {
+    let w = geom.width_dots;
+    let luma = imaging::luma_on_white(img);
+    let (luma, h) = if geom.is_continuous() {
+        let luma = imaging::rotate(luma, rotation, w, u32::MAX);
+        let h = imaging::continuous_length(luma.width(), luma.height(), w, scale);
+        (luma, h)
+    } else {
+        let h = geom.height_dots;
+        (imaging::rotate(luma, rotation, w, h), h)
+    };
+    dither_canvas(&imaging::fit_luma(&luma, w, h, scale), w, h)
+}
+
+/// Decode every page of a CUPS / PWG raster stream for a `dpi` printer.
+pub async fn raster_pages(bytes: &[u8], dpi: u32) -> Result<Vec<Canvas>, Box<dyn Error>> {
+    let stream = pin!(Cursor::new(bytes).compat());
+    let reader = CupsRasterUnifiedReader::new(stream)
+        .await
+        .map_err(|e| format!("raster: {e}"))?;
+    let mut pages = Vec::new();
+    let mut next = reader
+        .next_page()
+        .await
+        .map_err(|e| format!("raster page: {e}"))?;
+    while let Some(mut page) = next {
+        let h = &page.header().v1;
+        let (width, height, bpl) = (h.width, h.height, h.bytes_per_line);
+        let res = (h.resolution.cross_feed, h.resolution.feed);
+        let n = pages.len() + 1;
+        let format = RasterFormat::of(
+            h.color_space,
+            h.color_order,
+            h.bits_per_color,
+            h.bits_per_pixel,
+        )
+        .ok_or_else(|| {
+            format!(
+                "raster page {n}: unsupported {:?} {}-bit {:?} pixels",
+                h.color_space, h.bits_per_pixel, h.color_order
+            )
+        })?;
+        let size = format
+            .page_bytes(width, height, bpl)
+            .map_err(|e| format!("raster page {n}: {e}"))?;
+        let mut data = vec![0u8; size];
+        futures::AsyncReadExt::read_exact(page.content_mut(), &mut data)
+            .await
+            .map_err(|e| format!("raster page {n}: {e}"))?;
+        // Unknown (zero) resolution is taken as native.
+        let native = [res.0, res.1].iter().all(|&r| r == dpi || r == 0);
+        let canvas = if native && format == RasterFormat::Black1 {
+            // Re-pack at the canvas stride in case the header pads lines.
+            let mut c = Canvas::new(width, height);
+            let cbpl = c.bytes_per_line() as usize;
+            for (dst, src) in c
+                .data_mut()
+                .chunks_exact_mut(cbpl)
+                .zip(data.chunks_exact(bpl as usize))
+            {
+                dst.copy_from_slice(&src[..cbpl]);
+            }
+            c
+        } else {
+            let luma = format.to_luma(width, height, bpl, &data);
+            let luma = raster::resample_to_dpi(&luma, res, dpi);
+            dither_canvas(luma.as_raw(), luma.width(), luma.height())
+        };
+        pages.push(canvas);
+        next = page
+            .next_page()
+            .await
+            .map_err(|e| format!("raster page {}: {e}", n + 1))?;
+    }
+    if pages.is_empty() {
+        return Err("raster stream has no pages".into());
+    }
+    Ok(pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, GrayImage, Luma};
+
+    #[test]
+    fn sniffs_raster_sync_words() {
+        assert!(is_raster(b"RaS2PwgRaster\0"));
+        assert!(is_raster(b"3SaR...."));
+        assert!(!is_raster(b"\x89PNG\r\n"));
+        assert!(!is_raster(b"P4\n"));
+    }
+
+    /// An uncompressed CUPS v3 stream of one `w` × `h` page of `bpl`-byte
+    /// lines at `res` dpi.
+    fn raster(color_space: u32, bpp: u32, res: u32, (w, h, bpl): (u32, u32, u32)) -> Vec<u8> {
+        let mut header = vec![0u8; 1796];
+        for (at, v) in [
+            (276, res),
+            (280, res),
+            (372, w),
+            (376, h),
+            (384, bpp),
+            (388, bpp),
+            (392, bpl),
+            (400, color_space),
+        ] {
+            header[at..at + 4].copy_from_slice(&u32::to_be_bytes(v));
+        }
+        let data = vec![0u8; (bpl as usize).saturating_mul(h as usize).min(1 << 16)];
+        [b"RaS3".as_slice(), &header, &data].concat()
+    }
+
+    const BLACK: u32 = 3;
+    const SGRAY: u32 = 18;
+
+    #[tokio::test]
+    async fn raster_pages_honour_color_space_and_resolution() {
+        // sgray_1: 0 is black, so an all-zero page burns every dot.
+        let pages = raster_pages(&raster(SGRAY, 1, 203, (8, 2, 1)), 203)
+            .await
+            .unwrap();
+        assert!(pages[0].data().iter().all(|&b| b == 0xFF));
+        // black_1: 0 is white.
+        let pages = raster_pages(&raster(BLACK, 1, 203, (8, 2, 1)), 203)
+            .await
+            .unwrap();
+        assert!(pages[0].data().iter().all(|&b| b == 0));
+        // A 406 dpi page comes down to half its dots.
+        let pages = raster_pages(&raster(SGRAY, 8, 406, (16, 4, 16)), 203)
+            .await
+            .unwrap();
+        assert_eq!((pages[0].width(), pages[0].height()), (8, 2));
+    }
+
+    #[tokio::test]
+    async fn malformed_raster_headers_are_errors() {
+        for (w, h, bpl) in [(16, 2, 1), (0, 2, 0), (8, 2, 0), (65536, 65536, 65536)] {
+            let err = raster_pages(&raster(SGRAY, 8, 203, (w, h, bpl)), 203).await;
+            assert!(err.is_err(), "{w}x{h}, {bpl} bytes per line");
+        }
+        let err = raster_pages(&raster(BLACK, 1, 203, (16, 2, 1)), 203).await;
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn image_fills_label_geometry() {
+        let geom = LabelGeometry::from_mm(40.0, 30.0, 203, 384);
+        let img = DynamicImage::ImageLuma8(GrayImage::from_pixel(8, 6, Luma([0])));
+        let c = image_to_label(&img, &geom, Rotation::R0, ScaleMode::Fit);
+        assert_eq!((c.width(), c.height()), (320, 240));
+        // Same aspect as the label, so solid black edge to edge.
+        assert!(c.data().iter().all(|&b| b == 0xFF));
+    }
+}
@@ -8,15 +8,38 @@ pub const PRINT_BUF_SIZE: usize = 4096;
 pub const PRINT_BUF_HEADER: usize = 14;
 
 /// Margin clamp range (dots) for the print-buffer header.
-const MARGIN_MAX_DOTS: u16 = 900;
+pub const MARGIN_MAX_DOTS: u16 = 900;
 
 /// Maximum density / red-deepness value encoded in the buffer header.
-const MAX_DENSITY: u8 = 15;
+pub const MAX_DENSITY: u8 = 15;
 
 /// The firmware re-reads the running checksum at every Nth byte; the builder
 /// folds in the byte just before each boundary.
 const CHECKSUM_STRIDE: usize = 256;
 
+/// Media the roll carries, encoded in the `Mat` bits of PAGE_REG_BITS.
+///
+/// With gap media the firmware feeds to the next label gap after each page;
+/// continuous media stops exactly where the page's columns end.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MediaKind {
+    /// Die-cut labels separated by a gap (or black mark).
+    #[default]
+    Gap,
+    /// Continuous tape with no gaps; the page defines the label length.
+    Continuous,
+}
+
+impl MediaKind {
+    /// Value of the 2-bit `Mat` field.
+    pub fn mat_bits(self) -> u8 {
+        match self {
+            MediaKind::Gap => 1,
+            MediaKind::Continuous => 0,
+        }
+    }
+}
+
 /// Parameters for PAGE_REG_BITS construction.
 #[derive(Debug, Clone, Default)]
 pub struct PageRegBits {
@@ -79,6 +102,7 @@ pub struct PrintBufferParams<'a> {
     pub margin_top: u16,
     pub margin_bottom: u16,
     pub density: u8,
+    pub media: MediaKind,
 }
 
 /// Build a 4096-byte print buffer.
@@ -103,7 +127,7 @@ pub fn build_print_buffer(p: &PrintBufferParams) -> [u8; PRINT_BUF_SIZE] {
         page_end: p.page_end,
         prt_end: p.prt_end,
         nodu: p.density,
-        mat: 1,
+        mat: p.media.mat_bits(),
         ..Default::default()
     });
     buf[2] = page_bits[0];
@@ -128,8 +152,15 @@ pub fn build_print_buffer(p: &PrintBufferParams) -> [u8; PRINT_BUF_SIZE] {
     let data_len = p.image_data.len().min(PRINT_BUF_SIZE - PRINT_BUF_HEADER);
     buf[PRINT_BUF_HEADER..PRINT_BUF_HEADER + data_len].copy_from_slice(&p.image_data[..data_len]);
 
-    // Checksum: sum(buf[2..14]) + sum of bytes at each 256-byte boundary
-    let data_end = (p.cols_in_buf as usize) * (p.per_line_byte as usize) + PRINT_BUF_HEADER;
+    write_checksum(&mut buf);
+    buf
+}
+
+/// Checksum: sum(buf[2..14]) + sum of bytes at each 256-byte boundary of the
+/// image data the header declares.
+fn write_checksum(buf: &mut [u8; PRINT_BUF_SIZE]) {
+    let cols = u16::from_le_bytes([buf[4], buf[5]]) as usize;
+    let data_end = cols * buf[6] as usize + PRINT_BUF_HEADER;
     let mut chk: u32 = buf[2..14].iter().map(|&b| b as u32).sum();
     let n_strides = data_end / CHECKSUM_STRIDE;
     for i in 1..=n_strides {
@@ -139,8 +170,13 @@ pub fn build_print_buffer(p: &PrintBufferParams) -> [u8; PRINT_BUF_SIZE] {
         }
     }
     buf[0..2].copy_from_slice(&(chk as u16).to_le_bytes());
+}
 
-    buf
+/// Set the `Cut` mode (PAGE_REG_BITS byte 0, bits 4-6) of a built buffer,
+/// for printers with a cutter. Mode 1 cuts once the buffer's page ends.
+pub fn set_cut(buf: &mut [u8; PRINT_BUF_SIZE], cut: u8) {
+    buf[2] = (buf[2] & !0x70) | ((cut & 0x07) << 4);
+    write_checksum(buf);
 }
 
 /// Split column-major image data into multiple print buffers.
@@ -153,6 +189,7 @@ pub fn split_into_buffers(
     margin_top: u16,
     margin_bottom: u16,
     density: u8,
+    media: MediaKind,
 ) -> Vec<[u8; PRINT_BUF_SIZE]> {
     let max_cols = (MAX_BUF_DATA / per_line_byte as usize) as u16;
     let image_cols = total_cols - margin_top - margin_bottom;
@@ -179,6 +216,7 @@ pub fn split_into_buffers(
             margin_top,
             margin_bottom,
             density,
+            media,
         });
         buffers.push(buf);
         current_col += cols_in_buf;
@@ -233,6 +271,7 @@ mod tests {
             margin_top: 8,
             margin_bottom: 8,
             density: 4,
+            media: MediaKind::Gap,
         });
         // Verify buffer structure
         assert_eq!(buf[6], 48); // bytes per line
@@ -253,7 +292,48 @@ mod tests {
         let per_line_byte = 48u8;
         let total_cols = 240u16;
         let image_data = vec![0u8; total_cols as usize * per_line_byte as usize];
-        let bufs = split_into_buffers(&image_data, per_line_byte, total_cols, 8, 8, 4);
+        let bufs = split_into_buffers(
+            &image_data,
+            per_line_byte,
+            total_cols,
+            8,
+            8,
+            4,
+            MediaKind::Gap,
+        );
         assert_eq!(bufs.len(), 3);
     }
+
+    #[test]
+    fn continuous_media_clears_the_mat_bits() {
+        let image_data = vec![0u8; 40 * 48];
+        let gap = split_into_buffers(&image_data, 48, 40, 8, 8, 4, MediaKind::Gap);
+        let cont = split_into_buffers(&image_data, 48, 40, 8, 8, 4, MediaKind::Continuous);
+        assert_eq!(gap[0][3] >> 6, 1);
+        assert_eq!(cont[0][3] >> 6, 0);
+        assert_eq!(cont[0][3] & 0x3F, gap[0][3] & 0x3F);
+    }
+
+    #[test]
+    fn cut_mode_is_set_with_a_fresh_checksum() {
+        let image_data = vec![0x5Au8; 40 * 48];
+        let mut bufs = split_into_buffers(&image_data, 48, 40, 8, 8, 4, MediaKind::Gap);
+        let built = build_print_buffer(&PrintBufferParams {
+            image_data: &image_data[8 * 48..32 * 48],
+            per_line_byte: 48,
+            cols_in_buf: 24,
+            page_st: true,
+            page_end: true,
+            prt_end: true,
+            margin_top: 8,
+            margin_bottom: 8,
+            density: 4,
+            media: MediaKind::Gap,
+        });
+        assert_eq!(bufs[0], built);
+        set_cut(&mut bufs[0], 1);
+        assert_eq!(bufs[0][2], 0x1E);
+        let chk = u16::from_le_bytes([built[0], built[1]]).wrapping_add(0x10);
+        assert_eq!(u16::from_le_bytes([bufs[0][0], bufs[0][1]]), chk);
+    }
 }
@@ -0,0 +1,158 @@
+//! What a printer family can do, as far as the protocol is concerned.
+//!
+//! The wire format is shared across the range, but families differ in how
+//! they take density, how fast they feed, how much they accept per
+//! transfer and what hardware they carry. `models.toml` describes each
+//! family's [`Capabilities`]; the defaults are the T50's, which every family
+//! used before the table could say otherwise.
+
+use std::ops::RangeInclusive;
+
+use crate::bitmap::DEFAULT_MARGIN_DOTS;
+use crate::speed::SpeedTable;
+use crate::status::PrinterStatus;
+
+/// Transports a printer can be reached over, in the default preference order.
+pub const TRANSPORTS: [&str; 3] = ["usb", "bt", "ble"];
+
+/// The most compressed bytes one transfer can declare: BUF_FULL carries the
+/// length as a u16.
+pub const MAX_TRANSFER_BYTES: usize = u16::MAX as usize;
+
+/// How the printer takes the print density.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DensityMode {
+    /// In byte 12 (and the `Nodu` bits) of every print buffer header.
+    #[default]
+    Header,
+    /// With SEND_INF (0x35) before START_PRINT; the headers carry 0.
+    Command,
+}
+
+impl std::str::FromStr for DensityMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "header" => Ok(Self::Header),
+            "command" => Ok(Self::Command),
+            other => Err(format!(
+                "unknown density mode '{other}' (expected header or command)"
+            )),
+        }
+    }
+}
+
+/// One family's protocol capabilities.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capabilities {
+    /// Transports the family has, a subset of [`TRANSPORTS`].
+    pub transports: Vec<&'static str>,
+    /// Device density values darkness 0–100% maps onto.
+    pub density: RangeInclusive<u8>,
+    pub density_mode: DensityMode,
+    /// Transfer speed by compressed bytes per buffer.
+    pub speeds: SpeedTable,
+    /// Whether the printer cuts after each label.
+    pub cutter: bool,
+    /// Whether it prints through a ribbon. Without one, the ribbon status
+    /// flags mean nothing and are ignored.
+    pub ribbon: bool,
+    /// The RD_LAB_DPI opcode (`0x22`, `0x24` or `0x25`) that reads the loaded
+    /// label's DPI, if the family has one. Not sent yet: the response layout
+    /// is still unverified (see docs/PROTOCOL.md).
+    pub label_dpi_query: Option<u8>,
+    /// Most compressed bytes per NEXT_ZIPPEDBULK … BUF_FULL transfer; larger
+    /// pages go over several.
+    pub max_transfer_bytes: usize,
+    /// Unprinted rows at the leading and trailing edge of each label.
+    pub margin_dots: u16,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            transports: TRANSPORTS.to_vec(),
+            density: 0..=15,
+            density_mode: DensityMode::Header,
+            speeds: SpeedTable::default(),
+            cutter: false,
+            ribbon: false,
+            label_dpi_query: None,
+            max_transfer_bytes: MAX_TRANSFER_BYTES,
+            margin_dots: DEFAULT_MARGIN_DOTS,
+        }
+    }
+}
+
+impl Capabilities {
+    /// The device density for `darkness` (0–100%), rounded to nearest.
+    pub fn density_for(&self, darkness: i32) -> u8 {
+        let (min, max) = (
+            i32::from(*self.density.start()),
+            i32::from(*self.density.end()),
+        );
+        let darkness = darkness.clamp(0, 100);
+        (min + ((max - min) * darkness + 50) / 100) as u8
+    }
+
+    /// The density to write into the print buffer headers.
+    pub fn header_density(&self, density: u8) -> u8 {
+        match self.density_mode {
+            DensityMode::Header => density,
+            DensityMode::Command => 0,
+        }
+    }
+
+    /// The density to send with SEND_INF, if the family takes it that way.
+    pub fn command_density(&self, density: u8) -> Option<u8> {
+        (self.density_mode == DensityMode::Command).then_some(density)
+    }
+
+    /// `status` with the flags that cannot apply to this family cleared.
+    pub fn mask(&self, mut status: PrinterStatus) -> PrinterStatus {
+        if !self.ribbon {
+            status.ribbon_rw_error = false;
+            status.ribbon_end = false;
+        }
+        status
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn darkness_scales_onto_the_density_range() {
+        let t50 = Capabilities::default();
+        assert_eq!(
+            [0, 50, 100].map(|d| t50.density_for(d)),
+            [0, 8, 15],
+            "the T50 scale"
+        );
+        let narrow = Capabilities {
+            density: 4..=12,
+            density_mode: DensityMode::Command,
+            ..Capabilities::default()
+        };
+        assert_eq!([-5, 50, 200].map(|d| narrow.density_for(d)), [4, 8, 12]);
+        assert_eq!(narrow.header_density(8), 0);
+        assert_eq!(narrow.command_density(8), Some(8));
+        assert_eq!(t50.command_density(8), None);
+    }
+
+    #[test]
+    fn ribbon_flags_need_a_ribbon() {
+        let status = PrinterStatus {
+            ribbon_end: true,
+            ..Default::default()
+        };
+        assert!(!Capabilities::default().mask(status.clone()).has_error());
+        let ribbon = Capabilities {
+            ribbon: true,
+            ..Capabilities::default()
+        };
+        assert!(ribbon.mask(status).has_error());
+    }
+}
@@ -100,6 +100,33 @@ pub fn compress_buffers(
     Ok((compressed, avg))
 }
 
+/// [`compress_buffers`], split into as many LZMA streams as it takes to keep
+/// each within `max_bytes`, for printers that accept less per transfer. The
+/// buffers are halved until every stream fits; a single buffer that does not
+/// fit is an error.
+///
+/// Returns (streams, average_compressed_per_buffer).
+pub fn compress_transfers(
+    buffers: &[[u8; crate::buffer::PRINT_BUF_SIZE]],
+    max_bytes: usize,
+) -> Result<(Vec<Vec<u8>>, usize)> {
+    let (whole, avg) = compress_buffers(buffers)?;
+    if whole.len() <= max_bytes {
+        return Ok((vec![whole], avg));
+    }
+    if buffers.len() == 1 {
+        return Err(Error::InvalidParam(format!(
+            "one print buffer compresses to {} bytes, over the {max_bytes}-byte transfer limit",
+            whole.len()
+        )));
+    }
+    let (first, second) = buffers.split_at(buffers.len() / 2);
+    let (mut streams, _) = compress_transfers(first, max_bytes)?;
+    streams.extend(compress_transfers(second, max_bytes)?.0);
+    let total: usize = streams.iter().map(Vec::len).sum();
+    Ok((streams, total / buffers.len()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -146,4 +173,30 @@ mod tests {
         assert!(compressed.len() > 13); // at least header
         assert!(avg > 0);
     }
+
+    #[test]
+    fn transfers_split_to_fit_the_limit() {
+        // Incompressible buffers, so each stream is about its input's size.
+        let mut seed = 1u32;
+        let buffers: Vec<_> = (0..4)
+            .map(|_| {
+                std::array::from_fn(|_| {
+                    seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                    (seed >> 16) as u8
+                })
+            })
+            .collect();
+        let (one, _) = compress_transfers(&buffers, usize::MAX).unwrap();
+        assert_eq!(one.len(), 1);
+        let (split, avg) = compress_transfers(&buffers, 9000).unwrap();
+        assert_eq!(split.len(), 2);
+        assert!(split.iter().all(|s| s.len() <= 9000));
+        assert!(avg > 4000);
+        let streams: Vec<u8> = split
+            .iter()
+            .flat_map(|s| decompress_lzma(s).unwrap())
+            .collect();
+        assert_eq!(streams, buffers.concat());
+        assert!(compress_transfers(&buffers, 1000).is_err());
+    }
 }
@@ -19,6 +19,36 @@ pub enum Error {
 
     #[error("BLE error: {0}")]
     Ble(String),
+
+    #[error("pairing failed: {0}")]
+    Pairing(String),
+
+    /// The link was lost before the printer acknowledged the label's data,
+    /// so nothing printed and the label can be sent again.
+    #[error("{0} (label not sent)")]
+    Unsent(Box<Error>),
+}
+
+impl Error {
+    /// Whether the connection itself failed (socket error, closed link, BLE
+    /// fault), as opposed to the printer answering something unexpected.
+    pub fn is_link_lost(&self) -> bool {
+        match self {
+            Self::Io(_) | Self::Ble(_) => true,
+            Self::Unsent(e) => e.is_link_lost(),
+            _ => false,
+        }
+    }
+
+    /// Mark a link failure as [`Unsent`](Self::Unsent); other errors pass
+    /// through unchanged.
+    pub(crate) fn unsent(self) -> Self {
+        if self.is_link_lost() {
+            Self::Unsent(Box::new(self))
+        } else {
+            self
+        }
+    }
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,363 @@
+//! Printer identity registry: which physical printer is which, across
+//! transports and restarts.
+//!
+//! Each printer is keyed by its canonical serial — the name the firmware
+//! reports on every transport (`RETURN_MAT`'s device SN over USB, the BlueZ
+//! `Name` over BT and BLE). Its entry records the `supvan://` slug it was
+//! given when first seen, a display name, and every USB id (serial or bus
+//! path), BT address and BLE address it has been seen on, plus its detected
+//! driver family. The slug never changes once assigned, so a printer found
+//! first over Bluetooth and later over USB keeps the same URI, and the same
+//! CUPS queue.
+//!
+//! A sighting without a serial (a USB probe that got no answer) is matched
+//! by its USB id; an unknown one creates no entry. Each USB id and address
+//! belongs to one printer: seeing it on another moves it there.
+//!
+//! The registry is a JSON file shared by the printer app (which records
+//! sightings during discovery and hotplug) and `supvan-cli printers` (which
+//! lists, renames and forgets entries).
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::ledger;
+
+/// Where the registry lives: `$SUPVAN_IDENTITIES`, else next to the printer
+/// app's state file.
+pub fn default_path() -> PathBuf {
+    ledger::state_file("SUPVAN_IDENTITIES", "supvan-printer-app.printers.json")
+}
+
+/// Slugify a printer-reported name into something CUPS can use as a queue
+/// name. Lowercase ASCII alphanumerics; everything else becomes a hyphen.
+pub fn slugify(name: &str) -> String {
+    let s: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let s: String = s
+        .split('-')
+        .filter(|p| !p.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    if s.is_empty() {
+        "printer".to_string()
+    } else {
+        s
+    }
+}
+
+/// Everything the registry knows about one printer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Identity {
+    /// Canonical serial (the firmware-reported name).
+    pub serial: String,
+    /// The printer's URI is `supvan://<slug>`.
+    pub slug: String,
+    /// Human-readable name; the serial until renamed.
+    pub display_name: String,
+    /// USB ids it has been seen on: the USB serial, or `bus-<path>`.
+    #[serde(default)]
+    pub usb: Vec<String>,
+    #[serde(default)]
+    pub bt: Vec<String>,
+    #[serde(default)]
+    pub ble: Vec<String>,
+    /// Detected driver family (e.g. `supvan_t50`).
+    #[serde(default)]
+    pub family: Option<String>,
+    /// Seconds since the Unix epoch.
+    pub first_seen: u64,
+    pub last_seen: u64,
+}
+
+impl Identity {
+    /// The printer's device URI.
+    pub fn uri(&self) -> String {
+        format!("supvan://{}", self.slug)
+    }
+
+    /// Whether `key` names this printer: its slug, serial, display name, a
+    /// USB id or an address.
+    pub fn matches(&self, key: &str) -> bool {
+        self.slug == key
+            || self.serial == key
+            || self.display_name == key
+            || self.usb.iter().any(|u| u == key)
+            || self
+                .bt
+                .iter()
+                .chain(&self.ble)
+                .any(|a| a.eq_ignore_ascii_case(key))
+    }
+}
+
+/// One printer observed on one or more transports.
+#[derive(Debug, Clone, Default)]
+pub struct Sighting {
+    /// The firmware-reported name, if the transport surfaced it.
+    pub serial: Option<String>,
+    pub usb: Option<String>,
+    pub bt: Option<String>,
+    pub ble: Option<String>,
+    pub family: Option<String>,
+}
+
+/// The printers seen so far, by serial.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Identities {
+    printers: BTreeMap<String, Identity>,
+}
+
+impl Identities {
+    /// Read the registry at `path`; a missing file is an empty registry.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match std::fs::read(path) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Write the registry to `path`, replacing it atomically.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(tmp, path)
+    }
+
+    /// All printers, by serial.
+    pub fn printers(&self) -> impl Iterator<Item = &Identity> {
+        self.printers.values()
+    }
+
+    /// The printer `key` names (see [`Identity::matches`]).
+    pub fn find(&self, key: &str) -> Option<&Identity> {
+        self.printers.values().find(|p| p.matches(key))
+    }
+
+    /// Record a sighting at `now`. Returns the printer it belongs to and
+    /// whether the registry changed (beyond `last_seen`), or `None` for a
+    /// printer that has no serial and is not known by its USB id.
+    pub fn observe(&mut self, sighting: &Sighting, now: u64) -> Option<(&Identity, bool)> {
+        let serial = match &sighting.serial {
+            Some(serial) => serial.clone(),
+            None => {
+                let usb = sighting.usb.as_deref()?;
+                self.printers
+                    .values()
+                    .find(|p| p.usb.iter().any(|u| u == usb))?
+                    .serial
+                    .clone()
+            }
+        };
+        let mut changed = false;
+        if !self.printers.contains_key(&serial) {
+            let slug = self.free_slug(&slugify(&serial));
+            self.printers.insert(
+                serial.clone(),
+                Identity {
+                    serial: serial.clone(),
+                    slug,
+                    display_name: serial.clone(),
+                    usb: Vec::new(),
+                    bt: Vec::new(),
+                    ble: Vec::new(),
+                    family: None,
+                    first_seen: now,
+                    last_seen: now,
+                },
+            );
+            changed = true;
+        }
+        for (value, field) in [
+            (&sighting.usb, Field::Usb),
+            (&sighting.bt, Field::Bt),
+            (&sighting.ble, Field::Ble),
+        ] {
+            if let Some(value) = value {
+                changed |= self.claim(&serial, field, value);
+            }
+        }
+        let printer = self.printers.get_mut(&serial)?;
+        if sighting.family.is_some() && printer.family != sighting.family {
+            printer.family = sighting.family.clone();
+            changed = true;
+        }
+        printer.last_seen = now;
+        Some((printer, changed))
+    }
+
+    /// Give the printer `key` names a new display name.
+    pub fn rename(&mut self, key: &str, display_name: &str) -> Option<&Identity> {
+        let serial = self.find(key)?.serial.clone();
+        let printer = self.printers.get_mut(&serial)?;
+        printer.display_name = display_name.to_string();
+        Some(printer)
+    }
+
+    /// Drop the printer `key` names; it gets a new entry when next seen.
+    pub fn forget(&mut self, key: &str) -> Option<Identity> {
+        let serial = self.find(key)?.serial.clone();
+        self.printers.remove(&serial)
+    }
+
+    /// `base`, or `base-2`, `base-3`, … if another printer has it.
+    fn free_slug(&self, base: &str) -> String {
+        let taken = |slug: &str| self.printers.values().any(|p| p.slug == slug);
+        if !taken(base) {
+            return base.to_string();
+        }
+        (2..)
+            .map(|n| format!("{base}-{n}"))
+            .find(|s| !taken(s))
+            .unwrap()
+    }
+
+    /// Attach `value` to `serial`'s printer, taking it from any other.
+    fn claim(&mut self, serial: &str, field: Field, value: &str) -> bool {
+        let mut changed = false;
+        for printer in self.printers.values_mut() {
+            let owner = printer.serial == serial;
+            let list = field.of(printer);
+            let has = list.iter().any(|v| field.same(v, value));
+            if owner {
+                if !has {
+                    list.push(value.to_string());
+                    changed = true;
+                }
+            } else if has {
+                list.retain(|v| !field.same(v, value));
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Field {
+    Usb,
+    Bt,
+    Ble,
+}
+
+impl Field {
+    fn of(self, printer: &mut Identity) -> &mut Vec<String> {
+        match self {
+            Field::Usb => &mut printer.usb,
+            Field::Bt => &mut printer.bt,
+            Field::Ble => &mut printer.ble,
+        }
+    }
+
+    fn same(self, a: &str, b: &str) -> bool {
+        match self {
+            Field::Usb => a == b,
+            // Bluetooth addresses compare case-insensitively.
+            Field::Bt | Field::Ble => a.eq_ignore_ascii_case(b),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seen(serial: Option<&str>, usb: Option<&str>, bt: Option<&str>) -> Sighting {
+        Sighting {
+            serial: serial.map(String::from),
+            usb: usb.map(String::from),
+            bt: bt.map(String::from),
+            ble: None,
+            family: Some("supvan_t50".into()),
+        }
+    }
+
+    #[test]
+    fn slugify_makes_queue_names() {
+        assert_eq!(slugify("T0117A2507 Pro"), "t0117a2507-pro");
+        assert_eq!(slugify("--"), "printer");
+    }
+
+    #[test]
+    fn a_printer_keeps_its_slug_across_transports() {
+        let mut ids = Identities::default();
+        let (first, changed) = ids
+            .observe(
+                &seen(Some("T50Plus-01"), None, Some("AA:BB:CC:DD:EE:FF")),
+                10,
+            )
+            .unwrap();
+        assert!(changed);
+        assert_eq!(first.uri(), "supvan://t50plus-01");
+
+        // Later over USB, first with the serial, then without it.
+        let (later, _) = ids
+            .observe(&seen(Some("T50Plus-01"), Some("bus-1-2"), None), 20)
+            .unwrap();
+        assert_eq!(later.slug, "t50plus-01");
+        assert_eq!(later.bt, ["AA:BB:CC:DD:EE:FF"]);
+        let (anon, changed) = ids.observe(&seen(None, Some("bus-1-2"), None), 30).unwrap();
+        assert_eq!((anon.serial.as_str(), changed), ("T50Plus-01", false));
+        assert_eq!(anon.last_seen, 30);
+        // An unknown printer without a serial is not registered.
+        assert!(
+            ids.observe(&seen(None, Some("bus-3-1"), None), 40)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn slugs_stay_unique_and_ports_move() {
+        let mut ids = Identities::default();
+        ids.observe(&seen(Some("T50 A"), Some("bus-1-2"), None), 1);
+        let (other, _) = ids
+            .observe(&seen(Some("t50-a"), Some("bus-1-2"), None), 2)
+            .unwrap();
+        assert_eq!(other.slug, "t50-a-2");
+        assert!(ids.find("T50 A").unwrap().usb.is_empty());
+        assert_eq!(ids.find("bus-1-2").unwrap().serial, "t50-a");
+    }
+
+    #[test]
+    fn rename_and_forget_by_any_key() {
+        let mut ids = Identities::default();
+        ids.observe(
+            &seen(Some("T50Plus-01"), None, Some("AA:BB:CC:DD:EE:FF")),
+            1,
+        );
+        let renamed = ids.rename("aa:bb:cc:dd:ee:ff", "Shipping desk").unwrap();
+        assert_eq!(renamed.slug, "t50plus-01");
+        assert_eq!(ids.find("Shipping desk").unwrap().serial, "T50Plus-01");
+        assert!(ids.forget("t50plus-01").is_some());
+        assert!(ids.find("T50Plus-01").is_none());
+        assert!(ids.rename("nothing", "x").is_none());
+    }
+
+    #[test]
+    fn registry_round_trips_through_its_file() {
+        let dir = std::env::temp_dir().join(format!("supvan-identity-{}", std::process::id()));
+        let path = dir.join("printers.json");
+        assert_eq!(Identities::load(&path).unwrap().printers().count(), 0);
+        let mut ids = Identities::default();
+        ids.observe(&seen(Some("T50Plus-01"), Some("7E1222120101"), None), 5);
+        ids.save(&path).unwrap();
+        let loaded = Identities::load(&path).unwrap();
+        assert_eq!(loaded.find("7E1222120101"), ids.find("T50Plus-01"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
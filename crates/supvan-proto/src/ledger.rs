@@ -0,0 +1,480 @@
+//! Roll and job ledgers: what each RFID-tagged roll has been seen holding,
+//! and what each print job used.
+//!
+//! The material query reports labels *remaining* but not the roll's original
+//! count, so a percentage needs a capacity from somewhere. The ledger records
+//! every roll by its tag `uuid` — the first remaining count it was seen with,
+//! the latest one, and each change in between — and derives the capacity as
+//! the largest of:
+//!
+//! - a known capacity for the roll's SKU (its tag `code`),
+//! - the largest first-seen count of any roll with the same code (a fresh
+//!   roll teaches the ledger what the SKU holds),
+//! - this roll's own first-seen count.
+//!
+//! A part-used roll seen for the first time therefore reads 100% until a
+//! fresh roll of the same SKU is loaded or its capacity is configured.
+//!
+//! The roll ledger is a JSON file shared by the printer app (which records
+//! rolls as it polls) and `supvan-cli rolls` (which reads it).
+//!
+//! The job ledger is a JSON-lines file the printer app appends a
+//! [`JobRecord`] to as each job ends: labels printed, the roll and transport
+//! used, and the device's lifetime print counter (the odometer) afterwards.
+//! `supvan-cli ledger` exports it.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::status::MaterialInfo;
+
+/// Rolls kept in the ledger; the least recently seen are dropped first.
+const MAX_ROLLS: usize = 256;
+
+/// Remaining-count changes kept per roll, besides the first.
+const MAX_SAMPLES: usize = 128;
+
+/// Where the roll ledger lives: `$SUPVAN_LEDGER`, else next to the printer
+/// app's state file.
+pub fn default_path() -> PathBuf {
+    state_file("SUPVAN_LEDGER", "supvan-printer-app.rolls.json")
+}
+
+/// Where the job ledger lives: `$SUPVAN_JOB_LEDGER`, else next to the
+/// printer app's state file.
+pub fn jobs_path() -> PathBuf {
+    state_file("SUPVAN_JOB_LEDGER", "supvan-printer-app.jobs.jsonl")
+}
+
+/// `$var`, else `name` in `$XDG_STATE_HOME`, else in `~/.local/state`.
+pub(crate) fn state_file(var: &str, name: &str) -> PathBuf {
+    if let Some(path) = std::env::var_os(var).filter(|p| !p.is_empty()) {
+        return PathBuf::from(path);
+    }
+    let dir = match std::env::var("XDG_STATE_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var("HOME").unwrap_or_else(|_| "/tmp".into()))
+            .join(".local/state"),
+    };
+    dir.join(name)
+}
+
+/// Seconds since the Unix epoch.
+pub fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// `secs` since the Unix epoch as an RFC 3339 UTC timestamp.
+pub fn format_utc(secs: u64) -> String {
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+    // Civil-from-days (H. Hinnant), shifted so years start in March.
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60
+    )
+}
+
+/// A remaining-label count at a point in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sample {
+    /// Seconds since the Unix epoch.
+    pub at: u64,
+    pub remaining: u32,
+}
+
+/// Everything the ledger knows about one roll.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RollRecord {
+    pub uuid: String,
+    /// SKU code from the tag.
+    pub code: String,
+    pub width_mm: u8,
+    pub height_mm: u8,
+    /// Printer the roll was last seen in.
+    pub printer: String,
+    /// Capacity in labels, as of the last observation.
+    pub capacity: u32,
+    /// When the roll was first seen and its count then.
+    pub first: Sample,
+    /// Each change in the remaining count since, oldest first; the last one
+    /// is the current count.
+    pub history: Vec<Sample>,
+    /// Seconds since the Unix epoch the roll was last seen.
+    pub last_seen: u64,
+}
+
+impl RollRecord {
+    /// Labels left, as last reported.
+    pub fn remaining(&self) -> u32 {
+        self.history.last().unwrap_or(&self.first).remaining
+    }
+
+    /// Labels left as a percentage of the capacity, 0–100.
+    pub fn percent(&self) -> u8 {
+        if self.capacity == 0 {
+            return 0;
+        }
+        (u64::from(self.remaining()) * 100 / u64::from(self.capacity)).min(100) as u8
+    }
+
+    /// Labels printed since the roll was first seen.
+    pub fn used(&self) -> u32 {
+        self.first.remaining.saturating_sub(self.remaining())
+    }
+}
+
+/// The rolls seen so far, plus configured per-SKU capacities.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RollLedger {
+    /// By tag uuid.
+    rolls: BTreeMap<String, RollRecord>,
+    /// Known labels per roll by SKU code; configured, not persisted.
+    #[serde(skip)]
+    capacities: HashMap<String, u32>,
+}
+
+impl RollLedger {
+    /// Read the ledger at `path`; a missing file is an empty ledger.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match std::fs::read(path) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Write the ledger to `path`, replacing it atomically.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(tmp, path)
+    }
+
+    /// Set the known labels per roll for SKU codes.
+    pub fn set_capacities(&mut self, capacities: HashMap<String, u32>) {
+        self.capacities = capacities;
+    }
+
+    /// Every roll, most recently seen first.
+    pub fn rolls(&self) -> Vec<&RollRecord> {
+        let mut rolls: Vec<_> = self.rolls.values().collect();
+        rolls.sort_by_key(|r| std::cmp::Reverse(r.last_seen));
+        rolls
+    }
+
+    /// A roll by tag uuid.
+    pub fn get(&self, uuid: &str) -> Option<&RollRecord> {
+        self.rolls.get(uuid)
+    }
+
+    /// The roll most recently seen in `printer`.
+    pub fn current(&self, printer: &str) -> Option<&RollRecord> {
+        self.rolls
+            .values()
+            .filter(|r| r.printer == printer)
+            .max_by_key(|r| r.last_seen)
+    }
+
+    /// Record a material report from `printer` at `now`. Returns the roll's
+    /// record, or `None` for a report without a tag uuid or remaining count.
+    /// The record changes only when the count does or the roll moves; only
+    /// then does the ledger need saving, which `changed` reports.
+    pub fn observe(
+        &mut self,
+        printer: &str,
+        mat: &MaterialInfo,
+        now: u64,
+    ) -> Option<(&RollRecord, bool)> {
+        let remaining = mat.remaining?;
+        if mat.uuid.is_empty() {
+            return None;
+        }
+        let sample = Sample { at: now, remaining };
+        let mut changed = false;
+        let record = self.rolls.entry(mat.uuid.clone()).or_insert_with(|| {
+            changed = true;
+            RollRecord {
+                uuid: mat.uuid.clone(),
+                code: mat.code.clone(),
+                width_mm: mat.width_mm,
+                height_mm: mat.height_mm,
+                printer: printer.to_string(),
+                capacity: remaining,
+                first: sample,
+                history: Vec::new(),
+                last_seen: now,
+            }
+        });
+        if record.remaining() != remaining {
+            if record.history.len() >= MAX_SAMPLES {
+                record.history.remove(0);
+            }
+            record.history.push(sample);
+            changed = true;
+        }
+        if record.printer != printer {
+            record.printer = printer.to_string();
+            changed = true;
+        }
+        record.last_seen = now;
+
+        let capacity = self.capacity_for(&mat.code, &mat.uuid);
+        let record = self.rolls.get_mut(&mat.uuid)?;
+        if record.capacity != capacity {
+            record.capacity = capacity;
+            changed = true;
+        }
+        if changed {
+            self.prune();
+        }
+        Some((self.rolls.get(&mat.uuid)?, changed))
+    }
+
+    /// Capacity of the roll `uuid` of SKU `code`; see the module docs.
+    fn capacity_for(&self, code: &str, uuid: &str) -> u32 {
+        let learned = self
+            .rolls
+            .values()
+            .filter(|r| !code.is_empty() && r.code == code)
+            .map(|r| r.first.remaining)
+            .max();
+        let own = self
+            .rolls
+            .get(uuid)
+            .map(|r| r.first.remaining.max(r.remaining()));
+        [self.capacities.get(code).copied(), learned, own]
+            .into_iter()
+            .flatten()
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn prune(&mut self) {
+        while self.rolls.len() > MAX_ROLLS {
+            let Some(oldest) = self
+                .rolls
+                .values()
+                .min_by_key(|r| r.last_seen)
+                .map(|r| r.uuid.clone())
+            else {
+                break;
+            };
+            self.rolls.remove(&oldest);
+        }
+    }
+}
+
+/// How a job ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Completed,
+    /// Stopped by a clearable condition; the job is retried.
+    Held,
+    Failed,
+}
+
+/// One print job, or one attempt at a held job.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobRecord {
+    /// Seconds since the Unix epoch the job started.
+    pub at: u64,
+    pub printer: String,
+    pub job_id: Option<u32>,
+    pub document_format: String,
+    /// Labels fed, copies included.
+    pub labels: u32,
+    /// Tag uuid of the roll printed on.
+    pub roll: Option<String>,
+    pub density: Option<u8>,
+    /// `usb`, `bt`, `ble` or `mock`.
+    pub transport: Option<String>,
+    pub elapsed_ms: u64,
+    pub outcome: Outcome,
+    /// Why the job did not complete.
+    pub message: String,
+    /// The device's lifetime label count after the job.
+    pub odometer: Option<u32>,
+}
+
+/// Append `record` to the job ledger at `path`.
+pub fn append_job(path: &Path, record: &JobRecord) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    // One write per record, so concurrent appends do not interleave.
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(&line)
+}
+
+/// Read the job ledger at `path`, oldest first; a missing file is empty. A
+/// line that does not parse (a write cut short) is skipped with a warning.
+pub fn load_jobs(path: &Path) -> io::Result<Vec<JobRecord>> {
+    let file = match std::fs::File::open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut jobs = Vec::new();
+    for (n, line) in io::BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(job) => jobs.push(job),
+            Err(e) => log::warn!("{}:{}: skipping job record: {e}", path.display(), n + 1),
+        }
+    }
+    Ok(jobs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roll(uuid: &str, code: &str, remaining: u32) -> MaterialInfo {
+        MaterialInfo {
+            uuid: uuid.into(),
+            code: code.into(),
+            width_mm: 40,
+            height_mm: 30,
+            remaining: Some(remaining),
+            ..MaterialInfo::default()
+        }
+    }
+
+    #[test]
+    fn fresh_rolls_teach_the_sku_capacity() {
+        let mut ledger = RollLedger::default();
+        // A part-used roll seen first knows no better than its own count.
+        let (r, changed) = ledger.observe("t50", &roll("A", "SKU1", 120), 10).unwrap();
+        assert!(changed);
+        assert_eq!((r.capacity, r.percent()), (120, 100));
+        // A fresh roll of the same SKU sets the capacity for both.
+        let (r, _) = ledger.observe("t50", &roll("B", "SKU1", 500), 20).unwrap();
+        assert_eq!((r.capacity, r.percent()), (500, 100));
+        let (r, _) = ledger.observe("t50", &roll("B", "SKU1", 375), 30).unwrap();
+        assert_eq!((r.remaining(), r.percent(), r.used()), (375, 75, 125));
+        let (r, _) = ledger.observe("t80", &roll("A", "SKU1", 100), 40).unwrap();
+        assert_eq!(
+            (r.capacity, r.percent(), r.printer.as_str()),
+            (500, 20, "t80")
+        );
+        assert_eq!(ledger.current("t50").unwrap().uuid, "B");
+        assert_eq!(ledger.current("t80").unwrap().uuid, "A");
+    }
+
+    #[test]
+    fn configured_capacity_and_unchanged_polls() {
+        let mut ledger = RollLedger::default();
+        ledger.set_capacities(HashMap::from([("SKU2".to_string(), 400)]));
+        let (r, _) = ledger.observe("t50", &roll("C", "SKU2", 100), 10).unwrap();
+        assert_eq!((r.capacity, r.percent()), (400, 25));
+        let (r, changed) = ledger.observe("t50", &roll("C", "SKU2", 100), 20).unwrap();
+        assert!(!changed, "same count, same printer: nothing to save");
+        assert_eq!((r.last_seen, r.history.len()), (20, 0));
+        let (r, changed) = ledger.observe("t50", &roll("C", "SKU2", 99), 30).unwrap();
+        assert!(changed);
+        assert_eq!(
+            r.history,
+            [Sample {
+                at: 30,
+                remaining: 99
+            }]
+        );
+    }
+
+    #[test]
+    fn untracked_reports_are_ignored() {
+        let mut ledger = RollLedger::default();
+        assert!(ledger.observe("t50", &roll("", "SKU1", 10), 0).is_none());
+        let mut mat = roll("D", "SKU1", 10);
+        mat.remaining = None;
+        assert!(ledger.observe("t50", &mat, 0).is_none());
+        assert!(ledger.rolls().is_empty());
+    }
+
+    #[test]
+    fn ledger_round_trips_through_its_file() {
+        let dir = std::env::temp_dir().join(format!("supvan-ledger-{}", std::process::id()));
+        let path = dir.join("rolls.json");
+        assert!(RollLedger::load(&path).unwrap().rolls().is_empty());
+        let mut ledger = RollLedger::default();
+        ledger.observe("t50", &roll("E", "SKU3", 230), 10);
+        ledger.observe("t50", &roll("E", "SKU3", 229), 20);
+        ledger.save(&path).unwrap();
+        let loaded = RollLedger::load(&path).unwrap();
+        assert_eq!(loaded.get("E"), ledger.get("E"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn formats_utc_timestamps() {
+        assert_eq!(format_utc(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_utc(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(format_utc(1_792_373_707), "2026-10-19T01:35:07Z");
+    }
+
+    #[test]
+    fn job_ledger_appends_and_skips_torn_lines() {
+        let dir = std::env::temp_dir().join(format!("supvan-jobs-{}", std::process::id()));
+        let path = dir.join("jobs.jsonl");
+        assert!(load_jobs(&path).unwrap().is_empty());
+        let job = |job_id, outcome| JobRecord {
+            at: 1_792_373_707,
+            printer: "t50".into(),
+            job_id: Some(job_id),
+            document_format: "image/png".into(),
+            labels: 2,
+            roll: Some("A".into()),
+            density: Some(8),
+            transport: Some("usb".into()),
+            elapsed_ms: 1500,
+            outcome,
+            message: String::new(),
+            odometer: Some(1234),
+        };
+        append_job(&path, &job(1, Outcome::Completed)).unwrap();
+        // A write cut short.
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"at\":17\n")
+            .unwrap();
+        append_job(&path, &job(2, Outcome::Held)).unwrap();
+        assert_eq!(
+            load_jobs(&path).unwrap(),
+            [job(1, Outcome::Completed), job(2, Outcome::Held)]
+        );
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(text.contains(r#""outcome":"held""#));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
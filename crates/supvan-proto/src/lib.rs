@@ -6,7 +6,10 @@
 //! trait. On top of that it provides the print pipeline: 1-bit bitmap packing
 //! into the printhead's column-major layout ([`bitmap`]), LZMA1-"alone"
 //! compression of the print buffers ([`compress`]), status and loaded-material
-//! decoding ([`status`]), and the high-level print flow ([`printer::Printer`]).
+//! decoding ([`status`]), the high-level print flow ([`printer::Printer`]),
+//! the persisted roll ledger behind the supply gauge ([`ledger`]), the
+//! registry that keeps each printer's identity stable ([`identity`]), and the
+//! per-family capabilities the print path follows ([`caps`]).
 //!
 //! This crate has no IPP/CUPS knowledge — `supvan-app` layers that on via the
 //! `ipp-printer-app` framework. See `docs/PROTOCOL.md` for the wire format.
@@ -14,11 +17,16 @@
 pub mod ble;
 pub mod bitmap;
 pub mod buffer;
+pub mod caps;
 pub mod cmd;
 pub mod compress;
 pub mod data;
 pub mod error;
 pub mod hidraw;
+pub mod identity;
+pub mod ledger;
+#[cfg(feature = "pairing")]
+pub mod pairing;
 pub mod printer;
 pub mod rfcomm;
 pub mod speed;
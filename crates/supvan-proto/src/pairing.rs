@@ -0,0 +1,474 @@
+//! BlueZ pairing agent (`org.bluez.Agent1`) for unattended setups.
+//!
+//! Without a registered agent BlueZ cannot answer a printer that asks for a
+//! PIN or a passkey confirmation, and pairing fails silently. [`Agent`]
+//! registers one on the system bus and answers from a caller-supplied
+//! [`PinSource`], but only for addresses this process is pairing or dialing
+//! right now ([`expect`]). Anything else — a device that merely advertises a
+//! printer-like name, or a pairing the station did not start — is rejected,
+//! so the agent never pairs or trusts a foreign device on the station's
+//! behalf. Expected devices the source returns a PIN for are paired (the PIN
+//! doubles as the numeric passkey, confirmations are accepted) and, once
+//! BlueZ reports them `Paired`, marked `Trusted`, so later RFCOMM/GATT
+//! connects need no authorization.
+//!
+//! BlueZ sends an agent the pairings its own connection starts
+//! ([`Agent::pair`]). Pairings the kernel starts on connect only reach the
+//! host's default agent, which the agent becomes only when asked to, since
+//! that takes the role over from the desktop's agent for every device.
+//!
+//! [`pair`] pairs one address through the host's default agent instead.
+//! Both look the device up first (with a short targeted scan if BlueZ has
+//! never seen it). Gated behind the `pairing` feature.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use dbus::arg::{PropMap, RefArg, Variant};
+use dbus::blocking::stdintf::org_freedesktop_dbus::{ObjectManager, Properties};
+use dbus::blocking::{BlockingSender, Connection, Proxy, SyncConnection};
+use dbus::channel::MatchingReceiver;
+use dbus::message::MatchRule;
+use dbus::{MethodErr, Path};
+use dbus_crossroads::Crossroads;
+
+use crate::error::{Error, Result};
+
+const BLUEZ_SERVICE: &str = "org.bluez";
+const DEVICE_IFACE: &str = "org.bluez.Device1";
+const AGENT_PATH: &str = "/com/supvan/agent";
+/// Answers PINs, passkeys and yes/no confirmations without a human.
+const CAPABILITY: &str = "KeyboardDisplay";
+/// How long to wait for BlueZ to report a pairing the agent answered.
+const PAIRING_WINDOW: Duration = Duration::from_secs(30);
+/// How long [`pair`] scans for a device BlueZ does not know yet.
+const SCAN_WINDOW: Duration = Duration::from_secs(10);
+
+/// A device asking to pair.
+#[derive(Debug, Clone)]
+pub struct Peer {
+    pub address: String,
+    pub name: String,
+}
+
+/// The PIN to pair `Peer` with, or `None` to reject it.
+pub type PinSource = Arc<dyn Fn(&Peer) -> Option<String> + Send + Sync>;
+
+/// Addresses (uppercase) the agent may answer for, with how many pairings
+/// or dials are waiting on each.
+static EXPECTED: Mutex<Vec<(String, usize)>> = Mutex::new(Vec::new());
+
+/// Keeps an address expected; see [`expect`].
+#[must_use = "the address is only expected while the guard lives"]
+pub struct Expected(String);
+
+/// Let the agent answer `address` until the returned guard drops. Wrap every
+/// pairing or connect the process starts with it.
+pub fn expect(address: &str) -> Expected {
+    let address = address.to_ascii_uppercase();
+    let mut expected = EXPECTED.lock().unwrap_or_else(PoisonError::into_inner);
+    match expected.iter_mut().find(|(a, _)| *a == address) {
+        Some((_, count)) => *count += 1,
+        None => expected.push((address.clone(), 1)),
+    }
+    Expected(address)
+}
+
+impl Drop for Expected {
+    fn drop(&mut self) {
+        let mut expected = EXPECTED.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(i) = expected.iter().position(|(a, _)| *a == self.0) {
+            expected[i].1 -= 1;
+            if expected[i].1 == 0 {
+                expected.swap_remove(i);
+            }
+        }
+    }
+}
+
+/// Whether a pairing or dial of `address` is in progress.
+pub fn is_expected(address: &str) -> bool {
+    EXPECTED
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .any(|(a, _)| a.eq_ignore_ascii_case(address))
+}
+
+/// A registered pairing agent. Unregisters on drop.
+pub struct Agent {
+    conn: Arc<SyncConnection>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Agent {
+    /// Register the agent with BlueZ; `default` also makes it the host's
+    /// default agent, so it hears pairings the kernel starts on connect.
+    pub fn register(pins: PinSource, default: bool) -> Result<Agent> {
+        let conn = Arc::new(SyncConnection::new_system().map_err(pairing_error)?);
+
+        let mut cr = Crossroads::new();
+        let iface = cr.register("org.bluez.Agent1", |b| {
+            b.method("Release", (), (), |_, _: &mut PinSource, ()| {
+                log::info!("pairing: agent released by BlueZ");
+                Ok(())
+            });
+            b.method(
+                "RequestPinCode",
+                ("device",),
+                ("pincode",),
+                |_, pins: &mut PinSource, (device,): (Path<'static>,)| {
+                    let (peer, pin) = answer(pins, &device)?;
+                    log::info!("pairing: PIN requested by {} ({})", peer.name, peer.address);
+                    follow(device, peer);
+                    Ok((pin,))
+                },
+            );
+            b.method(
+                "RequestPasskey",
+                ("device",),
+                ("passkey",),
+                |_, pins: &mut PinSource, (device,): (Path<'static>,)| {
+                    let (peer, pin) = answer(pins, &device)?;
+                    let passkey: u32 = pin.parse().map_err(|_| {
+                        log::warn!("pairing: PIN '{pin}' for {} is not a passkey", peer.address);
+                        rejected("PIN is not numeric")
+                    })?;
+                    log::info!(
+                        "pairing: passkey requested by {} ({})",
+                        peer.name,
+                        peer.address
+                    );
+                    follow(device, peer);
+                    Ok((passkey,))
+                },
+            );
+            b.method(
+                "DisplayPinCode",
+                ("device", "pincode"),
+                (),
+                |_, _: &mut PinSource, (device, pin): (Path<'static>, String)| {
+                    log::info!("pairing: {device} displays PIN {pin}");
+                    Ok(())
+                },
+            );
+            b.method(
+                "DisplayPasskey",
+                ("device", "passkey", "entered"),
+                (),
+                |_, _: &mut PinSource, (device, passkey, _): (Path<'static>, u32, u16)| {
+                    log::info!("pairing: {device} displays passkey {passkey:06}");
+                    Ok(())
+                },
+            );
+            b.method(
+                "RequestConfirmation",
+                ("device", "passkey"),
+                (),
+                |_, pins: &mut PinSource, (device, passkey): (Path<'static>, u32)| {
+                    let (peer, _) = answer(pins, &device)?;
+                    log::info!(
+                        "pairing: confirming passkey {passkey:06} for {} ({})",
+                        peer.name,
+                        peer.address
+                    );
+                    follow(device, peer);
+                    Ok(())
+                },
+            );
+            // Incoming just-works pairings and service connections: only
+            // for a device the station is pairing or dialing itself.
+            b.method(
+                "RequestAuthorization",
+                ("device",),
+                (),
+                |_, pins: &mut PinSource, (device,): (Path<'static>,)| {
+                    let (peer, _) = answer(pins, &device)?;
+                    log::info!("pairing: authorizing {} ({})", peer.name, peer.address);
+                    follow(device, peer);
+                    Ok(())
+                },
+            );
+            b.method(
+                "AuthorizeService",
+                ("device", "uuid"),
+                (),
+                |_, pins: &mut PinSource, (device, uuid): (Path<'static>, String)| {
+                    let (peer, _) = answer(pins, &device)?;
+                    log::info!("pairing: authorizing {uuid} for {}", peer.address);
+                    Ok(())
+                },
+            );
+            b.method("Cancel", (), (), |_, _: &mut PinSource, ()| {
+                log::warn!("pairing: request cancelled by BlueZ");
+                Ok(())
+            });
+        });
+        cr.insert(AGENT_PATH, &[iface], pins);
+
+        let cr = Arc::new(Mutex::new(cr));
+        conn.start_receive(
+            MatchRule::new_method_call(),
+            Box::new(move |msg, conn| {
+                if let Ok(mut cr) = cr.lock() {
+                    let _ = cr.handle_message(msg, conn);
+                }
+                true
+            }),
+        );
+
+        let manager = conn.with_proxy(BLUEZ_SERVICE, "/org/bluez", Duration::from_secs(5));
+        manager
+            .method_call::<(), _, _, _>(
+                "org.bluez.AgentManager1",
+                "RegisterAgent",
+                (Path::from(AGENT_PATH), CAPABILITY),
+            )
+            .map_err(pairing_error)?;
+        if default
+            && let Err(e) = manager.method_call::<(), _, _, _>(
+                "org.bluez.AgentManager1",
+                "RequestDefaultAgent",
+                (Path::from(AGENT_PATH),),
+            )
+        {
+            // Still answers pairings this process starts.
+            log::warn!("pairing: not the default agent: {e}");
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let (conn, stop) = (Arc::clone(&conn), Arc::clone(&stop));
+            std::thread::Builder::new()
+                .name("pairing-agent".into())
+                .spawn(move || {
+                    while !stop.load(Ordering::Relaxed) {
+                        if let Err(e) = conn.process(Duration::from_millis(200)) {
+                            log::error!("pairing: D-Bus process error: {e}");
+                            return;
+                        }
+                    }
+                })?
+        };
+        log::info!(
+            "pairing: agent registered at {AGENT_PATH}{}",
+            if default { " as the default" } else { "" }
+        );
+        Ok(Agent {
+            conn,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Pair with `address` through this agent and trust it, as [`pair`].
+    pub fn pair(&self, address: &str) -> Result<()> {
+        pair_on(&*self.conn, address)
+    }
+}
+
+impl Drop for Agent {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        let manager = self
+            .conn
+            .with_proxy(BLUEZ_SERVICE, "/org/bluez", Duration::from_secs(5));
+        let _ = manager.method_call::<(), _, _, _>(
+            "org.bluez.AgentManager1",
+            "UnregisterAgent",
+            (Path::from(AGENT_PATH),),
+        );
+    }
+}
+
+/// Pair with `address` and trust it. A device that is already paired is
+/// just trusted. PIN and passkey requests go to the host's default agent.
+pub fn pair(address: &str) -> Result<()> {
+    let conn = Connection::new_system().map_err(pairing_error)?;
+    pair_on(&conn, address)
+}
+
+/// Pair on `conn`: BlueZ asks the agent that connection registered, if any.
+fn pair_on<C: BlockingSender>(conn: &C, address: &str) -> Result<()> {
+    let _expected = expect(address);
+    let device = find_device(conn, address)?;
+    let proxy = Proxy::new(BLUEZ_SERVICE, &device, PAIRING_WINDOW, conn);
+    log::info!("pairing: pairing {address} ({device})");
+    match proxy.method_call::<(), _, _, _>(DEVICE_IFACE, "Pair", ()) {
+        Ok(()) => log::info!("pairing: paired {address}"),
+        Err(e) if e.name() == Some("org.bluez.Error.AlreadyExists") => {
+            log::info!("pairing: {address} already paired");
+        }
+        Err(e) => {
+            log::error!("pairing: Pair({address}) failed: {e}");
+            return Err(pairing_error(e));
+        }
+    }
+    proxy
+        .set(DEVICE_IFACE, "Trusted", true)
+        .map_err(pairing_error)?;
+    log::info!("pairing: trusted {address}");
+    Ok(())
+}
+
+/// The BlueZ object for `address`, scanning briefly if BlueZ has not seen it.
+fn find_device<C: BlockingSender>(conn: &C, address: &str) -> Result<Path<'static>> {
+    let (adapter, device) = lookup(conn, address)?;
+    if let Some(path) = device {
+        return Ok(path);
+    }
+    let Some(adapter) = adapter else {
+        return Err(Error::Pairing("no Bluetooth adapter".into()));
+    };
+    log::info!("pairing: {address} unknown to BlueZ; scanning for it");
+    let proxy = Proxy::new(BLUEZ_SERVICE, &adapter, Duration::from_secs(5), conn);
+    proxy
+        .method_call::<(), _, _, _>("org.bluez.Adapter1", "StartDiscovery", ())
+        .map_err(pairing_error)?;
+    let deadline = Instant::now() + SCAN_WINDOW;
+    let found = loop {
+        std::thread::sleep(Duration::from_millis(500));
+        if let Some(path) = lookup(conn, address)?.1 {
+            break Some(path);
+        }
+        if Instant::now() >= deadline {
+            break None;
+        }
+    };
+    let _ = proxy.method_call::<(), _, _, _>("org.bluez.Adapter1", "StopDiscovery", ());
+    found.ok_or_else(|| Error::Pairing(format!("{address} not found")))
+}
+
+/// The first adapter and the device object for `address`, if any.
+fn lookup<C: BlockingSender>(
+    conn: &C,
+    address: &str,
+) -> Result<(Option<Path<'static>>, Option<Path<'static>>)> {
+    let proxy = Proxy::new(BLUEZ_SERVICE, "/", Duration::from_secs(5), conn);
+    let objects = proxy.get_managed_objects().map_err(pairing_error)?;
+    let adapter = objects
+        .iter()
+        .find(|(_, ifaces)| ifaces.contains_key("org.bluez.Adapter1"))
+        .map(|(path, _)| path.clone());
+    let device = objects
+        .into_iter()
+        .find(|(_, ifaces)| {
+            ifaces
+                .get(DEVICE_IFACE)
+                .and_then(|props| str_prop(props, "Address"))
+                .is_some_and(|a| a.eq_ignore_ascii_case(address))
+        })
+        .map(|(path, _)| path);
+    Ok((adapter, device))
+}
+
+/// Resolve the peer behind `device` and, if this process is pairing or
+/// dialing it, ask the PIN source about it.
+fn answer(
+    pins: &PinSource,
+    device: &Path<'static>,
+) -> std::result::Result<(Peer, String), MethodErr> {
+    let peer = peer(device).ok_or_else(|| rejected("unknown device"))?;
+    if !is_expected(&peer.address) {
+        log::warn!(
+            "pairing: rejecting unsolicited request from {} ({})",
+            peer.name,
+            peer.address
+        );
+        return Err(rejected("not pairing with this device"));
+    }
+    match pins(&peer) {
+        Some(pin) => Ok((peer, pin)),
+        None => {
+            log::info!("pairing: rejecting {} ({})", peer.name, peer.address);
+            Err(rejected("not a Supvan printer"))
+        }
+    }
+}
+
+/// Read the address and name of `device`. Uses its own connection: the
+/// agent's is busy dispatching the request being answered.
+fn peer(device: &Path<'static>) -> Option<Peer> {
+    let conn = Connection::new_system().ok()?;
+    let proxy = conn.with_proxy(BLUEZ_SERVICE, device, Duration::from_secs(5));
+    let props: PropMap = proxy.get_all(DEVICE_IFACE).ok()?;
+    Some(Peer {
+        address: str_prop(&props, "Address")?,
+        name: str_prop(&props, "Name")
+            .or_else(|| str_prop(&props, "Alias"))
+            .unwrap_or_default(),
+    })
+}
+
+/// Watch an answered pairing through: once BlueZ reports `Paired`, trust
+/// the device and log the outcome.
+fn follow(device: Path<'static>, peer: Peer) {
+    let watch = move || {
+        let Ok(conn) = Connection::new_system() else {
+            return;
+        };
+        let proxy = conn.with_proxy(BLUEZ_SERVICE, &device, Duration::from_secs(5));
+        let deadline = Instant::now() + PAIRING_WINDOW;
+        while Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(500));
+            let paired: bool = proxy.get(DEVICE_IFACE, "Paired").unwrap_or(false);
+            if !paired {
+                continue;
+            }
+            match proxy.set(DEVICE_IFACE, "Trusted", true) {
+                Ok(()) => log::info!(
+                    "pairing: paired and trusted {} ({})",
+                    peer.name,
+                    peer.address
+                ),
+                Err(e) => log::warn!("pairing: paired {} but cannot trust it: {e}", peer.address),
+            }
+            return;
+        }
+        log::warn!(
+            "pairing: {} ({}) did not complete pairing",
+            peer.name,
+            peer.address
+        );
+    };
+    let _ = std::thread::Builder::new()
+        .name("pairing-follow".into())
+        .spawn(watch);
+}
+
+fn str_prop(props: &HashMap<String, Variant<Box<dyn RefArg>>>, key: &str) -> Option<String> {
+    props.get(key).and_then(|v| v.0.as_str().map(String::from))
+}
+
+fn rejected(why: &str) -> MethodErr {
+    ("org.bluez.Error.Rejected", why).into()
+}
+
+fn pairing_error(e: dbus::Error) -> Error {
+    Error::Pairing(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_are_expected_while_a_guard_lives() {
+        let address = "AA:BB:CC:00:11:22";
+        assert!(!is_expected(address));
+        let pairing = expect(address);
+        let dialing = expect(&address.to_ascii_lowercase());
+        assert!(is_expected("aa:bb:cc:00:11:22"));
+        drop(pairing);
+        assert!(is_expected(address), "the dial still expects it");
+        drop(dialing);
+        assert!(!is_expected(address));
+    }
+}
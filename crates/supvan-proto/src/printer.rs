@@ -4,6 +4,7 @@
 //! CHECK_DEVICE -> poll ready -> START_PRINT -> poll printing ->
 //! transfer buffers -> poll complete.
 
+use crate::buffer::MediaKind;
 use crate::cmd::*;
 use crate::data::DATA_PAYLOAD_SIZE;
 use crate::error::{Error, Result};
@@ -22,6 +23,30 @@ const BUFFER_READY_ATTEMPTS: usize = 200;
 const COMPLETION_POLL_INTERVAL: Duration = Duration::from_millis(100);
 const COMPLETION_POLLS: usize = 300;
 
+/// One label ready to send: its compressed print buffers and how to print
+/// them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    /// LZMA streams, each sent as one NEXT_ZIPPEDBULK … BUF_FULL transfer;
+    /// more than one when the page exceeds the family's transfer size.
+    pub transfers: Vec<Vec<u8>>,
+    pub speed: u16,
+    /// Density to send with SEND_INF before START_PRINT, for families that
+    /// take it by command (see [`crate::caps::DensityMode`]).
+    pub density: Option<u8>,
+}
+
+impl Label {
+    /// A label sent as a single transfer, density in the buffer headers.
+    pub fn single(compressed: Vec<u8>, speed: u16) -> Self {
+        Self {
+            transfers: vec![compressed],
+            speed,
+            density: None,
+        }
+    }
+}
+
 /// High-level printer interface over a pluggable transport.
 pub struct Printer {
     transport: Box<dyn Transport>,
@@ -118,6 +143,21 @@ impl Printer {
         self.transport.send_cmd(CMD_STOP_PRINT, 0).await
     }
 
+    /// SEND_INF (0x35) — set the print density, for families that do not
+    /// take it from the buffer headers.
+    pub async fn set_density(&self, density: u8) -> Result<()> {
+        log::info!("SEND_INF: density={density}");
+        let resp = self
+            .transport
+            .send_cmd(CMD_SEND_INF, u16::from(density))
+            .await?;
+        if resp.is_some_and(|r| self.transport.validate_response(&r, CMD_SEND_INF)) {
+            Ok(())
+        } else {
+            Err(Error::InvalidResponse("SEND_INF: no ack".into()))
+        }
+    }
+
     /// PAPER_SKIP (0x2E) — feed/advance one blank label. Returns `Ok(())` once
     /// the device acks; errors if there is no response.
     pub async fn paper_skip(&self) -> Result<()> {
@@ -249,7 +289,51 @@ impl Printer {
     /// 4. Wait printing station
     /// 5. Wait buffer ready + transfer
     /// 6. Wait completion
+    ///
+    /// A link failure before the first BUF_FULL is acknowledged is returned
+    /// as [`Error::Unsent`]: the printer only starts on the label once it
+    /// is, so the caller may resend it after reconnecting. Later failures
+    /// are returned as they are, since part of the label may have printed.
     pub async fn print_compressed(&self, compressed: &[u8], speed: u16) -> Result<()> {
+        self.print_label(&Label::single(compressed.to_vec(), speed))
+            .await
+    }
+
+    /// [`print_compressed`](Self::print_compressed) for a [`Label`]: sends
+    /// its density first if it carries one, and waits for buffer space
+    /// before each of its transfers.
+    pub async fn print_label(&self, label: &Label) -> Result<()> {
+        let Some((first, rest)) = label.transfers.split_first() else {
+            return Err(Error::InvalidParam("label has no transfers".into()));
+        };
+        self.start_label(label, first)
+            .await
+            .map_err(Error::unsent)?;
+        // The printer is working on the label now: a lost link from here on
+        // must not lead to a resend.
+        for compressed in rest {
+            self.send_transfer(compressed, label.speed).await?;
+        }
+
+        // Step 6: Wait completion
+        for _ in 0..COMPLETION_POLLS {
+            tokio::time::sleep(COMPLETION_POLL_INTERVAL).await;
+            if let Some(s) = self.query_status().await?
+                && !s.printing
+                && !s.device_busy
+            {
+                log::info!("print complete");
+                return Ok(());
+            }
+        }
+
+        log::warn!("timeout waiting for print completion");
+        Err(Error::Timeout("print completion"))
+    }
+
+    /// Steps 1–5 of [`print_compressed`](Self::print_compressed), up to the
+    /// acknowledged BUF_FULL of the label's first transfer.
+    async fn start_label(&self, label: &Label, first: &[u8]) -> Result<()> {
         // Step 1: Check device
         if !self.check_device().await? {
             return Err(Error::InvalidResponse("CHECK_DEVICE failed".into()));
@@ -266,6 +350,9 @@ impl Printer {
                 status.error_description().unwrap_or_default()
             )));
         }
+        if let Some(density) = label.density {
+            self.set_density(density).await?;
+        }
 
         // Step 3: Start print
         self.start_print().await?;
@@ -276,6 +363,11 @@ impl Printer {
             .ok_or_else(|| Error::InvalidResponse("timeout waiting for printing station".into()))?;
 
         // Step 5: Wait buffer + transfer
+        self.send_transfer(first, label.speed).await
+    }
+
+    /// Wait for buffer space, then send one transfer.
+    async fn send_transfer(&self, compressed: &[u8], speed: u16) -> Result<()> {
         let buf_status = self
             .wait_buffer_ready(BUFFER_READY_ATTEMPTS)
             .await?
@@ -287,22 +379,68 @@ impl Printer {
                 buf_status.error_description().unwrap_or_default()
             )));
         }
-        self.transfer_compressed(compressed, speed).await?;
+        self.transfer_compressed(compressed, speed).await
+    }
 
-        // Step 6: Wait completion
-        for _ in 0..COMPLETION_POLLS {
-            tokio::time::sleep(COMPLETION_POLL_INTERVAL).await;
-            if let Some(s) = self.query_status().await?
-                && !s.printing
-                && !s.device_busy
-            {
-                log::info!("print complete");
-                return Ok(());
-            }
+    /// Print one label from a row-major, MSB-first 1bpp raster (1 = black).
+    ///
+    /// Runs the same pipeline as the IPP job path: rotate to column-major,
+    /// centre in the printhead, split into buffers with the default feed
+    /// margins, compress, and print. `height` runs along the feed direction
+    /// and must exceed both margins; on [`MediaKind::Continuous`] it is also
+    /// the printed length.
+    pub async fn print_raster(
+        &self,
+        raster: &[u8],
+        width: u32,
+        height: u32,
+        printhead_width_dots: u32,
+        density: u8,
+        media: MediaKind,
+    ) -> Result<()> {
+        use crate::bitmap::{DEFAULT_MARGIN_DOTS, center_in_printhead, raster_to_column_major};
+        use crate::buffer::split_into_buffers;
+        use crate::compress::compress_buffers;
+
+        if height <= 2 * DEFAULT_MARGIN_DOTS as u32 {
+            return Err(Error::InvalidParam(format!(
+                "raster height {height} dots does not exceed the {}-dot feed margins",
+                2 * DEFAULT_MARGIN_DOTS
+            )));
+        }
+        if width > printhead_width_dots {
+            return Err(Error::InvalidParam(format!(
+                "raster width {width} dots exceeds the {printhead_width_dots}-dot printhead"
+            )));
+        }
+        let expected = width.div_ceil(8) as usize * height as usize;
+        if raster.len() < expected {
+            return Err(Error::InvalidParam(format!(
+                "raster has {} bytes, {width}x{height} needs {expected}",
+                raster.len()
+            )));
         }
 
-        log::warn!("timeout waiting for print completion");
-        Err(Error::Timeout("print completion"))
+        let (col_data, num_cols, _) = raster_to_column_major(raster, width, height);
+        let (canvas, canvas_bpl) =
+            center_in_printhead(&col_data, num_cols, width, printhead_width_dots);
+        let buffers = split_into_buffers(
+            &canvas,
+            canvas_bpl as u8,
+            num_cols as u16,
+            DEFAULT_MARGIN_DOTS,
+            DEFAULT_MARGIN_DOTS,
+            density,
+            media,
+        );
+        log::info!(
+            "raster print: {width}x{height} dots, {} buffers",
+            buffers.len()
+        );
+
+        let (compressed, avg) = compress_buffers(&buffers)?;
+        let speed = calc_speed(avg);
+        self.print_compressed(&compressed, speed).await
     }
 
     /// Full test print workflow: generate test pattern, build buffers, compress, print.
@@ -319,14 +457,27 @@ impl Printer {
         };
 
         log::info!(
-            "test print: {}mm x {}mm, density={}",
+            "test print: {}mm x {}mm{}, density={}",
             label_width_mm,
             height_mm,
+            if mat.is_continuous() {
+                " (continuous)"
+            } else {
+                ""
+            },
             density
         );
 
         let (image_data, _w, h, bpl) = create_test_pattern(label_width_mm, height_mm);
-        let buffers = split_into_buffers(&image_data, bpl as u8, h as u16, 8, 8, density);
+        let buffers = split_into_buffers(
+            &image_data,
+            bpl as u8,
+            h as u16,
+            8,
+            8,
+            density,
+            mat.media_kind(),
+        );
         log::info!("{} print buffers", buffers.len());
 
         let (compressed, avg) = compress_buffers(&buffers)?;
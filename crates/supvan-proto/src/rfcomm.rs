@@ -131,7 +131,7 @@ impl RfcommSocket {
                         self.fd,
                         chunk[sent..].as_ptr() as *const libc::c_void,
                         chunk.len() - sent,
-                        0,
+                        libc::MSG_NOSIGNAL,
                     )
                 };
                 if n < 0 {
@@ -173,6 +173,10 @@ impl RfcommSocket {
         for _ in 0..polls {
             let n =
                 unsafe { libc::recv(self.fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len(), 0) };
+            if let Some(err) = link_error(n) {
+                self.set_timeout(self.timeout)?;
+                return Err(Error::Io(err));
+            }
             if n > 0 {
                 response.extend_from_slice(&buf[..n as usize]);
                 // Brief extra wait for trailing bytes
@@ -238,7 +242,7 @@ impl RfcommSocket {
                         self.fd,
                         chunk[sent..].as_ptr() as *const libc::c_void,
                         chunk.len() - sent,
-                        0,
+                        libc::MSG_NOSIGNAL,
                     )
                 };
                 if n < 0 {
@@ -255,6 +259,27 @@ impl RfcommSocket {
     }
 }
 
+/// The error behind a `recv` result that means the link is gone: EOF (the
+/// printer closed it) or a socket error other than the poll timeout.
+fn link_error(n: isize) -> Option<std::io::Error> {
+    match n {
+        0 => Some(std::io::Error::new(
+            std::io::ErrorKind::ConnectionReset,
+            "link closed by printer",
+        )),
+        n if n < 0 => {
+            let err = std::io::Error::last_os_error();
+            match err.kind() {
+                std::io::ErrorKind::WouldBlock
+                | std::io::ErrorKind::TimedOut
+                | std::io::ErrorKind::Interrupted => None,
+                _ => Some(err),
+            }
+        }
+        _ => None,
+    }
+}
+
 impl Drop for RfcommSocket {
     fn drop(&mut self) {
         unsafe {
@@ -1,28 +1,47 @@
-/// Calculate print speed based on compressed buffer size.
+/// Transfer speeds by average compressed bytes per buffer, as
+/// `(above, speed)` pairs in ascending order of `above`: a page prints at
+/// the speed of the last pair whose `above` it exceeds, else at the first
+/// pair's speed.
 ///
-/// From T50PlusPrint.multiCompression(): the speed is derived from the
-/// average compressed bytes per buffer. Lower speed values for larger data
+/// From T50PlusPrint.multiCompression(): lower speed values for larger data
 /// ensure the thermal head has enough time to heat properly.
-pub fn calc_speed(compressed_size: usize) -> u16 {
-    if compressed_size > 3000 {
-        10
-    } else if compressed_size > 2800 {
-        15
-    } else if compressed_size > 2500 {
-        20
-    } else if compressed_size > 2000 {
-        25
-    } else if compressed_size > 1500 {
-        40
-    } else if compressed_size > 1000 {
-        45
-    } else if compressed_size > 500 {
-        55
-    } else {
-        60
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpeedTable(pub Vec<(usize, u16)>);
+
+impl Default for SpeedTable {
+    /// The T50's table.
+    fn default() -> Self {
+        Self(vec![
+            (0, 60),
+            (500, 55),
+            (1000, 45),
+            (1500, 40),
+            (2000, 25),
+            (2500, 20),
+            (2800, 15),
+            (3000, 10),
+        ])
+    }
+}
+
+impl SpeedTable {
+    /// The speed for `compressed_size` average bytes per buffer.
+    pub fn speed_for(&self, compressed_size: usize) -> u16 {
+        self.0
+            .iter()
+            .rev()
+            .find(|(above, _)| compressed_size > *above)
+            .or(self.0.first())
+            .map_or(0, |(_, speed)| *speed)
     }
 }
 
+/// Calculate print speed based on compressed buffer size, with the T50's
+/// [`SpeedTable`].
+pub fn calc_speed(compressed_size: usize) -> u16 {
+    SpeedTable::default().speed_for(compressed_size)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -42,4 +61,12 @@ mod tests {
         assert_eq!(calc_speed(100), 60);
         assert_eq!(calc_speed(0), 60);
     }
+
+    #[test]
+    fn speed_tables_pick_the_last_threshold_exceeded() {
+        let table = SpeedTable(vec![(100, 30), (1000, 20)]);
+        assert_eq!(table.speed_for(50), 30);
+        assert_eq!(table.speed_for(101), 30);
+        assert_eq!(table.speed_for(1001), 20);
+    }
 }
@@ -1,3 +1,4 @@
+use crate::buffer::MediaKind;
 use crate::cmd::{
     CMD_INQUIRY_STA, CMD_RD_DEV_NAME, CMD_READ_FWVER, CMD_READ_REV, CMD_RETURN_MAT, MAGIC1, MAGIC2,
 };
@@ -71,7 +72,8 @@ impl PrinterStatus {
     }
 }
 
-/// Fallback label geometry (mm) used when the printer reports no material.
+/// Fallback label geometry (mm) used when the printer reports no material,
+/// and the test-print length on continuous rolls.
 pub const DEFAULT_LABEL_HEIGHT_MM: u8 = 25;
 pub const DEFAULT_LABEL_GAP_MM: u8 = 3;
 
@@ -89,6 +91,24 @@ pub struct MaterialInfo {
     pub device_sn: Option<String>,
 }
 
+impl MaterialInfo {
+    /// Continuous tape reports no label length; die-cut rolls always do.
+    /// `label_type` is not consulted until its values are known (see
+    /// `docs/PROTOCOL.md`, "MaterialInfo layout").
+    pub fn is_continuous(&self) -> bool {
+        self.height_mm == 0
+    }
+
+    /// Media kind to encode in the print buffers for this roll.
+    pub fn media_kind(&self) -> MediaKind {
+        if self.is_continuous() {
+            MediaKind::Continuous
+        } else {
+            MediaKind::Gap
+        }
+    }
+}
+
 /// Parse printer status from CMD_INQUIRY_STA response.
 pub fn parse_status(data: &[u8]) -> Option<PrinterStatus> {
     if data.len() < 20 {
@@ -8,7 +8,9 @@ use supvan_proto::bitmap::{
     DEFAULT_MARGIN_DOTS, DOTS_PER_MM, PRINTHEAD_BYTES_PER_LINE, PRINTHEAD_WIDTH_DOTS,
     center_in_printhead, create_test_pattern, raster_to_column_major,
 };
-use supvan_proto::buffer::{MAX_BUF_DATA, PRINT_BUF_HEADER, PRINT_BUF_SIZE, split_into_buffers};
+use supvan_proto::buffer::{
+    MAX_BUF_DATA, MediaKind, PRINT_BUF_HEADER, PRINT_BUF_SIZE, split_into_buffers,
+};
 use supvan_proto::compress::{compress_buffers, decompress_lzma};
 
 // ---------------------------------------------------------------------------
@@ -68,6 +70,7 @@ fn run_pipeline(
         DEFAULT_MARGIN_DOTS,
         DEFAULT_MARGIN_DOTS,
         4,
+        MediaKind::Gap,
     );
 
     let (compressed, _avg) = compress_buffers(&buffers).unwrap();
@@ -205,6 +208,7 @@ fn test_full_pipeline_test_pattern() {
         DEFAULT_MARGIN_DOTS,
         DEFAULT_MARGIN_DOTS,
         4,
+        MediaKind::Gap,
     );
     assert_eq!(buffers.len(), 3);
 
@@ -261,6 +265,7 @@ fn test_pipeline_various_sizes() {
             DEFAULT_MARGIN_DOTS,
             DEFAULT_MARGIN_DOTS,
             4,
+            MediaKind::Gap,
         );
 
         // Verify expected buffer count
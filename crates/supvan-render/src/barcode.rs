@@ -0,0 +1,168 @@
+//! Linear (1D) barcodes with every bar an exact number of dots wide.
+//!
+//! Same rationale as [`crate::code2d`]: a scanner decodes bar *ratios*, and
+//! resampling a barcode image onto the dot grid makes some bars a dot wider
+//! than others. Bars here are drawn at an integer dots-per-module.
+
+use std::str::FromStr;
+
+use barcoders::sym::code39::Code39;
+use barcoders::sym::code128::Code128;
+use barcoders::sym::ean8::EAN8;
+use barcoders::sym::ean13::EAN13;
+use serde::Deserialize;
+
+use crate::canvas::Canvas;
+use crate::error::{Error, Result};
+
+/// Supported linear symbologies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(try_from = "String")]
+pub enum LinearSymbology {
+    /// Code 128; code set C is picked automatically for even-length digit
+    /// strings, code set B otherwise.
+    #[default]
+    Code128,
+    Code39,
+    /// EAN-13 / UPC-A: 12 digits, or 13 with a check digit that must match.
+    Ean13,
+    /// EAN-8: 7 digits, or 8 with a check digit that must match.
+    Ean8,
+}
+
+impl LinearSymbology {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Code128 => "Code 128",
+            Self::Code39 => "Code 39",
+            Self::Ean13 => "EAN-13",
+            Self::Ean8 => "EAN-8",
+        }
+    }
+
+    /// Quiet zone each side, in modules.
+    fn quiet_zone(self) -> u32 {
+        match self {
+            Self::Code128 | Self::Code39 => 10,
+            Self::Ean13 => 11,
+            Self::Ean8 => 7,
+        }
+    }
+}
+
+impl FromStr for LinearSymbology {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace(['-', '_', ' '], "").as_str() {
+            "code128" => Ok(Self::Code128),
+            "code39" => Ok(Self::Code39),
+            "ean13" | "upca" => Ok(Self::Ean13),
+            "ean8" => Ok(Self::Ean8),
+            other => Err(format!(
+                "unknown barcode symbology '{other}' (expected code128, code39, ean13 or ean8)"
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for LinearSymbology {
+    type Error = String;
+
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// Encode `data` into its bar modules (`true` = bar), without quiet zones.
+pub fn encode(data: &str, symbology: LinearSymbology) -> Result<Vec<bool>> {
+    let err = |e: barcoders::error::Error| Error::Encode(format!("{}: {e}", symbology.name()));
+    let bits = match symbology {
+        LinearSymbology::Code128 => {
+            // barcoders wants an explicit start code set as the first char.
+            let set = if data.len().is_multiple_of(2) && data.bytes().all(|b| b.is_ascii_digit()) {
+                'Ć'
+            } else {
+                'Ɓ'
+            };
+            Code128::new(format!("{set}{data}")).map_err(err)?.encode()
+        }
+        LinearSymbology::Code39 => Code39::new(data).map_err(err)?.encode(),
+        LinearSymbology::Ean13 => EAN13::new(data).map_err(err)?.encode(),
+        LinearSymbology::Ean8 => EAN8::new(data).map_err(err)?.encode(),
+    };
+    Ok(bits.into_iter().map(|b| b != 0).collect())
+}
+
+/// Render `data` into a `avail_w` × `height` canvas at the widest whole-dot
+/// module that fits with quiet zones, bars centred horizontally.
+pub fn render(data: &str, symbology: LinearSymbology, avail_w: u32, height: u32) -> Result<Canvas> {
+    let modules = encode(data, symbology)?;
+    let total = modules.len() as u32 + 2 * symbology.quiet_zone();
+    let module_dots = avail_w / total;
+    if module_dots == 0 {
+        return Err(Error::BarcodeTooWide {
+            symbol: symbology.name(),
+            modules: total,
+            avail_w,
+        });
+    }
+    let mut canvas = Canvas::new(avail_w, height);
+    let x0 = (avail_w - modules.len() as u32 * module_dots) / 2;
+    for (i, _) in modules.iter().enumerate().filter(|(_, bar)| **bar) {
+        canvas.fill_rect(x0 + i as u32 * module_dots, 0, module_dots, height);
+    }
+    Ok(canvas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bars_are_whole_module_multiples() {
+        let c = render("SKU-1234", LinearSymbology::Code128, 300, 10).unwrap();
+        let row: Vec<bool> = (0..300).map(|x| c.get(x, 0)).collect();
+        // Every run of equal dots is a multiple of the module width.
+        let modules = encode("SKU-1234", LinearSymbology::Code128).unwrap().len() as u32;
+        let m = 300 / (modules + 20);
+        assert!(m >= 2);
+        let mut run = 1;
+        let mut runs = Vec::new();
+        for w in row.windows(2) {
+            if w[0] == w[1] {
+                run += 1;
+            } else {
+                runs.push(run);
+                run = 1;
+            }
+        }
+        assert!(runs[1..].iter().all(|r| r % m == 0), "{runs:?}");
+    }
+
+    #[test]
+    fn numeric_code128_uses_set_c() {
+        let c = encode("12345678", LinearSymbology::Code128).unwrap();
+        let b = encode("1234567", LinearSymbology::Code128).unwrap();
+        // Set C packs two digits per symbol: 8 digits are shorter than 7 in set B.
+        assert!(c.len() < b.len());
+    }
+
+    #[test]
+    fn ean13_rejects_bad_check_digit() {
+        assert!(encode("4006381333931", LinearSymbology::Ean13).is_ok());
+        assert!(matches!(
+            encode("4006381333932", LinearSymbology::Ean13),
+            Err(Error::Encode(_))
+        ));
+    }
+
+    #[test]
+    fn too_narrow_reports_module_count() {
+        let err = render("SKU-1234", LinearSymbology::Code128, 40, 10).unwrap_err();
+        assert!(
+            matches!(err, Error::BarcodeTooWide { avail_w: 40, .. }),
+            "{err}"
+        );
+    }
+}
@@ -0,0 +1,174 @@
+//! The 1-bit drawing surface every renderer targets.
+
+/// A row-major, MSB-first 1bpp bitmap; a set bit is a black (burned) dot.
+///
+/// This is the layout of a CUPS `black_1` raster line and of `KsJob`'s page
+/// buffer, so a finished canvas can be handed to the print pipeline as-is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Canvas {
+    width: u32,
+    height: u32,
+    bytes_per_line: u32,
+    data: Vec<u8>,
+}
+
+impl Canvas {
+    /// A blank (all-white) canvas of `width` × `height` dots.
+    pub fn new(width: u32, height: u32) -> Self {
+        let bytes_per_line = width.div_ceil(8);
+        Self {
+            width,
+            height,
+            bytes_per_line,
+            data: vec![0u8; (bytes_per_line * height) as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn bytes_per_line(&self) -> u32 {
+        self.bytes_per_line
+    }
+
+    /// The packed raster, `bytes_per_line * height` bytes.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Mutable access to the packed raster, for renderers that fill whole
+    /// scanlines at once.
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    /// Wrap an existing packed raster; `None` if `data` is shorter than
+    /// `width` × `height` needs. Extra trailing bytes are dropped.
+    pub fn from_data(width: u32, height: u32, mut data: Vec<u8>) -> Option<Self> {
+        let bytes_per_line = width.div_ceil(8);
+        let len = (bytes_per_line * height) as usize;
+        if data.len() < len {
+            return None;
+        }
+        data.truncate(len);
+        Some(Self {
+            width,
+            height,
+            bytes_per_line,
+            data,
+        })
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// One packed scanline.
+    pub fn row(&self, y: u32) -> &[u8] {
+        let start = (y * self.bytes_per_line) as usize;
+        &self.data[start..start + self.bytes_per_line as usize]
+    }
+
+    /// Whether the dot at `(x, y)` is black. Out-of-bounds reads are white.
+    pub fn get(&self, x: u32, y: u32) -> bool {
+        if x >= self.width || y >= self.height {
+            return false;
+        }
+        let byte = self.data[(y * self.bytes_per_line + x / 8) as usize];
+        byte & (0x80 >> (x & 7)) != 0
+    }
+
+    /// Blacken the dot at `(x, y)`. Out-of-bounds writes are clipped.
+    pub fn set(&mut self, x: u32, y: u32) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        self.data[(y * self.bytes_per_line + x / 8) as usize] |= 0x80 >> (x & 7);
+    }
+
+    /// Blacken a `w` × `h` rectangle at `(x, y)`, clipped to the canvas.
+    pub fn fill_rect(&mut self, x: u32, y: u32, w: u32, h: u32) {
+        let x_end = x.saturating_add(w).min(self.width);
+        let y_end = y.saturating_add(h).min(self.height);
+        for yy in y..y_end {
+            for xx in x..x_end {
+                self.set(xx, yy);
+            }
+        }
+    }
+
+    /// Encode as a binary PBM (`P4`) image — the canvas layout is already
+    /// P4's row format, so this is just a header.
+    pub fn to_pbm(&self) -> Vec<u8> {
+        let header = format!("P4\n{} {}\n", self.width, self.height);
+        let mut out = Vec::with_capacity(header.len() + self.data.len());
+        out.extend_from_slice(header.as_bytes());
+        out.extend_from_slice(&self.data);
+        out
+    }
+
+    /// OR `src` onto this canvas with its top-left corner at `(x, y)`.
+    pub fn blit(&mut self, src: &Canvas, x: u32, y: u32) {
+        for sy in 0..src.height {
+            for sx in 0..src.width {
+                if src.get(sx, sy) {
+                    self.set(x + sx, y + sy);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_canvas_is_white_and_padded() {
+        let c = Canvas::new(13, 2);
+        assert_eq!(c.bytes_per_line(), 2);
+        assert_eq!(c.data().len(), 4);
+        assert!(c.data().iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn pbm_is_header_plus_rows() {
+        let mut c = Canvas::new(9, 1);
+        c.set(0, 0);
+        assert_eq!(c.to_pbm(), b"P4\n9 1\n\x80\x00");
+    }
+
+    #[test]
+    fn set_is_msb_first() {
+        let mut c = Canvas::new(16, 1);
+        c.set(0, 0);
+        c.set(9, 0);
+        assert_eq!(c.row(0), &[0x80, 0x40]);
+        assert!(c.get(9, 0));
+        assert!(!c.get(8, 0));
+    }
+
+    #[test]
+    fn fill_rect_clips() {
+        let mut c = Canvas::new(8, 4);
+        c.fill_rect(6, 2, 10, 10);
+        assert_eq!(c.row(1), &[0x00]);
+        assert_eq!(c.row(2), &[0x03]);
+        assert_eq!(c.row(3), &[0x03]);
+    }
+
+    #[test]
+    fn blit_ors_at_offset() {
+        let mut src = Canvas::new(2, 2);
+        src.fill_rect(0, 0, 2, 2);
+        let mut dst = Canvas::new(8, 4);
+        dst.blit(&src, 3, 1);
+        assert!(dst.get(3, 1) && dst.get(4, 2));
+        assert!(!dst.get(2, 1) && !dst.get(5, 1) && !dst.get(3, 3));
+    }
+}
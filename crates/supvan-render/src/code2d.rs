@@ -0,0 +1,341 @@
+//! QR and Data Matrix symbols rendered at an integer number of dots per module.
+//!
+//! A thermal head burns square dots on a fixed grid, and a 2D symbol scans
+//! reliably only when every module covers the same whole number of dots.
+//! Resampling a symbol image to the label size (as the app's photo path does)
+//! smears module edges across dot boundaries, so these renderers place modules
+//! directly on the dot grid and pick the largest pitch that fits.
+
+use std::str::FromStr;
+
+use datamatrix::{DataMatrix, SymbolList};
+use qrcode::{Color, QrCode};
+use serde::Deserialize;
+
+use crate::canvas::Canvas;
+use crate::error::{Error, Result};
+use crate::label::LabelGeometry;
+
+/// Which 2D symbology to encode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Symbology {
+    #[default]
+    Qr,
+    /// ECC200 Data Matrix. Its Reed–Solomon level is fixed by the symbol
+    /// size, so [`EcLevel`] does not apply.
+    DataMatrix,
+}
+
+impl Symbology {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Qr => "QR code",
+            Self::DataMatrix => "Data Matrix",
+        }
+    }
+
+    /// Quiet zone required by the symbology spec, in modules.
+    fn default_quiet_zone(self) -> u32 {
+        match self {
+            Self::Qr => 4,
+            Self::DataMatrix => 1,
+        }
+    }
+}
+
+impl FromStr for Symbology {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "qr" | "qrcode" => Ok(Self::Qr),
+            "datamatrix" | "data-matrix" | "dm" => Ok(Self::DataMatrix),
+            other => Err(format!(
+                "unknown symbology '{other}' (expected qr or datamatrix)"
+            )),
+        }
+    }
+}
+
+/// QR error-correction level (≈7 / 15 / 25 / 30 % recoverable).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(try_from = "String")]
+pub enum EcLevel {
+    L,
+    #[default]
+    M,
+    Q,
+    H,
+}
+
+impl FromStr for EcLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "L" => Ok(Self::L),
+            "M" => Ok(Self::M),
+            "Q" => Ok(Self::Q),
+            "H" => Ok(Self::H),
+            other => Err(format!(
+                "unknown error-correction level '{other}' (expected L, M, Q or H)"
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for Symbology {
+    type Error = String;
+
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl TryFrom<String> for EcLevel {
+    type Error = String;
+
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<EcLevel> for qrcode::EcLevel {
+    fn from(l: EcLevel) -> Self {
+        match l {
+            EcLevel::L => qrcode::EcLevel::L,
+            EcLevel::M => qrcode::EcLevel::M,
+            EcLevel::Q => qrcode::EcLevel::Q,
+            EcLevel::H => qrcode::EcLevel::H,
+        }
+    }
+}
+
+/// What to encode and how.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CodeSpec {
+    pub symbology: Symbology,
+    pub ec_level: EcLevel,
+    /// Quiet zone in modules; `None` uses the symbology's required minimum
+    /// (4 for QR, 1 for Data Matrix).
+    pub quiet_zone: Option<u32>,
+}
+
+/// An encoded symbol: a grid of modules (`true` = dark) plus its quiet zone.
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    symbology: Symbology,
+    width: u32,
+    height: u32,
+    quiet_zone: u32,
+    modules: Vec<bool>,
+}
+
+/// Encode `payload` as a symbol. Fails with [`Error::Encode`] when the payload
+/// exceeds the largest symbol of the chosen symbology and EC level.
+pub fn encode(payload: &[u8], spec: &CodeSpec) -> Result<Symbol> {
+    let quiet_zone = spec
+        .quiet_zone
+        .unwrap_or_else(|| spec.symbology.default_quiet_zone());
+    match spec.symbology {
+        Symbology::Qr => {
+            let code = QrCode::with_error_correction_level(payload, spec.ec_level.into())
+                .map_err(|e| Error::Encode(format!("QR ({:?}): {e}", spec.ec_level)))?;
+            let n = code.width() as u32;
+            Ok(Symbol {
+                symbology: Symbology::Qr,
+                width: n,
+                height: n,
+                quiet_zone,
+                modules: code
+                    .to_colors()
+                    .into_iter()
+                    .map(|c| c == Color::Dark)
+                    .collect(),
+            })
+        }
+        Symbology::DataMatrix => {
+            let code = DataMatrix::encode(payload, SymbolList::default())
+                .map_err(|e| Error::Encode(format!("Data Matrix: {e:?}")))?;
+            let bitmap = code.bitmap();
+            Ok(Symbol {
+                symbology: Symbology::DataMatrix,
+                width: bitmap.width() as u32,
+                height: bitmap.height() as u32,
+                quiet_zone,
+                modules: bitmap.bits().to_vec(),
+            })
+        }
+    }
+}
+
+impl Symbol {
+    /// Width in modules, quiet zone included.
+    pub fn total_width(&self) -> u32 {
+        self.width + 2 * self.quiet_zone
+    }
+
+    /// Height in modules, quiet zone included.
+    pub fn total_height(&self) -> u32 {
+        self.height + 2 * self.quiet_zone
+    }
+
+    /// Largest whole number of dots per module at which the symbol (with its
+    /// quiet zone) fits `avail_w` × `avail_h` dots, or `None` if it doesn't
+    /// fit even at one dot per module.
+    pub fn module_dots_for(&self, avail_w: u32, avail_h: u32) -> Option<u32> {
+        let m = (avail_w / self.total_width()).min(avail_h / self.total_height());
+        (m > 0).then_some(m)
+    }
+
+    /// Render at exactly `module_dots` dots per module, quiet zone included.
+    pub fn render(&self, module_dots: u32) -> Canvas {
+        let mut canvas = Canvas::new(
+            self.total_width() * module_dots,
+            self.total_height() * module_dots,
+        );
+        let origin = self.quiet_zone * module_dots;
+        for (i, _) in self.modules.iter().enumerate().filter(|(_, dark)| **dark) {
+            let mx = i as u32 % self.width;
+            let my = i as u32 / self.width;
+            canvas.fill_rect(
+                origin + mx * module_dots,
+                origin + my * module_dots,
+                module_dots,
+                module_dots,
+            );
+        }
+        canvas
+    }
+
+    /// Render at the largest module pitch that fits `avail_w` × `avail_h`.
+    pub fn render_fit(&self, avail_w: u32, avail_h: u32) -> Result<Canvas> {
+        let m = self
+            .module_dots_for(avail_w, avail_h)
+            .ok_or(Error::DoesNotFit {
+                symbol: self.symbology.name(),
+                modules_w: self.total_width(),
+                modules_h: self.total_height(),
+                avail_w,
+                avail_h,
+            })?;
+        Ok(self.render(m))
+    }
+}
+
+/// Render `payload` onto a whole-label canvas: the symbol is drawn at the
+/// largest module pitch that fits the printable band of `geom` and centred in
+/// it. Returns the canvas and the chosen dots-per-module.
+pub fn render_on_label(
+    payload: &[u8],
+    spec: &CodeSpec,
+    geom: &LabelGeometry,
+) -> Result<(Canvas, u32)> {
+    let symbol = encode(payload, spec)?;
+    let (px, py, pw, ph) = geom.printable();
+    let code = symbol.render_fit(pw, ph)?;
+    let module_dots = code.width() / symbol.total_width();
+    let mut canvas = geom.canvas();
+    canvas.blit(
+        &code,
+        px + (pw - code.width()) / 2,
+        py + (ph - code.height()) / 2,
+    );
+    Ok((canvas, module_dots))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn qr(ec_level: EcLevel) -> CodeSpec {
+        CodeSpec {
+            symbology: Symbology::Qr,
+            ec_level,
+            quiet_zone: None,
+        }
+    }
+
+    #[test]
+    fn qr_modules_land_on_whole_dots() {
+        let sym = encode(b"ASSET-0042", &qr(EcLevel::M)).unwrap();
+        assert_eq!(sym.total_width(), 21 + 8, "version 1 + 4-module quiet zone");
+        let c = sym.render(3);
+        assert_eq!((c.width(), c.height()), (87, 87));
+        // Quiet zone white, then the finder pattern's dark 7-module border.
+        assert!(!c.get(11, 11));
+        assert!(c.get(12, 12) && c.get(14, 14));
+        // Second finder ring (module 1) is light across its full 3-dot pitch.
+        assert!(!c.get(15, 15) && !c.get(17, 17));
+    }
+
+    #[test]
+    fn picks_largest_pitch_for_25mm_tag() {
+        let geom = LabelGeometry::from_mm(25.0, 25.0, 203, 384);
+        let (canvas, m) = render_on_label(b"ASSET-0042", &qr(EcLevel::M), &geom).unwrap();
+        assert_eq!((canvas.width(), canvas.height()), (200, 200));
+        // 184 printable rows / 29 modules = 6 dots per module.
+        assert_eq!(m, 6);
+        // Nothing lands in the unprinted feed margins.
+        assert!((0..8).all(|y| canvas.row(y).iter().all(|&b| b == 0)));
+    }
+
+    #[test]
+    fn higher_ec_means_smaller_modules() {
+        let geom = LabelGeometry::from_mm(25.0, 25.0, 203, 384);
+        let payload = b"https://inventory.example/asset/0042?site=berlin-lab-7";
+        let (_, m_low) = render_on_label(payload, &qr(EcLevel::L), &geom).unwrap();
+        let (_, m_high) = render_on_label(payload, &qr(EcLevel::H), &geom).unwrap();
+        assert!(
+            m_high < m_low,
+            "H ({m_high}) should be finer than L ({m_low})"
+        );
+    }
+
+    #[test]
+    fn oversized_payload_reports_does_not_fit() {
+        let geom = LabelGeometry::from_mm(12.0, 12.0, 203, 384);
+        let payload = vec![b'A'; 1500];
+        let err = render_on_label(&payload, &qr(EcLevel::L), &geom).unwrap_err();
+        assert!(matches!(err, Error::DoesNotFit { .. }), "{err}");
+    }
+
+    #[test]
+    fn unencodable_payload_is_an_encode_error() {
+        let payload = vec![b'A'; 8000];
+        assert!(matches!(
+            encode(&payload, &qr(EcLevel::H)),
+            Err(Error::Encode(_))
+        ));
+    }
+
+    #[test]
+    fn datamatrix_round_trips_through_decoder() {
+        let spec = CodeSpec {
+            symbology: Symbology::DataMatrix,
+            quiet_zone: Some(0),
+            ..Default::default()
+        };
+        let sym = encode(b"SKU 12345", &spec).unwrap();
+        let c = sym.render(4);
+        // Sample each module centre back into a bit grid and decode it.
+        let (w, h) = (sym.total_width(), sym.total_height());
+        let bits: Vec<bool> = (0..h)
+            .flat_map(|my| (0..w).map(move |mx| (mx, my)))
+            .map(|(mx, my)| c.get(mx * 4 + 2, my * 4 + 2))
+            .collect();
+        assert_eq!(DataMatrix::decode(&bits, w as usize).unwrap(), b"SKU 12345");
+    }
+
+    #[test]
+    fn parses_cli_spellings() {
+        assert_eq!("QR".parse::<Symbology>(), Ok(Symbology::Qr));
+        assert_eq!(
+            "data-matrix".parse::<Symbology>(),
+            Ok(Symbology::DataMatrix)
+        );
+        assert_eq!("q".parse::<EcLevel>(), Ok(EcLevel::Q));
+        assert!("x".parse::<EcLevel>().is_err());
+    }
+}
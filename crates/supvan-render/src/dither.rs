@@ -0,0 +1,518 @@
+//! 8bpp grayscale → 1bpp for the thermal head.
+//!
+//! A [`Dither`] pairs a screening [`DitherMethod`] with a [`ToneCurve`] that
+//! compensates for thermal dot spread. The default (4×4 Bayer on the
+//! calibrated thermal curve) is what `KsJob` has always applied to
+//! continuous-tone pages; [`dither_line`] remains that default as a stateless
+//! per-line function. Error diffusion carries state between rows, so the
+//! general entry point is a per-page [`Ditherer`] fed one row at a time.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::canvas::Canvas;
+
+/// Thermal-compensated sRGB-to-dither LUT.
+///
+/// Combines standard sRGB linearization (gamma ~2.2) with a thermal bleed
+/// compensation curve (gamma correction factor = 5.0). This pushes mid-tones
+/// significantly lighter to compensate for dot spread on thermal printers,
+/// where anything above ~50% dot density appears solid black.
+///
+/// Key mappings (W colorspace: 0=black, 255=white):
+///   W=  0 -> 100% dots, W= 48 -> 50%, W=128 -> 25%, W=192 -> 12.5%, W=255 -> 0%
+static SRGB_TO_LINEAR: [u8; 256] = [
+    0, 50, 58, 63, 67, 70, 72, 74, 76, 78, 80, 82, 83, 85, 86, 88, 89, 90, 92, 93, 95, 96, 97, 98,
+    100, 101, 102, 103, 105, 106, 107, 108, 109, 110, 112, 113, 114, 115, 116, 117, 118, 119, 120,
+    121, 122, 123, 124, 125, 126, 127, 128, 129, 130, 131, 132, 133, 134, 135, 135, 136, 137, 138,
+    139, 140, 141, 142, 142, 143, 144, 145, 146, 147, 148, 148, 149, 150, 151, 152, 152, 153, 154,
+    155, 156, 156, 157, 158, 159, 159, 160, 161, 162, 162, 163, 164, 165, 165, 166, 167, 167, 168,
+    169, 170, 170, 171, 172, 172, 173, 174, 174, 175, 176, 177, 177, 178, 179, 179, 180, 181, 181,
+    182, 183, 183, 184, 184, 185, 186, 186, 187, 188, 188, 189, 190, 190, 191, 191, 192, 193, 193,
+    194, 195, 195, 196, 196, 197, 198, 198, 199, 199, 200, 201, 201, 202, 202, 203, 203, 204, 205,
+    205, 206, 206, 207, 207, 208, 209, 209, 210, 210, 211, 211, 212, 213, 213, 214, 214, 215, 215,
+    216, 216, 217, 217, 218, 219, 219, 220, 220, 221, 221, 222, 222, 223, 223, 224, 224, 225, 225,
+    226, 226, 227, 227, 228, 228, 229, 230, 230, 231, 231, 232, 232, 233, 233, 234, 234, 235, 235,
+    236, 236, 237, 237, 238, 238, 238, 239, 239, 240, 240, 241, 241, 242, 242, 243, 243, 244, 244,
+    245, 245, 246, 246, 247, 247, 248, 248, 249, 249, 249, 250, 250, 251, 251, 252, 252, 253, 253,
+    254, 254, 255, 255,
+];
+
+/// 4x4 Bayer ordered dither threshold matrix, scaled 0-255.
+static BAYER4: [[u8; 4]; 4] = [
+    [8, 136, 40, 168],
+    [200, 72, 232, 104],
+    [56, 184, 24, 152],
+    [248, 120, 216, 88],
+];
+
+/// 8x8 Bayer index matrix (0-63); thresholds are `4 * v + 2`.
+static BAYER8: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+/// Dither an 8bpp sRGB grayscale line to 1bpp MSB-first, with horizontal mirror.
+///
+/// `line`: input grayscale pixels (0x00 = black, 0xFF = white / W colorspace), length >= `width`.
+/// `width`: number of pixels.
+/// `y`: current scanline index (for Bayer pattern row selection).
+/// `mono`: output 1bpp buffer, length >= `(width + 7) / 8`. Caller must zero it first.
+pub fn dither_line(line: &[u8], width: u32, y: u32, mono: &mut [u8]) {
+    for x in 0..width {
+        let mx = width - 1 - x; // mirror
+        if dither_dot(line[x as usize], mx, y) {
+            mono[(mx / 8) as usize] |= 0x80 >> (mx & 7);
+        }
+    }
+}
+
+/// Whether the dot at (`x`, `y`) of an 8bpp page burns, for callers that
+/// dither only part of a page. Same LUT and Bayer phase as [`dither_line`].
+pub fn dither_dot(luma: u8, x: u32, y: u32) -> bool {
+    SRGB_TO_LINEAR[luma as usize] < BAYER4[(y & 3) as usize][(x & 3) as usize]
+}
+
+/// Dither a whole row-major 8bpp image (`width` bytes per row) into a canvas
+/// with [`dither_line`], row by row — exactly what `KsJob` does to an 8bpp page.
+pub fn dither_canvas(luma: &[u8], width: u32, height: u32) -> Canvas {
+    dither_canvas_with(luma, width, height, &Dither::default())
+}
+
+/// [`dither_canvas`] with an explicit method and tone curve. Mirrored like
+/// `KsJob`'s 8bpp path.
+pub fn dither_canvas_with(luma: &[u8], width: u32, height: u32, dither: &Dither) -> Canvas {
+    let mut canvas = Canvas::new(width, height);
+    let bpl = canvas.bytes_per_line() as usize;
+    let mut ditherer = Ditherer::new(dither, width).mirrored(true);
+    for (line, mono) in luma
+        .chunks_exact(width as usize)
+        .zip(canvas.data_mut().chunks_exact_mut(bpl))
+    {
+        ditherer.row(line, mono);
+    }
+    canvas
+}
+
+/// How continuous tone is screened into dots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(try_from = "String")]
+pub enum DitherMethod {
+    /// 4x4 ordered dither: a coarse, regular screen that survives dot spread.
+    #[default]
+    Bayer4,
+    /// 8x8 ordered dither: finer screen, more tone levels.
+    Bayer8,
+    /// Floyd–Steinberg error diffusion: the most tonal detail, for photos.
+    FloydSteinberg,
+    /// Atkinson error diffusion: diffuses only 3/4 of the error, keeping
+    /// highlights and shadows clean; suits graphics and screenshots.
+    Atkinson,
+    /// Fixed 50 % threshold: no screening at all, for text and line art.
+    Threshold,
+}
+
+impl FromStr for DitherMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "bayer4" | "bayer" | "ordered" => Ok(Self::Bayer4),
+            "bayer8" => Ok(Self::Bayer8),
+            "floyd-steinberg" | "floydsteinberg" | "fs" => Ok(Self::FloydSteinberg),
+            "atkinson" => Ok(Self::Atkinson),
+            "threshold" | "none" => Ok(Self::Threshold),
+            other => Err(format!(
+                "unknown dither method '{other}' (expected bayer4, bayer8, \
+                 floyd-steinberg, atkinson or threshold)"
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for DitherMethod {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for DitherMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Bayer4 => "bayer4",
+            Self::Bayer8 => "bayer8",
+            Self::FloydSteinberg => "floyd-steinberg",
+            Self::Atkinson => "atkinson",
+            Self::Threshold => "threshold",
+        })
+    }
+}
+
+/// Input luma (`0` black … `255` white) → screening level, compensating for
+/// the head's dot spread. A level `v` ends up as roughly `1 - v / 255` dot
+/// coverage whichever [`DitherMethod`] screens it.
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct ToneCurve {
+    name: String,
+    lut: [u8; 256],
+}
+
+impl ToneCurve {
+    /// The calibrated thermal curve `KsJob` has always used
+    /// (see `SRGB_TO_LINEAR`).
+    pub fn thermal() -> Self {
+        Self {
+            name: "thermal".into(),
+            lut: SRGB_TO_LINEAR,
+        }
+    }
+
+    /// No compensation: level = input luma.
+    pub fn linear() -> Self {
+        Self::gamma(1.0, 0.0).expect("valid curve")
+    }
+
+    /// A parametric curve. `gamma` decodes the input (`2.2` ≈ sRGB; the
+    /// thermal curve is close to `gamma(2.2, 0.0)`), then `bleed` thins the
+    /// resulting coverage as `c^(1 + bleed)` to offset dot gain; `0` disables
+    /// it.
+    pub fn gamma(gamma: f32, bleed: f32) -> Result<Self, String> {
+        let valid = gamma.is_finite() && gamma > 0.0 && bleed.is_finite() && bleed >= 0.0;
+        if !valid {
+            return Err(format!(
+                "tone curve needs gamma > 0 and bleed >= 0 (got gamma={gamma}, bleed={bleed})"
+            ));
+        }
+        let mut lut = [0u8; 256];
+        for (w, level) in lut.iter_mut().enumerate() {
+            let coverage = 1.0 - (w as f32 / 255.0).powf(1.0 / gamma);
+            let coverage = coverage.powf(1.0 + bleed);
+            *level = (255.0 * (1.0 - coverage)).round() as u8;
+        }
+        let name = if gamma == 1.0 && bleed == 0.0 {
+            "linear".into()
+        } else {
+            format!("gamma={gamma},bleed={bleed}")
+        };
+        Ok(Self { name, lut })
+    }
+
+    /// Screening level for one input luma.
+    pub fn apply(&self, luma: u8) -> u8 {
+        self.lut[luma as usize]
+    }
+}
+
+impl Default for ToneCurve {
+    fn default() -> Self {
+        Self::thermal()
+    }
+}
+
+impl fmt::Debug for ToneCurve {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ToneCurve({})", self.name)
+    }
+}
+
+impl fmt::Display for ToneCurve {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+/// `thermal`, `linear`, or `gamma=G[,bleed=B]`.
+impl FromStr for ToneCurve {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "thermal" => Ok(Self::thermal()),
+            "linear" => Ok(Self::linear()),
+            spec => {
+                let (mut gamma, mut bleed) = (None, 0.0);
+                for part in spec.split(',') {
+                    let (key, value) = part.split_once('=').ok_or_else(|| {
+                        format!(
+                            "unknown tone curve '{s}' (expected thermal, linear \
+                             or gamma=G[,bleed=B])"
+                        )
+                    })?;
+                    let value: f32 = value
+                        .trim()
+                        .parse()
+                        .map_err(|_| format!("tone curve {key}: '{value}' is not a number"))?;
+                    match key.trim() {
+                        "gamma" => gamma = Some(value),
+                        "bleed" => bleed = value,
+                        other => return Err(format!("unknown tone curve parameter '{other}'")),
+                    }
+                }
+                let gamma = gamma.ok_or_else(|| format!("tone curve '{s}' has no gamma"))?;
+                Self::gamma(gamma, bleed)
+            }
+        }
+    }
+}
+
+impl TryFrom<String> for ToneCurve {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// A screening method and the tone curve feeding it.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Dither {
+    pub method: DitherMethod,
+    pub curve: ToneCurve,
+}
+
+/// Stateful per-page ditherer: feed rows top to bottom with [`Ditherer::row`].
+/// Ordered methods ignore the state; error diffusion carries quantisation
+/// error into the next two rows.
+pub struct Ditherer {
+    method: DitherMethod,
+    curve: ToneCurve,
+    width: usize,
+    mirror: bool,
+    y: u32,
+    /// Accumulated error for rows y, y+1 and y+2, with two columns of
+    /// padding either side so kernels need no edge checks.
+    errors: [Vec<i32>; 3],
+}
+
+impl Ditherer {
+    pub fn new(dither: &Dither, width: u32) -> Self {
+        let padded = width as usize + 4;
+        Self {
+            method: dither.method,
+            curve: dither.curve.clone(),
+            width: width as usize,
+            mirror: false,
+            y: 0,
+            errors: [vec![0; padded], vec![0; padded], vec![0; padded]],
+        }
+    }
+
+    /// Write each row right-to-left, as `KsJob`'s 8bpp path does.
+    pub fn mirrored(mut self, mirror: bool) -> Self {
+        self.mirror = mirror;
+        self
+    }
+
+    /// Dither the next row. `line` holds at least `width` luma bytes; `mono`
+    /// receives `width` bits MSB-first (it is cleared first).
+    pub fn row(&mut self, line: &[u8], mono: &mut [u8]) {
+        mono.fill(0);
+        let y = self.y;
+        for (x, &luma) in line[..self.width].iter().enumerate() {
+            let out_x = if self.mirror { self.width - 1 - x } else { x } as u32;
+            let level = self.curve.apply(luma);
+            let black = match self.method {
+                DitherMethod::Bayer4 => level < BAYER4[(y & 3) as usize][(out_x & 3) as usize],
+                DitherMethod::Bayer8 => {
+                    level < BAYER8[(y & 7) as usize][(out_x & 7) as usize] * 4 + 2
+                }
+                DitherMethod::Threshold => level < 128,
+                DitherMethod::FloydSteinberg | DitherMethod::Atkinson => {
+                    self.diffuse(x + 2, i32::from(level))
+                }
+            };
+            if black {
+                mono[(out_x / 8) as usize] |= 0x80 >> (out_x & 7);
+            }
+        }
+        if matches!(
+            self.method,
+            DitherMethod::FloydSteinberg | DitherMethod::Atkinson
+        ) {
+            self.errors.rotate_left(1);
+            self.errors[2].fill(0);
+        }
+        self.y += 1;
+    }
+
+    /// Quantise one level (at padded column `px`) and spread its error.
+    fn diffuse(&mut self, px: usize, level: i32) -> bool {
+        let value = level + self.errors[0][px];
+        let black = value < 128;
+        let err = value - if black { 0 } else { 255 };
+        let [cur, next, next2] = &mut self.errors;
+        if self.method == DitherMethod::FloydSteinberg {
+            cur[px + 1] += err * 7 / 16;
+            next[px - 1] += err * 3 / 16;
+            next[px] += err * 5 / 16;
+            next[px + 1] += err / 16;
+        } else {
+            let e = err / 8;
+            cur[px + 1] += e;
+            cur[px + 2] += e;
+            next[px - 1] += e;
+            next[px] += e;
+            next[px + 1] += e;
+            next2[px] += e;
+        }
+        black
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dither_all_black() {
+        let line = vec![0x00; 8]; // all black (W colorspace)
+        let mut mono = vec![0u8; 1];
+        dither_line(&line, 8, 0, &mut mono);
+        // All pixels are 0 -> LUT[0]=0 < any threshold -> all bits set
+        assert_eq!(mono[0], 0xFF);
+    }
+
+    #[test]
+    fn test_dither_all_white() {
+        let line = vec![0xFF; 8]; // all white (W colorspace)
+        let mut mono = vec![0u8; 1];
+        dither_line(&line, 8, 0, &mut mono);
+        // All pixels are 255 -> LUT[255]=255 < threshold never -> all bits clear
+        assert_eq!(mono[0], 0x00);
+    }
+
+    #[test]
+    fn test_dither_midtone_lighter_than_50_percent() {
+        // With thermal compensation, sRGB mid-gray should produce well under 50% dots
+        let line = vec![0x80; 32]; // sRGB 128 → LUT value 188 → ~25% dots
+        let mut total_bits = 0u32;
+        for y in 0..4 {
+            let mut mono = vec![0u8; 4];
+            dither_line(&line, 32, y, &mut mono);
+            for &b in &mono {
+                total_bits += b.count_ones();
+            }
+        }
+        // With gamma=5 compensation, sRGB 0x80 → ~25% coverage (32 of 128)
+        assert!(
+            total_bits > 16 && total_bits < 64,
+            "expected ~25% bits set, got {total_bits}/128"
+        );
+    }
+
+    #[test]
+    fn dither_canvas_matches_per_line() {
+        let luma: Vec<u8> = (0..24u32 * 5).map(|i| (i * 7 % 256) as u8).collect();
+        let canvas = dither_canvas(&luma, 24, 5);
+        for y in 0..5u32 {
+            let mut mono = vec![0u8; 3];
+            dither_line(&luma[(y * 24) as usize..][..24], 24, y, &mut mono);
+            assert_eq!(canvas.row(y), &mono[..]);
+        }
+    }
+
+    fn coverage(dither: &Dither, luma: u8) -> f32 {
+        let canvas = dither_canvas_with(&[luma; 64 * 64], 64, 64, dither);
+        let dots: u32 = canvas.data().iter().map(|b| b.count_ones()).sum();
+        dots as f32 / (64.0 * 64.0)
+    }
+
+    #[test]
+    fn default_ditherer_matches_dither_line() {
+        let luma: Vec<u8> = (0..37u32 * 9).map(|i| (i * 13 % 256) as u8).collect();
+        let mut ditherer = Ditherer::new(&Dither::default(), 37).mirrored(true);
+        for (y, line) in luma.chunks_exact(37).enumerate() {
+            let (mut expected, mut got) = (vec![0u8; 5], vec![0u8; 5]);
+            dither_line(line, 37, y as u32, &mut expected);
+            ditherer.row(line, &mut got);
+            assert_eq!(got, expected, "row {y}");
+        }
+    }
+
+    #[test]
+    fn every_method_tracks_the_tone_curve() {
+        // Linear curve: 25 % grey should come out near 75 % dots.
+        for method in [
+            DitherMethod::Bayer4,
+            DitherMethod::Bayer8,
+            DitherMethod::FloydSteinberg,
+        ] {
+            let dither = Dither {
+                method,
+                curve: ToneCurve::linear(),
+            };
+            let c = coverage(&dither, 64);
+            assert!((0.70..0.80).contains(&c), "{method}: {c}");
+        }
+        // Atkinson drops a quarter of the error, which pushes shadows darker
+        // and highlights lighter.
+        let atkinson = Dither {
+            method: DitherMethod::Atkinson,
+            curve: ToneCurve::linear(),
+        };
+        assert!((0.75..0.95).contains(&coverage(&atkinson, 64)));
+        assert!(coverage(&atkinson, 192) < 0.25);
+    }
+
+    #[test]
+    fn threshold_is_a_hard_cut() {
+        let dither = Dither {
+            method: DitherMethod::Threshold,
+            curve: ToneCurve::linear(),
+        };
+        assert_eq!(coverage(&dither, 127), 1.0);
+        assert_eq!(coverage(&dither, 128), 0.0);
+    }
+
+    #[test]
+    fn tone_curves_parse_and_shape() {
+        assert_eq!(
+            "thermal".parse::<ToneCurve>().unwrap(),
+            ToneCurve::thermal()
+        );
+        let linear: ToneCurve = "gamma=1".parse().unwrap();
+        assert_eq!(linear, ToneCurve::linear());
+        assert!((0..=255).all(|w| linear.apply(w) == w));
+        // More bleed compensation → lighter mid-tones.
+        let soft: ToneCurve = "gamma=2.2".parse().unwrap();
+        let hard: ToneCurve = "gamma=2.2, bleed=1.5".parse().unwrap();
+        assert!(hard.apply(128) > soft.apply(128));
+        assert_eq!((hard.apply(0), hard.apply(255)), (0, 255));
+        // gamma=2.2 approximates the calibrated table.
+        assert!(soft.apply(128).abs_diff(ToneCurve::thermal().apply(128)) < 8);
+        assert!("gamma=0".parse::<ToneCurve>().is_err());
+        assert!("bleed=1".parse::<ToneCurve>().is_err());
+        assert!("sepia".parse::<ToneCurve>().is_err());
+    }
+
+    #[test]
+    fn methods_parse() {
+        assert_eq!("fs".parse(), Ok(DitherMethod::FloydSteinberg));
+        assert_eq!("Atkinson".parse(), Ok(DitherMethod::Atkinson));
+        assert_eq!(
+            DitherMethod::Bayer8.to_string().parse(),
+            Ok(DitherMethod::Bayer8)
+        );
+        assert!("random".parse::<DitherMethod>().is_err());
+    }
+
+    #[test]
+    fn test_dither_output_size() {
+        let line = vec![0x80; 13]; // non-aligned width
+        let bpl = 13_usize.div_ceil(8);
+        let mut mono = vec![0u8; bpl];
+        dither_line(&line, 13, 0, &mut mono);
+        // Just verify it doesn't panic
+    }
+}
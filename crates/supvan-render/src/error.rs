@@ -0,0 +1,89 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The payload could not be encoded at all (too long for the largest
+    /// symbol, unsupported characters, …).
+    #[error("cannot encode payload: {0}")]
+    Encode(String),
+
+    /// The encoded symbol is larger than the space available for it, even at
+    /// one dot per module.
+    #[error(
+        "{symbol} of {modules_w}x{modules_h} modules (incl. quiet zone) does not fit \
+         {avail_w}x{avail_h} printable dots; shorten the payload, lower the \
+         error-correction level, or load a larger label"
+    )]
+    DoesNotFit {
+        symbol: &'static str,
+        modules_w: u32,
+        modules_h: u32,
+        avail_w: u32,
+        avail_h: u32,
+    },
+
+    /// A linear barcode is wider than its box even at one dot per module.
+    #[error(
+        "{symbol} of {modules} modules (incl. quiet zones) does not fit {avail_w} dots; \
+         shorten the data or widen the element"
+    )]
+    BarcodeTooWide {
+        symbol: &'static str,
+        modules: u32,
+        avail_w: u32,
+    },
+
+    /// Text does not fit its box even at the smallest font scale.
+    #[error("text \"{text}\" does not fit {avail_w}x{avail_h} dots even at the smallest size")]
+    TextOverflow {
+        text: String,
+        avail_w: u32,
+        avail_h: u32,
+    },
+
+    /// The template file is malformed or structurally invalid.
+    #[error("invalid label template: {0}")]
+    Template(String),
+
+    /// A required template field has no value.
+    #[error("no value for template field '{0}'")]
+    MissingField(String),
+
+    /// The template's label size is not in the printer's media list.
+    #[error(
+        "template label {width_mm}x{height_mm} mm is not a supported media size \
+         (supported: {supported} mm)"
+    )]
+    UnsupportedMedia {
+        width_mm: f32,
+        height_mm: f32,
+        supported: String,
+    },
+
+    /// An image element could not be read or decoded.
+    #[error("image: {0}")]
+    Image(String),
+
+    /// Content on continuous media runs past the longest label the family
+    /// prints.
+    #[error("label would be {length_mm:.0} mm long, over the {max_mm} mm maximum")]
+    TooLong { length_mm: f32, max_mm: u32 },
+
+    /// A CUPS / PWG raster page header describes a page we cannot read.
+    #[error("bad raster header: {0}")]
+    Raster(String),
+
+    /// An SVG document could not be parsed.
+    #[error("invalid SVG: {0}")]
+    Svg(String),
+
+    /// Rendering one template element failed.
+    #[error("element {index} ({kind}): {source}")]
+    Element {
+        index: usize,
+        kind: &'static str,
+        source: Box<Error>,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
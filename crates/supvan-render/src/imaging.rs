@@ -0,0 +1,378 @@
+//! Continuous-tone image helpers: decoding to luma, rotation, resampling, and
+//! fitting a photo onto the label canvas ahead of [`crate::dither`].
+
+use std::str::FromStr;
+
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GrayImage, Luma};
+
+/// How an image is scaled onto the label.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScaleMode {
+    /// Scale to fit inside the label, aspect preserved, white padding.
+    #[default]
+    Fit,
+    /// Scale to cover the whole label, aspect preserved, overflow cropped.
+    Fill,
+    /// One image pixel per dot, centred and cropped.
+    None,
+    /// IPP `print-scaling=auto`: [`Fill`](Self::Fill) when the image and
+    /// label aspect ratios are within 10 % of each other (nothing of note is
+    /// cropped), [`Fit`](Self::Fit) otherwise.
+    Auto,
+    /// IPP `print-scaling=auto-fit`: [`Fit`](Self::Fit) when the image is
+    /// larger than the label, [`None`](Self::None) when it already fits.
+    AutoFit,
+}
+
+impl ScaleMode {
+    /// The concrete mode for an `iw` × `ih` image on a `label_w` × `label_h`
+    /// label ([`Auto`](Self::Auto) and [`AutoFit`](Self::AutoFit) resolved).
+    pub fn resolve(self, iw: u32, ih: u32, label_w: u32, label_h: u32) -> Self {
+        match self {
+            Self::Auto => {
+                let aspect = |w: u32, h: u32| w as f32 / h.max(1) as f32;
+                let ratio = aspect(iw, ih) / aspect(label_w, label_h);
+                if (1.0 / 1.1..=1.1).contains(&ratio) {
+                    Self::Fill
+                } else {
+                    Self::Fit
+                }
+            }
+            Self::AutoFit if iw > label_w || ih > label_h => Self::Fit,
+            Self::AutoFit => Self::None,
+            mode => mode,
+        }
+    }
+}
+
+impl FromStr for ScaleMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "fit" | "contain" => Ok(Self::Fit),
+            "fill" | "cover" => Ok(Self::Fill),
+            "none" => Ok(Self::None),
+            "auto" => Ok(Self::Auto),
+            "auto-fit" => Ok(Self::AutoFit),
+            other => Err(format!(
+                "unknown scaling mode '{other}' (expected fit, fill, none, auto or auto-fit)"
+            )),
+        }
+    }
+}
+
+/// Clockwise rotation applied before scaling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rotation {
+    #[default]
+    R0,
+    R90,
+    R180,
+    R270,
+    /// Quarter-turn when the image and label orientations disagree
+    /// (landscape image on a portrait label or vice versa).
+    Auto,
+}
+
+impl FromStr for Rotation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "0" => Ok(Self::R0),
+            "90" => Ok(Self::R90),
+            "180" => Ok(Self::R180),
+            "270" | "-90" => Ok(Self::R270),
+            "auto" => Ok(Self::Auto),
+            other => Err(format!(
+                "unknown rotation '{other}' (expected 0, 90, 180, 270 or auto)"
+            )),
+        }
+    }
+}
+
+/// Rotate `img` for a `label_w` × `label_h` label. On continuous media pass
+/// `u32::MAX` as the height: the tape counts as a portrait label, so
+/// [`Rotation::Auto`] runs landscape images along the feed.
+pub fn rotate(img: GrayImage, rotation: Rotation, label_w: u32, label_h: u32) -> GrayImage {
+    let rotation = match rotation {
+        Rotation::Auto => {
+            let (iw, ih) = img.dimensions();
+            if (iw > ih) != (label_w > label_h) && iw != ih && label_w != label_h {
+                Rotation::R90
+            } else {
+                Rotation::R0
+            }
+        }
+        r => r,
+    };
+    match rotation {
+        Rotation::R90 => imageops::rotate90(&img),
+        Rotation::R180 => imageops::rotate180(&img),
+        Rotation::R270 => imageops::rotate270(&img),
+        Rotation::R0 | Rotation::Auto => img,
+    }
+}
+
+/// Length along the feed of a continuous label `label_w` dots wide that
+/// holds an `iw` × `ih` image: the image's own aspect ratio, so fitting and
+/// filling coincide. [`ScaleMode::None`] keeps one pixel per dot, and
+/// [`ScaleMode::AutoFit`] only shrinks images wider than the tape.
+pub fn continuous_length(iw: u32, ih: u32, label_w: u32, mode: ScaleMode) -> u32 {
+    let scaled = || (ih as f32 * label_w as f32 / iw.max(1) as f32).round() as u32;
+    match mode {
+        ScaleMode::None => ih,
+        ScaleMode::AutoFit if iw <= label_w => ih,
+        _ => scaled(),
+    }
+}
+
+/// Place a grayscale image on a white `label_w` × `label_h` canvas according
+/// to `mode`, centred. Returns the row-major 8bpp canvas (`label_w` bytes per
+/// row), ready for [`crate::dither::dither_canvas`].
+pub fn fit_luma(img: &GrayImage, label_w: u32, label_h: u32, mode: ScaleMode) -> Vec<u8> {
+    let mut canvas = vec![0xFFu8; (label_w * label_h) as usize];
+    let (iw, ih) = img.dimensions();
+    if iw == 0 || ih == 0 || label_w == 0 || label_h == 0 {
+        return canvas;
+    }
+
+    let (sx, sy) = (label_w as f32 / iw as f32, label_h as f32 / ih as f32);
+    let scale = match mode.resolve(iw, ih, label_w, label_h) {
+        ScaleMode::Fit => sx.min(sy),
+        ScaleMode::Fill => sx.max(sy),
+        ScaleMode::None | ScaleMode::Auto | ScaleMode::AutoFit => 1.0,
+    };
+    let resized;
+    let src = if scale == 1.0 {
+        img
+    } else {
+        let rw = ((iw as f32 * scale).round() as u32).max(1);
+        let rh = ((ih as f32 * scale).round() as u32).max(1);
+        resized = imageops::resize(img, rw, rh, FilterType::Triangle);
+        &resized
+    };
+
+    // Centre: a positive offset pads the canvas, a negative one crops the image.
+    let (rw, rh) = src.dimensions();
+    let off = |label: u32, img: u32| (label as i64 - img as i64) / 2;
+    let (ox, oy) = (off(label_w, rw), off(label_h, rh));
+    let raw = src.as_raw();
+    for y in 0..label_h as i64 {
+        let iy = y - oy;
+        if iy < 0 || iy >= rh as i64 {
+            continue;
+        }
+        let x0 = ox.max(0);
+        let x1 = (ox + rw as i64).min(label_w as i64);
+        if x1 <= x0 {
+            continue;
+        }
+        let dst = (y * label_w as i64 + x0) as usize;
+        let src_start = (iy * rw as i64 + (x0 - ox)) as usize;
+        let n = (x1 - x0) as usize;
+        canvas[dst..dst + n].copy_from_slice(&raw[src_start..src_start + n]);
+    }
+    canvas
+}
+
+/// Resample to `w` × `h` by area averaging: each output pixel is the mean of
+/// the source area it covers, partial pixels weighted by overlap. Unlike a
+/// point or bilinear filter this keeps hairlines and halftone grey levels
+/// when a 300 or 600 dpi page comes down to the printhead's resolution.
+pub fn resample_area(img: &GrayImage, w: u32, h: u32) -> GrayImage {
+    let (iw, ih) = img.dimensions();
+    if (iw, ih) == (w, h) {
+        return img.clone();
+    }
+    if iw == 0 || ih == 0 || w == 0 || h == 0 {
+        return GrayImage::from_pixel(w, h, Luma([0xFF]));
+    }
+    let (xw, yw) = (area_weights(iw, w), area_weights(ih, h));
+
+    // Horizontal pass into `ih` rows of `w` sums, then vertical.
+    let mut rows = vec![0f32; (ih * w) as usize];
+    for (src, dst) in img
+        .as_raw()
+        .chunks_exact(iw as usize)
+        .zip(rows.chunks_exact_mut(w as usize))
+    {
+        for (out, weights) in dst.iter_mut().zip(&xw) {
+            *out = weights.iter().map(|&(i, wt)| src[i] as f32 * wt).sum();
+        }
+    }
+    let mut out = GrayImage::new(w, h);
+    for (y, weights) in yw.iter().enumerate() {
+        for x in 0..w as usize {
+            let v: f32 = weights
+                .iter()
+                .map(|&(j, wt)| rows[j * w as usize + x] * wt)
+                .sum();
+            out.put_pixel(
+                x as u32,
+                y as u32,
+                Luma([v.round().clamp(0.0, 255.0) as u8]),
+            );
+        }
+    }
+    out
+}
+
+/// For each of `dst` output pixels along one axis, the `src` pixels it covers
+/// and their weights (summing to 1).
+fn area_weights(src: u32, dst: u32) -> Vec<Vec<(usize, f32)>> {
+    let scale = src as f64 / dst as f64;
+    (0..dst)
+        .map(|i| {
+            let (start, end) = (i as f64 * scale, (i + 1) as f64 * scale);
+            let first = start.floor() as u32;
+            let last = (end.ceil() as u32).min(src);
+            (first..last)
+                .map(|j| {
+                    let overlap = end.min(j as f64 + 1.0) - start.max(j as f64);
+                    (j as usize, (overlap / scale) as f32)
+                })
+                .filter(|&(_, wt)| wt > 0.0)
+                .collect()
+        })
+        .collect()
+}
+
+/// Convert any decoded image to 8-bit luma, compositing transparency onto
+/// white paper (a transparent PNG background must not print black).
+pub fn luma_on_white(img: &DynamicImage) -> GrayImage {
+    if !img.color().has_alpha() {
+        return img.to_luma8();
+    }
+    let la = img.to_luma_alpha8();
+    GrayImage::from_fn(la.width(), la.height(), |x, y| {
+        let [l, a] = la.get_pixel(x, y).0;
+        let (l, a) = (l as u32, a as u32);
+        Luma([((l * a + 255 * (255 - a)) / 255) as u8])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{LumaA, Rgba, RgbaImage};
+
+    #[test]
+    fn fit_pads_and_fill_crops() {
+        // 10×5 black image onto a 20×20 label.
+        let img = GrayImage::from_pixel(10, 5, Luma([0]));
+        let fit = fit_luma(&img, 20, 20, ScaleMode::Fit);
+        assert_eq!(fit[0], 0xFF, "fit pads top-left");
+        assert_eq!(fit[10 * 20 + 10], 0, "fit covers centre");
+        let fill = fit_luma(&img, 20, 20, ScaleMode::Fill);
+        assert!(fill.iter().all(|&p| p == 0), "fill covers everything");
+    }
+
+    #[test]
+    fn none_is_pixel_exact_and_centred() {
+        let mut img = GrayImage::from_pixel(4, 4, Luma([0xFF]));
+        img.put_pixel(0, 0, Luma([0]));
+        let out = fit_luma(&img, 8, 6, ScaleMode::None);
+        assert_eq!(out[8 + 2], 0, "pixel (0,0) lands at (2,1)");
+        assert_eq!(out.iter().filter(|&&p| p == 0).count(), 1);
+        // Larger than the label: cropped around the centre, no panic.
+        let big = GrayImage::from_pixel(30, 30, Luma([0]));
+        assert!(
+            fit_luma(&big, 8, 6, ScaleMode::None)
+                .iter()
+                .all(|&p| p == 0)
+        );
+    }
+
+    #[test]
+    fn auto_modes_pick_fill_fit_or_none() {
+        // Near the label's aspect: fill; far from it: fit.
+        assert_eq!(ScaleMode::Auto.resolve(105, 100, 200, 200), ScaleMode::Fill);
+        assert_eq!(ScaleMode::Auto.resolve(300, 100, 200, 200), ScaleMode::Fit);
+        // Small images print 1:1, large ones shrink to fit.
+        assert_eq!(
+            ScaleMode::AutoFit.resolve(50, 50, 200, 100),
+            ScaleMode::None
+        );
+        assert_eq!(
+            ScaleMode::AutoFit.resolve(50, 150, 200, 100),
+            ScaleMode::Fit
+        );
+        assert_eq!(ScaleMode::Fill.resolve(1, 1, 200, 100), ScaleMode::Fill);
+
+        let small = GrayImage::from_pixel(2, 2, Luma([0]));
+        let out = fit_luma(&small, 8, 6, ScaleMode::AutoFit);
+        assert_eq!(out.iter().filter(|&&p| p == 0).count(), 4);
+    }
+
+    #[test]
+    fn auto_rotation_matches_label_orientation() {
+        let landscape = GrayImage::new(30, 10);
+        assert_eq!(
+            rotate(landscape.clone(), Rotation::Auto, 10, 30).dimensions(),
+            (10, 30)
+        );
+        assert_eq!(
+            rotate(landscape.clone(), Rotation::Auto, 30, 20).dimensions(),
+            (30, 10)
+        );
+        assert_eq!(
+            rotate(landscape, Rotation::R270, 30, 20).dimensions(),
+            (10, 30)
+        );
+    }
+
+    #[test]
+    fn continuous_length_follows_the_image_aspect() {
+        assert_eq!(continuous_length(200, 50, 96, ScaleMode::Fit), 24);
+        assert_eq!(continuous_length(200, 50, 96, ScaleMode::Auto), 24);
+        assert_eq!(continuous_length(200, 50, 96, ScaleMode::None), 50);
+        assert_eq!(continuous_length(40, 50, 96, ScaleMode::AutoFit), 50);
+        assert_eq!(continuous_length(192, 50, 96, ScaleMode::AutoFit), 25);
+
+        // Landscape artwork turns to run along the tape.
+        let landscape = GrayImage::new(300, 100);
+        assert_eq!(
+            rotate(landscape, Rotation::Auto, 96, u32::MAX).dimensions(),
+            (100, 300)
+        );
+    }
+
+    #[test]
+    fn area_resample_averages_coverage() {
+        // A 1-pixel black line every 3 pixels averages to one-third grey
+        // when 3:1 comes down to 1:1.
+        let img = GrayImage::from_fn(9, 3, |x, _| Luma([if x % 3 == 0 { 0 } else { 255 }]));
+        let out = resample_area(&img, 3, 1);
+        assert!(out.pixels().all(|p| p.0[0] == 170), "{:?}", out.as_raw());
+
+        // 300 → 203 dpi keeps the mean level of a fine checkerboard.
+        let checker = GrayImage::from_fn(300, 300, |x, y| {
+            Luma([if (x + y) % 2 == 0 { 0 } else { 255 }])
+        });
+        let out = resample_area(&checker, 203, 203);
+        let mean = out.pixels().map(|p| p.0[0] as u32).sum::<u32>() / (203 * 203);
+        assert!((120..=135).contains(&mean), "mean {mean}");
+
+        // Upscaling repeats pixels.
+        let out = resample_area(&GrayImage::from_pixel(1, 1, Luma([7])), 2, 2);
+        assert_eq!(out.as_raw(), &[7, 7, 7, 7]);
+    }
+
+    #[test]
+    fn transparent_pixels_become_white() {
+        let mut img = RgbaImage::from_pixel(2, 1, Rgba([0, 0, 0, 0]));
+        img.put_pixel(1, 0, Rgba([0, 0, 0, 255]));
+        let luma = luma_on_white(&DynamicImage::ImageRgba8(img));
+        assert_eq!(luma.as_raw(), &[255, 0]);
+    }
+
+    #[test]
+    fn half_alpha_blends_toward_white() {
+        let img = image::ImageBuffer::from_pixel(1, 1, LumaA([0u8, 128]));
+        let luma = luma_on_white(&DynamicImage::ImageLumaA8(img));
+        assert_eq!(luma.as_raw(), &[127]);
+    }
+}
@@ -0,0 +1,142 @@
+//! Label geometry: millimetres on the roll → dots on the canvas.
+
+use supvan_proto::bitmap::DEFAULT_MARGIN_DOTS;
+use supvan_proto::status::{DEFAULT_LABEL_HEIGHT_MM, MaterialInfo};
+
+use crate::canvas::Canvas;
+
+/// Convert a length in millimetres to whole dots at `dpi`, rounding to the
+/// nearest dot (203 dpi → 8 dots/mm for every label size in `models.toml`).
+pub fn mm_to_dots(mm: f32, dpi: u32) -> u32 {
+    (mm.max(0.0) * dpi as f32 / 25.4).round() as u32
+}
+
+/// Dot geometry of one label: the page a renderer draws on, plus the band
+/// along the feed that the printer actually burns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LabelGeometry {
+    /// Across the printhead, capped at the printhead width.
+    pub width_dots: u32,
+    /// Along the feed direction.
+    pub height_dots: u32,
+    /// Rows skipped at the top and bottom of the page: the print buffers are
+    /// built with the family's feed margin on each end (`DEFAULT_MARGIN_DOTS`
+    /// unless `models.toml` sets `margin_dots`), and those raster rows never
+    /// reach the head.
+    pub feed_margin_dots: u32,
+    pub dpi: u32,
+}
+
+impl LabelGeometry {
+    /// Geometry for a `width_mm` × `height_mm` label.
+    pub fn from_mm(width_mm: f32, height_mm: f32, dpi: u32, printhead_width_dots: u32) -> Self {
+        Self {
+            width_dots: mm_to_dots(width_mm, dpi).min(printhead_width_dots),
+            height_dots: mm_to_dots(height_mm, dpi),
+            feed_margin_dots: DEFAULT_MARGIN_DOTS as u32,
+            dpi,
+        }
+    }
+
+    /// Geometry of the roll the printer reports as loaded. A zero width means
+    /// "unknown" and uses the full printhead; a zero height falls back to
+    /// [`DEFAULT_LABEL_HEIGHT_MM`], as the test print does.
+    pub fn from_material(mat: &MaterialInfo, dpi: u32, printhead_width_dots: u32) -> Self {
+        let height_mm = if mat.height_mm == 0 {
+            DEFAULT_LABEL_HEIGHT_MM
+        } else {
+            mat.height_mm
+        };
+        let mut geom = Self::from_mm(
+            mat.width_mm as f32,
+            height_mm as f32,
+            dpi,
+            printhead_width_dots,
+        );
+        if mat.width_mm == 0 {
+            geom.width_dots = printhead_width_dots;
+        }
+        geom
+    }
+
+    /// Geometry of the loaded roll, leaving the length open on continuous
+    /// media: `height_dots` is 0 there and the caller sizes the page from
+    /// its content (see [`is_continuous`](Self::is_continuous)).
+    pub fn from_roll(mat: &MaterialInfo, dpi: u32, printhead_width_dots: u32) -> Self {
+        let mut geom = Self::from_material(mat, dpi, printhead_width_dots);
+        if mat.is_continuous() {
+            geom.height_dots = 0;
+        }
+        geom
+    }
+
+    /// Whether the label length is still open (continuous media).
+    pub fn is_continuous(&self) -> bool {
+        self.height_dots == 0
+    }
+
+    /// The burned area as `(x, y, width, height)` in canvas dots.
+    pub fn printable(&self) -> (u32, u32, u32, u32) {
+        let margin = self.feed_margin_dots.min(self.height_dots / 2);
+        (0, margin, self.width_dots, self.height_dots - 2 * margin)
+    }
+
+    /// A blank canvas the size of the whole label.
+    pub fn canvas(&self) -> Canvas {
+        Canvas::new(self.width_dots, self.height_dots)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t50_is_eight_dots_per_mm() {
+        assert_eq!(mm_to_dots(25.0, 203), 200);
+        assert_eq!(mm_to_dots(40.0, 203), 320);
+        assert_eq!(mm_to_dots(-3.0, 203), 0);
+    }
+
+    #[test]
+    fn from_material_caps_width_and_defaults_height() {
+        let mat = MaterialInfo {
+            width_mm: 60,
+            height_mm: 0,
+            ..Default::default()
+        };
+        let g = LabelGeometry::from_material(&mat, 203, 384);
+        assert_eq!(g.width_dots, 384);
+        assert_eq!(
+            g.height_dots,
+            mm_to_dots(DEFAULT_LABEL_HEIGHT_MM as f32, 203)
+        );
+    }
+
+    #[test]
+    fn from_roll_leaves_continuous_length_open() {
+        let tape = MaterialInfo {
+            width_mm: 12,
+            height_mm: 0,
+            ..Default::default()
+        };
+        let g = LabelGeometry::from_roll(&tape, 203, 384);
+        assert_eq!((g.width_dots, g.height_dots), (96, 0));
+        assert!(g.is_continuous());
+
+        let die_cut = MaterialInfo {
+            height_mm: 30,
+            ..tape
+        };
+        assert_eq!(
+            LabelGeometry::from_roll(&die_cut, 203, 384).height_dots,
+            240
+        );
+    }
+
+    #[test]
+    fn printable_excludes_feed_margins() {
+        let g = LabelGeometry::from_mm(25.0, 25.0, 203, 384);
+        assert_eq!(g.printable(), (0, 8, 200, 184));
+    }
+}
@@ -0,0 +1,25 @@
+//! Label rendering for Supvan printers — everything that turns label *content*
+//! into the row-major 1bpp raster the print pipeline consumes.
+//!
+//! The output of every renderer here is a [`canvas::Canvas`]: row-major,
+//! MSB-first, `1` = black dot. That is exactly what `supvan-app`'s `KsJob`
+//! accepts as a 1-bit page and what [`supvan_proto::printer::Printer::print_raster`]
+//! packs into the printhead's column-major layout, so rendered content reaches
+//! the printhead dot-for-dot with no resampling in between.
+//!
+//! Shared by `supvan-app` (IPP jobs) and `supvan-cli` (direct printing), as
+//! is [`models`], the validated printer model table whose families carry
+//! the rendering parameters (resolution, head width, default dither).
+
+pub mod barcode;
+pub mod canvas;
+pub mod code2d;
+pub mod dither;
+pub mod error;
+pub mod imaging;
+pub mod label;
+pub mod models;
+pub mod raster;
+pub mod svg;
+pub mod template;
+pub mod text;
@@ -0,0 +1,630 @@
+//! The printer model table, `models.toml`: parsed and fully validated.
+//!
+//! Shared by the printer app, which builds its registry from a
+//! [`ModelTable`] at startup and again whenever the file changes, and by
+//! `supvan-cli models check`. [`parse`] either returns a table the app can
+//! use as is or every problem it found, each with the line and column it
+//! points at, so a bad edit never reaches a running server.
+//!
+//! Beyond the TOML syntax and field types (including `dither` and
+//! `tone_curve` values), it checks that family names and USB PIDs are
+//! unique, PIDs are four hex digits, every model and `bt_patterns` entry
+//! names a known family, Bluetooth patterns are lowercase (names are
+//! lowercased before matching), sizes are positive, continuous ranges run
+//! from min to max, and the default family exists. Each family's
+//! [`Capabilities`] are checked against what the protocol can carry: known
+//! transports, a density range the buffer header holds (unless density goes
+//! by command), an ascending speed table, an RD_LAB_DPI opcode, and transfer
+//! sizes and margins in range.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use supvan_proto::buffer::{MARGIN_MAX_DOTS, MAX_DENSITY, PRINT_BUF_SIZE};
+use supvan_proto::caps::{Capabilities, DensityMode, MAX_TRANSFER_BYTES, TRANSPORTS};
+use supvan_proto::cmd::{CMD_RD_LAB_DPI, CMD_RD_LAB_DPI_24, CMD_RD_LAB_DPI_25};
+use supvan_proto::speed::SpeedTable;
+
+use crate::dither::{Dither, DitherMethod, ToneCurve};
+
+/// The family printers fall back to when nothing more specific matches.
+pub const DEFAULT_FAMILY: &str = "supvan_t50";
+
+/// A validated model table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelTable {
+    pub families: Vec<Family>,
+    pub models: Vec<Model>,
+    /// `(pattern, family index)`, longest patterns first.
+    pub bt_patterns: Vec<(String, usize)>,
+    /// Index of [`DEFAULT_FAMILY`] in `families`.
+    pub default_family: usize,
+    /// Labels per roll by RFID tag SKU code.
+    pub roll_capacities: HashMap<String, u32>,
+}
+
+impl ModelTable {
+    /// The family called `name`, e.g. `supvan_t50`.
+    pub fn family(&self, name: &str) -> Option<&Family> {
+        self.families.iter().find(|f| f.name == name)
+    }
+
+    /// The family a model or Bluetooth name belongs to (see [`match_name`]),
+    /// else the default family.
+    pub fn family_for_name(&self, name: &str) -> &Family {
+        let idx = match_name(&self.bt_patterns, name).unwrap_or(self.default_family);
+        &self.families[idx]
+    }
+}
+
+/// The family index of the longest `bt_patterns` entry that `name`,
+/// lowercased, contains. `patterns` must be sorted longest first, as
+/// [`ModelTable::bt_patterns`] is.
+pub fn match_name(patterns: &[(String, usize)], name: &str) -> Option<usize> {
+    let lower = name.to_lowercase();
+    patterns
+        .iter()
+        .find(|(pattern, _)| lower.contains(pattern.as_str()))
+        .map(|&(_, idx)| idx)
+}
+
+/// One `[[families]]` entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Family {
+    pub name: String,
+    pub description: String,
+    pub dpi: i32,
+    pub printhead_dots: u32,
+    /// Fixed label sizes, width × height in millimetres.
+    pub media_mm: Vec<[i32; 2]>,
+    /// Continuous-tape range, `[[min_w, min_len], [max_w, max_len]]`.
+    pub continuous_mm: Option<[[i32; 2]; 2]>,
+    pub dither: Dither,
+    /// Bluetooth pairing PIN; `0000` when the file sets none.
+    pub bt_pin: String,
+    /// Protocol behaviour; the T50's where the file says nothing.
+    pub caps: Capabilities,
+}
+
+/// One `[[models]]` entry: a USB PID (VID 0x1820).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Model {
+    /// Lowercase hex, e.g. `2073`.
+    pub pid: String,
+    pub name: String,
+    /// Index into [`ModelTable::families`].
+    pub family: usize,
+}
+
+/// One problem in a model table, at a 1-based line and column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+/// Every problem [`parse`] found, in file order.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub struct ModelsError(pub Vec<Issue>);
+
+impl fmt::Display for ModelsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, issue) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{issue}")?;
+        }
+        Ok(())
+    }
+}
+
+/// One `[bytes, speed]` pair of a family's `speeds`.
+type SpeedEntry = Spanned<(usize, u16)>;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FamilyToml {
+    name: Spanned<String>,
+    description: String,
+    dpi: Spanned<i32>,
+    printhead_dots: Spanned<u32>,
+    media_mm: Vec<Spanned<[i32; 2]>>,
+    #[serde(default)]
+    dither: DitherMethod,
+    #[serde(default)]
+    tone_curve: ToneCurve,
+    continuous_mm: Option<Spanned<[[i32; 2]; 2]>>,
+    bt_pin: Option<Spanned<String>>,
+    transports: Option<Spanned<Vec<Spanned<String>>>>,
+    density: Option<Spanned<[u8; 2]>>,
+    density_mode: Option<Spanned<String>>,
+    speeds: Option<Spanned<Vec<SpeedEntry>>>,
+    #[serde(default)]
+    cutter: bool,
+    #[serde(default)]
+    ribbon: bool,
+    label_dpi_query: Option<Spanned<u8>>,
+    max_transfer_bytes: Option<Spanned<usize>>,
+    margin_dots: Option<Spanned<u16>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ModelToml {
+    pid: Spanned<String>,
+    name: String,
+    family: Spanned<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ModelsToml {
+    families: Vec<FamilyToml>,
+    models: Vec<ModelToml>,
+    #[serde(default)]
+    bt_patterns: BTreeMap<Spanned<String>, Vec<Spanned<String>>>,
+    #[serde(default)]
+    roll_capacities: BTreeMap<String, Spanned<u32>>,
+}
+
+/// Collects issues against the source text.
+struct Checker<'a> {
+    text: &'a str,
+    issues: Vec<(usize, Issue)>,
+}
+
+impl Checker<'_> {
+    fn at(&mut self, offset: usize, message: impl Into<String>) {
+        let before = &self.text[..offset.min(self.text.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+        self.issues.push((
+            offset,
+            Issue {
+                line,
+                column,
+                message: message.into(),
+            },
+        ));
+    }
+
+    fn on<T>(&mut self, value: &Spanned<T>, message: impl Into<String>) {
+        self.at(value.span().start, message);
+    }
+}
+
+/// Parse and validate a model table.
+pub fn parse(text: &str) -> Result<ModelTable, ModelsError> {
+    let mut check = Checker {
+        text,
+        issues: Vec::new(),
+    };
+    let toml: ModelsToml = match toml::from_str(text) {
+        Ok(toml) => toml,
+        Err(e) => {
+            let offset = e.span().map_or(0, |s| s.start);
+            check.at(offset, e.message().trim_end());
+            return Err(ModelsError(
+                check.issues.into_iter().map(|(_, i)| i).collect(),
+            ));
+        }
+    };
+
+    let mut index: HashMap<&str, usize> = HashMap::new();
+    for (i, f) in toml.families.iter().enumerate() {
+        let name = f.name.get_ref();
+        if index.insert(name, i).is_some() {
+            check.on(&f.name, format!("duplicate family '{name}'"));
+        }
+        if name.is_empty() || name.contains('\0') {
+            check.on(&f.name, "family name must be non-empty text");
+        }
+        if *f.dpi.get_ref() <= 0 {
+            check.on(&f.dpi, "dpi must be positive");
+        }
+        if *f.printhead_dots.get_ref() == 0 {
+            check.on(&f.printhead_dots, "printhead_dots must be positive");
+        }
+        for size in &f.media_mm {
+            let [w, h] = *size.get_ref();
+            if w <= 0 || h <= 0 {
+                check.on(size, format!("media size {w}x{h} mm must be positive"));
+            }
+        }
+        if let Some(range) = &f.continuous_mm {
+            let [[min_w, min_len], [max_w, max_len]] = *range.get_ref();
+            if min_w <= 0 || min_len <= 0 {
+                check.on(range, "continuous_mm minimum must be positive");
+            } else if min_w > max_w || min_len > max_len {
+                check.on(
+                    range,
+                    "continuous_mm must be [[min_w, min_len], [max_w, max_len]]",
+                );
+            }
+        }
+        if let Some(pin) = &f.bt_pin {
+            let p = pin.get_ref();
+            if p.is_empty() || p.len() > 16 || p.contains('\0') {
+                check.on(pin, "bt_pin must be 1 to 16 characters");
+            }
+        }
+        check_caps(&mut check, f);
+    }
+
+    let mut pids = HashSet::new();
+    let mut models = Vec::new();
+    for m in &toml.models {
+        let pid = m.pid.get_ref().to_ascii_lowercase();
+        if pid.len() != 4 || !pid.chars().all(|c| c.is_ascii_hexdigit()) {
+            check.on(
+                &m.pid,
+                format!("pid '{}' is not four hex digits", m.pid.get_ref()),
+            );
+        } else if !pids.insert(pid.clone()) {
+            check.on(&m.pid, format!("duplicate pid '{pid}'"));
+        }
+        match index.get(m.family.get_ref().as_str()) {
+            Some(&family) => models.push(Model {
+                pid,
+                name: m.name.clone(),
+                family,
+            }),
+            None => check.on(
+                &m.family,
+                format!(
+                    "model '{}' names unknown family '{}'",
+                    m.name,
+                    m.family.get_ref()
+                ),
+            ),
+        }
+    }
+
+    let mut bt_patterns = Vec::new();
+    for (family, patterns) in &toml.bt_patterns {
+        let Some(&idx) = index.get(family.get_ref().as_str()) else {
+            check.on(
+                family,
+                format!("bt_patterns names unknown family '{}'", family.get_ref()),
+            );
+            continue;
+        };
+        for pattern in patterns {
+            let p = pattern.get_ref();
+            if p.is_empty() {
+                check.on(pattern, "empty Bluetooth name pattern");
+            } else if *p != p.to_lowercase() {
+                check.on(
+                    pattern,
+                    format!("pattern '{p}' must be lowercase; names are lowercased to match"),
+                );
+            }
+            bt_patterns.push((p.clone(), idx));
+        }
+    }
+    bt_patterns.sort_by_key(|p| std::cmp::Reverse(p.0.len()));
+
+    for (sku, labels) in &toml.roll_capacities {
+        if *labels.get_ref() == 0 {
+            check.on(labels, format!("roll '{sku}' capacity must be positive"));
+        }
+    }
+
+    let default_family = index.get(DEFAULT_FAMILY).copied();
+    if default_family.is_none() {
+        check.at(0, format!("no '{DEFAULT_FAMILY}' family (the default)"));
+    }
+
+    if !check.issues.is_empty() {
+        check.issues.sort_by_key(|(offset, _)| *offset);
+        return Err(ModelsError(
+            check.issues.into_iter().map(|(_, i)| i).collect(),
+        ));
+    }
+
+    let families = toml
+        .families
+        .into_iter()
+        .map(|f| Family {
+            caps: caps(&f),
+            name: f.name.into_inner(),
+            description: f.description,
+            dpi: f.dpi.into_inner(),
+            printhead_dots: f.printhead_dots.into_inner(),
+            media_mm: f.media_mm.into_iter().map(Spanned::into_inner).collect(),
+            continuous_mm: f.continuous_mm.map(Spanned::into_inner),
+            dither: Dither {
+                method: f.dither,
+                curve: f.tone_curve,
+            },
+            bt_pin: f
+                .bt_pin
+                .map_or_else(|| "0000".to_string(), Spanned::into_inner),
+        })
+        .collect();
+    Ok(ModelTable {
+        families,
+        models,
+        bt_patterns,
+        default_family: default_family.unwrap(),
+        roll_capacities: toml
+            .roll_capacities
+            .into_iter()
+            .map(|(sku, labels)| (sku, labels.into_inner()))
+            .collect(),
+    })
+}
+
+fn density_mode(f: &FamilyToml) -> Result<DensityMode, String> {
+    f.density_mode
+        .as_ref()
+        .map_or(Ok(DensityMode::Header), |m| m.get_ref().parse())
+}
+
+/// The capability checks for one family.
+fn check_caps(check: &mut Checker<'_>, f: &FamilyToml) {
+    if let Some(transports) = &f.transports {
+        if transports.get_ref().is_empty() {
+            check.on(transports, "transports must name at least one transport");
+        }
+        let mut seen = HashSet::new();
+        for t in transports.get_ref() {
+            let name = t.get_ref().as_str();
+            if !TRANSPORTS.contains(&name) {
+                check.on(
+                    t,
+                    format!(
+                        "unknown transport '{name}' (expected {})",
+                        TRANSPORTS.join(", ")
+                    ),
+                );
+            } else if !seen.insert(name) {
+                check.on(t, format!("transport '{name}' listed twice"));
+            }
+        }
+    }
+    let mode = density_mode(f);
+    if let (Err(e), Some(m)) = (&mode, &f.density_mode) {
+        check.on(m, e.clone());
+    }
+    if let Some(range) = &f.density {
+        let [min, max] = *range.get_ref();
+        if min > max {
+            check.on(range, "density must be [min, max]");
+        } else if mode == Ok(DensityMode::Header) && max > MAX_DENSITY {
+            check.on(
+                range,
+                format!(
+                    "density above {MAX_DENSITY} does not fit the buffer header; \
+                     set density_mode = \"command\""
+                ),
+            );
+        }
+    }
+    if let Some(speeds) = &f.speeds {
+        if speeds.get_ref().is_empty() {
+            check.on(speeds, "speeds must have at least one entry");
+        }
+        let mut last = None;
+        for entry in speeds.get_ref() {
+            let (above, speed) = *entry.get_ref();
+            if speed == 0 {
+                check.on(entry, "speed must be positive");
+            }
+            if last.is_some_and(|l| above <= l) {
+                check.on(entry, "speeds must be in ascending order of bytes");
+            }
+            last = Some(above);
+        }
+    }
+    if let Some(op) = &f.label_dpi_query {
+        let op = *op.get_ref();
+        if ![CMD_RD_LAB_DPI, CMD_RD_LAB_DPI_24, CMD_RD_LAB_DPI_25].contains(&op) {
+            check.on(
+                f.label_dpi_query.as_ref().unwrap(),
+                format!("label_dpi_query {op:#04x} is not 0x22, 0x24 or 0x25"),
+            );
+        }
+    }
+    if let Some(max) = &f.max_transfer_bytes
+        && !(PRINT_BUF_SIZE..=MAX_TRANSFER_BYTES).contains(max.get_ref())
+    {
+        check.on(
+            max,
+            format!("max_transfer_bytes must be {PRINT_BUF_SIZE} to {MAX_TRANSFER_BYTES}"),
+        );
+    }
+    if let Some(margin) = &f.margin_dots
+        && !(1..=MARGIN_MAX_DOTS).contains(margin.get_ref())
+    {
+        check.on(
+            margin,
+            format!("margin_dots must be 1 to {MARGIN_MAX_DOTS}"),
+        );
+    }
+}
+
+/// A validated family's capabilities, defaults filled in.
+fn caps(f: &FamilyToml) -> Capabilities {
+    let defaults = Capabilities::default();
+    Capabilities {
+        transports: f.transports.as_ref().map_or(defaults.transports, |list| {
+            // Validated against TRANSPORTS, in the file's order.
+            list.get_ref()
+                .iter()
+                .filter_map(|t| TRANSPORTS.into_iter().find(|&n| n == t.get_ref()))
+                .collect()
+        }),
+        density: f
+            .density
+            .as_ref()
+            .map_or(defaults.density, |d| d.get_ref()[0]..=d.get_ref()[1]),
+        density_mode: density_mode(f).unwrap_or_default(),
+        speeds: f.speeds.as_ref().map_or(defaults.speeds, |s| {
+            SpeedTable(s.get_ref().iter().map(|e| *e.get_ref()).collect())
+        }),
+        cutter: f.cutter,
+        ribbon: f.ribbon,
+        label_dpi_query: f.label_dpi_query.as_ref().map(|q| *q.get_ref()),
+        max_transfer_bytes: f
+            .max_transfer_bytes
+            .as_ref()
+            .map_or(defaults.max_transfer_bytes, |m| *m.get_ref()),
+        margin_dots: f
+            .margin_dots
+            .as_ref()
+            .map_or(defaults.margin_dots, |m| *m.get_ref()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHIPPED: &str = include_str!("../../../data/models.toml");
+
+    #[test]
+    fn shipped_table_is_valid() {
+        let table = parse(SHIPPED).unwrap();
+        assert_eq!(table.families[table.default_family].name, DEFAULT_FAMILY);
+        let t50m = table.models.iter().find(|m| m.pid == "2072").unwrap();
+        assert_eq!(table.families[t50m.family].name, "supvan_t50");
+        let lengths: Vec<_> = table.bt_patterns.iter().map(|p| p.0.len()).collect();
+        assert!(lengths.is_sorted_by(|a, b| a >= b));
+    }
+
+    #[test]
+    fn issues_point_at_their_line_and_column() {
+        let text = r#"[[families]]
+name = "supvan_t50"
+description = "T50"
+dpi = 203
+printhead_dots = 384
+media_mm = [[40, 30], [0, 30]]
+
+[[models]]
+pid = "207G"
+name = "T50M"
+family = "supvan_t50"
+
+[[models]]
+pid = "2073"
+name = "Ghost"
+family = "supvan_t99"
+
+[bt_patterns]
+supvan_t50 = ["T50"]
+"#;
+        let issues = parse(text).unwrap_err().0;
+        let found: Vec<_> = issues
+            .iter()
+            .map(|i| (i.line, i.column, i.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (6, 23, "media size 0x30 mm must be positive"),
+                (9, 7, "pid '207G' is not four hex digits"),
+                (16, 10, "model 'Ghost' names unknown family 'supvan_t99'"),
+                (
+                    19,
+                    15,
+                    "pattern 'T50' must be lowercase; names are lowercased to match"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn syntax_and_value_errors_are_located() {
+        let err = parse("[[families]]\nname = \"x\"\ndpi = \"high\"\n").unwrap_err();
+        assert_eq!((err.0[0].line, err.0[0].column), (3, 7));
+
+        let dither = SHIPPED.replacen(
+            "printhead_dots = 384\n",
+            "printhead_dots = 384\ndither = \"halftone\"\n",
+            1,
+        );
+        let err = parse(&dither).unwrap_err();
+        assert!(err.0[0].message.contains("halftone"), "{err}");
+        let line = dither.lines().position(|l| l.contains("halftone")).unwrap();
+        assert_eq!(err.0[0].line, line + 1);
+    }
+
+    #[test]
+    fn capabilities_default_to_the_t50_and_are_read() {
+        let table = parse(SHIPPED).unwrap();
+        let family = |name: &str| &table.families.iter().find(|f| f.name == name).unwrap().caps;
+        assert_eq!(*family("supvan_t50"), Capabilities::default());
+
+        let p70 = SHIPPED.replacen(
+            "printhead_dots = 568\n",
+            "printhead_dots = 568\ntransports = [\"bt\", \"usb\"]\ndensity = [1, 20]\n\
+             density_mode = \"command\"\nspeeds = [[0, 40], [2000, 20]]\ncutter = true\n\
+             label_dpi_query = 0x24\nmax_transfer_bytes = 8192\nmargin_dots = 12\n",
+            1,
+        );
+        let table = parse(&p70).unwrap();
+        let t80 = &table
+            .families
+            .iter()
+            .find(|f| f.name == "supvan_t80")
+            .unwrap()
+            .caps;
+        assert_eq!(t80.transports, ["bt", "usb"]);
+        assert_eq!(t80.density, 1..=20);
+        assert_eq!(t80.density_mode, DensityMode::Command);
+        assert_eq!(t80.speeds, SpeedTable(vec![(0, 40), (2000, 20)]));
+        assert!(t80.cutter && !t80.ribbon);
+        assert_eq!(t80.label_dpi_query, Some(CMD_RD_LAB_DPI_24));
+        assert_eq!((t80.max_transfer_bytes, t80.margin_dots), (8192, 12));
+    }
+
+    #[test]
+    fn capability_mistakes_are_located() {
+        let text = SHIPPED.replacen(
+            "printhead_dots = 384\n",
+            "printhead_dots = 384\ntransports = [\"usb\", \"wifi\"]\ndensity = [0, 20]\n\
+             speeds = [[500, 40], [100, 0]]\nlabel_dpi_query = 0x23\nmargin_dots = 0\n",
+            1,
+        );
+        let err = parse(&text).unwrap_err();
+        let messages: Vec<_> = err.0.iter().map(|i| i.message.as_str()).collect();
+        assert_eq!(messages.len(), 6, "{err}");
+        assert!(messages[0].starts_with("unknown transport 'wifi'"));
+        assert!(messages[1].contains("set density_mode"));
+        assert_eq!(messages[2], "speed must be positive");
+        assert_eq!(messages[3], "speeds must be in ascending order of bytes");
+        assert_eq!(
+            messages[4],
+            "label_dpi_query 0x23 is not 0x22, 0x24 or 0x25"
+        );
+        assert!(messages[5].starts_with("margin_dots"));
+        let line = text.lines().position(|l| l.contains("wifi")).unwrap();
+        assert_eq!((err.0[0].line, err.0[0].column), (line + 1, 22));
+    }
+
+    #[test]
+    fn default_family_is_required() {
+        let text = "families = [{ name = \"other\", description = \"Other\", dpi = 203, \
+                    printhead_dots = 384, media_mm = [[40, 30]] }]\nmodels = []\n";
+        let err = parse(text).unwrap_err();
+        assert_eq!(err.0.len(), 1, "{err}");
+        assert!(err.0[0].message.contains("default"));
+    }
+}
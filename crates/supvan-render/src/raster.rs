@@ -0,0 +1,180 @@
+//! CUPS / PWG raster pages: the pixel layouts we print, checking a page
+//! header before its data is read, and conversion to luma at the printhead's
+//! resolution. Shared by the app's raster path and `supvan-cli print`.
+
+use image::{GrayImage, Pixel};
+use print_raster::model::cups::{CupsColorOrder, CupsColorSpace};
+
+use crate::error::{Error, Result};
+use crate::imaging;
+
+/// The largest page we read, in bytes. A letter page of 8-bit gray at
+/// 600 dpi is about 34 MB; anything past this is a broken or hostile header.
+pub const MAX_PAGE_BYTES: u64 = 256 << 20;
+
+/// Raster pixel layouts we can print (PWG 5102.4 `pwg-raster-document-type`
+/// names in the comments).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RasterFormat {
+    /// `black_1`: 1 is a burned dot — the printhead's own format.
+    Black1,
+    /// 1-bit `sgray_1` / CUPS `W`: 1 is white.
+    Gray1,
+    /// `black_8`: 255 is black.
+    Black8,
+    /// `sgray_8` / CUPS `W`: 0 is black.
+    Gray8,
+    /// `srgb_8` / CUPS `RGB`, chunky.
+    Rgb8,
+}
+
+impl RasterFormat {
+    /// The format a page header describes, or `None` if we cannot print it.
+    pub fn of(
+        color_space: CupsColorSpace,
+        color_order: CupsColorOrder,
+        bits_per_color: u32,
+        bits_per_pixel: u32,
+    ) -> Option<Self> {
+        use CupsColorSpace as Cs;
+        match (color_space, bits_per_color, bits_per_pixel) {
+            (Cs::Black, 1, 1) => Some(Self::Black1),
+            (Cs::Gray | Cs::sGray, 1, 1) => Some(Self::Gray1),
+            (Cs::Black, 8, 8) => Some(Self::Black8),
+            (Cs::Gray | Cs::sGray, 8, 8) => Some(Self::Gray8),
+            (Cs::RGB | Cs::sRGB, 8, 24) if color_order == CupsColorOrder::Chunky => {
+                Some(Self::Rgb8)
+            }
+            _ => None,
+        }
+    }
+
+    fn bits_per_pixel(self) -> u64 {
+        match self {
+            Self::Black1 | Self::Gray1 => 1,
+            Self::Black8 | Self::Gray8 => 8,
+            Self::Rgb8 => 24,
+        }
+    }
+
+    /// Check a `width` × `height` page of `bytes_per_line`-byte lines and
+    /// return its size in bytes: lines must hold `width` pixels, and the page
+    /// must be non-empty and at most [`MAX_PAGE_BYTES`].
+    pub fn page_bytes(self, width: u32, height: u32, bytes_per_line: u32) -> Result<usize> {
+        if width == 0 || height == 0 {
+            return Err(Error::Raster(format!("empty {width}x{height} page")));
+        }
+        let line = (u64::from(width) * self.bits_per_pixel()).div_ceil(8);
+        if u64::from(bytes_per_line) < line {
+            return Err(Error::Raster(format!(
+                "{bytes_per_line} bytes per line cannot hold {width} {self:?} pixels \
+                 ({line} needed)"
+            )));
+        }
+        let bytes = u64::from(bytes_per_line) * u64::from(height);
+        if bytes > MAX_PAGE_BYTES {
+            return Err(Error::Raster(format!(
+                "{width}x{height} page of {bytes} bytes is over the {MAX_PAGE_BYTES}-byte limit"
+            )));
+        }
+        Ok(bytes as usize)
+    }
+
+    /// Convert one page (`bpl`-byte lines) to 8-bit luma, 0 black. The
+    /// geometry must have passed [`page_bytes`](Self::page_bytes).
+    pub fn to_luma(self, width: u32, height: u32, bpl: u32, data: &[u8]) -> GrayImage {
+        let (w, bpl) = (width as usize, bpl as usize);
+        let mut luma = GrayImage::new(width, height);
+        for (dst, src) in luma
+            .as_mut()
+            .chunks_exact_mut(w)
+            .zip(data.chunks_exact(bpl))
+        {
+            match self {
+                Self::Black1 | Self::Gray1 => {
+                    let burned = u8::from(self == Self::Black1);
+                    for (x, px) in dst.iter_mut().enumerate() {
+                        let bit = (src[x / 8] >> (7 - x % 8)) & 1;
+                        *px = if bit == burned { 0 } else { 0xFF };
+                    }
+                }
+                Self::Black8 => dst.iter_mut().zip(src).for_each(|(px, &k)| *px = 0xFF - k),
+                Self::Gray8 => dst.copy_from_slice(&src[..w]),
+                Self::Rgb8 => {
+                    for (px, rgb) in dst.iter_mut().zip(src.chunks_exact(3)) {
+                        *px = image::Rgb([rgb[0], rgb[1], rgb[2]]).to_luma().0[0];
+                    }
+                }
+            }
+        }
+        luma
+    }
+}
+
+/// Area-resample a page rendered at `xres` × `yres` dpi to `dpi`, so it
+/// prints at its physical size. An unknown (zero) resolution is taken as
+/// `dpi` already.
+pub fn resample_to_dpi(luma: &GrayImage, (xres, yres): (u32, u32), dpi: u32) -> GrayImage {
+    let scale = |len: u32, res: u32| match res {
+        0 => len,
+        res => {
+            ((u64::from(len) * u64::from(dpi) + u64::from(res) / 2) / u64::from(res)).max(1) as u32
+        }
+    };
+    let (w, h) = (scale(luma.width(), xres), scale(luma.height(), yres));
+    imaging::resample_area(luma, w, h)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raster_formats_convert_to_luma() {
+        use CupsColorSpace as Cs;
+        let of = |cs, bpc, bpp| RasterFormat::of(cs, CupsColorOrder::Chunky, bpc, bpp);
+        assert_eq!(of(Cs::Black, 1, 1), Some(RasterFormat::Black1));
+        assert_eq!(of(Cs::sGray, 8, 8), Some(RasterFormat::Gray8));
+        assert_eq!(of(Cs::sRGB, 8, 24), Some(RasterFormat::Rgb8));
+        assert_eq!(of(Cs::CMYK, 8, 32), None);
+
+        // Two pixels per format: black then white; lines padded to 4 bytes.
+        let cases: [(RasterFormat, &[u8]); 5] = [
+            (RasterFormat::Black1, &[0b1000_0000, 0, 0, 0]),
+            (RasterFormat::Gray1, &[0b0100_0000, 0, 0, 0]),
+            (RasterFormat::Black8, &[0xFF, 0x00, 0, 0]),
+            (RasterFormat::Gray8, &[0x00, 0xFF, 0, 0]),
+            (RasterFormat::Rgb8, &[0, 0, 0, 0xFF, 0xFF, 0xFF, 0, 0]),
+        ];
+        for (format, line) in cases {
+            let luma = format.to_luma(2, 1, line.len() as u32, line);
+            assert_eq!(luma.as_raw(), &[0, 0xFF], "{format:?}");
+        }
+    }
+
+    #[test]
+    fn page_headers_are_checked_before_reading() {
+        assert_eq!(RasterFormat::Black1.page_bytes(9, 4, 2).unwrap(), 8);
+        assert_eq!(RasterFormat::Rgb8.page_bytes(2, 3, 8).unwrap(), 24);
+        // Lines too short for the width, at 1, 8 and 24 bits per pixel.
+        assert!(RasterFormat::Black1.page_bytes(9, 4, 1).is_err());
+        assert!(RasterFormat::Gray8.page_bytes(9, 4, 8).is_err());
+        assert!(RasterFormat::Rgb8.page_bytes(2, 3, 5).is_err());
+        assert!(RasterFormat::Gray8.page_bytes(0, 4, 0).is_err());
+        assert!(RasterFormat::Gray8.page_bytes(4, 0, 4).is_err());
+        assert!(
+            RasterFormat::Gray8
+                .page_bytes(u32::MAX, u32::MAX, u32::MAX)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn pages_are_resampled_to_the_printhead_resolution() {
+        let page = GrayImage::new(600, 300);
+        let at = |res| resample_to_dpi(&page, res, 203).dimensions();
+        assert_eq!(at((600, 300)), (203, 203));
+        assert_eq!(at((203, 203)), (600, 300));
+        assert_eq!(at((0, 0)), (600, 300));
+    }
+}
@@ -0,0 +1,237 @@
+//! SVG labels rendered straight onto the printhead dot grid.
+//!
+//! The document is parsed with its physical units resolved at the printer's
+//! DPI, so an SVG drawn at the label's size (`width="40mm" height="30mm"`)
+//! lands one user pixel per dot with no resampling. Anything else is
+//! contain-fit and centred on the label.
+//!
+//! Vector content (paths, text, strokes) is thresholded per dot at 50 %
+//! coverage, which keeps edges exactly where the design puts them. Only the
+//! areas covered by embedded raster images are dithered.
+
+use std::sync::{Arc, OnceLock};
+
+use image::{Pixel, Rgb};
+use resvg::tiny_skia::{Color, Pixmap, Rect, Transform};
+use resvg::usvg::{self, ImageHrefResolver, ImageKind, Node, Tree};
+
+use crate::canvas::Canvas;
+use crate::dither::dither_dot;
+use crate::error::{Error, Result};
+use crate::label::{LabelGeometry, mm_to_dots};
+
+/// Vector content darker than this (0–255 luma, after compositing onto white)
+/// burns a dot.
+const THRESHOLD: u8 = 128;
+
+/// System fonts for `<text>`, loaded once per process.
+fn fontdb() -> Arc<usvg::fontdb::Database> {
+    static DB: OnceLock<Arc<usvg::fontdb::Database>> = OnceLock::new();
+    DB.get_or_init(|| {
+        let mut db = usvg::fontdb::Database::new();
+        db.load_system_fonts();
+        Arc::new(db)
+    })
+    .clone()
+}
+
+/// Render an SVG document onto a whole-label canvas. On continuous media
+/// (`geom.height_dots == 0`) the label is as long as the document's aspect
+/// ratio makes it at the label width, up to `max_length_mm` (the family's
+/// `continuous_mm` maximum); a longer document is an error.
+///
+/// Only `data:` image hrefs are honoured; references to files or URLs are
+/// ignored, since documents may arrive from the network.
+pub fn render(svg: &[u8], geom: &LabelGeometry, max_length_mm: u32) -> Result<Canvas> {
+    let options = usvg::Options {
+        dpi: geom.dpi as f32,
+        image_href_resolver: ImageHrefResolver {
+            resolve_data: ImageHrefResolver::default_data_resolver(),
+            resolve_string: Box::new(|_, _| None),
+        },
+        fontdb: fontdb(),
+        ..Default::default()
+    };
+    let tree = Tree::from_data(svg, &options).map_err(|e| Error::Svg(e.to_string()))?;
+
+    let size = tree.size();
+    let label_w = geom.width_dots;
+    let label_h = if geom.is_continuous() {
+        let length = size.height() * label_w as f32 / size.width();
+        if length > mm_to_dots(max_length_mm as f32, geom.dpi) as f32 {
+            return Err(Error::TooLong {
+                length_mm: length * 25.4 / geom.dpi as f32,
+                max_mm: max_length_mm,
+            });
+        }
+        length.round() as u32
+    } else {
+        geom.height_dots
+    };
+    let mut pixmap = Pixmap::new(label_w, label_h)
+        .ok_or_else(|| Error::Svg(format!("empty label geometry {label_w}x{label_h} dots")))?;
+    pixmap.fill(Color::WHITE);
+
+    let scale = (label_w as f32 / size.width()).min(label_h as f32 / size.height());
+    let transform = Transform::from_translate(
+        (label_w as f32 - size.width() * scale) / 2.0,
+        (label_h as f32 - size.height() * scale) / 2.0,
+    )
+    .pre_scale(scale, scale);
+    resvg::render(&tree, transform, &mut pixmap.as_mut());
+
+    let mut bitmaps = Vec::new();
+    raster_image_bounds(tree.root().children(), transform, &mut bitmaps);
+
+    let mut canvas = Canvas::new(label_w, label_h);
+    for (i, px) in pixmap.pixels().iter().enumerate() {
+        let (x, y) = (i as u32 % label_w, i as u32 / label_w);
+        // The pixmap was filled opaque white, so every pixel is opaque.
+        let luma = Rgb([px.red(), px.green(), px.blue()]).to_luma().0[0];
+        let (cx, cy) = (x as f32 + 0.5, y as f32 + 0.5);
+        let in_bitmap = bitmaps
+            .iter()
+            .any(|r| (r.left()..r.right()).contains(&cx) && (r.top()..r.bottom()).contains(&cy));
+        let black = if in_bitmap {
+            dither_dot(luma, x, y)
+        } else {
+            luma < THRESHOLD
+        };
+        if black {
+            canvas.set(x, y);
+        }
+    }
+    Ok(canvas)
+}
+
+/// Collect the on-label bounds of every embedded raster image (nested SVG
+/// images are vector content and are skipped).
+fn raster_image_bounds(nodes: &[Node], transform: Transform, out: &mut Vec<Rect>) {
+    for node in nodes {
+        match node {
+            Node::Group(group) => raster_image_bounds(group.children(), transform, out),
+            Node::Image(image) if !matches!(image.kind(), ImageKind::SVG(_)) => {
+                if let Some(rect) = image.abs_bounding_box().transform(transform) {
+                    out.push(rect);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The T50's continuous tape runs to 500 mm.
+    const MAX_MM: u32 = 500;
+
+    fn geom() -> LabelGeometry {
+        // 40x30 mm at 203 dpi: 320x240 dots.
+        LabelGeometry::from_mm(40.0, 30.0, 203, 384)
+    }
+
+    #[test]
+    fn label_sized_svg_maps_one_unit_per_dot() {
+        // 40 mm at 203 dpi is 320 user units; the black bar covers x 10..20.
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="40mm" height="30mm"
+            viewBox="0 0 320 240"><rect x="10" y="0" width="10" height="240"/></svg>"#;
+        let canvas = render(svg, &geom(), MAX_MM).unwrap();
+        assert_eq!((canvas.width(), canvas.height()), (320, 240));
+        assert!(!canvas.get(9, 100));
+        assert!(canvas.get(10, 100));
+        assert!(canvas.get(19, 100));
+        assert!(!canvas.get(20, 100));
+    }
+
+    #[test]
+    fn continuous_media_takes_the_document_length() {
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="12mm" height="60mm"
+            viewBox="0 0 96 480"><rect width="96" height="480"/></svg>"#;
+        let tape = LabelGeometry {
+            width_dots: 96,
+            height_dots: 0,
+            ..geom()
+        };
+        let canvas = render(svg, &tape, MAX_MM).unwrap();
+        assert_eq!((canvas.width(), canvas.height()), (96, 480));
+
+        // 60 mm fits a 60 mm maximum; a 59 mm one, or a degenerate
+        // zero-width document, is refused before anything is allocated.
+        assert!(render(svg, &tape, 60).is_ok());
+        assert!(matches!(
+            render(svg, &tape, 59),
+            Err(Error::TooLong { max_mm: 59, .. })
+        ));
+        let sliver = br#"<svg xmlns="http://www.w3.org/2000/svg" width="0.001mm" height="60mm">
+            <rect width="100%" height="100%"/></svg>"#;
+        assert!(render(sliver, &tape, MAX_MM).is_err());
+    }
+
+    #[test]
+    fn vector_grey_is_thresholded_not_dithered() {
+        let svg = br##"<svg xmlns="http://www.w3.org/2000/svg" width="40mm" height="30mm">
+            <rect width="100%" height="50%" fill="#404040"/>
+            <rect y="50%" width="100%" height="50%" fill="#c0c0c0"/></svg>"##;
+        let canvas = render(svg, &geom(), MAX_MM).unwrap();
+        assert!((0..320).all(|x| canvas.get(x, 60)));
+        assert!((0..320).all(|x| !canvas.get(x, 180)));
+    }
+
+    #[test]
+    fn embedded_bitmap_is_dithered() {
+        // A 1x1 mid-grey PNG stretched over the whole label.
+        let mut png = Vec::new();
+        image::GrayImage::from_pixel(1, 1, image::Luma([128]))
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let b64 = base64_encode(&png);
+        let svg = format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="40mm" height="30mm">
+            <image width="100%" height="100%" preserveAspectRatio="none"
+                href="data:image/png;base64,{b64}"/></svg>"#
+        );
+        let canvas = render(svg.as_bytes(), &geom(), MAX_MM).unwrap();
+        // One full Bayer period of rows.
+        let black = (100..104)
+            .flat_map(|y| (0..320).map(move |x| (x, y)))
+            .filter(|&(x, y)| canvas.get(x, y))
+            .count();
+        assert!(
+            black > 0 && black < 4 * 320,
+            "expected a dither pattern, got {black}"
+        );
+    }
+
+    #[test]
+    fn file_references_are_not_followed() {
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="40mm" height="30mm">
+            <image width="100%" height="100%" href="/etc/passwd"/></svg>"#;
+        let canvas = render(svg, &geom(), MAX_MM).unwrap();
+        assert!(canvas.data().iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn malformed_svg_is_an_error() {
+        assert!(matches!(
+            render(b"<svg", &geom(), MAX_MM),
+            Err(Error::Svg(_))
+        ));
+    }
+
+    fn base64_encode(data: &[u8]) -> String {
+        const ALPHABET: &[u8; 64] =
+            b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut out = String::new();
+        for chunk in data.chunks(3) {
+            let n =
+                chunk.iter().fold(0u32, |n, &b| n << 8 | u32::from(b)) << (8 * (3 - chunk.len()));
+            for i in 0..=chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            }
+            out.push_str(&"=".repeat(3 - chunk.len()));
+        }
+        out
+    }
+}
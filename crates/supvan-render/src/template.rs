@@ -0,0 +1,827 @@
+//! Declarative label templates.
+//!
+//! A template is a label size plus an ordered list of elements — text,
+//! barcodes, QR / Data Matrix codes, images, lines and boxes — positioned in
+//! millimetres from the label's top-left corner (x across the printhead, y
+//! along the feed). String properties may contain `{field}` placeholders that
+//! are filled in at render time; `{{` and `}}` are literal braces.
+//!
+//! ```toml
+//! width_mm = 25
+//! height_mm = 25
+//!
+//! [fields.sku]
+//! description = "Asset number"
+//!
+//! [[elements]]
+//! type = "qr"
+//! x = 3.5
+//! y = 1.5
+//! w = 18
+//! h = 18
+//! data = "https://inventory.example/{sku}"
+//!
+//! [[elements]]
+//! type = "text"
+//! x = 1
+//! y = 20
+//! w = 23
+//! h = 3.5
+//! text = "{sku}"
+//! align = "center"
+//! ```
+//!
+//! The same structure is accepted as JSON. An optional `[values]` table
+//! supplies field values inline, which is how a template travels as a single
+//! IPP document. Remember that the first and last millimetre along the feed
+//! are not printed (see [`LabelGeometry::printable`]).
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::barcode::{self, LinearSymbology};
+use crate::canvas::Canvas;
+use crate::code2d::{self, CodeSpec, EcLevel, Symbology};
+use crate::error::{Error, Result};
+use crate::imaging::luma_on_white;
+use crate::label::{LabelGeometry, mm_to_dots};
+use crate::text;
+
+/// Slack when comparing millimetre extents, to absorb float noise in
+/// hand-written layouts.
+const MM_EPSILON: f32 = 0.01;
+
+fn default_text_size() -> f32 {
+    3.0
+}
+
+fn default_stroke() -> f32 {
+    0.25
+}
+
+fn default_threshold() -> u8 {
+    128
+}
+
+/// A parsed and validated label template.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Template {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub width_mm: f32,
+    pub height_mm: f32,
+    /// Named fields the elements may reference as `{name}`.
+    #[serde(default)]
+    pub fields: BTreeMap<String, FieldDef>,
+    /// Inline field values; overridden by values passed to [`Template::render`].
+    #[serde(default)]
+    pub values: BTreeMap<String, String>,
+    #[serde(default)]
+    pub elements: Vec<Element>,
+    /// Directory relative image paths resolve against. `None` for templates
+    /// parsed from a string, which may not reference files at all.
+    #[serde(skip)]
+    base_dir: Option<PathBuf>,
+}
+
+/// Declaration of one template field.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FieldDef {
+    /// Used when no value is supplied; a field without one is required.
+    #[serde(default)]
+    pub default: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// An axis-aligned box in millimetres.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VAlign {
+    #[default]
+    Top,
+    Middle,
+    Bottom,
+}
+
+/// One drawable element, tagged by `type`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Element {
+    /// Text in the built-in bitmap font. `size_mm` is the glyph cell height;
+    /// text that doesn't fit the box at that size is shrunk by whole steps.
+    Text {
+        #[serde(flatten)]
+        rect: Rect,
+        text: String,
+        #[serde(default = "default_text_size")]
+        size_mm: f32,
+        #[serde(default)]
+        align: Align,
+        #[serde(default)]
+        valign: VAlign,
+    },
+    /// A linear barcode filling the box height.
+    Barcode {
+        #[serde(flatten)]
+        rect: Rect,
+        data: String,
+        #[serde(default)]
+        symbology: LinearSymbology,
+    },
+    Qr {
+        #[serde(flatten)]
+        rect: Rect,
+        data: String,
+        #[serde(default)]
+        ec: EcLevel,
+        #[serde(default)]
+        quiet_zone: Option<u32>,
+    },
+    Datamatrix {
+        #[serde(flatten)]
+        rect: Rect,
+        data: String,
+        #[serde(default)]
+        quiet_zone: Option<u32>,
+    },
+    /// A PNG or JPEG, contain-fitted into the box and thresholded.
+    Image {
+        #[serde(flatten)]
+        rect: Rect,
+        path: PathBuf,
+        /// Luma below this prints black.
+        #[serde(default = "default_threshold")]
+        threshold: u8,
+    },
+    Line {
+        x1: f32,
+        y1: f32,
+        x2: f32,
+        y2: f32,
+        #[serde(default = "default_stroke")]
+        thickness_mm: f32,
+    },
+    Box {
+        #[serde(flatten)]
+        rect: Rect,
+        #[serde(default = "default_stroke")]
+        thickness_mm: f32,
+        #[serde(default)]
+        fill: bool,
+    },
+}
+
+impl Element {
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::Text { .. } => "text",
+            Self::Barcode { .. } => "barcode",
+            Self::Qr { .. } => "qr",
+            Self::Datamatrix { .. } => "datamatrix",
+            Self::Image { .. } => "image",
+            Self::Line { .. } => "line",
+            Self::Box { .. } => "box",
+        }
+    }
+
+    /// The string property that takes placeholders, if any.
+    fn template_string(&self) -> Option<&str> {
+        match self {
+            Self::Text { text, .. } => Some(text),
+            Self::Barcode { data, .. } | Self::Qr { data, .. } | Self::Datamatrix { data, .. } => {
+                Some(data)
+            }
+            _ => None,
+        }
+    }
+
+    /// Bounding box in millimetres.
+    fn bounds(&self) -> Rect {
+        match *self {
+            Self::Line {
+                x1,
+                y1,
+                x2,
+                y2,
+                thickness_mm,
+            } => Rect {
+                x: x1.min(x2),
+                y: y1.min(y2),
+                w: (x2 - x1).abs().max(thickness_mm),
+                h: (y2 - y1).abs().max(thickness_mm),
+            },
+            Self::Text { rect, .. }
+            | Self::Barcode { rect, .. }
+            | Self::Qr { rect, .. }
+            | Self::Datamatrix { rect, .. }
+            | Self::Image { rect, .. }
+            | Self::Box { rect, .. } => rect,
+        }
+    }
+}
+
+/// Split `s` into literal text and `{field}` references.
+fn parse_placeholders(s: &str) -> std::result::Result<Vec<Piece<'_>>, String> {
+    let mut pieces = Vec::new();
+    let mut rest = s;
+    while let Some(i) = rest.find(['{', '}']) {
+        if i > 0 {
+            pieces.push(Piece::Literal(&rest[..i]));
+        }
+        let tail = &rest[i..];
+        if let Some(after) = tail.strip_prefix("{{") {
+            pieces.push(Piece::Literal("{"));
+            rest = after;
+        } else if let Some(after) = tail.strip_prefix("}}") {
+            pieces.push(Piece::Literal("}"));
+            rest = after;
+        } else if tail.starts_with('}') {
+            return Err(format!(
+                "unmatched '}}' in \"{s}\" (write '}}}}' for a literal brace)"
+            ));
+        } else {
+            let end = tail
+                .find('}')
+                .ok_or_else(|| format!("unterminated placeholder in \"{s}\""))?;
+            let name = tail[1..end].trim();
+            if name.is_empty() {
+                return Err(format!("empty placeholder in \"{s}\""));
+            }
+            pieces.push(Piece::Field(name));
+            rest = &tail[end + 1..];
+        }
+    }
+    if !rest.is_empty() {
+        pieces.push(Piece::Literal(rest));
+    }
+    Ok(pieces)
+}
+
+enum Piece<'a> {
+    Literal(&'a str),
+    Field(&'a str),
+}
+
+impl Template {
+    /// Parse a TOML template. It may not reference image files.
+    pub fn from_toml_str(s: &str) -> Result<Self> {
+        let t: Self = toml::from_str(s).map_err(|e| Error::Template(e.to_string()))?;
+        t.validated()
+    }
+
+    /// Parse a JSON template. It may not reference image files.
+    pub fn from_json_str(s: &str) -> Result<Self> {
+        let t: Self = serde_json::from_str(s).map_err(|e| Error::Template(e.to_string()))?;
+        t.validated()
+    }
+
+    /// Load a template file: `.json` is parsed as JSON, anything else as TOML.
+    /// Relative image paths resolve against the file's directory.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| Error::Template(format!("{}: {e}", path.display())))?;
+        let is_json = path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("json"));
+        let mut t: Self = if is_json {
+            serde_json::from_str(&contents).map_err(|e| e.to_string())
+        } else {
+            toml::from_str(&contents).map_err(|e| e.to_string())
+        }
+        .map_err(|e| Error::Template(format!("{}: {e}", path.display())))?;
+        t.base_dir = Some(
+            path.parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| PathBuf::from(".")),
+        );
+        t.validated()
+    }
+
+    fn validated(self) -> Result<Self> {
+        self.validate().map_err(Error::Template)?;
+        Ok(self)
+    }
+
+    /// Structural checks that don't depend on field values: positive label
+    /// size, every element inside the label, every placeholder declared, and
+    /// image files only when loaded from disk.
+    fn validate(&self) -> std::result::Result<(), String> {
+        if !(self.width_mm > 0.0 && self.height_mm > 0.0) {
+            return Err(format!(
+                "label size {}x{} mm must be positive",
+                self.width_mm, self.height_mm
+            ));
+        }
+        for name in self.values.keys() {
+            if !self.fields.contains_key(name) {
+                return Err(format!("value given for undeclared field '{name}'"));
+            }
+        }
+        for (i, el) in self.elements.iter().enumerate() {
+            let at = |msg: String| format!("element {} ({}): {msg}", i + 1, el.kind());
+            let b = el.bounds();
+            if !(b.w > 0.0 && b.h > 0.0) {
+                return Err(at(format!("size {}x{} mm must be positive", b.w, b.h)));
+            }
+            if b.x < -MM_EPSILON
+                || b.y < -MM_EPSILON
+                || b.x + b.w > self.width_mm + MM_EPSILON
+                || b.y + b.h > self.height_mm + MM_EPSILON
+            {
+                return Err(at(format!(
+                    "{}x{} mm at ({}, {}) extends past the {}x{} mm label",
+                    b.w, b.h, b.x, b.y, self.width_mm, self.height_mm
+                )));
+            }
+            if let Some(s) = el.template_string() {
+                for piece in parse_placeholders(s).map_err(at)? {
+                    if let Piece::Field(name) = piece
+                        && !self.fields.contains_key(name)
+                    {
+                        return Err(at(format!(
+                            "placeholder {{{name}}} is not a declared field"
+                        )));
+                    }
+                }
+            }
+            if let Element::Image { path, .. } = el
+                && self.base_dir.is_none()
+            {
+                return Err(at(format!(
+                    "image '{}' can only be used from a template file",
+                    path.display()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the label size against a printer family's media list
+    /// (`media_mm` from `models.toml`), to the nearest millimetre.
+    pub fn check_media(&self, media_mm: &[[u32; 2]]) -> Result<()> {
+        let fits = media_mm.iter().any(|[w, h]| {
+            (self.width_mm - *w as f32).abs() < 0.5 && (self.height_mm - *h as f32).abs() < 0.5
+        });
+        if fits {
+            return Ok(());
+        }
+        Err(Error::UnsupportedMedia {
+            width_mm: self.width_mm,
+            height_mm: self.height_mm,
+            supported: media_mm
+                .iter()
+                .map(|[w, h]| format!("{w}x{h}"))
+                .collect::<Vec<_>>()
+                .join(", "),
+        })
+    }
+
+    /// Dot geometry of this template's label.
+    pub fn geometry(&self, dpi: u32, printhead_width_dots: u32) -> LabelGeometry {
+        LabelGeometry::from_mm(self.width_mm, self.height_mm, dpi, printhead_width_dots)
+    }
+
+    /// Resolve every declared field: explicit `values`, then the template's
+    /// `[values]`, then the field default. Unknown keys in `values` are
+    /// ignored so a CSV may carry extra columns.
+    pub fn resolve<'a>(
+        &'a self,
+        values: &'a BTreeMap<String, String>,
+    ) -> Result<BTreeMap<&'a str, &'a str>> {
+        self.fields
+            .iter()
+            .map(|(name, def)| {
+                values
+                    .get(name)
+                    .or_else(|| self.values.get(name))
+                    .or(def.default.as_ref())
+                    .map(|v| (name.as_str(), v.as_str()))
+                    .ok_or_else(|| Error::MissingField(name.clone()))
+            })
+            .collect()
+    }
+
+    /// Render the label with `values` substituted into the placeholders.
+    pub fn render(
+        &self,
+        values: &BTreeMap<String, String>,
+        dpi: u32,
+        printhead_width_dots: u32,
+    ) -> Result<Canvas> {
+        let resolved = self.resolve(values)?;
+        let mut canvas = self.geometry(dpi, printhead_width_dots).canvas();
+        for (i, el) in self.elements.iter().enumerate() {
+            self.draw(el, &resolved, dpi, &mut canvas)
+                .map_err(|e| Error::Element {
+                    index: i + 1,
+                    kind: el.kind(),
+                    source: Box::new(e),
+                })?;
+        }
+        Ok(canvas)
+    }
+
+    fn draw(
+        &self,
+        el: &Element,
+        values: &BTreeMap<&str, &str>,
+        dpi: u32,
+        canvas: &mut Canvas,
+    ) -> Result<()> {
+        let dots = |mm: f32| mm_to_dots(mm, dpi);
+        let bx = |r: Rect| (dots(r.x), dots(r.y), dots(r.w), dots(r.h));
+        let fill = |s: &str| -> Result<String> {
+            let mut out = String::new();
+            for piece in parse_placeholders(s).map_err(Error::Template)? {
+                match piece {
+                    Piece::Literal(l) => out.push_str(l),
+                    Piece::Field(f) => out.push_str(values.get(f).copied().unwrap_or_default()),
+                }
+            }
+            Ok(out)
+        };
+        match el {
+            Element::Text {
+                rect,
+                text,
+                size_mm,
+                align,
+                valign,
+            } => {
+                let (x, y, w, h) = bx(*rect);
+                let s = fill(text)?;
+                draw_text(canvas, &s, (x, y, w, h), dots(*size_mm), *align, *valign)
+            }
+            Element::Barcode {
+                rect,
+                data,
+                symbology,
+            } => {
+                let (x, y, w, h) = bx(*rect);
+                let bars = barcode::render(&fill(data)?, *symbology, w, h)?;
+                canvas.blit(&bars, x, y);
+                Ok(())
+            }
+            Element::Qr {
+                rect,
+                data,
+                ec,
+                quiet_zone,
+            } => {
+                let spec = CodeSpec {
+                    symbology: Symbology::Qr,
+                    ec_level: *ec,
+                    quiet_zone: *quiet_zone,
+                };
+                blit_code(canvas, fill(data)?.as_bytes(), &spec, bx(*rect))
+            }
+            Element::Datamatrix {
+                rect,
+                data,
+                quiet_zone,
+            } => {
+                let spec = CodeSpec {
+                    symbology: Symbology::DataMatrix,
+                    quiet_zone: *quiet_zone,
+                    ..Default::default()
+                };
+                blit_code(canvas, fill(data)?.as_bytes(), &spec, bx(*rect))
+            }
+            Element::Image {
+                rect,
+                path,
+                threshold,
+            } => {
+                let full = match &self.base_dir {
+                    Some(dir) => dir.join(path),
+                    None => path.clone(),
+                };
+                draw_image(canvas, &full, *threshold, bx(*rect))
+            }
+            Element::Line {
+                x1,
+                y1,
+                x2,
+                y2,
+                thickness_mm,
+            } => {
+                let t = dots(*thickness_mm).max(1);
+                draw_line(canvas, (dots(*x1), dots(*y1)), (dots(*x2), dots(*y2)), t);
+                Ok(())
+            }
+            Element::Box {
+                rect,
+                thickness_mm,
+                fill: solid,
+            } => {
+                let (x, y, w, h) = bx(*rect);
+                if *solid {
+                    canvas.fill_rect(x, y, w, h);
+                } else {
+                    let t = dots(*thickness_mm).max(1).min(w).min(h);
+                    canvas.fill_rect(x, y, w, t);
+                    canvas.fill_rect(x, y + h - t, w, t);
+                    canvas.fill_rect(x, y, t, h);
+                    canvas.fill_rect(x + w - t, y, t, h);
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn draw_text(
+    canvas: &mut Canvas,
+    s: &str,
+    (x, y, w, h): (u32, u32, u32, u32),
+    size_dots: u32,
+    align: Align,
+    valign: VAlign,
+) -> Result<()> {
+    let wanted = (size_dots / text::GLYPH_SIZE).max(1);
+    let scale = (1..=wanted)
+        .rev()
+        .find(|&sc| {
+            let (tw, th) = text::measure(s, sc);
+            tw <= w && th <= h
+        })
+        .ok_or_else(|| Error::TextOverflow {
+            text: s.to_string(),
+            avail_w: w,
+            avail_h: h,
+        })?;
+    let (_, block_h) = text::measure(s, scale);
+    let top = match valign {
+        VAlign::Top => y,
+        VAlign::Middle => y + (h - block_h) / 2,
+        VAlign::Bottom => y + h - block_h,
+    };
+    for (i, line) in s.split('\n').enumerate() {
+        let (lw, _) = text::measure(line, scale);
+        let left = match align {
+            Align::Left => x,
+            Align::Center => x + (w - lw) / 2,
+            Align::Right => x + w - lw,
+        };
+        text::draw_line(
+            canvas,
+            line,
+            left,
+            top + text::line_offset(i as u32, scale),
+            scale,
+        );
+    }
+    Ok(())
+}
+
+fn blit_code(
+    canvas: &mut Canvas,
+    payload: &[u8],
+    spec: &CodeSpec,
+    (x, y, w, h): (u32, u32, u32, u32),
+) -> Result<()> {
+    let code = code2d::encode(payload, spec)?.render_fit(w, h)?;
+    canvas.blit(
+        &code,
+        x + (w - code.width()) / 2,
+        y + (h - code.height()) / 2,
+    );
+    Ok(())
+}
+
+fn draw_image(
+    canvas: &mut Canvas,
+    path: &Path,
+    threshold: u8,
+    (x, y, w, h): (u32, u32, u32, u32),
+) -> Result<()> {
+    let img = image::open(path).map_err(|e| Error::Image(format!("{}: {e}", path.display())))?;
+    let luma = luma_on_white(&img);
+    let (iw, ih) = luma.dimensions();
+    if iw == 0 || ih == 0 {
+        return Ok(());
+    }
+    let scale = (w as f32 / iw as f32).min(h as f32 / ih as f32);
+    let rw = ((iw as f32 * scale).round() as u32).clamp(1, w.max(1));
+    let rh = ((ih as f32 * scale).round() as u32).clamp(1, h.max(1));
+    let resized = image::imageops::resize(&luma, rw, rh, image::imageops::FilterType::Triangle);
+    let (ox, oy) = (x + (w - rw.min(w)) / 2, y + (h - rh.min(h)) / 2);
+    for (px, py, p) in resized.enumerate_pixels() {
+        if p.0[0] < threshold {
+            canvas.set(ox + px, oy + py);
+        }
+    }
+    Ok(())
+}
+
+/// Stamp a `t`-dot square brush along the segment from `a` to `b`.
+fn draw_line(canvas: &mut Canvas, a: (u32, u32), b: (u32, u32), t: u32) {
+    let (x1, y1) = (a.0 as i64, a.1 as i64);
+    let (dx, dy) = (b.0 as i64 - x1, b.1 as i64 - y1);
+    let steps = dx.abs().max(dy.abs()).max(1);
+    let half = (t / 2) as i64;
+    for i in 0..=steps {
+        let px = (x1 + dx * i / steps - half).max(0) as u32;
+        let py = (y1 + dy * i / steps - half).max(0) as u32;
+        canvas.fill_rect(px, py, t, t);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ASSET_TAG: &str = r#"
+width_mm = 25
+height_mm = 25
+
+[fields.sku]
+description = "Asset number"
+
+[fields.site]
+default = "HQ"
+
+[[elements]]
+type = "qr"
+x = 3.5
+y = 1.5
+w = 18
+h = 18
+data = "https://inventory.example/{sku}"
+
+[[elements]]
+type = "text"
+x = 1
+y = 20
+w = 23
+h = 3.5
+text = "{site}-{sku}"
+align = "center"
+
+[[elements]]
+type = "line"
+x1 = 0
+y1 = 19.75
+x2 = 25
+y2 = 19.75
+"#;
+
+    fn values(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn ink_in(c: &Canvas, x0: u32, y0: u32, x1: u32, y1: u32) -> bool {
+        (y0..y1).any(|y| (x0..x1).any(|x| c.get(x, y)))
+    }
+
+    #[test]
+    fn renders_asset_tag_with_fields() {
+        let t = Template::from_toml_str(ASSET_TAG).unwrap();
+        let c = t.render(&values(&[("sku", "0042")]), 203, 384).unwrap();
+        assert_eq!((c.width(), c.height()), (200, 200));
+        // QR in the top box, rule at y≈158, caption below it.
+        assert!(ink_in(&c, 28, 12, 172, 156));
+        assert!((0..200).all(|x| c.get(x, 158)));
+        assert!(ink_in(&c, 8, 160, 192, 188));
+    }
+
+    #[test]
+    fn missing_required_field_is_reported() {
+        let t = Template::from_toml_str(ASSET_TAG).unwrap();
+        let err = t.render(&BTreeMap::new(), 203, 384).unwrap_err();
+        assert!(
+            matches!(err, Error::MissingField(ref f) if f == "sku"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn inline_values_and_defaults_resolve() {
+        let src = format!("{ASSET_TAG}\n[values]\nsku = \"7\"\n");
+        let t = Template::from_toml_str(&src).unwrap();
+        let v = BTreeMap::new();
+        let r = t.resolve(&v).unwrap();
+        assert_eq!(r["sku"], "7");
+        assert_eq!(r["site"], "HQ");
+    }
+
+    #[test]
+    fn rejects_undeclared_placeholder() {
+        let src = ASSET_TAG.replace("{site}-{sku}", "{room}");
+        let err = Template::from_toml_str(&src).unwrap_err().to_string();
+        assert!(err.contains("{room}") && err.contains("element 2"), "{err}");
+    }
+
+    #[test]
+    fn rejects_element_outside_label() {
+        let src = ASSET_TAG.replace("w = 23", "w = 30");
+        let err = Template::from_toml_str(&src).unwrap_err().to_string();
+        assert!(err.contains("extends past"), "{err}");
+    }
+
+    #[test]
+    fn image_needs_a_template_file() {
+        let src = r#"
+width_mm = 40
+height_mm = 30
+[[elements]]
+type = "image"
+x = 0
+y = 0
+w = 10
+h = 10
+path = "/etc/logo.png"
+"#;
+        assert!(matches!(
+            Template::from_toml_str(src),
+            Err(Error::Template(_))
+        ));
+    }
+
+    #[test]
+    fn json_and_escaped_braces() {
+        let src = r#"{
+            "width_mm": 40, "height_mm": 30,
+            "fields": { "n": {} },
+            "elements": [
+                { "type": "text", "x": 1, "y": 2, "w": 38, "h": 5, "text": "{{{n}}}" },
+                { "type": "box", "x": 0, "y": 0, "w": 40, "h": 30, "thickness_mm": 0.5 }
+            ]
+        }"#;
+        let t = Template::from_json_str(src).unwrap();
+        assert!(t.render(&values(&[("n", "1")]), 203, 384).is_ok());
+        let mut out = String::new();
+        for p in parse_placeholders("{{{n}}}").unwrap() {
+            match p {
+                Piece::Literal(l) => out.push_str(l),
+                Piece::Field(f) => out.push_str(&format!("<{f}>")),
+            }
+        }
+        assert_eq!(out, "{<n>}");
+    }
+
+    #[test]
+    fn text_shrinks_then_overflows() {
+        let src = r#"
+width_mm = 40
+height_mm = 30
+[fields.t]
+[[elements]]
+type = "text"
+x = 0
+y = 2
+w = 40
+h = 6
+size_mm = 6
+text = "{t}"
+"#;
+        let t = Template::from_toml_str(src).unwrap();
+        // 10 chars × 6 dots-per-pixel × 8 = 480 > 320: shrinks to fit.
+        assert!(t.render(&values(&[("t", "ABCDEFGHIJ")]), 203, 384).is_ok());
+        let long = "X".repeat(60);
+        let err = t.render(&values(&[("t", &long)]), 203, 384).unwrap_err();
+        assert!(
+            matches!(err, Error::Element { ref source, .. } if matches!(**source, Error::TextOverflow { .. })),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn media_check_matches_model_table() {
+        let t = Template::from_toml_str(ASSET_TAG).unwrap();
+        assert!(t.check_media(&[[40, 30], [25, 25]]).is_ok());
+        let err = t
+            .check_media(&[[40, 30], [50, 30]])
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("25x25") && err.contains("40x30, 50x30"),
+            "{err}"
+        );
+    }
+}
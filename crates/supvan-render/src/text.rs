@@ -0,0 +1,91 @@
+//! Text in the built-in 8×8 bitmap font, scaled by whole dots.
+//!
+//! A bitmap face scaled by an integer factor keeps every stroke on the dot
+//! grid, so small label text stays crisp where an anti-aliased outline font
+//! would have to be thresholded. Covers ASCII and Latin-1; anything else
+//! renders as `?`.
+
+use font8x8::{BASIC_FONTS, LATIN_FONTS, UnicodeFonts};
+
+use crate::canvas::Canvas;
+
+/// Glyph cell edge in font pixels.
+pub const GLYPH_SIZE: u32 = 8;
+
+/// Line pitch in font pixels: the 8-pixel cell plus one pixel of leading.
+const LINE_PITCH: u32 = GLYPH_SIZE + 1;
+
+fn glyph(c: char) -> [u8; 8] {
+    BASIC_FONTS
+        .get(c)
+        .or_else(|| LATIN_FONTS.get(c))
+        .or_else(|| BASIC_FONTS.get('?'))
+        .unwrap_or_default()
+}
+
+/// Size in dots of `text` (lines split on `\n`) at `scale` dots per font pixel.
+pub fn measure(text: &str, scale: u32) -> (u32, u32) {
+    let lines = text.split('\n');
+    let (mut w, mut n) = (0, 0);
+    for line in lines {
+        w = w.max(line.chars().count() as u32 * GLYPH_SIZE * scale);
+        n += 1;
+    }
+    let h = if n == 0 {
+        0
+    } else {
+        ((n - 1) * LINE_PITCH + GLYPH_SIZE) * scale
+    };
+    (w, h)
+}
+
+/// Draw one line of `text` with its top-left corner at (`x`, `y`).
+pub fn draw_line(canvas: &mut Canvas, text: &str, x: u32, y: u32, scale: u32) {
+    for (i, c) in text.chars().enumerate() {
+        let gx = x + i as u32 * GLYPH_SIZE * scale;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_SIZE {
+                // font8x8 packs each row LSB = leftmost pixel.
+                if bits & (1 << col) != 0 {
+                    canvas.fill_rect(gx + col * scale, y + row as u32 * scale, scale, scale);
+                }
+            }
+        }
+    }
+}
+
+/// Vertical offset of line `index` within a text block at `scale`.
+pub fn line_offset(index: u32, scale: u32) -> u32 {
+    index * LINE_PITCH * scale
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_multiline_blocks() {
+        assert_eq!(measure("AB", 1), (16, 8));
+        assert_eq!(measure("AB\nC", 2), (32, 34));
+        assert_eq!(measure("", 3), (0, 24));
+    }
+
+    #[test]
+    fn scaled_glyph_covers_whole_dot_blocks() {
+        let mut c = Canvas::new(24, 24);
+        draw_line(&mut c, "\u{2588}", 0, 0, 3);
+        // Unknown glyphs fall back to '?', which is not a solid block.
+        assert!(!(0..24).all(|x| c.get(x, 0)));
+
+        let mut c = Canvas::new(24, 24);
+        draw_line(&mut c, "I", 0, 0, 3);
+        // Every lit font pixel is a full 3×3 block.
+        for y in (0..24).step_by(3) {
+            for x in (0..24).step_by(3) {
+                let lit = c.get(x, y);
+                assert!((0..3).all(|d| c.get(x + d, y + d) == lit));
+            }
+        }
+        assert!(c.data().iter().any(|&b| b != 0));
+    }
+}